@@ -0,0 +1,42 @@
+#import bevy_pbr::{
+    mesh_functions,
+    mesh_view_bindings::view,
+    view_transformations::position_world_to_clip,
+    forward_io::{Vertex, VertexOutput},
+}
+
+struct ParticleSettings {
+    color: vec4<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> particle: ParticleSettings;
+
+// Spans the quad along the camera's right and up axes around the entity's position,
+// scaled by the entity's scale, so every particle faces the camera
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    let center = world_from_local[3].xyz;
+    let size = length(world_from_local[0].xyz);
+    let right = view.world_from_view[0].xyz;
+    let up = view.world_from_view[1].xyz;
+    let world_position = center + (right * vertex.position.x + up * vertex.position.y) * size;
+
+    var out: VertexOutput;
+    out.world_position = vec4<f32>(world_position, 1.0);
+    out.position = position_world_to_clip(world_position);
+    out.world_normal = view.world_from_view[2].xyz;
+#ifdef VERTEX_UVS_A
+    out.uv = vertex.uv;
+#endif
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // A 3x3 pattern of slightly different shades reads as a chip of the block's texture
+    let cell = floor(in.uv * 3.0);
+    let noise = fract(sin(dot(cell, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+    return vec4<f32>(particle.color.rgb * (0.8 + 0.2 * noise), 1.0);
+}
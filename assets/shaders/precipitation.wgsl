@@ -0,0 +1,44 @@
+#import bevy_pbr::{
+    mesh_functions,
+    mesh_view_bindings::view,
+    view_transformations::position_world_to_clip,
+    forward_io::{Vertex, VertexOutput},
+}
+
+struct PrecipitationSettings {
+    color: vec4<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> precipitation: PrecipitationSettings;
+
+// Like particle.wgsl, but the quad stays upright and only turns about the vertical axis to
+// face the camera, so raindrops scaled tall read as streaks from every side. Width comes from
+// the entity's x scale and height from its y scale.
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    let center = world_from_local[3].xyz;
+    let width = length(world_from_local[0].xyz);
+    let height = length(world_from_local[1].xyz);
+    let to_camera = view.world_position - center;
+    let right = normalize(vec3<f32>(to_camera.z, 0.0, -to_camera.x) + vec3<f32>(1e-5, 0.0, 0.0));
+    let world_position = center + right * vertex.position.x * width + vec3<f32>(0.0, vertex.position.y * height, 0.0);
+
+    var out: VertexOutput;
+    out.world_position = vec4<f32>(world_position, 1.0);
+    out.position = position_world_to_clip(world_position);
+    out.world_normal = normalize(vec3<f32>(to_camera.x, 0.0, to_camera.z) + vec3<f32>(0.0, 1e-5, 0.0));
+#ifdef VERTEX_UVS_A
+    out.uv = vertex.uv;
+#endif
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Soft edges: round flakes, and streaks thinning out towards their ends
+    let offset = (in.uv - vec2<f32>(0.5)) * 2.0;
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(offset));
+    return vec4<f32>(precipitation.color.rgb, precipitation.color.a * falloff);
+}
@@ -0,0 +1,25 @@
+#import bevy_pbr::forward_io::VertexOutput
+
+struct SelectionSettings {
+    color: vec4<f32>,
+    line_width: f32,
+}
+
+@group(2) @binding(0)
+var<uniform> selection: SelectionSettings;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Every cuboid face spans 0..1 in UV, so its edges sit where either coordinate hits 0 or 1
+    let edge = min(in.uv, vec2<f32>(1.0) - in.uv);
+    // Divide by the screen-space derivative to get the distance to each edge in pixels
+    let pixels = edge / max(fwidth(in.uv), vec2<f32>(1e-6));
+    let distance = min(pixels.x, pixels.y);
+
+    // One-pixel falloff around the line's border for antialiasing
+    let coverage = clamp(selection.line_width * 0.5 - distance + 0.5, 0.0, 1.0);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(selection.color.rgb, selection.color.a * coverage);
+}
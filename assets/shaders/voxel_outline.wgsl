@@ -0,0 +1,84 @@
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+struct VoxelOutlineSettings {
+    color: vec4<f32>,
+    width: f32,
+    ao_strength: f32,
+    skylight: f32,
+}
+
+@group(2) @binding(100)
+var<uniform> outline: VoxelOutlineSettings;
+
+// Distance (in voxels) from this fragment to the closest voxel edge lying in the face plane
+fn edge_distance(world_position: vec3<f32>, world_normal: vec3<f32>) -> f32 {
+    let cell = fract(world_position);
+    let edge = min(cell, vec3<f32>(1.0) - cell);
+    let n = abs(world_normal);
+
+    if n.x > 0.5 {
+        return min(edge.y, edge.z);
+    } else if n.y > 0.5 {
+        return min(edge.x, edge.z);
+    }
+    return min(edge.x, edge.y);
+}
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef VERTEX_UVS_B
+    // Light levels packed into the fractions of UV_1, as in voxel_terrain.wgsl
+    let light_levels = fract(in.uv_b) * 16.0 - 0.5;
+    let brightness = pow(0.8, 15.0 - max(light_levels.y * outline.skylight, light_levels.x));
+    pbr_input.material.base_color = vec4<f32>(pbr_input.material.base_color.rgb * brightness, pbr_input.material.base_color.a);
+#endif
+
+#ifdef VERTEX_COLORS
+    // Ambient occlusion packed into vertex alpha, as in voxel_terrain.wgsl
+    let occlusion = (in.color.a - step(0.875, in.color.a)) * 4.0;
+    let ao = pow(max(1.0 - outline.ao_strength, 0.001), occlusion);
+    pbr_input.material.base_color = vec4<f32>(pbr_input.material.base_color.rgb * ao, pbr_input.material.base_color.a);
+#endif
+
+    let distance = edge_distance(in.world_position.xyz, in.world_normal);
+    // Screen-space derivative keeps the line antialiased and roughly constant in width
+    let aa = fwidth(distance);
+    let line = 1.0 - smoothstep(outline.width - aa, outline.width + aa, distance);
+
+#ifdef PREPASS_PIPELINE
+    pbr_input.material.base_color = vec4<f32>(
+        mix(pbr_input.material.base_color.rgb, outline.color.rgb, line * outline.color.a),
+        pbr_input.material.base_color.a,
+    );
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = vec4<f32>(mix(out.color.rgb, outline.color.rgb, line * outline.color.a), out.color.a);
+    // Post-lighting processing applies distance fog, so outlines fade with the terrain
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
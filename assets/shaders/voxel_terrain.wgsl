@@ -0,0 +1,144 @@
+#import bevy_pbr::{
+    pbr_bindings,
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+    mesh_view_bindings::{globals, view},
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+
+struct VoxelTerrainSettings {
+    caustics_strength: f32,
+    caustics_range: f32,
+    wetness: f32,
+    season_tint: vec4<f32>,
+    ambient_strength: f32,
+    ao_strength: f32,
+    skylight: f32,
+}
+
+@group(2) @binding(100)
+var<uniform> terrain: VoxelTerrainSettings;
+@group(2) @binding(101)
+var block_textures: texture_2d_array<f32>;
+@group(2) @binding(102)
+var block_sampler: sampler;
+
+// Texture coordinates in the face plane, one texture repeat per voxel. Side faces keep
+// world up as the texture's up.
+fn face_coordinates(world_position: vec3<f32>, world_normal: vec3<f32>) -> vec2<f32> {
+    let n = abs(world_normal);
+    if n.x > 0.5 {
+        return vec2<f32>(world_position.z, -world_position.y);
+    } else if n.z > 0.5 {
+        return vec2<f32>(world_position.x, -world_position.y);
+    }
+    return world_position.xz;
+}
+
+// Bright, thin, wandering lines like light focused by a moving water surface
+fn caustic_pattern(p: vec2<f32>, t: f32) -> f32 {
+    let a = sin(p.x * 2.1 + sin(p.y * 1.3 + t) * 1.5 + t * 0.7);
+    let b = sin(p.y * 2.4 + sin(p.x * 1.1 - t * 0.8) * 1.5 - t * 0.6);
+    let c = sin((p.x + p.y) * 1.7 + t * 0.9);
+    let v = (a + b + c) / 3.0;
+    return pow(1.0 - abs(v), 6.0);
+}
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    // Vertex alpha flags foliage (see below), so coverage comes from the material's alpha
+    // and the block texture instead. Only the cutout and translucent sections use it.
+    var alpha = pbr_bindings::material.base_color.a;
+
+#ifdef VERTEX_UVS_B
+    // The integer parts are flags, the same on every corner of a face: x packs the water flag
+    // with the texture layer + 1, see block_textures::pack_shader_flag. The fractions are the
+    // corner's block light (x) and skylight (y), see lighting::pack_light_level.
+    let flags = floor(in.uv_b);
+    let light_levels = fract(in.uv_b) * 16.0 - 0.5;
+    let packed_layer = floor(flags.x / 2.0 + 0.25);
+    let underwater = step(0.5, flags.x - 2.0 * packed_layer);
+    let layer = i32(packed_layer) - 1;
+    let sky_exposed = flags.y;
+    let light_level = max(light_levels.y * terrain.skylight, light_levels.x);
+#else
+    let underwater = 0.0;
+    let layer = -1;
+    let sky_exposed = 0.0;
+    let light_level = 15.0;
+#endif
+
+    // Gradients of the unwrapped coordinates, so mip selection doesn't jump at voxel edges
+    let face_uv = face_coordinates(in.world_position.xyz, in.world_normal);
+    let uv_dx = dpdx(face_uv);
+    let uv_dy = dpdy(face_uv);
+    if layer >= 0 {
+        let texel = textureSampleGrad(block_textures, block_sampler, fract(face_uv), layer, uv_dx, uv_dy);
+        pbr_input.material.base_color = vec4<f32>(
+            pbr_input.material.base_color.rgb * texel.rgb,
+            pbr_input.material.base_color.a,
+        );
+        alpha *= texel.a;
+    }
+    pbr_input.material.base_color.a = alpha;
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+    if underwater > 0.0 {
+        let distance = length(in.world_position.xyz - view.world_position);
+        let fade = underwater * (1.0 - smoothstep(terrain.caustics_range * 0.5, terrain.caustics_range, distance));
+        // Project from above so every face of the same spot shows the same pattern
+        let light = caustic_pattern(in.world_position.xz, globals.time) * terrain.caustics_strength * fade;
+        pbr_input.material.base_color = vec4<f32>(
+            pbr_input.material.base_color.rgb * (1.0 + light),
+            pbr_input.material.base_color.a,
+        );
+    }
+
+    // Each light level is 80% as bright as the one above, like Minecraft's light curve
+    let brightness = pow(0.8, 15.0 - light_level);
+    pbr_input.material.base_color = vec4<f32>(pbr_input.material.base_color.rgb * brightness, pbr_input.material.base_color.a);
+
+#ifdef VERTEX_COLORS
+    // Alpha is 1 on foliage plus a quarter per opaque cell around the corner, see
+    // terrain::OCCLUSION_ALPHA_STEP. Foliage is the same on all corners of a face, so only the
+    // occlusion is interpolated across it.
+    let is_foliage = step(0.875, in.color.a);
+    let occlusion = (in.color.a - is_foliage) * 4.0;
+    // pow(0, 0) is undefined
+    let ao = pow(max(1.0 - terrain.ao_strength, 0.001), occlusion);
+    pbr_input.material.base_color = vec4<f32>(pbr_input.material.base_color.rgb * ao, pbr_input.material.base_color.a);
+
+    // Foliage keeps its brightness but moves towards the season's hue
+    let foliage = is_foliage * terrain.season_tint.a;
+    if foliage > 0.0 {
+        let luminance_weights = vec3<f32>(0.2126, 0.7152, 0.0722);
+        let base = pbr_input.material.base_color.rgb;
+        let tint = terrain.season_tint.rgb;
+        let seasonal = tint * dot(base, luminance_weights) / max(dot(tint, luminance_weights), 0.001);
+        pbr_input.material.base_color = vec4<f32>(mix(base, seasonal, foliage), pbr_input.material.base_color.a);
+    }
+#endif
+
+    // Wet surfaces absorb more light and turn into a thin reflective film
+    let wet = terrain.wetness * sky_exposed;
+    if wet > 0.0 {
+        pbr_input.material.base_color = vec4<f32>(
+            pbr_input.material.base_color.rgb * (1.0 - 0.4 * wet),
+            pbr_input.material.base_color.a,
+        );
+        pbr_input.material.perceptual_roughness = mix(pbr_input.material.perceptual_roughness, 0.15, wet);
+        pbr_input.material.reflectance = mix(pbr_input.material.reflectance, 0.8, wet);
+    }
+
+    pbr_input.diffuse_occlusion *= terrain.ambient_strength;
+
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+    return out;
+}
@@ -0,0 +1,47 @@
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    mesh_view_bindings::globals,
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+
+struct VoxelWaterSettings {
+    foam_color: vec4<f32>,
+    ripple_strength: f32,
+}
+
+@group(2) @binding(100)
+var<uniform> water: VoxelWaterSettings;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    let t = globals.time;
+    let p = in.world_position.xz;
+
+    // Two crossing wave trains tilt the normal so highlights ripple across the surface
+    let ripple = vec2<f32>(
+        sin(p.x * 1.7 + t * 1.3) + sin(p.y * 2.3 - t * 0.9),
+        cos(p.y * 1.9 + t * 1.1) + cos(p.x * 2.1 - t * 1.4),
+    ) * water.ripple_strength;
+    pbr_input.N = normalize(pbr_input.N + vec3<f32>(ripple.x, 0.0, ripple.y));
+
+#ifdef VERTEX_UVS_B
+    let shore = in.uv_b.x;
+#else
+    let shore = 0.0;
+#endif
+
+    // Foam breaks up along the shore instead of forming a solid band
+    let foam_noise = 0.5 + 0.5 * sin(p.x * 6.0 + t * 2.0) * sin(p.y * 6.0 - t * 1.7);
+    let foam = smoothstep(0.3, 0.9, shore * (0.6 + 0.4 * foam_noise));
+    pbr_input.material.base_color = mix(pbr_input.material.base_color, water.foam_color, foam);
+
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+    return out;
+}
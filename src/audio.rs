@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use bevy::audio::Volume;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::block::{self, SoundSet, AIR};
+use crate::console::{Console, ConsoleCommand};
+use crate::day_night::TimeOfDay;
+use crate::decoration::ChunkRng;
+use crate::dimension::Dimension;
+use crate::player::{GameMode, Noclip, PlayerBody, EYE_HEIGHT};
+use crate::settings::AudioSettings;
+use crate::voxel_events::{VoxelBrokenEvent, VoxelPlacedEvent};
+use crate::voxel_world::VoxelWorld;
+use crate::worldgen::WorldGenerator;
+use crate::{AUDIO_SETTINGS_FILE, CONTENT_PACK_DIRECTORY};
+
+// Sounds from the content packs. A pack's `sounds` folder holds a folder per block sound set
+// (`stone`, `wood`, ...; see block::sound_set) with `break`, `place` and `step` clips in any
+// number of variants (`step.ogg`, `step2.ogg`, ...), and an `ambient` folder of loops named
+// after the biome and time of day they play at (`forest_day.ogg`, `night.ogg`, `cave.ogg`).
+// Packs apply in name order; a later pack's clips replace an earlier pack's for the same sound.
+// Only Ogg Vorbis files are read. Missing clips simply stay silent.
+
+const AUDIO_SEED: u32 = 0x50_07d5;
+// Block sounds started per frame, so region edits and explosions don't play hundreds at once
+const MAX_BLOCK_SOUNDS_PER_FRAME: usize = 4;
+// Voxels walked between footsteps
+const STEP_DISTANCE: f32 = 1.7;
+// Underground deeper than this below the column's ground plays the cave ambience
+const CAVE_DEPTH: i32 = 8;
+// Daylight above which the day loops play
+const DAY_DAYLIGHT: f32 = 0.5;
+// Seconds for one ambient loop to fade into the next
+const AMBIENT_FADE_SECONDS: f32 = 3.0;
+// Clip speed varies by up to this much either way so repeated sounds don't drone
+const PITCH_VARIATION: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SoundEvent {
+    Break,
+    Place,
+    Step,
+}
+
+impl SoundEvent {
+    const ALL: [SoundEvent; 3] = [SoundEvent::Break, SoundEvent::Place, SoundEvent::Step];
+
+    fn name(self) -> &'static str {
+        match self {
+            SoundEvent::Break => "break",
+            SoundEvent::Place => "place",
+            SoundEvent::Step => "step",
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct SoundLibrary {
+    clips: HashMap<(SoundSet, SoundEvent), Vec<Handle<AudioSource>>>,
+    // Keyed by file stem
+    ambient: HashMap<String, Handle<AudioSource>>,
+    rng: ChunkRng,
+}
+
+impl SoundLibrary {
+    // A random variant of a sound, None if no pack has one
+    fn pick(&mut self, set: SoundSet, event: SoundEvent) -> Option<(Handle<AudioSource>, f32)> {
+        let variants = self.clips.get(&(set, event)).filter(|variants| !variants.is_empty())?;
+        let clip = variants[self.rng.range(0, variants.len() as i32 - 1) as usize].clone();
+        let speed = 1.0 + (self.rng.next_f32() * 2.0 - 1.0) * PITCH_VARIATION;
+        Some((clip, speed))
+    }
+}
+
+// An ambient loop playing or fading out. `level` is its own volume before the settings apply.
+#[derive(Component)]
+pub struct AmbientLoop {
+    name: String,
+    level: f32,
+    fading_out: bool,
+}
+
+// Ogg files in `dir` whose stem is `name` or `name` followed by digits
+fn clip_variants(dir: &Path, name: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ogg"))
+        .filter(|path| {
+            path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.strip_prefix(name))
+                .is_some_and(|suffix| suffix.chars().all(|c| c.is_ascii_digit()))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn read_clip(path: &Path, sources: &mut Assets<AudioSource>) -> Option<Handle<AudioSource>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Some(sources.add(AudioSource { bytes: bytes.into() })),
+        Err(err) => {
+            println!("Skipping sound {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+pub fn load_sounds(mut commands: Commands, mut sources: ResMut<Assets<AudioSource>>) {
+    let mut packs: Vec<PathBuf> = std::fs::read_dir(CONTENT_PACK_DIRECTORY)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect())
+        .unwrap_or_default();
+    packs.sort();
+
+    let mut clips = HashMap::new();
+    let mut ambient = HashMap::new();
+    for pack in packs {
+        let sounds = pack.join("sounds");
+        for set in SoundSet::ALL {
+            for event in SoundEvent::ALL {
+                let variants: Vec<_> = clip_variants(&sounds.join(set.name()), event.name()).iter()
+                    .filter_map(|path| read_clip(path, &mut sources))
+                    .collect();
+                if !variants.is_empty() {
+                    clips.insert((set, event), variants);
+                }
+            }
+        }
+        let Ok(entries) = std::fs::read_dir(sounds.join("ambient")) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string) else {
+                continue;
+            };
+            if path.extension().is_some_and(|extension| extension == "ogg") {
+                if let Some(clip) = read_clip(&path, &mut sources) {
+                    ambient.insert(stem, clip);
+                }
+            }
+        }
+    }
+
+    let count = clips.values().map(Vec::len).sum::<usize>() + ambient.len();
+    if count > 0 {
+        println!("Loaded {} sound(s) from {}", count, CONTENT_PACK_DIRECTORY);
+    }
+    commands.insert_resource(SoundLibrary { clips, ambient, rng: ChunkRng::for_chunk((0, 0, 0), AUDIO_SEED) });
+}
+
+// One-shot clip at a point in the world, heard through the camera's SpatialListener
+fn play_at(commands: &mut Commands, clip: Handle<AudioSource>, speed: f32, position: Vec3, volume: f32) {
+    commands.spawn((
+        AudioBundle {
+            source: clip,
+            settings: PlaybackSettings::DESPAWN
+                .with_volume(Volume::new(volume))
+                .with_speed(speed)
+                .with_spatial(true),
+        },
+        TransformBundle::from_transform(Transform::from_translation(position)),
+    ));
+}
+
+// What the one-shot sound systems need to start a clip
+#[derive(SystemParam)]
+pub struct SoundPlayer<'w, 's> {
+    commands: Commands<'w, 's>,
+    library: ResMut<'w, SoundLibrary>,
+    settings: Res<'w, AudioSettings>,
+}
+
+impl SoundPlayer<'_, '_> {
+    // A variant of the set's clip for `event` at `position`; silent at zero volume
+    fn play(&mut self, set: SoundSet, event: SoundEvent, position: Vec3, volume: f32) {
+        if volume <= 0.0 {
+            return;
+        }
+        if let Some((clip, speed)) = self.library.pick(set, event) {
+            play_at(&mut self.commands, clip, speed, position, volume);
+        }
+    }
+}
+
+// The break or place sound of the block's set for every block broken or placed this frame
+pub fn play_block_sounds(
+    mut sounds: SoundPlayer,
+    voxel_world: VoxelWorld,
+    mut broken_events: EventReader<VoxelBrokenEvent>,
+    mut placed_events: EventReader<VoxelPlacedEvent>,
+) {
+    let events = broken_events.read()
+        .map(|event| (event.chunk_key, event.voxel_pos, event.block, SoundEvent::Break))
+        .chain(placed_events.read().map(|event| (event.chunk_key, event.voxel_pos, event.block, SoundEvent::Place)))
+        .take(MAX_BLOCK_SOUNDS_PER_FRAME)
+        .collect::<Vec<_>>();
+    let volume = sounds.settings.master * sounds.settings.blocks;
+    for (chunk_key, voxel_pos, block, event) in events {
+        let position = voxel_world.to_world(chunk_key, voxel_pos).as_vec3() + Vec3::splat(0.5);
+        sounds.play(block::sound_set(block), event, position, volume);
+    }
+}
+
+// A step sound of the block underfoot every STEP_DISTANCE the player walks on the ground.
+// Snow layers lying on the ground sound like snow rather than what's under them.
+pub fn play_footsteps(
+    mut sounds: SoundPlayer,
+    game_mode: Res<GameMode>,
+    noclip: Res<Noclip>,
+    voxel_world: VoxelWorld,
+    player_query: Query<(&Transform, &PlayerBody)>,
+    mut walked: Local<(Option<Vec3>, f32)>,
+) {
+    let Ok((transform, body)) = player_query.get_single() else {
+        return;
+    };
+    let feet = transform.translation - Vec3::Y * EYE_HEIGHT;
+    let (last, distance) = &mut *walked;
+    let moved = last.map_or(0.0, |last| (feet - last).xz().length());
+    *last = Some(feet);
+
+    let grounded = *game_mode == GameMode::Walking && !noclip.enabled && body.vertical_velocity == 0.0;
+    let underfoot = [voxel_world.voxel_at(feet), voxel_world.voxel_at(feet - Vec3::Y * 0.1)]
+        .into_iter()
+        .filter_map(|pos| voxel_world.get_block(pos))
+        .find(|&block| block != AIR && !block::is_liquid(block));
+    let Some(block) = underfoot.filter(|_| grounded) else {
+        *distance = 0.0;
+        return;
+    };
+    *distance += moved;
+    if *distance < STEP_DISTANCE {
+        return;
+    }
+    *distance = 0.0;
+
+    let volume = sounds.settings.master * sounds.settings.footsteps;
+    sounds.play(block::sound_set(block), SoundEvent::Step, feet, volume);
+}
+
+// The ambient loop for where the player is: `cave` deep underground and in dimensions without a
+// sky, otherwise `<biome>_day` / `<biome>_night`, falling back to `day` / `night`
+fn ambient_name(library: &SoundLibrary, dimension: Dimension, generator: &WorldGenerator, daylight: f32, eye: Vec3) -> Option<String> {
+    let pos = eye.floor().as_ivec3();
+    if !dimension.has_skylight() || pos.y < generator.ground_height(pos.x, pos.z) - CAVE_DEPTH {
+        return library.ambient.contains_key("cave").then(|| "cave".to_string());
+    }
+    let time = if daylight > DAY_DAYLIGHT { "day" } else { "night" };
+    let biome_loop = format!("{}_{}", generator.biome(pos.x, pos.z).name(), time);
+    [biome_loop, time.to_string()].into_iter().find(|name| library.ambient.contains_key(name))
+}
+
+// What decides which ambient loop plays, apart from where the player is
+#[derive(SystemParam)]
+pub struct Surroundings<'w> {
+    time_of_day: Res<'w, TimeOfDay>,
+    dimension: Res<'w, Dimension>,
+    generator: Res<'w, WorldGenerator>,
+}
+
+// Starts the ambient loop for the player's surroundings when they change, fading it in while
+// the previous one fades out. Loops are non-spatial and follow the ambient volume live.
+pub fn update_ambient(
+    mut commands: Commands,
+    time: Res<Time>,
+    library: Res<SoundLibrary>,
+    settings: Res<AudioSettings>,
+    surroundings: Surroundings,
+    player_query: Query<&Transform, With<PlayerBody>>,
+    mut loops: Query<(Entity, &mut AmbientLoop, Option<&AudioSink>)>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let wanted = ambient_name(
+        &library,
+        *surroundings.dimension,
+        &surroundings.generator,
+        surroundings.time_of_day.daylight(),
+        player.translation,
+    );
+
+    let mut playing = false;
+    let step = time.delta_seconds() / AMBIENT_FADE_SECONDS;
+    for (entity, mut ambient, sink) in &mut loops {
+        if !ambient.fading_out && wanted.as_deref() != Some(ambient.name.as_str()) {
+            ambient.fading_out = true;
+        }
+        playing |= !ambient.fading_out;
+        ambient.level = (ambient.level + if ambient.fading_out { -step } else { step }).clamp(0.0, 1.0);
+        if ambient.fading_out && ambient.level == 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if let Some(sink) = sink {
+            sink.set_volume(ambient.level * settings.master * settings.ambient);
+        }
+    }
+
+    if let (false, Some(name)) = (playing, wanted) {
+        commands.spawn((
+            AudioBundle {
+                source: library.ambient[&name].clone(),
+                settings: PlaybackSettings::LOOP.with_volume(Volume::ZERO),
+            },
+            AmbientLoop { name, level: 0.0, fading_out: false },
+        ));
+    }
+}
+
+// `volume` lists the volumes, `volume <name>` shows one and `volume <name> <0..1>` sets it
+pub fn volume_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut settings: ResMut<AudioSettings>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "volume") {
+        let Some(name) = command.args.first() else {
+            for name in AudioSettings::NAMES {
+                console.print(format!("{} = {}", name, settings.get(name).unwrap()));
+            }
+            continue;
+        };
+        let Some(current) = settings.get(name) else {
+            console.print(format!("Unknown volume '{}', expected one of {}", name, AudioSettings::NAMES.join(", ")));
+            continue;
+        };
+
+        match command.args.get(1).map(|value| value.parse::<f32>()) {
+            None => console.print(format!("{} = {}", name, current)),
+            Some(Ok(value)) if (0.0..=1.0).contains(&value) => {
+                *settings.get_mut(name).unwrap() = value;
+                console.print(format!("{} volume set to {}", name, value));
+                if let Err(err) = settings.save(Path::new(AUDIO_SETTINGS_FILE)) {
+                    console.print(format!("Could not save audio settings: {}", err));
+                }
+            }
+            Some(_) => console.print(format!("Volumes go from 0 to 1, got '{}'", command.args[1])),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::fs;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::tasks::block_on;
+use futures::FutureExt;
+use crate::block::AIR;
+use crate::console::{Console, ConsoleCommand};
+use crate::day_night::{TimeOfDay, WorldClock};
+use crate::inventory::Inventory;
+use crate::notifications::NotificationEvent;
+use crate::player::PlayerBody;
+use crate::save::{self, FailedRegion};
+use crate::tasks::{self, BackgroundTask};
+use crate::world::World;
+use crate::AUTOSAVE_INTERVAL_SECONDS;
+
+pub const PLAYER_STATE_FILE: &str = "player.txt";
+
+// Edited chunks of one autosave, written on the IO task pool, and the regions that failed
+struct ChunkWrite {
+    chunk_keys: Vec<(i32, i32, i32)>,
+    task: BackgroundTask<Vec<FailedRegion>>,
+}
+
+// Saves edited chunks and the player every `interval` while playing, and everything on exit.
+// `/autosave` shows the interval, `/autosave <seconds>` changes it, `/autosave off` stops it
+// and `/autosave now` saves right away. Not saved.
+#[derive(Resource)]
+pub struct Autosave {
+    pub enabled: bool,
+    pub timer: Timer,
+    // At most one write runs at a time; a tick while it does waits for the next one
+    write: Option<ChunkWrite>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timer: Timer::from_seconds(AUTOSAVE_INTERVAL_SECONDS, TimerMode::Repeating),
+            write: None,
+        }
+    }
+}
+
+impl Autosave {
+    // Hands every unsaved chunk to a background write, unless one is still running
+    fn start_chunk_write(&mut self, world: &mut World) {
+        if self.write.is_some() {
+            return;
+        }
+        let Some(save_dir) = world.save_dir.clone() else {
+            return;
+        };
+        let blobs = world.take_unsaved_chunks();
+        if blobs.is_empty() {
+            return;
+        }
+        let chunk_keys = blobs.iter().map(|(chunk_key, _)| *chunk_key).collect();
+        let task = tasks::spawn_io(async move { save::save_chunk_blobs(&save_dir, blobs) });
+        self.write = Some(ChunkWrite { chunk_keys, task });
+    }
+
+    // Blocks until the running write, if any, has finished
+    fn wait_for_chunk_write(&mut self, world: &mut World) {
+        if let Some(write) = self.write.take() {
+            let failed = block_on(write.task);
+            world.finish_background_save(&write.chunk_keys, failed);
+        }
+    }
+}
+
+// Where the player stands and looks, what they carry and the time of day, as `name values...`
+// lines in the world's save directory. Unknown and malformed lines are skipped when loading.
+#[derive(Default)]
+struct PlayerState {
+    transform: Option<Transform>,
+    inventory: Vec<(u16, u32)>,
+    time_of_day: Option<f32>,
+    day: Option<u32>,
+    tick: Option<u64>,
+}
+
+impl PlayerState {
+    fn load(save_dir: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(save_dir.join(PLAYER_STATE_FILE)).ok()?;
+        let mut state = Self::default();
+        let mut position = None;
+        let mut rotation = Quat::IDENTITY;
+        for line in contents.lines() {
+            let mut parts = line.split_whitespace();
+            let name = parts.next();
+            // Integers, some too large for the f32 below to hold exactly
+            match name {
+                Some("tick") => {
+                    state.tick = parts.next().and_then(|tick| tick.parse().ok());
+                    continue;
+                }
+                Some("inventory") => {
+                    let values: Vec<u32> = parts.map_while(|part| part.parse().ok()).collect();
+                    if let &[block, count] = values.as_slice() {
+                        if let Ok(block) = u16::try_from(block) {
+                            state.inventory.push((block, count));
+                        }
+                    }
+                    continue;
+                }
+                Some("day") => {
+                    state.day = parts.next().and_then(|day| day.parse().ok());
+                    continue;
+                }
+                _ => {}
+            }
+            let values: Vec<f32> = parts.map_while(|part| part.parse().ok()).collect();
+            match (name, values.as_slice()) {
+                (Some("position"), &[x, y, z]) => position = Some(Vec3::new(x, y, z)),
+                (Some("rotation"), &[x, y, z, w]) => rotation = Quat::from_xyzw(x, y, z, w).normalize(),
+                (Some("timeOfDay"), &[time]) => state.time_of_day = Some(time.rem_euclid(1.0)),
+                _ => {}
+            }
+        }
+        state.transform = position.map(|position| Transform::from_translation(position).with_rotation(rotation));
+        Some(state)
+    }
+
+    fn save(&self, save_dir: &Path) -> std::io::Result<()> {
+        let mut contents = String::new();
+        if let Some(transform) = self.transform {
+            let Vec3 { x, y, z } = transform.translation;
+            contents += &format!("position {} {} {}\n", x, y, z);
+            let [x, y, z, w] = transform.rotation.to_array();
+            contents += &format!("rotation {} {} {} {}\n", x, y, z, w);
+        }
+        for (block, count) in &self.inventory {
+            contents += &format!("inventory {} {}\n", block, count);
+        }
+        if let Some(time_of_day) = self.time_of_day {
+            contents += &format!("timeOfDay {}\n", time_of_day);
+        }
+        if let Some(day) = self.day {
+            contents += &format!("day {}\n", day);
+        }
+        if let Some(tick) = self.tick {
+            contents += &format!("tick {}\n", tick);
+        }
+
+        fs::create_dir_all(save_dir)?;
+        // Same write-and-rename as region files
+        let path = save_dir.join(PLAYER_STATE_FILE);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, contents)?;
+        fs::rename(&tmp_path, &path)
+    }
+}
+
+// Everything PlayerState is taken from
+#[derive(SystemParam)]
+pub struct PlayerSaveData<'w, 's> {
+    player_query: Query<'w, 's, &'static Transform, With<PlayerBody>>,
+    inventory: Res<'w, Inventory>,
+    time_of_day: Res<'w, TimeOfDay>,
+    clock: Res<'w, WorldClock>,
+}
+
+impl PlayerSaveData<'_, '_> {
+    // Failures are reported through the world's notifications
+    fn save(&self, world: &mut World) {
+        let Some(save_dir) = world.save_dir.as_deref() else {
+            return;
+        };
+        let mut inventory: Vec<(u16, u32)> = self.inventory.counts.iter()
+            .map(|(&block, &count)| (world.block_ids.to_saved(block), count))
+            .collect();
+        inventory.sort_unstable();
+        let state = PlayerState {
+            transform: self.player_query.get_single().ok().copied(),
+            inventory,
+            time_of_day: Some(self.time_of_day.0),
+            day: Some(self.clock.day),
+            tick: Some(self.clock.tick),
+        };
+        if let Err(err) = state.save(save_dir) {
+            world.notifications.push(NotificationEvent::error(format!("Could not save the player: {}", err)));
+        }
+    }
+}
+
+// Puts the player back where the last session saved it. Runs after setup spawned the camera.
+pub fn load_player_state(
+    world: Res<World>,
+    mut player_query: Query<(&mut Transform, &mut PlayerBody)>,
+    mut inventory: ResMut<Inventory>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut clock: ResMut<WorldClock>,
+) {
+    let Some(state) = world.save_dir.as_deref().and_then(PlayerState::load) else {
+        return;
+    };
+    if let (Some(saved), Ok((mut transform, mut body))) = (state.transform, player_query.get_single_mut()) {
+        *transform = saved;
+        // Don't replay the move from the spawn point against the terrain
+        body.last_position = None;
+    }
+    // Blocks this build no longer has come back as air, which nobody carries
+    for (block, count) in state.inventory {
+        let block = world.block_ids.to_current(block);
+        if block != AIR {
+            inventory.add(block, count);
+        }
+    }
+    if let Some(time) = state.time_of_day {
+        time_of_day.0 = time;
+    }
+    if let Some(day) = state.day {
+        clock.day = day;
+    }
+    if let Some(tick) = state.tick {
+        clock.tick = tick;
+    }
+}
+
+pub fn autosave(
+    time: Res<Time>,
+    mut autosave: ResMut<Autosave>,
+    mut world: ResMut<World>,
+    player: PlayerSaveData,
+) {
+    // Collect a finished write first so its chunks can unload again
+    if let Some(write) = &mut autosave.write {
+        let mut context = Context::from_waker(futures::task::noop_waker_ref());
+        if let Poll::Ready(failed) = Pin::new(&mut write.task).poll_unpin(&mut context) {
+            let chunk_keys = std::mem::take(&mut write.chunk_keys);
+            autosave.write = None;
+            world.finish_background_save(&chunk_keys, failed);
+        }
+    }
+
+    if !autosave.enabled || !autosave.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    autosave.start_chunk_write(&mut world);
+    player.save(&mut world);
+}
+
+// Waits for a running autosave, then writes everything still unsaved before the app closes
+pub fn save_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    mut autosave: ResMut<Autosave>,
+    mut world: ResMut<World>,
+    player: PlayerSaveData,
+) {
+    if exit_events.read().next().is_none() {
+        return;
+    }
+
+    autosave.wait_for_chunk_write(&mut world);
+    world.save_all_modified();
+    let pending: Vec<_> = world.pending_structures.keys().copied().collect();
+    world.store_pending_structures(&pending);
+    player.save(&mut world);
+}
+
+pub fn autosave_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut autosave: ResMut<Autosave>,
+    mut world: ResMut<World>,
+    player: PlayerSaveData,
+) {
+    for command in console_commands.read().filter(|command| command.name == "autosave") {
+        match command.args.first().map(String::as_str) {
+            None if autosave.enabled => console.print(format!("Autosaving every {} seconds", autosave.timer.duration().as_secs_f32())),
+            None => console.print("Autosave is off".to_string()),
+            Some("off") => {
+                autosave.enabled = false;
+                console.print("Autosave off".to_string());
+            }
+            Some("now") => {
+                // Synchronous so the message below is true once printed
+                autosave.wait_for_chunk_write(&mut world);
+                let saved = world.save_all_modified();
+                player.save(&mut world);
+                console.print(format!("Saved {} chunk(s) and the player", saved));
+            }
+            Some(arg) => match arg.parse::<f32>() {
+                Ok(seconds) if seconds > 0.0 => {
+                    autosave.enabled = true;
+                    autosave.timer = Timer::from_seconds(seconds, TimerMode::Repeating);
+                    console.print(format!("Autosaving every {} seconds", seconds));
+                }
+                _ => console.print(format!("Expected a number of seconds, 'off' or 'now', got '{}'", arg)),
+            },
+        }
+    }
+}
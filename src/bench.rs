@@ -0,0 +1,168 @@
+use bevy::tasks::{block_on, AsyncComputeTaskPool, TaskPool};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use bevy::utils::Instant;
+use crate::dimension::Dimension;
+use crate::world::World;
+use crate::{CHUNK_SIZE, DEFAULT_RENDER_DISTANCE};
+
+pub const DEFAULT_BENCH_CHUNKS: usize = 256;
+
+// Timings of one stage over every chunk of the run
+#[derive(Default)]
+struct StageTimes {
+    total: Duration,
+    max: Duration,
+    count: u32,
+}
+
+impl StageTimes {
+    fn record(&mut self, elapsed: Duration) {
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        self.count += 1;
+    }
+
+    fn to_json(&self) -> String {
+        let mean = if self.count > 0 { self.total / self.count } else { Duration::ZERO };
+        format!(
+            "{{\"total_ms\": {:.3}, \"mean_us\": {:.1}, \"max_us\": {:.1}, \"count\": {}}}",
+            self.total.as_secs_f64() * 1000.0,
+            mean.as_secs_f64() * 1_000_000.0,
+            self.max.as_secs_f64() * 1_000_000.0,
+            self.count,
+        )
+    }
+}
+
+// The first `count` chunks around the origin, nearest first, in three layers (underground,
+// surface, sky) so every kind of chunk is covered
+fn bench_chunk_keys(count: usize) -> Vec<(i32, i32, i32)> {
+    let radius = ((count as f32 / 3.0).sqrt() / 2.0).ceil() as i32;
+    let mut keys = Vec::new();
+    for x in -radius..=radius {
+        for y in -1..=1 {
+            for z in -radius..=radius {
+                keys.push((x, y, z));
+            }
+        }
+    }
+    keys.sort_by_key(|&(x, y, z)| (x * x + z * z, y, x, z));
+    keys.truncate(count);
+    keys
+}
+
+// Resident set size of this process, where the OS reports it
+pub fn resident_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+// `text` as a quoted JSON string. Stage names come from GenStage::name, which custom stages
+// pick freely.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn json_or_null(value: Option<u64>) -> String {
+    value.map_or("null".to_string(), |value| value.to_string())
+}
+
+// `voxelfun --bench [chunks]`: generates and meshes `chunks` chunks without opening a window,
+// then prints per-stage timings and memory use as one JSON object on stdout. Nothing is
+// read from or written to the save directory, so runs are comparable.
+pub fn run(chunk_count: usize, dimension: Dimension) {
+    AsyncComputeTaskPool::get_or_init(TaskPool::new);
+    // Always seed 0, so runs on different machines and worlds generate the same terrain
+    let generator = dimension.generator(0);
+    let mut world = World { dimension, save_dir: None, ..World::new(CHUNK_SIZE, DEFAULT_RENDER_DISTANCE) };
+    let keys = bench_chunk_keys(chunk_count);
+
+    let mut stages: BTreeMap<String, StageTimes> = BTreeMap::new();
+    let mut overflow_blocks = 0;
+    let generation_start = Instant::now();
+    for &chunk_key in &keys {
+        let (chunk, overflow) = generator.generate_timed(chunk_key, CHUNK_SIZE, |stage, elapsed| {
+            stages.entry(format!("gen.{}", stage)).or_default().record(elapsed);
+        });
+        // Structure overflow is left out; it only moves blocks between chunks
+        overflow_blocks += overflow.len();
+        world.chunks.insert(chunk_key, chunk);
+    }
+    let generation_time = generation_start.elapsed();
+
+    // Meshed after generation so every chunk sees its neighbours, like in game
+    let mut mesh_bytes = 0u64;
+    let mut vertices = 0usize;
+    let mut lod_vertices = 0usize;
+    let meshing_start = Instant::now();
+    for &chunk_key in &keys {
+        let Some(task) = world.mesh_task(chunk_key) else {
+            continue;
+        };
+        let start = Instant::now();
+        let meshes = block_on(task.0);
+        stages.entry("mesh".to_string()).or_default().record(start.elapsed());
+
+        let terrain = &meshes.terrain;
+        let sections = terrain.cutout.iter().chain(&terrain.translucent).chain(&meshes.water);
+        for mesh in std::iter::once(&terrain.opaque).chain(sections) {
+            vertices += mesh.count_vertices();
+            mesh_bytes += mesh.get_vertex_size() * mesh.count_vertices() as u64;
+            mesh_bytes += mesh.indices().map_or(0, |indices| indices.len() as u64 * 4);
+        }
+        lod_vertices += meshes.lod.count_vertices();
+    }
+    let meshing_time = meshing_start.elapsed();
+
+    let voxel_bytes: usize = world.chunks.values().map(|chunk| chunk.voxels.heap_size()).sum();
+    let stage_json = stages.iter()
+        .map(|(name, times)| format!("{}: {}", json_string(name), times.to_json()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    println!(
+        "{{\"dimension\": {}, \"chunks\": {}, \"chunk_size\": {}, \"generation_ms\": {:.3}, \"meshing_ms\": {:.3}, \
+         \"stages\": {{{}}}, \"memory\": {{\"voxel_storage_bytes\": {}, \"mesh_bytes\": {}, \
+         \"vertices\": {}, \"lod_vertices\": {}, \"resident_bytes\": {}}}, \"overflow_blocks\": {}}}",
+        json_string(dimension.name()),
+        keys.len(),
+        CHUNK_SIZE,
+        generation_time.as_secs_f64() * 1000.0,
+        meshing_time.as_secs_f64() * 1000.0,
+        stage_json,
+        voxel_bytes,
+        mesh_bytes,
+        vertices,
+        lod_vertices,
+        json_or_null(resident_bytes()),
+        overflow_blocks,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("gen.caves"), r#""gen.caves""#);
+        assert_eq!(json_string("a \"b\"\\c\nd\u{1}"), r#""a \"b\"\\c\nd\u0001""#);
+    }
+}
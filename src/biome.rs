@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use noise::{NoiseFn, Perlin};
+use crate::block::{self, BlockId, DIRT, GRASS};
+use crate::worldgen::mix_seed;
+
+const HEIGHT_SEED: u32 = 0;
+const HEIGHT_FREQUENCY: f64 = 0.01;
+const BIOME_SEED: u32 = 1;
+const BIOME_FREQUENCY: f64 = 0.004;
+// Biomes are sampled on a grid this many voxels either side of a column and averaged, so
+// parameters cross a biome border over a band of twice the radius instead of in one step
+const BLEND_RADIUS: i32 = 4;
+const BLEND_STEP: i32 = 2;
+const PALETTE_SEED: u32 = 0xb10e_5eed;
+const TEMPERATURE_LAPSE: f32 = 0.015;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Meadow,
+    Forest,
+}
+
+// What a biome does to the columns inside it
+#[derive(Clone, Copy, Debug)]
+pub struct BiomeParams {
+    // Terrain height is offset + amplitude * height noise (0..1)
+    pub height_offset: f32,
+    pub height_amplitude: f32,
+    // Top block of exposed ground, and what fills the layers below it
+    pub surface: BlockId,
+    pub filler: BlockId,
+    pub filler_depth: usize,
+    // Chance per surface column of a tree, a grass tuft and a flower
+    pub tree_chance: f32,
+    pub grass_chance: f32,
+    pub flower_chance: f32,
+    // At sea level; it drops with altitude, see ColumnSampler::temperature
+    pub temperature: f32,
+}
+
+impl Biome {
+    const ALL: [Biome; 3] = [Biome::Plains, Biome::Meadow, Biome::Forest];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Plains => "plains",
+            Biome::Meadow => "meadow",
+            Biome::Forest => "forest",
+        }
+    }
+
+    // The biome whose region a column lies in, without blending
+    pub fn at(biome_noise: &Perlin, world_x: i32, world_z: i32) -> Self {
+        let value = biome_noise.get([world_x as f64 * BIOME_FREQUENCY, world_z as f64 * BIOME_FREQUENCY]);
+        if value > 0.25 {
+            Biome::Forest
+        } else if value < -0.25 {
+            Biome::Meadow
+        } else {
+            Biome::Plains
+        }
+    }
+
+    pub fn params(self) -> BiomeParams {
+        match self {
+            Biome::Plains => BiomeParams {
+                height_offset: 0.0,
+                height_amplitude: 32.0,
+                surface: GRASS,
+                filler: DIRT,
+                filler_depth: 2,
+                tree_chance: 0.004,
+                grass_chance: 0.12,
+                flower_chance: 0.01,
+                temperature: 0.7,
+            },
+            Biome::Meadow => BiomeParams {
+                height_offset: 4.0,
+                height_amplitude: 20.0,
+                surface: GRASS,
+                filler: DIRT,
+                filler_depth: 3,
+                tree_chance: 0.001,
+                grass_chance: 0.2,
+                flower_chance: 0.06,
+                temperature: 0.3,
+            },
+            Biome::Forest => BiomeParams {
+                height_offset: 0.0,
+                height_amplitude: 38.0,
+                surface: GRASS,
+                filler: DIRT,
+                filler_depth: 4,
+                tree_chance: 0.035,
+                grass_chance: 0.05,
+                flower_chance: 0.005,
+                temperature: 0.5,
+            },
+        }
+    }
+}
+
+// Everything generation needs to know about one world column, with the parameters of the
+// biomes around it already blended
+#[derive(Clone, Copy, Debug)]
+pub struct Column {
+    // World-space height of the base terrain: the column is solid below it
+    pub height: usize,
+    pub surface: BlockId,
+    pub filler: BlockId,
+    pub filler_depth: usize,
+    pub tree_chance: f32,
+    pub grass_chance: f32,
+    pub flower_chance: f32,
+    pub temperature: f32,
+}
+
+// Column generation API. Columns depend on world coordinates only, so the same column comes
+// out identical from whichever chunk asks, and blended biome borders line up across chunks.
+pub struct ColumnSampler {
+    height_noise: Perlin,
+    biome_noise: Perlin,
+    palette_seed: u32,
+}
+
+impl ColumnSampler {
+    pub fn new(world_seed: u32) -> Self {
+        Self {
+            height_noise: Perlin::new(mix_seed(HEIGHT_SEED, world_seed)),
+            biome_noise: Perlin::new(mix_seed(BIOME_SEED, world_seed)),
+            palette_seed: mix_seed(PALETTE_SEED, world_seed),
+        }
+    }
+
+    // Share of each biome (in Biome::ALL order) in the blend band around a column
+    fn biome_weights(&self, world_x: i32, world_z: i32) -> [f32; 3] {
+        let mut weights = [0.0; 3];
+        let mut samples = 0.0;
+        for dx in (-BLEND_RADIUS..=BLEND_RADIUS).step_by(BLEND_STEP as usize) {
+            for dz in (-BLEND_RADIUS..=BLEND_RADIUS).step_by(BLEND_STEP as usize) {
+                let biome = Biome::at(&self.biome_noise, world_x + dx, world_z + dz);
+                weights[biome as usize] += 1.0;
+                samples += 1.0;
+            }
+        }
+        weights.map(|weight| weight / samples)
+    }
+
+    fn height_noise(&self, world_x: i32, world_z: i32) -> f32 {
+        let value = self.height_noise.get([world_x as f64 * HEIGHT_FREQUENCY, world_z as f64 * HEIGHT_FREQUENCY]);
+        ((value + 1.0) * 0.5) as f32
+    }
+
+    pub fn column(&self, world_x: i32, world_z: i32) -> Column {
+        let weights = self.biome_weights(world_x, world_z);
+        let blend = |param: fn(&BiomeParams) -> f32| {
+            Biome::ALL.iter().zip(weights).map(|(biome, weight)| param(&biome.params()) * weight).sum::<f32>()
+        };
+        let noise = self.height_noise(world_x, world_z);
+        let height = blend(|params| params.height_offset) + blend(|params| params.height_amplitude) * noise;
+
+        // Blocks can't be averaged, so the palette comes from one of the biomes in the band,
+        // picked with odds matching their share. Borders become a dithered mix.
+        let roll = block::position_hash(world_x, 0, world_z, self.palette_seed) as f32 / u32::MAX as f32;
+        let mut cumulative = 0.0;
+        let palette_biome = Biome::ALL.into_iter().zip(weights)
+            .find(|&(_, weight)| {
+                cumulative += weight;
+                roll < cumulative
+            })
+            .map_or(Biome::Plains, |(biome, _)| biome);
+        let palette = palette_biome.params();
+
+        Column {
+            height: height.max(0.0) as usize,
+            surface: palette.surface,
+            filler: palette.filler,
+            filler_depth: palette.filler_depth,
+            tree_chance: blend(|params| params.tree_chance),
+            grass_chance: blend(|params| params.grass_chance),
+            flower_chance: blend(|params| params.flower_chance),
+            temperature: blend(|params| params.temperature),
+        }
+    }
+
+    // Just the terrain height of a column, for structures planning around the ground
+    pub fn height(&self, world_x: i32, world_z: i32) -> usize {
+        self.column(world_x, world_z).height
+    }
+
+    // The biome a column lies in, without blending
+    pub fn biome(&self, world_x: i32, world_z: i32) -> Biome {
+        Biome::at(&self.biome_noise, world_x, world_z)
+    }
+
+    // How warm it is at a point, 0 freezing to 1 hot: the column's blended biome temperature,
+    // falling by TEMPERATURE_LAPSE per voxel above `sea_level` so high ground gets snow
+    pub fn temperature(&self, world_x: i32, world_y: i32, world_z: i32, sea_level: i32) -> f32 {
+        let altitude = (world_y - sea_level).max(0) as f32;
+        (self.column(world_x, world_z).temperature - altitude * TEMPERATURE_LAPSE).clamp(0.0, 1.0)
+    }
+}
+
+// The columns of one chunk, sampled once and shared by the generation stages
+pub struct ChunkColumns {
+    width: usize,
+    columns: Vec<Column>,
+}
+
+impl ChunkColumns {
+    pub fn sample(sampler: &ColumnSampler, chunk_key: (i32, i32, i32), width: usize, depth: usize) -> Self {
+        let mut columns = Vec::with_capacity(width * depth);
+        for z in 0..depth {
+            for x in 0..width {
+                let world_x = chunk_key.0 * width as i32 + x as i32;
+                let world_z = chunk_key.2 * depth as i32 + z as i32;
+                columns.push(sampler.column(world_x, world_z));
+            }
+        }
+        Self { width, columns }
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> &Column {
+        &self.columns[x + z * self.width]
+    }
+
+    // Lowest and highest column height, world space
+    pub fn height_range(&self) -> (i32, i32) {
+        self.columns.iter().fold((i32::MAX, i32::MIN), |(lowest, highest), column| {
+            (lowest.min(column.height as i32), highest.max(column.height as i32))
+        })
+    }
+}
+
+// Columns of recently generated chunk columns, keyed by the chunks' (x, z), so every chunk
+// stacked in a column and each of its stages share one sampling of the noise. The least
+// recently used column goes once `capacity` are held.
+pub struct HeightmapCache {
+    capacity: usize,
+    entries: Mutex<HeightmapEntries>,
+}
+
+#[derive(Default)]
+struct HeightmapEntries {
+    columns: HashMap<(i32, i32), (Arc<ChunkColumns>, u64)>,
+    // Bumped on every lookup, stamps each entry with when it was last used
+    clock: u64,
+}
+
+impl HeightmapCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Mutex::default() }
+    }
+
+    pub fn columns(&self, sampler: &ColumnSampler, chunk_key: (i32, i32, i32), width: usize, depth: usize) -> Arc<ChunkColumns> {
+        let key = (chunk_key.0, chunk_key.2);
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.clock += 1;
+            let clock = entries.clock;
+            if let Some((columns, last_used)) = entries.columns.get_mut(&key) {
+                if columns.width == width && columns.columns.len() == width * depth {
+                    *last_used = clock;
+                    return columns.clone();
+                }
+            }
+        }
+
+        // Sampled outside the lock so other columns can be looked up meanwhile
+        let columns = Arc::new(ChunkColumns::sample(sampler, chunk_key, width, depth));
+        let mut entries = self.entries.lock().unwrap();
+        if entries.columns.len() >= self.capacity {
+            let oldest = entries.columns.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(&key, _)| key);
+            if let Some(oldest) = oldest {
+                entries.columns.remove(&oldest);
+            }
+        }
+        let clock = entries.clock;
+        entries.columns.insert(key, (columns.clone(), clock));
+        columns
+    }
+}
@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use crate::block;
+use crate::voxel_world::VoxelWorld;
+
+const SHADOW_TEXTURE_SIZE: u32 = 32;
+// Casters further above the ground than this cast no blob shadow
+const MAX_SHADOW_HEIGHT: i32 = 16;
+// Lifts the decal off the surface so it doesn't z-fight with the block face
+const SHADOW_SURFACE_OFFSET: f32 = 0.01;
+const SHADOW_OPACITY: f32 = 0.55;
+
+// Cheap round shadow on the ground under an entity (player, mobs, dropped items), independent
+// of the shadow-map lights. It shrinks as the caster rises and disappears past MAX_SHADOW_HEIGHT.
+#[derive(Component)]
+pub struct BlobShadow {
+    pub radius: f32,
+}
+
+// The decal entity drawing the shadow of `0`
+#[derive(Component)]
+pub struct BlobShadowDecal(pub Entity);
+
+#[derive(Resource)]
+pub struct BlobShadowAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+// Black with a smooth radial falloff in alpha
+fn build_shadow_texture() -> Image {
+    let size = SHADOW_TEXTURE_SIZE;
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let offset = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) / size as f32 * 2.0 - Vec2::ONE;
+            let falloff = 1.0 - offset.length().min(1.0);
+            let alpha = falloff * falloff * (3.0 - 2.0 * falloff) * SHADOW_OPACITY;
+            data.extend_from_slice(&[0, 0, 0, (alpha * 255.0) as u8]);
+        }
+    }
+    Image::new(
+        Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+pub fn setup_blob_shadows(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    commands.insert_resource(BlobShadowAssets {
+        mesh: meshes.add(Plane3d::default().mesh().size(1.0, 1.0)),
+        material: materials.add(StandardMaterial {
+            base_color_texture: Some(images.add(build_shadow_texture())),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            fog_enabled: true,
+            ..default()
+        }),
+    });
+}
+
+// Keeps one decal per caster on top of the highest solid block below it
+pub fn update_blob_shadows(
+    mut commands: Commands,
+    assets: Res<BlobShadowAssets>,
+    voxel_world: VoxelWorld,
+    casters: Query<(Entity, &GlobalTransform, &BlobShadow)>,
+    mut decals: Query<(Entity, &BlobShadowDecal, &mut Transform, &mut Visibility)>,
+) {
+    let mut has_decal = Vec::new();
+
+    for (decal_entity, decal, mut transform, mut visibility) in &mut decals {
+        let Ok((_, caster_transform, shadow)) = casters.get(decal.0) else {
+            commands.entity(decal_entity).despawn();
+            continue;
+        };
+        has_decal.push(decal.0);
+
+        let position = caster_transform.translation();
+        let ground = voxel_world.ground_below(voxel_world.voxel_at(position), MAX_SHADOW_HEIGHT);
+        let Some(ground) = ground else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        // Rest on a snow layer rather than under it
+        let surface_height = match voxel_world.get_block(ground + IVec3::Y) {
+            Some(above) if block::is_thin(above) => block::top_height(above, voxel_world.get_state(ground + IVec3::Y).unwrap_or(0)),
+            _ => 0.0,
+        };
+        let surface_y = (ground.y + 1) as f32 + surface_height;
+        let height = (position.y - surface_y).max(0.0);
+        let scale = shadow.radius * 2.0 * (1.0 - height / MAX_SHADOW_HEIGHT as f32).max(0.0);
+
+        *visibility = Visibility::Inherited;
+        transform.translation = Vec3::new(position.x, surface_y + SHADOW_SURFACE_OFFSET, position.z);
+        transform.scale = Vec3::new(scale, 1.0, scale);
+    }
+
+    for (caster, _, _) in &casters {
+        if has_decal.contains(&caster) {
+            continue;
+        }
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material.clone(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            BlobShadowDecal(caster),
+            bevy::pbr::NotShadowCaster,
+        ));
+    }
+}
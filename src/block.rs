@@ -0,0 +1,377 @@
+use std::sync::OnceLock;
+
+pub type BlockId = u16;
+
+pub const AIR: BlockId = 0;
+pub const STONE: BlockId = 1;
+pub const GLASS: BlockId = 2;
+pub const STONE_BRICKS: BlockId = 3;
+pub const DIRT: BlockId = 4;
+pub const GRASS: BlockId = 5;
+pub const LOG: BlockId = 6;
+pub const LEAVES: BlockId = 7;
+pub const TALL_GRASS: BlockId = 8;
+pub const FLOWER: BlockId = 9;
+pub const COAL_ORE: BlockId = 10;
+pub const IRON_ORE: BlockId = 11;
+pub const GOLD_ORE: BlockId = 12;
+pub const WATER: BlockId = 13;
+pub const CHEST: BlockId = 14;
+pub const SIGN: BlockId = 15;
+pub const SNOW_LAYER: BlockId = 16;
+pub const TORCH: BlockId = 17;
+pub const LAVA: BlockId = 18;
+pub const GLOWSTONE: BlockId = 19;
+
+// Height of one layer of a thin block as a fraction of a full voxel
+pub const THIN_BLOCK_HEIGHT: f32 = 0.125;
+// Snow piles up to a full voxel of layers, see top_height
+pub const MAX_SNOW_LAYERS: u8 = 8;
+
+// Texture array layer of each block, indexed by BlockId. Filled in once at startup by
+// block_textures from the content packs; blocks without a texture only use their colors.
+static TEXTURE_LAYERS: OnceLock<Vec<Option<u32>>> = OnceLock::new();
+
+// Blocks registered by script mods at startup, numbered on from the last of BLOCK_DEFINITIONS.
+// See scripting.
+static SCRIPT_BLOCKS: OnceLock<Vec<BlockDefinition>> = OnceLock::new();
+
+// Seed for the per-position variant hash. Changing it reshuffles every variant in the world.
+const VARIATION_SEED: u32 = 0x5eed_b10c;
+
+// How much of what lies behind a block shows through it. Decides which neighbouring faces
+// the block hides and which section of the chunk mesh it is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transparency {
+    Opaque,
+    // Alpha-tested: texels are either drawn or cut away, like the gaps between leaves
+    Cutout,
+    // Blended: the whole block tints what is behind it, like glass
+    Translucent,
+}
+
+#[derive(Clone)]
+pub struct BlockDefinition {
+    pub name: &'static str,
+    // Color variants picked per voxel by a hash of its world position, so large surfaces
+    // get subtle deterministic variation without storing anything per voxel.
+    // The first entry is the block's base color.
+    pub variants: &'static [[f32; 3]],
+    // Faces pick border/interior tiles from same-block neighbours so large panes read as one surface
+    pub connected_texture: bool,
+    pub transparency: Transparency,
+}
+
+// Indexed by BlockId
+pub const BLOCK_DEFINITIONS: &[BlockDefinition] = &[
+    BlockDefinition {
+        name: "air",
+        variants: &[[0.0, 0.0, 0.0]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "stone",
+        variants: &[
+            [0.80, 0.70, 0.60],
+            [0.77, 0.67, 0.58],
+            [0.83, 0.72, 0.61],
+            [0.79, 0.70, 0.62],
+        ],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "glass",
+        variants: &[[0.75, 0.9, 0.95]],
+        connected_texture: true,
+        transparency: Transparency::Translucent,
+    },
+    BlockDefinition {
+        name: "stone_bricks",
+        variants: &[[0.62, 0.6, 0.58]],
+        connected_texture: true,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "dirt",
+        variants: &[[0.45, 0.32, 0.2], [0.42, 0.3, 0.19], [0.47, 0.34, 0.22]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "grass",
+        variants: &[[0.35, 0.6, 0.25], [0.33, 0.57, 0.24], [0.37, 0.62, 0.26]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "log",
+        variants: &[[0.4, 0.28, 0.15], [0.38, 0.27, 0.14]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "leaves",
+        variants: &[[0.2, 0.5, 0.18], [0.18, 0.46, 0.16], [0.23, 0.53, 0.2]],
+        connected_texture: false,
+        transparency: Transparency::Cutout,
+    },
+    BlockDefinition {
+        name: "tall_grass",
+        variants: &[[0.42, 0.7, 0.3], [0.45, 0.72, 0.32]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "flower",
+        variants: &[[0.9, 0.3, 0.35], [0.95, 0.85, 0.2], [0.7, 0.4, 0.9]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "coal_ore",
+        variants: &[[0.25, 0.24, 0.23], [0.28, 0.27, 0.26]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "iron_ore",
+        variants: &[[0.72, 0.55, 0.45], [0.75, 0.58, 0.47]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "gold_ore",
+        variants: &[[0.92, 0.78, 0.3], [0.95, 0.82, 0.34]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "water",
+        variants: &[[0.12, 0.32, 0.55]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "chest",
+        variants: &[[0.55, 0.36, 0.16]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "sign",
+        variants: &[[0.78, 0.64, 0.42]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "snow_layer",
+        variants: &[[0.93, 0.95, 0.98], [0.9, 0.93, 0.97]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "torch",
+        variants: &[[1.0, 0.78, 0.35]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "lava",
+        variants: &[[1.0, 0.42, 0.08], [0.96, 0.36, 0.06], [1.0, 0.5, 0.12]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+    BlockDefinition {
+        name: "glowstone",
+        variants: &[[1.0, 0.88, 0.55], [0.95, 0.82, 0.5]],
+        connected_texture: false,
+        transparency: Transparency::Opaque,
+    },
+];
+
+// Solid blocks collide and stop raycasts. Whether they also hide the faces of their
+// neighbours depends on their transparency, see hides_face.
+pub fn is_solid(block: BlockId) -> bool {
+    block != AIR && !is_liquid(block) && !is_thin(block) && block != TORCH
+}
+
+pub fn transparency(block: BlockId) -> Transparency {
+    definition(block).transparency
+}
+
+// Opaque solid blocks stop light and hide every face behind them
+pub fn is_opaque(block: BlockId) -> bool {
+    is_solid(block) && transparency(block) == Transparency::Opaque
+}
+
+// Whether `front` covers the face of `block` it touches. See-through blocks only cover faces of
+// their own kind, so a glass wall has no inner faces but the terrain behind it still draws.
+pub fn hides_face(front: BlockId, block: BlockId) -> bool {
+    is_opaque(front) || (is_solid(front) && front == block)
+}
+
+// Blocks with their own geometry drawn by the terrain mesher. Torches are drawn by their
+// block entity instead, see torch.
+pub fn is_visible(block: BlockId) -> bool {
+    block != AIR && !is_liquid(block) && block != TORCH
+}
+
+// Blocks the crosshair can pick for breaking
+pub fn is_targetable(block: BlockId) -> bool {
+    is_solid(block) || block == TORCH
+}
+
+// Block light level a block gives off, 0 for most. Emitters flood light into their
+// surroundings like skylight does, see lighting.
+pub fn light_emission(block: BlockId) -> u8 {
+    match block {
+        TORCH => 14,
+        LAVA | GLOWSTONE => 15,
+        _ => 0,
+    }
+}
+
+// Liquids are see-through and walkable, and get their own mesh and material
+pub fn is_liquid(block: BlockId) -> bool {
+    block == WATER
+}
+
+// Thin blocks only cover the bottom of their cell, THIN_BLOCK_HEIGHT per layer. They don't
+// collide or hide neighbouring faces, and placing a block simply replaces them.
+pub fn is_thin(block: BlockId) -> bool {
+    block == SNOW_LAYER
+}
+
+// How far up its cell a block reaches. Thin blocks keep their layer count in the block state:
+// state n is n + 1 layers.
+pub fn top_height(block: BlockId, state: u8) -> f32 {
+    if is_thin(block) {
+        THIN_BLOCK_HEIGHT * (state.min(MAX_SNOW_LAYERS - 1) + 1) as f32
+    } else {
+        1.0
+    }
+}
+
+pub fn is_replaceable(block: BlockId) -> bool {
+    block == AIR || is_liquid(block) || is_thin(block)
+}
+
+// Which clips of the content packs a block plays when broken, placed and walked on, see audio
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SoundSet {
+    Stone,
+    Dirt,
+    Grass,
+    Wood,
+    Glass,
+    Snow,
+}
+
+impl SoundSet {
+    pub const ALL: [SoundSet; 6] = [SoundSet::Stone, SoundSet::Dirt, SoundSet::Grass, SoundSet::Wood, SoundSet::Glass, SoundSet::Snow];
+
+    // Folder of the set's clips in a pack's `sounds` folder
+    pub fn name(self) -> &'static str {
+        match self {
+            SoundSet::Stone => "stone",
+            SoundSet::Dirt => "dirt",
+            SoundSet::Grass => "grass",
+            SoundSet::Wood => "wood",
+            SoundSet::Glass => "glass",
+            SoundSet::Snow => "snow",
+        }
+    }
+}
+
+pub fn sound_set(block: BlockId) -> SoundSet {
+    match block {
+        DIRT => SoundSet::Dirt,
+        GRASS | LEAVES | TALL_GRASS | FLOWER => SoundSet::Grass,
+        LOG | CHEST | SIGN | TORCH => SoundSet::Wood,
+        GLASS | GLOWSTONE => SoundSet::Glass,
+        SNOW_LAYER => SoundSet::Snow,
+        _ => SoundSet::Stone,
+    }
+}
+
+// Plant blocks whose color follows the seasons, see seasons
+pub fn is_foliage(block: BlockId) -> bool {
+    matches!(block, GRASS | LEAVES | TALL_GRASS)
+}
+
+// Blocks carrying extra state in a block entity, see block_entity
+pub fn has_block_entity(block: BlockId) -> bool {
+    matches!(block, CHEST | SIGN | TORCH)
+}
+
+pub fn definition(block: BlockId) -> &'static BlockDefinition {
+    let index = block as usize;
+    BLOCK_DEFINITIONS.get(index)
+        .or_else(|| script_blocks().get(index - BLOCK_DEFINITIONS.len()))
+        .unwrap_or(&BLOCK_DEFINITIONS[AIR as usize])
+}
+
+// Only the first assignment counts. Has to happen before worlds are opened, since their block
+// ID tables are built from the registry.
+pub fn set_script_blocks(blocks: Vec<BlockDefinition>) {
+    let _ = SCRIPT_BLOCKS.set(blocks);
+}
+
+fn script_blocks() -> &'static [BlockDefinition] {
+    SCRIPT_BLOCKS.get().map_or(&[], Vec::as_slice)
+}
+
+// Number of registered blocks, built-in and scripted; every BlockId below it is valid
+pub fn block_count() -> usize {
+    BLOCK_DEFINITIONS.len() + script_blocks().len()
+}
+
+// Every registered block in BlockId order
+pub fn definitions() -> impl Iterator<Item = &'static BlockDefinition> {
+    BLOCK_DEFINITIONS.iter().chain(script_blocks())
+}
+
+// Only the first assignment counts, chunks meshed since then rely on it
+pub fn set_texture_layers(layers: Vec<Option<u32>>) {
+    let _ = TEXTURE_LAYERS.set(layers);
+}
+
+pub fn texture_layer(block: BlockId) -> Option<u32> {
+    TEXTURE_LAYERS.get()?.get(block as usize).copied().flatten()
+}
+
+pub fn position_hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut hash = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0x5bd1_e995);
+    hash ^= hash >> 15;
+    hash
+}
+
+pub fn variant_index(block: BlockId, world_x: i32, world_y: i32, world_z: i32) -> usize {
+    let variant_count = definition(block).variants.len();
+    if variant_count <= 1 {
+        return 0;
+    }
+    position_hash(world_x, world_y, world_z, VARIATION_SEED) as usize % variant_count
+}
+
+pub fn variant_color(block: BlockId, variant: usize) -> [f32; 3] {
+    let variants = definition(block).variants;
+    variants[variant.min(variants.len() - 1)]
+}
+
+// Vertex color the mesher gives a block's faces. Textured blocks get their color from the
+// texture, so they stay white and only carry the lighting.
+pub fn face_color(block: BlockId, variant: usize) -> [f32; 3] {
+    if texture_layer(block).is_some() {
+        [1.0, 1.0, 1.0]
+    } else {
+        variant_color(block, variant)
+    }
+}
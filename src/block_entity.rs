@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use crate::block::{self, BlockId, CHEST, SIGN, TORCH};
+use crate::storage::ByteReader;
+use crate::world::{ChunkScoped, World};
+
+// Bytes per chest slot in the encoded form: block ID and count
+const CHEST_ITEM_LEN: usize = 6;
+
+// Extra state for blocks that need more than a BlockId. The chunk owns the data (so it is
+// saved, loaded and unloaded with the voxels); an ECS entity carrying the matching component
+// mirrors it while the chunk is loaded, so gameplay systems can query chests and signs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockEntityData {
+    Chest { items: Vec<(BlockId, u32)> },
+    Sign { text: String },
+    // No state; the entity carries the torch's mesh and marks it as a light source
+    Torch,
+}
+
+impl BlockEntityData {
+    // Data a freshly placed block starts with, None for plain blocks
+    pub fn default_for(block: BlockId) -> Option<Self> {
+        match block {
+            CHEST => Some(BlockEntityData::Chest { items: Vec::new() }),
+            SIGN => Some(BlockEntityData::Sign { text: String::new() }),
+            TORCH => Some(BlockEntityData::Torch),
+            _ => None,
+        }
+    }
+
+    // Serialized form used inside chunk blobs, see chunk_format. Lengths are stored as u16, so
+    // chests keep their first u16::MAX slots and signs their first u16::MAX bytes of text, cut
+    // at a character boundary.
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        match self {
+            BlockEntityData::Chest { items } => {
+                let items = &items[..items.len().min(u16::MAX as usize)];
+                bytes.push(0);
+                bytes.extend_from_slice(&(items.len() as u16).to_le_bytes());
+                for (block, count) in items {
+                    bytes.extend_from_slice(&block.to_le_bytes());
+                    bytes.extend_from_slice(&count.to_le_bytes());
+                }
+            }
+            BlockEntityData::Sign { text } => {
+                let mut len = text.len().min(u16::MAX as usize);
+                while !text.is_char_boundary(len) {
+                    len -= 1;
+                }
+                bytes.push(1);
+                bytes.extend_from_slice(&(len as u16).to_le_bytes());
+                bytes.extend_from_slice(&text.as_bytes()[..len]);
+            }
+            BlockEntityData::Torch => bytes.push(2),
+        }
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        match reader.read_u8()? {
+            0 => {
+                let count = reader.read_u16()?;
+                if reader.remaining() < count as usize * CHEST_ITEM_LEN {
+                    return None;
+                }
+                let items = (0..count)
+                    .map(|_| Some((reader.read_u16()?, reader.read_u32()?)))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(BlockEntityData::Chest { items })
+            }
+            1 => {
+                let len = reader.read_u16()?;
+                let text = String::from_utf8(reader.take_slice(len as usize)?.to_vec()).ok()?;
+                Some(BlockEntityData::Sign { text })
+            }
+            2 => Some(BlockEntityData::Torch),
+            _ => None,
+        }
+    }
+}
+
+// Marks the entity mirroring a block entity
+#[derive(Component)]
+pub struct BlockEntity {
+    pub pos: IVec3,
+    pub block: BlockId,
+}
+
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct Chest {
+    pub items: Vec<(BlockId, u32)>,
+}
+
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct Sign {
+    pub text: String,
+}
+
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct Torch;
+
+// World voxel position -> mirroring entity, for every block entity in a loaded chunk
+#[derive(Resource, Default)]
+pub struct BlockEntities {
+    entities: HashMap<IVec3, Entity>,
+}
+
+impl BlockEntities {
+    pub fn get(&self, pos: IVec3) -> Option<Entity> {
+        self.entities.get(&pos).copied()
+    }
+}
+
+// Spawns entities for block entity data that appeared (placed blocks, loaded chunks) and
+// despawns those whose data went away (broken blocks, unloaded chunks)
+pub fn sync_block_entities(
+    world: Res<World>,
+    mut block_entities: ResMut<BlockEntities>,
+    mut commands: Commands,
+) {
+    block_entities.entities.retain(|&pos, &mut entity| {
+        let (chunk_key, voxel_pos) = world.world_to_voxel(pos);
+        let alive = world.chunks.get(&chunk_key).is_some_and(|chunk| chunk.block_entities.contains_key(&voxel_pos));
+        if !alive {
+            // Unloaded chunks may already have taken their scoped children with them
+            if let Some(entity) = commands.get_entity(entity) {
+                entity.despawn_recursive();
+            }
+        }
+        alive
+    });
+
+    for (&chunk_key, chunk) in &world.chunks {
+        for (&voxel_pos, data) in &chunk.block_entities {
+            let pos = world.voxel_to_world(chunk_key, voxel_pos);
+            if block_entities.entities.contains_key(&pos) {
+                continue;
+            }
+
+            let block = chunk.get_block(voxel_pos.0, voxel_pos.1, voxel_pos.2);
+            let transform = Transform::from_translation(pos.as_vec3() + Vec3::splat(0.5));
+            // Visible so meshes like torch::attach_torch_meshes can hang off it
+            #[cfg(feature = "render")]
+            let placement = SpatialBundle::from_transform(transform);
+            #[cfg(not(feature = "render"))]
+            let placement = TransformBundle::from_transform(transform);
+            let mut entity = commands.spawn((
+                BlockEntity { pos, block },
+                ChunkScoped(chunk_key),
+                placement,
+                Name::new(block::definition(block).name),
+            ));
+            match data.clone() {
+                BlockEntityData::Chest { items } => entity.insert(Chest { items }),
+                BlockEntityData::Sign { text } => entity.insert(Sign { text }),
+                BlockEntityData::Torch => entity.insert(Torch),
+            };
+            block_entities.entities.insert(pos, entity.id());
+        }
+    }
+}
+
+// Copies component edits back into the owning chunk so they get saved
+pub fn store_block_entity_data(
+    mut world: ResMut<World>,
+    chests: Query<(&BlockEntity, &Chest), Changed<Chest>>,
+    signs: Query<(&BlockEntity, &Sign), Changed<Sign>>,
+) {
+    let changed = chests.iter().map(|(owner, chest)| (owner.pos, BlockEntityData::Chest { items: chest.items.clone() }))
+        .chain(signs.iter().map(|(owner, sign)| (owner.pos, BlockEntityData::Sign { text: sign.text.clone() })));
+    for (pos, data) in changed {
+        world.set_block_entity_data(pos, data);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::block::{self, BlockId, AIR};
+
+// Block IDs as a world's save stores them. A world hands its IDs out once and never changes
+// them, so blocks added to block::BLOCK_DEFINITIONS later, or script blocks registered in
+// another order, can't turn saved blocks into something else. WorldMeta keeps the table of the
+// block saved under each ID by name; chunks and the player's inventory are translated through
+// it as they load and save. Worlds whose table matches the registry skip the translation.
+#[derive(Clone, Debug)]
+pub struct BlockIdMap {
+    identity: bool,
+    // Indexed by saved ID
+    to_current: Vec<BlockId>,
+    // Indexed by current ID
+    to_saved: Vec<BlockId>,
+}
+
+impl Default for BlockIdMap {
+    fn default() -> Self {
+        Self { identity: true, to_current: Vec::new(), to_saved: Vec::new() }
+    }
+}
+
+impl BlockIdMap {
+    // `names` lists the block saved under each ID, every registered block among them (see
+    // register_new_blocks). Saved blocks that are no longer registered load as air.
+    pub fn new(names: &[String]) -> Self {
+        let to_current: Vec<BlockId> = names.iter()
+            .map(|name| registered_id(name).unwrap_or(AIR))
+            .collect();
+        let to_saved: Vec<BlockId> = block::definitions()
+            .map(|definition| names.iter().position(|name| name == definition.name).map_or(AIR, |id| id as BlockId))
+            .collect();
+        let identity = to_current.iter().enumerate().all(|(id, &current)| id as BlockId == current)
+            && to_saved.iter().enumerate().all(|(id, &saved)| id as BlockId == saved);
+        Self { identity, to_current, to_saved }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.identity
+    }
+
+    pub fn to_current(&self, saved: BlockId) -> BlockId {
+        if self.identity {
+            return saved;
+        }
+        self.to_current.get(saved as usize).copied().unwrap_or(AIR)
+    }
+
+    pub fn to_saved(&self, current: BlockId) -> BlockId {
+        if self.identity {
+            return current;
+        }
+        self.to_saved.get(current as usize).copied().unwrap_or(AIR)
+    }
+}
+
+fn registered_id(name: &str) -> Option<BlockId> {
+    block::definitions().position(|definition| definition.name == name).map(|id| id as BlockId)
+}
+
+// The ID table of a world saved with this build's registry
+pub fn registered_names() -> Vec<String> {
+    block::definitions().map(|definition| definition.name.to_string()).collect()
+}
+
+// Gives registered blocks missing from a world's table the next free IDs. Returns whether any
+// were added, in which case the table needs saving.
+pub fn register_new_blocks(names: &mut Vec<String>) -> bool {
+    let before = names.len();
+    for definition in block::definitions() {
+        if !names.iter().any(|name| name == definition.name) {
+            names.push(definition.name.to_string());
+        }
+    }
+    names.len() > before
+}
+
+// Saved blocks of a world's table this build doesn't know
+pub fn unknown_blocks(names: &[String]) -> Vec<&str> {
+    names.iter().map(String::as_str).filter(|name| registered_id(name).is_none()).collect()
+}
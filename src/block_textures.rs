@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+};
+use bevy::render::texture::{
+    CompressedImageFormats, ImageAddressMode, ImageFilterMode, ImageSampler, ImageSamplerDescriptor, ImageType,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::block::{self, BlockId};
+use crate::keybindings::{Action, Actions};
+use crate::settings::TextureFiltering;
+use crate::world::World;
+
+// Every layer of the array has this size; textures of other sizes are resampled to it
+const BLOCK_TEXTURE_SIZE: u32 = 16;
+
+// Per-block textures from content packs, stitched into one array texture at startup.
+// A pack is a folder under CONTENT_PACK_DIRECTORY with a `blocks` folder of PNGs named after
+// the blocks (`stone.png`, `log.png`). Packs apply in name order, so a later pack overrides
+// a block an earlier one provided. Blocks without a texture keep their flat vertex colors.
+
+// Texture file of every block some pack provides, the last pack winning
+fn find_block_textures(pack_dir: &Path) -> BTreeMap<BlockId, PathBuf> {
+    let mut packs: Vec<PathBuf> = std::fs::read_dir(pack_dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect())
+        .unwrap_or_default();
+    packs.sort();
+
+    let mut textures = BTreeMap::new();
+    for pack in packs {
+        for (id, definition) in block::definitions().enumerate() {
+            let path = pack.join("blocks").join(format!("{}.png", definition.name));
+            if path.is_file() {
+                textures.insert(id as BlockId, path);
+            }
+        }
+    }
+    textures
+}
+
+// RGBA8 pixels of a PNG at BLOCK_TEXTURE_SIZE, nearest-neighbour resampled so pixel art stays crisp
+fn decode_texture(path: &Path) -> Result<Vec<u8>, String> {
+    let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+    let image = Image::from_buffer(
+        &bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::MAIN_WORLD,
+    )
+    .map_err(|err| err.to_string())?
+    .convert(TextureFormat::Rgba8UnormSrgb)
+    .ok_or_else(|| "unsupported pixel format".to_string())?;
+
+    let (width, height) = (image.width(), image.height());
+    let size = BLOCK_TEXTURE_SIZE;
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let offset = (((y * height / size) * width + x * width / size) * 4) as usize;
+            pixels.extend_from_slice(&image.data[offset..offset + 4]);
+        }
+    }
+    Ok(pixels)
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    (value as f32 / 255.0).powf(2.2)
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    (value.powf(1.0 / 2.2) * 255.0).round() as u8
+}
+
+// Full mip chain of one layer, level 0 first. Each level averages 2x2 texels of the level above
+// within the same layer, so unlike a flat atlas no neighbouring block bleeds into distant mips.
+fn mip_chain(level0: Vec<u8>) -> Vec<u8> {
+    let mut chain = level0.clone();
+    let mut level = level0;
+    let mut size = BLOCK_TEXTURE_SIZE;
+    while size > 1 {
+        let half = size / 2;
+        let mut next = Vec::with_capacity((half * half * 4) as usize);
+        for y in 0..half {
+            for x in 0..half {
+                let texel = |dx: u32, dy: u32| {
+                    let offset = (((y * 2 + dy) * size + x * 2 + dx) * 4) as usize;
+                    [level[offset], level[offset + 1], level[offset + 2], level[offset + 3]]
+                };
+                next.extend_from_slice(&downsample([texel(0, 0), texel(1, 0), texel(0, 1), texel(1, 1)]));
+            }
+        }
+        chain.extend_from_slice(&next);
+        level = next;
+        size = half;
+    }
+    chain
+}
+
+// One texel of the next mip level. Colors average in linear space, weighted by alpha so the
+// (usually black) color of transparent texels doesn't darken the edges of cut-out textures.
+// The result is clamped to the colors it was made from, keeping every level inside the
+// texture's own palette so distant faces don't show seams of off colors.
+fn downsample(texels: [[u8; 4]; 4]) -> [u8; 4] {
+    let alpha_sum: u32 = texels.iter().map(|texel| texel[3] as u32).sum();
+    let mut result = [0, 0, 0, (alpha_sum / 4) as u8];
+    for channel in 0..3 {
+        let weight = |texel: &[u8; 4]| if alpha_sum > 0 { texel[3] as f32 } else { 1.0 };
+        let total_weight: f32 = texels.iter().map(weight).sum();
+        let linear = texels.iter().map(|texel| srgb_to_linear(texel[channel]) * weight(texel)).sum::<f32>() / total_weight;
+        let sources = texels.iter().filter(|texel| alpha_sum == 0 || texel[3] > 0).map(|texel| texel[channel]);
+        let (low, high) = sources.fold((u8::MAX, u8::MIN), |(low, high), value| (low.min(value), high.max(value)));
+        result[channel] = linear_to_srgb(linear).clamp(low, high);
+    }
+    result
+}
+
+// Sampler for the array under the given settings. Texels are clamped at the edges of every
+// layer; the shader does the tiling, so one face never filters in the opposite edge.
+pub fn block_texture_sampler(filtering: TextureFiltering) -> ImageSampler {
+    let descriptor = ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::ClampToEdge,
+        address_mode_v: ImageAddressMode::ClampToEdge,
+        min_filter: ImageFilterMode::Linear,
+        mipmap_filter: ImageFilterMode::Linear,
+        ..default()
+    };
+    ImageSampler::Descriptor(if filtering.pixel_art {
+        ImageSamplerDescriptor { mag_filter: ImageFilterMode::Nearest, ..descriptor }
+    } else {
+        ImageSamplerDescriptor {
+            mag_filter: ImageFilterMode::Linear,
+            anisotropy_clamp: filtering.anisotropy.clamp(1, 16),
+            ..descriptor
+        }
+    })
+}
+
+// Builds the array texture from the content packs and records each block's layer in the
+// block table (block::texture_layer). Broken or unreadable files are skipped with a message.
+// Always returns at least one (white) layer, since the terrain material needs a valid array.
+pub fn load_block_textures(pack_dir: &Path, filtering: TextureFiltering) -> Image {
+    let mut layers = vec![None; block::block_count()];
+    let mut data = Vec::new();
+    let mut layer_count = 0;
+
+    for (block, path) in find_block_textures(pack_dir) {
+        match decode_texture(&path) {
+            Ok(pixels) => {
+                data.extend(mip_chain(pixels));
+                layers[block as usize] = Some(layer_count);
+                layer_count += 1;
+            }
+            Err(err) => println!("Skipping block texture {}: {}", path.display(), err),
+        }
+    }
+    if layer_count == 0 {
+        data = mip_chain(vec![255; (BLOCK_TEXTURE_SIZE * BLOCK_TEXTURE_SIZE * 4) as usize]);
+        layer_count = 1;
+    } else {
+        println!("Loaded {} block texture(s) from {}", layer_count, pack_dir.display());
+    }
+    block::set_texture_layers(layers);
+
+    // Layers one after another, each followed by its mips, which is the order the GPU upload
+    // expects. Image::new only takes the base level, so the descriptor is filled in by hand.
+    let mut image = Image { data, asset_usage: RenderAssetUsages::RENDER_WORLD, ..default() };
+    image.texture_descriptor.size = Extent3d {
+        width: BLOCK_TEXTURE_SIZE,
+        height: BLOCK_TEXTURE_SIZE,
+        depth_or_array_layers: layer_count,
+    };
+    image.texture_descriptor.dimension = TextureDimension::D2;
+    image.texture_descriptor.format = TextureFormat::Rgba8UnormSrgb;
+    image.texture_descriptor.mip_level_count = BLOCK_TEXTURE_SIZE.ilog2() + 1;
+    // A single layer would otherwise get a plain 2D view
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+    image.sampler = block_texture_sampler(filtering);
+    image
+}
+
+// Integer part of UV_1.x of terrain vertices: the water flag (0 or 1) plus twice the texture
+// layer + 1, with 0 for untextured blocks. The fraction is left for the corner's block light,
+// see lighting::pack_light_level. voxel_terrain.wgsl unpacks all three.
+pub fn pack_shader_flag(in_water: f32, layer: Option<u32>) -> f32 {
+    in_water + 2.0 * layer.map_or(0.0, |layer| (layer + 1) as f32)
+}
+
+// The array texture, kept so sampler changes can be applied to it
+#[derive(Resource)]
+pub struct BlockTextures(pub Handle<Image>);
+
+pub fn toggle_pixel_art(actions: Actions, mut world: ResMut<World>) {
+    if !actions.just_pressed(Action::PixelArt) {
+        return;
+    }
+
+    let filtering = &mut world.graphics.texture_filtering;
+    filtering.pixel_art = !filtering.pixel_art;
+    println!("Pixel art textures {}", if filtering.pixel_art { "enabled" } else { "disabled" });
+}
+
+// Steps through 1x, 2x, 4x, 8x and 16x anisotropic filtering
+pub fn cycle_anisotropy(actions: Actions, mut world: ResMut<World>) {
+    if !actions.just_pressed(Action::Anisotropy) {
+        return;
+    }
+
+    let filtering = &mut world.graphics.texture_filtering;
+    filtering.anisotropy = if filtering.anisotropy >= 16 { 1 } else { filtering.anisotropy * 2 };
+    if filtering.pixel_art {
+        println!("Anisotropic filtering {}x (only applies with pixel art textures disabled)", filtering.anisotropy);
+    } else {
+        println!("Anisotropic filtering {}x", filtering.anisotropy);
+    }
+}
+
+// Swaps the sampler of the block array when the filtering settings change. Only the image's
+// sampler changes; the texture data and the chunk meshes stay as they are.
+pub fn apply_texture_filtering(
+    world: Res<World>,
+    block_textures: Res<BlockTextures>,
+    mut images: ResMut<Assets<Image>>,
+    mut applied: Local<Option<TextureFiltering>>,
+) {
+    let filtering = world.graphics.texture_filtering;
+    if *applied == Some(filtering) {
+        return;
+    }
+    if let Some(image) = images.get_mut(&block_textures.0) {
+        image.sampler = block_texture_sampler(filtering);
+        *applied = Some(filtering);
+    }
+}
@@ -0,0 +1,362 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use bevy::prelude::*;
+use crate::block::{self, BlockId, AIR, DIRT, FLOWER, GRASS, LEAVES, LOG, SNOW_LAYER, TALL_GRASS, TORCH};
+use crate::day_night::WorldClock;
+use crate::dimension::Dimension;
+use crate::item_drop::{SpawnItemDrop, POP_SPEED};
+use crate::torch;
+use crate::voxel_world::VoxelWorld;
+use crate::weather::SNOW_TEMPERATURE;
+use crate::world::World;
+use crate::worldgen::WorldGenerator;
+use crate::{BLOCK_UPDATES_PER_TICK, SCHEDULED_TICKS_PER_TICK};
+
+// Fixed ticks between a liquid noticing room to flow and flowing into it
+const LIQUID_FLOW_TICKS: u64 = 6;
+// Leaves cut off from their tree fall apart one by one after this plus up to LEAF_DECAY_SPREAD ticks
+const LEAF_DECAY_TICKS: u64 = 40;
+const LEAF_DECAY_SPREAD: u32 = 160;
+// Leaves stay as long as a log is reachable through at most this many leaves
+const LEAF_LOG_DISTANCE: i32 = 4;
+// Dirt next to grass turns to grass after this plus up to GRASS_SPREAD_SPREAD ticks, covered
+// grass back to dirt after GRASS_DECAY_TICKS plus up to the same spread
+const GRASS_SPREAD_TICKS: u64 = 200;
+const GRASS_DECAY_TICKS: u64 = 100;
+const GRASS_SPREAD_SPREAD: u32 = 400;
+// Snow somewhere warmer than weather::SNOW_TEMPERATURE loses a layer after this plus up to
+// SNOW_MELT_SPREAD ticks
+pub const SNOW_MELT_TICKS: u64 = 300;
+const SNOW_MELT_SPREAD: u32 = 300;
+
+const NEIGHBOURS: [IVec3; 6] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Y, IVec3::Y, IVec3::NEG_Z, IVec3::Z];
+const HORIZONTAL_NEIGHBOURS: [IVec3; 4] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z];
+
+// Voxels next to a changed voxel, waiting for a chance to react to it. apply_voxel_events queues
+// the six neighbours of every change; a voxel already waiting isn't queued twice. Reactions are
+// ordinary block writes, so they queue their own neighbours in turn. A reaction that should
+// happen later schedules a tick instead, which lands in the chunk's ScheduledTicks. Not saved;
+// scheduled ticks are.
+#[derive(Resource, Default)]
+pub struct BlockUpdates {
+    queue: VecDeque<IVec3>,
+    queued: HashSet<IVec3>,
+    // (voxel, delay in ticks) requested since the last tick, filed into chunks by run_scheduled_ticks
+    requested: Vec<(IVec3, u64)>,
+    // Voxels whose scheduled tick came up this tick
+    due: Vec<IVec3>,
+}
+
+impl BlockUpdates {
+    // Ticks `pos` again after `delay` fixed ticks, at least one. Ignored if it already has a
+    // tick pending or its chunk isn't loaded.
+    pub fn schedule(&mut self, pos: IVec3, delay: u64) {
+        self.requested.push((pos, delay.max(1)));
+    }
+
+    pub fn notify_neighbours(&mut self, pos: IVec3) {
+        for offset in NEIGHBOURS {
+            if self.queued.insert(pos + offset) {
+                self.queue.push_back(pos + offset);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // Voxels whose scheduled tick came up this tick, between run_scheduled_ticks and
+    // process_block_updates
+    pub fn due(&self) -> &[IVec3] {
+        &self.due
+    }
+
+    fn pop(&mut self) -> Option<IVec3> {
+        let pos = self.queue.pop_front()?;
+        self.queued.remove(&pos);
+        Some(pos)
+    }
+}
+
+// (due WorldClock tick, local position)
+type ScheduledTick = (u64, (usize, usize, usize));
+
+// Block ticks waiting in a chunk, soonest first. A voxel has at most one tick pending. Saved with
+// the chunk, see chunk_format.
+#[derive(Clone, Debug, Default)]
+pub struct ScheduledTicks {
+    queue: BinaryHeap<Reverse<ScheduledTick>>,
+    // The voxels in `queue`, for the one-tick-per-voxel check
+    pending: HashSet<(usize, usize, usize)>,
+}
+
+impl ScheduledTicks {
+    // False if the voxel already had a tick pending
+    pub fn schedule(&mut self, voxel_pos: (usize, usize, usize), due: u64) -> bool {
+        if !self.pending.insert(voxel_pos) {
+            return false;
+        }
+        self.queue.push(Reverse((due, voxel_pos)));
+        true
+    }
+
+    fn next_due(&self) -> Option<u64> {
+        self.queue.peek().map(|Reverse((due, _))| *due)
+    }
+
+    fn pop_due(&mut self, now: u64) -> Option<(usize, usize, usize)> {
+        if self.next_due()? > now {
+            return None;
+        }
+        let Reverse((_, pos)) = self.queue.pop()?;
+        self.pending.remove(&pos);
+        Some(pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.pending.clear();
+    }
+
+    // (due, local position) in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = ScheduledTick> + '_ {
+        self.queue.iter().map(|Reverse(tick)| *tick)
+    }
+}
+
+// Advances the world tick, files newly requested ticks into their chunks and collects up to
+// SCHEDULED_TICKS_PER_TICK that came up for process_block_updates. Chunks are visited in key
+// order so the same inputs always tick the same voxels; overdue ticks wait for the next tick.
+pub fn run_scheduled_ticks(
+    mut world: ResMut<World>,
+    mut clock: ResMut<WorldClock>,
+    mut updates: ResMut<BlockUpdates>,
+) {
+    clock.tick += 1;
+    let now = clock.tick;
+
+    for (pos, delay) in std::mem::take(&mut updates.requested) {
+        let (chunk_key, voxel_pos) = world.world_to_voxel(pos);
+        let Some(chunk) = world.chunks.get_mut(&chunk_key) else {
+            continue;
+        };
+        if chunk.scheduled_ticks.schedule(voxel_pos, now + delay) {
+            world.modified_chunks.insert(chunk_key);
+        }
+    }
+
+    let mut chunk_keys: Vec<_> = world.chunks.iter()
+        .filter(|(_, chunk)| chunk.scheduled_ticks.next_due().is_some_and(|due| due <= now))
+        .map(|(&chunk_key, _)| chunk_key)
+        .collect();
+    chunk_keys.sort_unstable();
+    for chunk_key in chunk_keys {
+        while updates.due.len() < SCHEDULED_TICKS_PER_TICK {
+            let Some(voxel_pos) = world.chunks.get_mut(&chunk_key).and_then(|chunk| chunk.scheduled_ticks.pop_due(now)) else {
+                break;
+            };
+            world.modified_chunks.insert(chunk_key);
+            let pos = world.voxel_to_world(chunk_key, voxel_pos);
+            updates.due.push(pos);
+        }
+    }
+}
+
+// Runs this tick's scheduled ticks, then up to BLOCK_UPDATES_PER_TICK queued neighbour updates,
+// oldest first; the rest wait for the next tick so a long chain of reactions is spread over time
+pub fn process_block_updates(
+    mut updates: ResMut<BlockUpdates>,
+    mut voxel_world: VoxelWorld,
+    dimension: Res<Dimension>,
+    generator: Res<WorldGenerator>,
+    mut drop_events: EventWriter<SpawnItemDrop>,
+) {
+    let sky = dimension.has_skylight();
+    for pos in std::mem::take(&mut updates.due) {
+        let Some(current) = voxel_world.get_block(pos) else {
+            continue;
+        };
+        match react_to_tick(&voxel_world, &generator, sky, pos, current) {
+            Some(Reaction::Set(target, next)) => voxel_world.set_block(target, next),
+            // A block that only changed its state, like snow losing a layer, keeps ticking
+            Some(Reaction::SetState(target, next, state)) => {
+                voxel_world.set_block_with_state(target, next, state);
+                updates.schedule(target, tick_delay(target, SNOW_MELT_TICKS, SNOW_MELT_SPREAD));
+            }
+            _ => {}
+        }
+    }
+
+    for _ in 0..BLOCK_UPDATES_PER_TICK {
+        let Some(pos) = updates.pop() else {
+            break;
+        };
+        // Updates reaching into unloaded chunks are dropped
+        let Some(current) = voxel_world.get_block(pos) else {
+            continue;
+        };
+        match react_to_update(&voxel_world, &generator, sky, pos, current) {
+            Some(Reaction::Set(target, next)) => {
+                voxel_world.set_block(target, next);
+                if current == TORCH {
+                    drop_events.send(SpawnItemDrop {
+                        position: pos.as_vec3() + Vec3::splat(0.5),
+                        block: TORCH,
+                        count: 1,
+                        velocity: Vec3::Y * POP_SPEED,
+                    });
+                }
+            }
+            Some(Reaction::Schedule(delay)) => updates.schedule(pos, delay),
+            _ => {}
+        }
+    }
+}
+
+enum Reaction {
+    // Write a block, not necessarily at the updated voxel
+    Set(IVec3, BlockId),
+    // Write a block with a state, see Chunk::block_states
+    SetState(IVec3, BlockId, u8),
+    // Look again after this many ticks, see react_to_tick
+    Schedule(u64),
+}
+
+// What the block at `pos` does after something next to it changed, if anything. `sky` is
+// whether the dimension has skylight at all.
+// - torches pop off once neither the floor nor any wall holds them
+// - plants and snow layers vanish without a solid block underneath
+// - liquids that can flow, leaves cut off from their tree, dirt that can grow grass, covered
+//   grass and snow somewhere warm wait for a scheduled tick
+fn react_to_update(voxel_world: &VoxelWorld, generator: &WorldGenerator, sky: bool, pos: IVec3, current: BlockId) -> Option<Reaction> {
+    let below = pos - IVec3::Y;
+    match current {
+        TORCH => (!torch::is_supported(voxel_world, pos)).then_some(Reaction::Set(pos, AIR)),
+        TALL_GRASS | FLOWER => (!voxel_world.is_solid(below)).then_some(Reaction::Set(pos, AIR)),
+        block if block::is_thin(block) && !voxel_world.is_solid(below) => Some(Reaction::Set(pos, AIR)),
+        SNOW_LAYER if melts(generator, pos) => Some(Reaction::Schedule(tick_delay(pos, SNOW_MELT_TICKS, SNOW_MELT_SPREAD))),
+        LEAVES if !reaches_log(voxel_world, pos) => Some(Reaction::Schedule(tick_delay(pos, LEAF_DECAY_TICKS, LEAF_DECAY_SPREAD))),
+        GRASS if grass_covered(voxel_world, pos) => Some(Reaction::Schedule(tick_delay(pos, GRASS_DECAY_TICKS, GRASS_SPREAD_SPREAD))),
+        DIRT if sky && grows_grass(voxel_world, pos) => Some(Reaction::Schedule(tick_delay(pos, GRASS_SPREAD_TICKS, GRASS_SPREAD_SPREAD))),
+        _ => liquid_flow(voxel_world, pos, current).map(|_| Reaction::Schedule(LIQUID_FLOW_TICKS)),
+    }
+}
+
+// The write a scheduled tick of the block at `pos` makes. Conditions are checked again since
+// the neighbourhood may have changed while the tick was pending.
+fn react_to_tick(voxel_world: &VoxelWorld, generator: &WorldGenerator, sky: bool, pos: IVec3, current: BlockId) -> Option<Reaction> {
+    match current {
+        LEAVES => (!reaches_log(voxel_world, pos)).then_some(Reaction::Set(pos, AIR)),
+        GRASS => grass_covered(voxel_world, pos).then_some(Reaction::Set(pos, DIRT)),
+        DIRT => (sky && grows_grass(voxel_world, pos)).then_some(Reaction::Set(pos, GRASS)),
+        SNOW_LAYER if melts(generator, pos) => match voxel_world.get_state(pos) {
+            Some(0) | None => Some(Reaction::Set(pos, AIR)),
+            Some(state) => Some(Reaction::SetState(pos, SNOW_LAYER, state - 1)),
+        },
+        _ => liquid_flow(voxel_world, pos, current).map(|(target, next)| Reaction::Set(target, next)),
+    }
+}
+
+// Snow lying at `pos` is too warm to stay
+fn melts(generator: &WorldGenerator, pos: IVec3) -> bool {
+    generator.temperature(pos.x, pos.y, pos.z) >= SNOW_TEMPERATURE
+}
+
+// `base` plus a per-voxel part of `spread`, so neighbours that start waiting together don't all
+// change in the same tick
+fn tick_delay(pos: IVec3, base: u64, spread: u32) -> u64 {
+    base + (block::position_hash(pos.x, pos.y, pos.z, 0) % spread) as u64
+}
+
+// Grass needs air above it; under an opaque block or a liquid it dies back to dirt
+fn grass_covered(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    voxel_world.get_block(pos + IVec3::Y).is_some_and(|above| block::is_opaque(above) || block::is_liquid(above))
+}
+
+// Dirt with nothing covering it that sees the sky turns to grass when grass grows within one
+// block sideways, from one below to one above
+fn grows_grass(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    if voxel_world.get_block(pos + IVec3::Y).is_none() || grass_covered(voxel_world, pos) || !sees_sky(voxel_world, pos) {
+        return false;
+    }
+    (-1..=1).any(|dx| (-1..=1).any(|dy| (-1..=1).any(|dz| {
+        voxel_world.get_block(pos + IVec3::new(dx, dy, dz)) == Some(GRASS)
+    })))
+}
+
+// Whether nothing opaque stands anywhere above `pos`. Uniform chunks are passed in one step and
+// the scan ends at the first chunk that isn't loaded, which counts as open sky.
+fn sees_sky(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    let world = voxel_world.world();
+    let mut cell = pos + IVec3::Y;
+    loop {
+        let (chunk_key, (x, y, z)) = world.world_to_voxel(cell);
+        let Some(chunk) = world.chunks.get(&chunk_key) else {
+            return true;
+        };
+        match chunk.voxels.uniform_block() {
+            Some(block) if block::is_opaque(block) => return false,
+            Some(_) => cell.y += (chunk.height - y) as i32,
+            None if chunk.is_opaque(x, y, z) => return false,
+            None => cell.y += 1,
+        }
+    }
+}
+
+// Liquids pour down into air, and air fills with liquid from above or between two liquid cells
+// beside it, which closes holes dug into a lake without flooding open ground
+fn liquid_flow(voxel_world: &VoxelWorld, pos: IVec3, current: BlockId) -> Option<(IVec3, BlockId)> {
+    let below = pos - IVec3::Y;
+    match current {
+        liquid if block::is_liquid(liquid) => (voxel_world.get_block(below) == Some(AIR)).then_some((below, liquid)),
+        AIR => {
+            let above = voxel_world.get_block(pos + IVec3::Y).filter(|&above| block::is_liquid(above));
+            above.or_else(|| {
+                let beside: Vec<BlockId> = HORIZONTAL_NEIGHBOURS.iter()
+                    .filter_map(|&offset| voxel_world.get_block(pos + offset))
+                    .filter(|&neighbour| block::is_liquid(neighbour))
+                    .collect();
+                beside.iter().copied().find(|&liquid| beside.iter().filter(|&&other| other == liquid).count() >= 2)
+            }).map(|liquid| (pos, liquid))
+        }
+        _ => None,
+    }
+}
+
+// Whether a log is reachable from the leaves at `pos` through at most LEAF_LOG_DISTANCE leaves.
+// Unloaded cells count as a log so trees on chunk borders don't decay while streaming in.
+fn reaches_log(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    let mut visited = HashSet::from([pos]);
+    let mut frontier = vec![pos];
+    for _ in 0..LEAF_LOG_DISTANCE {
+        let mut next = Vec::new();
+        for cell in frontier {
+            for offset in NEIGHBOURS {
+                let neighbour = cell + offset;
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                match voxel_world.get_block(neighbour) {
+                    None | Some(LOG) => return true,
+                    Some(LEAVES) => next.push(neighbour),
+                    _ => {}
+                }
+            }
+        }
+        frontier = next;
+    }
+    false
+}
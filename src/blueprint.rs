@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use crate::selection::TargetedBlock;
+use crate::block::{self, BlockId, AIR};
+use crate::history::{EditHistory, VoxelEdit};
+use crate::keybindings::{Action, Actions};
+use crate::notifications::NotificationEvent;
+use crate::region_edit::RegionSelection;
+use crate::voxel_world::VoxelWorld;
+use crate::{VoxelRemover, MAX_REGION_VOLUME};
+
+const GHOST_ALPHA: f32 = 0.35;
+const INVALID_COLOR: [f32; 4] = [1.0, 0.05, 0.05, 0.6];
+
+// A captured piece of the world. Cells are relative to the blueprint's minimum corner
+// and only non-air blocks are stored, so pasting never carves out existing terrain.
+#[derive(Clone, Debug)]
+pub struct Blueprint {
+    pub size: IVec3,
+    pub cells: Vec<(IVec3, BlockId)>,
+}
+
+impl Blueprint {
+    pub fn capture(voxel_world: &VoxelWorld, min: IVec3, max: IVec3) -> Self {
+        let mut cells = Vec::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let pos = IVec3::new(x, y, z);
+                    if let Some(block) = voxel_world.get_block(pos).filter(|&block| block != AIR) {
+                        cells.push((pos - min, block));
+                    }
+                }
+            }
+        }
+        Self { size: max - min + IVec3::ONE, cells }
+    }
+
+    // Cells after `quarter_turns` 90 degree turns around the vertical axis, still relative to
+    // the rotated blueprint's minimum corner
+    pub fn rotated_cells(&self, quarter_turns: u8) -> impl Iterator<Item = (IVec3, BlockId)> + '_ {
+        let size = self.size;
+        self.cells.iter().map(move |&(pos, block)| {
+            let rotated = match quarter_turns % 4 {
+                0 => pos,
+                1 => IVec3::new(size.z - 1 - pos.z, pos.y, pos.x),
+                2 => IVec3::new(size.x - 1 - pos.x, pos.y, size.z - 1 - pos.z),
+                _ => IVec3::new(pos.z, pos.y, size.x - 1 - pos.x),
+            };
+            (rotated, block)
+        })
+    }
+}
+
+// Blueprint being placed: a ghost follows the targeted voxel until it is confirmed
+#[derive(Resource, Default)]
+pub struct BlueprintPlacement {
+    pub blueprint: Option<Blueprint>,
+    pub active: bool,
+    pub rotation: u8,
+    pub anchor: Option<IVec3>,
+    ghost: Option<Entity>,
+    // (anchor, rotation, invalid cell count) the ghost mesh was built for
+    ghost_state: Option<(IVec3, u8, usize)>,
+}
+
+// BlueprintCapture copies the region selection into the blueprint, Blueprint shows or hides
+// the ghost, BlueprintRotate turns it and BlueprintPlace places it
+pub fn blueprint_input(
+    actions: Actions,
+    mut voxel_world: VoxelWorld,
+    selection: Res<RegionSelection>,
+    mut placement: ResMut<BlueprintPlacement>,
+    mut history: ResMut<EditHistory>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if actions.just_pressed(Action::BlueprintCapture) {
+        let Some((min, max)) = selection.bounds() else {
+            notifications.send(NotificationEvent::warning("Select both region corners before capturing a blueprint"));
+            return;
+        };
+        let size = (max - min + IVec3::ONE).as_i64vec3();
+        if size.x * size.y * size.z > MAX_REGION_VOLUME {
+            notifications.send(NotificationEvent::warning("Selection is too large for a blueprint"));
+            return;
+        }
+        let blueprint = Blueprint::capture(&voxel_world, min, max);
+        notifications.send(NotificationEvent::info(format!("Captured blueprint of {} block(s), {:?}", blueprint.cells.len(), blueprint.size)));
+        placement.blueprint = Some(blueprint);
+        placement.rotation = 0;
+        placement.active = true;
+        return;
+    }
+
+    if actions.just_pressed(Action::Blueprint) && placement.blueprint.is_some() {
+        placement.active = !placement.active;
+        return;
+    }
+
+    if !placement.active {
+        return;
+    }
+
+    if actions.just_pressed(Action::BlueprintRotate) {
+        placement.rotation = (placement.rotation + 1) % 4;
+    }
+
+    if actions.just_pressed(Action::BlueprintPlace) {
+        let (Some(blueprint), Some(anchor)) = (placement.blueprint.as_ref(), placement.anchor) else {
+            return;
+        };
+        let cells: Vec<_> = blueprint.rotated_cells(placement.rotation).map(|(pos, block)| (anchor + pos, block)).collect();
+        if cells.iter().any(|&(pos, _)| !cell_is_free(&voxel_world, pos)) {
+            notifications.send(NotificationEvent::warning("Blueprint overlaps existing blocks, move it before placing"));
+            return;
+        }
+
+        // One event batch and one undo step for the whole paste
+        let mut edits = Vec::new();
+        for (pos, block) in cells {
+            voxel_world.set_block(pos, block);
+            let (chunk_key, voxel_pos) = voxel_world.to_chunk_local(pos);
+            edits.push(VoxelEdit { chunk_key, voxel_pos, old: AIR, new: block });
+        }
+        notifications.send(NotificationEvent::info(format!("Placed blueprint, {} block(s)", edits.len())));
+        history.record(edits);
+        placement.active = false;
+    }
+}
+
+// Loaded and empty
+fn cell_is_free(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    voxel_world.get_block(pos) == Some(AIR)
+}
+
+// Snaps the ghost to the grid cell in front of the targeted face and rebuilds its mesh
+// whenever the anchor, rotation or overlap state changes
+pub fn update_blueprint_ghost(
+    mut commands: Commands,
+    voxel_world: VoxelWorld,
+    targeted_block: Res<TargetedBlock>,
+    mut placement: ResMut<BlueprintPlacement>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ghost_query: Query<(&mut Handle<Mesh>, &mut Transform, &mut Visibility), Without<VoxelRemover>>,
+) {
+    let target = targeted_block.place_at;
+
+    let ghost_entity = match placement.ghost {
+        Some(entity) => entity,
+        None => {
+            let material = materials.add(StandardMaterial {
+                base_color: Color::WHITE,
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            });
+            let entity = commands.spawn(PbrBundle {
+                material,
+                visibility: Visibility::Hidden,
+                ..default()
+            }).id();
+            placement.ghost = Some(entity);
+            return;
+        }
+    };
+    let Ok((mut mesh_handle, mut transform, mut visibility)) = ghost_query.get_mut(ghost_entity) else {
+        return;
+    };
+
+    let (Some(blueprint), Some(anchor), true) = (placement.blueprint.as_ref(), target, placement.active) else {
+        *visibility = Visibility::Hidden;
+        placement.anchor = None;
+        return;
+    };
+
+    let cells: Vec<_> = blueprint.rotated_cells(placement.rotation).collect();
+    let invalid: Vec<bool> = cells.iter().map(|&(pos, _)| !cell_is_free(&voxel_world, anchor + pos)).collect();
+    let state = (anchor, placement.rotation, invalid.iter().filter(|&&bad| bad).count());
+
+    *visibility = Visibility::Visible;
+    transform.translation = anchor.as_vec3();
+    placement.anchor = Some(anchor);
+    if placement.ghost_state == Some(state) {
+        return;
+    }
+    placement.ghost_state = Some(state);
+
+    let colored_cells = cells.iter().zip(&invalid).map(|(&(pos, block), &bad)| {
+        let color = if bad {
+            INVALID_COLOR
+        } else {
+            let [r, g, b] = block::variant_color(block, 0);
+            [r, g, b, GHOST_ALPHA]
+        };
+        (pos, color)
+    });
+    *mesh_handle = meshes.add(ghost_mesh(colored_cells));
+}
+
+// Slightly inflated, vertex-coloured cubes so the ghost doesn't z-fight with neighbours
+fn ghost_mesh(cells: impl Iterator<Item = (IVec3, [f32; 4])>) -> Mesh {
+    const FACES: [([f32; 3], [[f32; 3]; 4]); 6] = [
+        ([1.0, 0.0, 0.0], [[1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0], [1.0, 0.0, 1.0]]),
+        ([-1.0, 0.0, 0.0], [[0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]]),
+        ([0.0, 1.0, 0.0], [[0.0, 1.0, 0.0], [0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 0.0]]),
+        ([0.0, -1.0, 0.0], [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0]]),
+        ([0.0, 0.0, 1.0], [[1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0], [0.0, 0.0, 1.0]]),
+        ([0.0, 0.0, -1.0], [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [1.0, 0.0, 0.0]]),
+    ];
+    const INFLATE: f32 = 0.01;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+
+    for (cell, color) in cells {
+        let base = cell.as_vec3();
+        for (normal, corners) in FACES {
+            let start = positions.len() as u32;
+            for corner in corners {
+                let corner = Vec3::from(corner);
+                let position = base + corner + (corner - Vec3::splat(0.5)).signum() * INFLATE;
+                positions.push(position.to_array());
+                normals.push(normal);
+                colors.push(color);
+            }
+            indices.extend_from_slice(&[start, start + 1, start + 2, start, start + 2, start + 3]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, Default::default());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_indices(Indices::U32(indices));
+    mesh
+}
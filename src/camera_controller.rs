@@ -0,0 +1,259 @@
+use std::path::Path;
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow, WindowFocused};
+use crate::console::{Console, ConsoleCommand};
+use crate::keybindings::{Action, Actions, KeyBindings};
+use crate::notifications::NotificationEvent;
+use crate::theme::Theme;
+use crate::player::{GameMode, Noclip, PlayerBody};
+use crate::settings::MovementSettings;
+use crate::{CAMERA_SENSITIVITY, FLY_SPEED_TIERS, MOVEMENT_SETTINGS_FILE};
+
+// Most of a right angle, so looking straight up or down never flips the camera
+const MAX_PITCH: f32 = 1.54;
+
+// First-person camera: mouse look plus WASD / Space / Shift movement with smoothed velocity,
+// at the MovementSettings speed for the game mode and whether Sprint is held.
+// Collision is applied afterwards by player::apply_player_physics, which also zeroes the
+// velocity along any axis it blocked so the camera doesn't keep pushing into walls.
+#[derive(Component)]
+pub struct CameraController {
+    // Radians per pixel of mouse motion
+    pub sensitivity: f32,
+    pub velocity: Vec3,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            sensitivity: CAMERA_SENSITIVITY,
+            velocity: Vec3::ZERO,
+        }
+    }
+}
+
+// Pause menu; while it is open the cursor is free and game input is ignored
+#[derive(Resource, Default)]
+pub struct PauseMenu {
+    pub open: bool,
+}
+
+#[derive(Component)]
+pub struct PauseMenuRoot;
+
+pub fn spawn_pause_menu(mut commands: Commands, theme: Res<Theme>) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            background_color: theme.hud_background.into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        PauseMenuRoot,
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("Paused", TextStyle { font_size: 40.0, color: theme.hud_text, ..default() }));
+        parent.spawn(TextBundle::from_section(
+            "Click or press Esc to resume",
+            TextStyle { font_size: 18.0, color: theme.hud_text, ..default() },
+        ));
+    });
+}
+
+// The Pause action toggles the menu, clicking back into the game resumes, and losing window focus pauses.
+// Runs before console_input so the Escape that closes the console doesn't also pause.
+pub fn toggle_pause_menu(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut mouse_button_input: ResMut<ButtonInput<MouseButton>>,
+    bindings: Res<KeyBindings>,
+    mut focus_events: EventReader<WindowFocused>,
+    console: Res<Console>,
+    mut pause: ResMut<PauseMenu>,
+) {
+    if focus_events.read().any(|event| !event.focused) {
+        pause.open = true;
+    }
+    if console.open {
+        return;
+    }
+    if bindings.just_pressed(Action::Pause, &keyboard_input, &mouse_button_input) {
+        pause.open = !pause.open;
+    } else if pause.open && mouse_button_input.just_pressed(MouseButton::Left) {
+        pause.open = false;
+        // The click only resumes; it shouldn't also break a block
+        mouse_button_input.reset_all();
+    }
+}
+
+pub fn suppress_game_input_while_paused(
+    pause: Res<PauseMenu>,
+    mut keyboard_input: ResMut<ButtonInput<KeyCode>>,
+    mut mouse_button_input: ResMut<ButtonInput<MouseButton>>,
+) {
+    if pause.open {
+        keyboard_input.reset_all();
+        mouse_button_input.reset_all();
+    }
+}
+
+// The cursor is grabbed exactly while the game is being played
+pub fn apply_cursor_grab(
+    pause: Res<PauseMenu>,
+    console: Res<Console>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    mut menu_query: Query<&mut Visibility, With<PauseMenuRoot>>,
+) {
+    if !pause.is_changed() && !console.is_changed() {
+        return;
+    }
+
+    let grabbed = !pause.open && !console.open;
+    if let Ok(mut window) = window_query.get_single_mut() {
+        window.cursor.grab_mode = if grabbed { CursorGrabMode::Confined } else { CursorGrabMode::None };
+        window.cursor.visible = !grabbed;
+    }
+    for mut visibility in &mut menu_query {
+        *visibility = if pause.open { Visibility::Visible } else { Visibility::Hidden };
+    }
+}
+
+fn cursor_grabbed(window_query: &Query<&Window, With<PrimaryWindow>>) -> bool {
+    window_query.get_single().is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None)
+}
+
+pub fn camera_look(
+    mut motion_events: EventReader<MouseMotion>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&CameraController, &mut Transform)>,
+) {
+    let delta: Vec2 = motion_events.read().map(|event| event.delta).sum();
+    if delta == Vec2::ZERO || !cursor_grabbed(&window_query) {
+        return;
+    }
+
+    for (controller, mut transform) in &mut camera_query {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        let yaw = yaw - delta.x * controller.sensitivity;
+        let pitch = (pitch - delta.y * controller.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        // Yaw around world up, then pitch, so the camera never rolls
+        transform.rotation = Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch);
+    }
+}
+
+pub fn camera_move(
+    actions: Actions,
+    time: Res<Time>,
+    movement: Res<MovementSettings>,
+    game_mode: Res<GameMode>,
+    noclip: Res<Noclip>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&mut CameraController, &mut Transform, Has<PlayerBody>)>,
+) {
+    let dt = time.delta_seconds();
+    let grabbed = cursor_grabbed(&window_query);
+    let flying = *game_mode == GameMode::Flying || noclip.enabled;
+    let sprinting = grabbed && actions.pressed(Action::Sprint);
+
+    for (mut controller, mut transform, has_body) in &mut camera_query {
+        // A camera without the player's body (spectating) always flies
+        let speed = movement.speed(flying || !has_body, sprinting);
+        // Horizontal movement follows the view direction flattened onto the ground plane
+        let forward = transform.forward().with_y(0.0).normalize_or_zero();
+        let right = transform.right().with_y(0.0).normalize_or_zero();
+
+        let mut direction = Vec3::ZERO;
+        if grabbed {
+            direction += forward * actions.axis(Action::MoveForward, Action::MoveBack);
+            direction += right * actions.axis(Action::MoveRight, Action::MoveLeft);
+            direction += Vec3::Y * actions.axis(Action::Ascend, Action::Descend);
+        }
+
+        let target = direction.normalize_or_zero() * speed;
+        // Letting go or dropping out of sprint brakes at the deceleration rate
+        let rate = if target.length_squared() >= controller.velocity.length_squared() {
+            movement.acceleration
+        } else {
+            movement.deceleration
+        };
+        let blend = 1.0 - (-rate * dt).exp();
+        controller.velocity = controller.velocity.lerp(target, blend);
+        if controller.velocity.length_squared() < 1e-6 {
+            controller.velocity = Vec3::ZERO;
+        }
+        transform.translation += controller.velocity * dt;
+    }
+}
+
+// Modifier + mouse wheel steps the flying speed through FLY_SPEED_TIERS; the hotbar ignores the
+// wheel meanwhile
+pub fn change_fly_speed(
+    actions: Actions,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut movement: ResMut<MovementSettings>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if !actions.pressed(Action::SpeedModifier) {
+        return;
+    }
+    let scroll: f32 = mouse_wheel_events.read().map(|event| event.y).sum();
+    if scroll == 0.0 {
+        return;
+    }
+
+    // The speed may have been set to something in between with `/movement`
+    let current = movement.fly_speed;
+    let next = if scroll > 0.0 {
+        FLY_SPEED_TIERS.into_iter().find(|&speed| speed > current)
+    } else {
+        FLY_SPEED_TIERS.into_iter().rev().find(|&speed| speed < current)
+    };
+    let Some(speed) = next else {
+        return;
+    };
+    movement.fly_speed = speed;
+    let tier = FLY_SPEED_TIERS.iter().position(|&tier| tier == speed).unwrap_or(0);
+    notifications.send(NotificationEvent::info(format!("Fly speed {} ({} voxels/s)", tier + 1, speed)));
+    if let Err(err) = movement.save(Path::new(MOVEMENT_SETTINGS_FILE)) {
+        println!("Could not save movement settings: {}", err);
+    }
+}
+
+pub fn movement_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut movement: ResMut<MovementSettings>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "movement") {
+        let Some(name) = command.args.first() else {
+            for name in MovementSettings::NAMES {
+                console.print(format!("{} = {}", name, movement.get(name).unwrap()));
+            }
+            continue;
+        };
+        let Some(current) = movement.get(name) else {
+            console.print(format!("Unknown movement setting '{}', expected one of {}", name, MovementSettings::NAMES.join(", ")));
+            continue;
+        };
+
+        match command.args.get(1).map(|value| value.parse::<f32>()) {
+            None => console.print(format!("{} = {}", name, current)),
+            Some(Ok(value)) if value.is_finite() && value >= 0.0 => {
+                *movement.get_mut(name).unwrap() = value;
+                console.print(format!("{} set to {}", name, value));
+                if let Err(err) = movement.save(Path::new(MOVEMENT_SETTINGS_FILE)) {
+                    console.print(format!("Could not save movement settings: {}", err));
+                }
+            }
+            Some(_) => console.print(format!("Movement settings are non-negative numbers, got '{}'", command.args[1])),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy::window::PrimaryWindow;
+use futures::FutureExt;
+use crate::console::{Console, ConsoleCommand};
+use crate::gif::{self, GifFrame};
+use crate::keybindings::{Action, Actions};
+use crate::notifications::NotificationEvent;
+use crate::tasks::{self, BackgroundTask};
+use crate::{GIF_FRAME_RATE, GIF_MAX_WIDTH, MAX_RECORDING_SECONDS, SCREENSHOT_DIRECTORY};
+
+// Frames of a running `/record`, in the order they were asked for. Screenshot callbacks run on
+// the compute pool and may finish out of order, so each frame carries its index.
+struct Recording {
+    path: PathBuf,
+    frame_timer: Timer,
+    frames_left: usize,
+    requested: usize,
+    frames: Arc<Mutex<Vec<(usize, GifFrame)>>>,
+}
+
+// `/record <seconds>` captures the window at GIF_FRAME_RATE and writes an animated GIF to
+// SCREENSHOT_DIRECTORY; `/record stop` ends it early. Frames are scaled down and the GIF is
+// encoded off the main thread.
+#[derive(Resource, Default)]
+pub struct Recorder {
+    recording: Option<Recording>,
+    // The GIF being written and how many frames it holds
+    encoding: Option<(PathBuf, BackgroundTask<std::io::Result<usize>>)>,
+}
+
+// UTC date and time for file names, e.g. 2024-05-01_18-30-05
+fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // Howard Hinnant's civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year, month, day, time / 3600, time / 60 % 60, time % 60,
+    )
+}
+
+// A path in SCREENSHOT_DIRECTORY that doesn't exist yet, named after the current time
+fn capture_path(prefix: &str, extension: &str) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(SCREENSHOT_DIRECTORY)?;
+    let stem = format!("{}-{}", prefix, timestamp());
+    let directory = Path::new(SCREENSHOT_DIRECTORY);
+    let mut path = directory.join(format!("{}.{}", stem, extension));
+    let mut count = 2;
+    while path.exists() {
+        path = directory.join(format!("{}-{}.{}", stem, count, extension));
+        count += 1;
+    }
+    Ok(path)
+}
+
+// Box-filters the screenshot down by a whole factor until it is at most GIF_MAX_WIDTH wide
+fn gif_frame(image: Image) -> Option<GifFrame> {
+    let rgb = image.try_into_dynamic().ok()?.to_rgb8();
+    let (width, height) = (rgb.width() as usize, rgb.height() as usize);
+    let factor = width.div_ceil(GIF_MAX_WIDTH).max(1);
+    let (out_width, out_height) = (width / factor, height / factor);
+    let pixels = rgb.into_raw();
+    let mut out = Vec::with_capacity(out_width * out_height * 3);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let mut sum = [0u32; 3];
+            for sy in y * factor..(y + 1) * factor {
+                for sx in x * factor..(x + 1) * factor {
+                    let i = (sy * width + sx) * 3;
+                    for channel in 0..3 {
+                        sum[channel] += pixels[i + channel] as u32;
+                    }
+                }
+            }
+            out.extend(sum.map(|total| (total / (factor * factor) as u32) as u8));
+        }
+    }
+    Some(GifFrame { width: out_width as u16, height: out_height as u16, rgb: out })
+}
+
+pub fn take_screenshot(
+    actions: Actions,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if !actions.just_pressed(Action::Screenshot) {
+        return;
+    }
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    match capture_path("screenshot", "png") {
+        // Written by the renderer once the frame is drawn
+        Ok(path) => match screenshots.save_screenshot_to_disk(window, &path) {
+            Ok(()) => notifications.send(NotificationEvent::info(format!("Screenshot saved to {}", path.display()))),
+            Err(err) => notifications.send(NotificationEvent::warning(err.to_string())),
+        },
+        Err(err) => notifications.send(NotificationEvent::error(format!("Could not save the screenshot: {}", err))),
+    };
+}
+
+pub fn record_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut recorder: ResMut<Recorder>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "record") {
+        match command.args.first().map(String::as_str) {
+            None => match (&recorder.recording, &recorder.encoding) {
+                (Some(recording), _) => console.print(format!("Recording {}, {} frame(s) to go", recording.path.display(), recording.frames_left)),
+                (None, Some((path, _))) => console.print(format!("Writing {}", path.display())),
+                (None, None) => console.print("usage: record <seconds> | record stop"),
+            },
+            Some("stop") => match &mut recorder.recording {
+                Some(recording) => {
+                    recording.frames_left = 0;
+                    console.print("Recording stopped");
+                }
+                None => console.print("Not recording"),
+            },
+            Some(arg) => {
+                let seconds = match arg.parse::<f32>() {
+                    Ok(seconds) if seconds > 0.0 => seconds.min(MAX_RECORDING_SECONDS),
+                    _ => {
+                        console.print(format!("Expected a number of seconds or 'stop', got '{}'", arg));
+                        continue;
+                    }
+                };
+                if recorder.recording.is_some() || recorder.encoding.is_some() {
+                    console.print("Already recording");
+                    continue;
+                }
+                let path = match capture_path("recording", "gif") {
+                    Ok(path) => path,
+                    Err(err) => {
+                        console.print(format!("Could not start recording: {}", err));
+                        continue;
+                    }
+                };
+                let frames = (seconds * GIF_FRAME_RATE).ceil().max(1.0) as usize;
+                console.print(format!("Recording {} second(s) to {}", seconds, path.display()));
+                let mut frame_timer = Timer::from_seconds(1.0 / GIF_FRAME_RATE, TimerMode::Once);
+                // The first frame is taken right away
+                frame_timer.set_elapsed(frame_timer.duration());
+                recorder.recording = Some(Recording { path, frame_timer, frames_left: frames, requested: 0, frames: default() });
+            }
+        }
+    }
+}
+
+// Asks for a frame every tick of the recording's timer, hands the frames to the encoder once
+// they have all arrived and reports the written GIF
+pub fn capture_gif_frames(
+    time: Res<Time>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    mut recorder: ResMut<Recorder>,
+    mut console: ResMut<Console>,
+) {
+    if let Some((path, task)) = &mut recorder.encoding {
+        let mut context = Context::from_waker(futures::task::noop_waker_ref());
+        if let Poll::Ready(result) = Pin::new(task).poll_unpin(&mut context) {
+            match result {
+                Ok(frames) => console.print(format!("Saved {} frame(s) to {}", frames, path.display())),
+                Err(err) => console.print(format!("Could not write {}: {}", path.display(), err)),
+            }
+            recorder.encoding = None;
+        }
+    }
+
+    let Some(recording) = &mut recorder.recording else {
+        return;
+    };
+    if recording.frames_left > 0 {
+        let Ok(window) = window_query.get_single() else {
+            return;
+        };
+        if recording.frame_timer.tick(time.delta()).finished() {
+            let index = recording.requested;
+            let frames = recording.frames.clone();
+            // Fails if something else asked for this frame already; the timer stays finished
+            // so the next frame is tried instead
+            let requested = screenshots.take_screenshot(window, move |image| {
+                if let Some(frame) = gif_frame(image) {
+                    frames.lock().unwrap().push((index, frame));
+                }
+            });
+            if requested.is_ok() {
+                recording.frame_timer.reset();
+                recording.requested += 1;
+                recording.frames_left -= 1;
+            }
+        }
+        return;
+    }
+    // A frame that couldn't be converted never arrives; don't wait for it forever
+    if Arc::strong_count(&recording.frames) > 1 {
+        return;
+    }
+
+    let Some(Recording { path, frames, .. }) = recorder.recording.take() else {
+        return;
+    };
+    let mut frames = std::mem::take(&mut *frames.lock().unwrap());
+    let task_path = path.clone();
+    let task = tasks::spawn_compute(async move {
+        frames.sort_unstable_by_key(|(index, _)| *index);
+        let frames: Vec<GifFrame> = frames.into_iter().map(|(_, frame)| frame).collect();
+        let delay = (100.0 / GIF_FRAME_RATE).round() as u16;
+        fs::write(&task_path, gif::encode(&frames, delay))?;
+        Ok(frames.len())
+    });
+    recorder.encoding = Some((path, task));
+}
@@ -0,0 +1,156 @@
+use noise::{NoiseFn, Perlin};
+use crate::block::{self, AIR, GLOWSTONE, LAVA, STONE};
+use crate::terrain::Chunk;
+use crate::worldgen::{mix_seed, GenContext, GenStage, OreStage, WorldGenConfig, WorldGenerator};
+
+const CAVE_SEED: u32 = 0xca5e_0001;
+const CAVE_FREQUENCY: f64 = 0.025;
+// Caverns are stretched sideways, so they read as wide halls rather than round bubbles
+const CAVE_VERTICAL_SQUASH: f64 = 1.6;
+// World-space floor and ceiling of the cave layer. Everything outside is solid rock.
+const CAVE_FLOOR_Y: i32 = -48;
+const CAVE_CEILING_Y: i32 = 80;
+// Voxels over which the rock thickens towards the floor and ceiling
+const CAVE_SHELL: f32 = 24.0;
+// Below this every open cell fills with lava, exclusive
+const LAVA_LEVEL: i32 = -28;
+const GLOW_SEED: u32 = 0x6104_5eed;
+// Chance per ceiling cell of a glowstone cluster hanging from it
+const GLOW_CHANCE: f32 = 0.012;
+const GLOW_MAX_LENGTH: u32 = 4;
+// Kept open around the spawn point so new players don't start inside rock
+const SPAWN_CAVERN_CENTRE: [f32; 3] = [10.0, 20.0, 10.0];
+const SPAWN_CAVERN_RADIUS: f32 = 8.0;
+
+// Names of the cave stages, for WorldGenerator::insert_before / insert_after
+pub const CAVE_TERRAIN_STAGE: &str = "cave_terrain";
+pub const LAVA_STAGE: &str = "lava";
+pub const GLOW_STAGE: &str = "glow";
+
+// Rock from 3D noise, with a floor and a ceiling closing off the layer. Terrain here has no
+// surface, so every chunk in the layer is a mix of rock and open space.
+pub struct CaveTerrainStage {
+    noise: Perlin,
+}
+
+impl CaveTerrainStage {
+    pub fn new(world_seed: u32) -> Self {
+        Self { noise: Perlin::new(mix_seed(CAVE_SEED, world_seed)) }
+    }
+
+    // Above 0 is rock
+    fn density(&self, world_x: i32, world_y: i32, world_z: i32) -> f32 {
+        if world_y <= CAVE_FLOOR_Y || world_y >= CAVE_CEILING_Y {
+            return 1.0;
+        }
+        let value = self.noise.get([
+            world_x as f64 * CAVE_FREQUENCY,
+            world_y as f64 * CAVE_FREQUENCY * CAVE_VERTICAL_SQUASH,
+            world_z as f64 * CAVE_FREQUENCY,
+        ]) as f32;
+        // Rock gets denser towards the floor and the ceiling, so they close up smoothly
+        let shell_distance = (world_y - CAVE_FLOOR_Y).min(CAVE_CEILING_Y - world_y) as f32;
+        let shell = (1.0 - shell_distance / CAVE_SHELL).max(0.0);
+
+        let spawn_distance = [world_x as f32, world_y as f32, world_z as f32].iter()
+            .zip(SPAWN_CAVERN_CENTRE)
+            .map(|(coordinate, centre)| (coordinate - centre).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let spawn = (1.0 - spawn_distance / SPAWN_CAVERN_RADIUS).max(0.0);
+
+        value + 0.1 + shell * 1.5 - spawn * 2.0
+    }
+}
+
+impl GenStage for CaveTerrainStage {
+    fn name(&self) -> &str {
+        CAVE_TERRAIN_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        let (key_x, key_y, key_z) = context.chunk_key;
+        for x in 0..chunk.width {
+            for y in 0..chunk.height {
+                for z in 0..chunk.depth {
+                    let world_x = key_x * chunk.width as i32 + x as i32;
+                    let world_y = key_y * chunk.height as i32 + y as i32;
+                    let world_z = key_z * chunk.depth as i32 + z as i32;
+                    if self.density(world_x, world_y, world_z) > 0.0 {
+                        chunk.set_block(x, y, z, STONE);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Floods the bottom of the cave layer with lava
+pub struct LavaStage;
+
+impl GenStage for LavaStage {
+    fn name(&self) -> &str {
+        LAVA_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        let base_y = context.chunk_key.1 * chunk.height as i32;
+        let top = (LAVA_LEVEL - base_y).clamp(0, chunk.height as i32) as usize;
+        for x in 0..chunk.width {
+            for y in 0..top {
+                for z in 0..chunk.depth {
+                    if chunk.get_block(x, y, z) == AIR {
+                        chunk.set_block(x, y, z, LAVA);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Glowstone clusters hanging from cave ceilings, the only light above the lava. Clusters are
+// cut off at the bottom of the chunk instead of spilling into the one below.
+pub struct GlowStage;
+
+impl GenStage for GlowStage {
+    fn name(&self) -> &str {
+        GLOW_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        let (key_x, key_y, key_z) = context.chunk_key;
+        for x in 0..chunk.width {
+            for z in 0..chunk.depth {
+                for y in 1..chunk.height {
+                    if chunk.get_block(x, y, z) != STONE || chunk.get_block(x, y - 1, z) != AIR {
+                        continue;
+                    }
+                    let world_x = key_x * chunk.width as i32 + x as i32;
+                    let world_y = key_y * chunk.height as i32 + y as i32;
+                    let world_z = key_z * chunk.depth as i32 + z as i32;
+                    let hash = block::position_hash(world_x, world_y, world_z, mix_seed(GLOW_SEED, context.config.seed));
+                    if (hash as f32 / u32::MAX as f32) >= GLOW_CHANCE {
+                        continue;
+                    }
+
+                    let length = 1 + (hash >> 8) % GLOW_MAX_LENGTH;
+                    for step in 1..=length as usize {
+                        if step > y || chunk.get_block(x, y - step, z) != AIR {
+                            break;
+                        }
+                        chunk.set_block(x, y - step, z, GLOWSTONE);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Generator of the cave dimension: noise caverns, lava seas, ores in the rock and glowstone
+pub fn cave_generator(seed: u32) -> WorldGenerator {
+    WorldGenerator::new(WorldGenConfig { seed, ..Default::default() })
+        .with_stage(CaveTerrainStage::new(seed))
+        .with_stage(LavaStage)
+        .with_stage(OreStage)
+        .with_stage(GlowStage)
+}
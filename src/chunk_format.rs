@@ -0,0 +1,613 @@
+use std::fmt;
+use std::fs;
+use crate::block;
+use crate::block_entity::BlockEntityData;
+use crate::block_ids::BlockIdMap;
+use crate::item_drop::StoredItemDrop;
+use crate::migration::{self, MIGRATIONS};
+use crate::save::ChunkBlob;
+use crate::storage::{ByteReader, ChunkStorage};
+use crate::terrain::Chunk;
+use crate::world::{ChunkPendingBlock, PendingBlock};
+use crate::CHUNK_SIZE;
+
+// On-disk chunk and region format. Everything is little-endian.
+//
+// Chunk blob:
+//   magic        [u8; 4]  "VXFC"
+//   version      u16      FORMAT_VERSION; older blobs are upgraded by migration.rs on load
+//   dims         u16 x3   width, height, depth
+//   chunk key    i32 x3
+//   palette size u16      distinct blocks in the chunk
+//   bits/index   u8       width of a packed palette index
+//   codec        u8       how the payload is encoded, see ChunkCodec
+//   payload len  u32
+//   payload      [u8]     the voxels, encoded by encode_voxels
+//   entity count u16      block entities, absent in blobs written before they existed
+//   entities     (local pos u16 x3, BlockEntityData::encode()) per block entity
+//   drop count   u16      item drops, absent in older blobs
+//   drops        StoredItemDrop::encode() per drop
+//   generator    u32      worldgen::GENERATOR_VERSION of the terrain, absent (0) in older blobs
+//   edit count   u32      the chunk's edit log
+//   edits        (local pos u16 x3, block u16) per edited voxel
+//   tick count   u32      scheduled block ticks, absent in older blobs
+//   ticks        (local pos u16 x3, due WorldClock tick u64) per tick
+//
+// Region file, a container for many chunk blobs:
+//   magic        [u8; 4]  "VXFR"
+//   version      u16      REGION_VERSION, independent of the chunk blobs' own versions
+//   entry count  u32
+//   entries      (chunk key i32 x3, offset u32, length u32) per chunk, offsets from file start
+//   chunk blobs
+//
+// Pending structure file, overflow for a region's chunks that haven't generated yet:
+//   magic        [u8; 4]  "VXFP"
+//   version      u16      PENDING_VERSION
+//   entry count  u32
+//   entries      (chunk key i32 x3, local pos u16 x3, block u16, replace u8) per block
+pub const CHUNK_MAGIC: [u8; 4] = *b"VXFC";
+pub const REGION_MAGIC: [u8; 4] = *b"VXFR";
+pub const PENDING_MAGIC: [u8; 4] = *b"VXFP";
+pub const FORMAT_VERSION: u16 = 2;
+pub const REGION_VERSION: u16 = 1;
+pub const PENDING_VERSION: u16 = 1;
+// Bytes of one pending structure file entry
+const PENDING_ENTRY_LEN: usize = 12 + 6 + 2 + 1;
+
+// zstd level for chunk payloads; chunks are saved and streamed often, so favour speed
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkCodec {
+    // Paletted storage bytes as-is (ChunkStorage::to_bytes), written before compression existed
+    Raw = 0,
+    // Bit-packed palette indices (ChunkStorage::to_bytes), zstd-compressed
+    PackedZstd = 1,
+    // Runs of palette indices (ChunkStorage::to_run_bytes), zstd-compressed
+    RunsZstd = 2,
+}
+
+impl ChunkCodec {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ChunkCodec::Raw),
+            1 => Some(ChunkCodec::PackedZstd),
+            2 => Some(ChunkCodec::RunsZstd),
+            _ => None,
+        }
+    }
+}
+
+// The canonical voxel encoding, shared by saves and the network protocol: palette header with
+// either bit-packed indices or runs, whichever is smaller for this chunk, then zstd
+pub fn encode_voxels(storage: &ChunkStorage) -> (ChunkCodec, Vec<u8>) {
+    let packed = storage.to_bytes();
+    let runs = storage.to_run_bytes();
+    let (codec, bytes) = if runs.len() < packed.len() {
+        (ChunkCodec::RunsZstd, &runs)
+    } else {
+        (ChunkCodec::PackedZstd, &packed)
+    };
+    match zstd::bulk::compress(bytes, ZSTD_LEVEL) {
+        Ok(compressed) if compressed.len() < packed.len() => (codec, compressed),
+        // Uniform chunks are a few bytes already, zstd's frame would only add to them
+        _ => (ChunkCodec::Raw, packed),
+    }
+}
+
+// `len` is the voxel count of the chunk the payload belongs to, width * height * depth
+pub fn decode_voxels(codec: ChunkCodec, payload: &[u8], len: usize) -> Result<ChunkStorage, FormatError> {
+    let storage = match codec {
+        ChunkCodec::Raw => ChunkStorage::from_bytes(payload, len),
+        ChunkCodec::PackedZstd => ChunkStorage::from_bytes(&decompress(payload, len)?, len),
+        ChunkCodec::RunsZstd => ChunkStorage::from_run_bytes(&decompress(payload, len)?, len),
+    };
+    storage.ok_or(FormatError::CorruptPayload)
+}
+
+// Refuses to inflate past the largest encoding `len` voxels can have, so a small corrupt or
+// hostile payload can't claim gigabytes
+fn decompress(payload: &[u8], len: usize) -> Result<Vec<u8>, FormatError> {
+    zstd::bulk::decompress(payload, max_voxel_bytes(len)).map_err(|_| FormatError::CorruptPayload)
+}
+
+// Headers plus a full u16 palette plus the larger of 16-bit packed indices (2 bytes a voxel) and
+// one run per voxel (4 bytes a voxel)
+fn max_voxel_bytes(len: usize) -> usize {
+    16 + len.min(u16::MAX as usize) * 2 + len * 4
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(std::io::Error),
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u16),
+    UnknownCodec(u8),
+    Truncated,
+    CorruptPayload,
+    // A chunk blob whose width, height and depth aren't CHUNK_SIZE
+    WrongDimensions((u16, u16, u16)),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Io(err) => write!(f, "io error: {}", err),
+            FormatError::BadMagic(magic) => write!(f, "unrecognised magic {:?}", String::from_utf8_lossy(magic)),
+            FormatError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            FormatError::UnknownCodec(codec) => write!(f, "unknown codec {}", codec),
+            FormatError::Truncated => write!(f, "data ends early"),
+            FormatError::CorruptPayload => write!(f, "payload does not decode"),
+            FormatError::WrongDimensions((width, height, depth)) => write!(f, "chunk is {}x{}x{}, expected {}", width, height, depth, CHUNK_SIZE),
+        }
+    }
+}
+
+impl From<std::io::Error> for FormatError {
+    fn from(err: std::io::Error) -> Self {
+        FormatError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChunkHeader {
+    pub version: u16,
+    pub dims: (u16, u16, u16),
+    pub chunk_key: (i32, i32, i32),
+    pub palette_size: u16,
+    pub bits_per_index: u8,
+    pub codec: ChunkCodec,
+    pub payload_len: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct RegionEntry {
+    pub chunk_key: (i32, i32, i32),
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl RegionEntry {
+    // The entry's blob within the region file's `bytes`, None if it reaches past their end
+    pub fn blob<'a>(&self, bytes: &'a [u8]) -> Option<&'a [u8]> {
+        let start = self.offset as usize;
+        bytes.get(start..start.checked_add(self.length as usize)?)
+    }
+}
+
+pub fn encode_chunk(chunk_key: (i32, i32, i32), chunk: &Chunk) -> Vec<u8> {
+    encode_chunk_with_ids(chunk_key, chunk, &BlockIdMap::default())
+}
+
+// Like encode_chunk, with every block written under a world's saved ID
+pub fn encode_chunk_with_ids(chunk_key: (i32, i32, i32), chunk: &Chunk, ids: &BlockIdMap) -> Vec<u8> {
+    let mut storage = chunk.voxels.clone();
+    if !ids.is_identity() {
+        storage.remap(|block| ids.to_saved(block));
+    }
+    storage.compact();
+    let (codec, payload) = encode_voxels(&storage);
+
+    let mut bytes = Vec::with_capacity(32 + payload.len());
+    bytes.extend_from_slice(&CHUNK_MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    for dim in [chunk.width, chunk.height, chunk.depth] {
+        bytes.extend_from_slice(&(dim as u16).to_le_bytes());
+    }
+    for coord in [chunk_key.0, chunk_key.1, chunk_key.2] {
+        bytes.extend_from_slice(&coord.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(storage.palette().len() as u16).to_le_bytes());
+    bytes.push(storage.bits_per_index() as u8);
+    bytes.push(codec as u8);
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+
+    bytes.extend_from_slice(&(chunk.block_entities.len() as u16).to_le_bytes());
+    for (&(x, y, z), data) in &chunk.block_entities {
+        for coord in [x, y, z] {
+            bytes.extend_from_slice(&(coord as u16).to_le_bytes());
+        }
+        data.encode(&mut bytes);
+    }
+
+    // Drops past what the u16 count holds are left out
+    let item_drops = &chunk.item_drops[..chunk.item_drops.len().min(u16::MAX as usize)];
+    bytes.extend_from_slice(&(item_drops.len() as u16).to_le_bytes());
+    for drop in item_drops {
+        StoredItemDrop { block: ids.to_saved(drop.block), ..*drop }.encode(&mut bytes);
+    }
+
+    bytes.extend_from_slice(&chunk.generator_version.to_le_bytes());
+    bytes.extend_from_slice(&(chunk.edits.len() as u32).to_le_bytes());
+    for (&(x, y, z), &block) in &chunk.edits {
+        for value in [x as u16, y as u16, z as u16, ids.to_saved(block)] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    bytes.extend_from_slice(&(chunk.scheduled_ticks.len() as u32).to_le_bytes());
+    for (due, (x, y, z)) in chunk.scheduled_ticks.iter() {
+        for coord in [x, y, z] {
+            bytes.extend_from_slice(&(coord as u16).to_le_bytes());
+        }
+        bytes.extend_from_slice(&due.to_le_bytes());
+    }
+
+    bytes.extend_from_slice(&(chunk.block_states.len() as u32).to_le_bytes());
+    for (&(x, y, z), &state) in &chunk.block_states {
+        for coord in [x, y, z] {
+            bytes.extend_from_slice(&(coord as u16).to_le_bytes());
+        }
+        bytes.push(state);
+    }
+    bytes
+}
+
+fn read_magic(reader: &mut ByteReader, expected: [u8; 4], expected_version: u16) -> Result<(), FormatError> {
+    let magic: [u8; 4] = reader.take_slice(4).ok_or(FormatError::Truncated)?.try_into().unwrap();
+    if magic != expected {
+        return Err(FormatError::BadMagic(magic));
+    }
+    let version = reader.read_u16().ok_or(FormatError::Truncated)?;
+    if version != expected_version {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+pub fn read_chunk_header(reader: &mut ByteReader) -> Result<ChunkHeader, FormatError> {
+    read_magic(reader, CHUNK_MAGIC, FORMAT_VERSION)?;
+    let truncated = || FormatError::Truncated;
+
+    let dims = (
+        reader.read_u16().ok_or_else(truncated)?,
+        reader.read_u16().ok_or_else(truncated)?,
+        reader.read_u16().ok_or_else(truncated)?,
+    );
+    // Every buffer of the chunk is sized from these, so a blob claiming more is refused before
+    // anything is allocated for it
+    if dims != (CHUNK_SIZE as u16, CHUNK_SIZE as u16, CHUNK_SIZE as u16) {
+        return Err(FormatError::WrongDimensions(dims));
+    }
+    let chunk_key = (
+        reader.read_i32().ok_or_else(truncated)?,
+        reader.read_i32().ok_or_else(truncated)?,
+        reader.read_i32().ok_or_else(truncated)?,
+    );
+    let palette_size = reader.read_u16().ok_or_else(truncated)?;
+    let bits_per_index = reader.read_u8().ok_or_else(truncated)?;
+    let codec_byte = reader.read_u8().ok_or_else(truncated)?;
+    let codec = ChunkCodec::from_u8(codec_byte).ok_or(FormatError::UnknownCodec(codec_byte))?;
+    let payload_len = reader.read_u32().ok_or_else(truncated)?;
+
+    Ok(ChunkHeader { version: FORMAT_VERSION, dims, chunk_key, palette_size, bits_per_index, codec, payload_len })
+}
+
+// The header's version is the one the blob was stored with, before any migration
+pub fn decode_chunk(bytes: &[u8]) -> Result<(ChunkHeader, Chunk), FormatError> {
+    decode_chunk_with_ids(bytes, &BlockIdMap::default())
+}
+
+// Like decode_chunk, for a blob whose blocks are stored under a world's saved IDs
+pub fn decode_chunk_with_ids(bytes: &[u8], ids: &BlockIdMap) -> Result<(ChunkHeader, Chunk), FormatError> {
+    let stored_version = migration::blob_version(bytes)?;
+    let bytes = migration::migrate_blob(bytes)?;
+    let mut reader = ByteReader::new(&bytes);
+    let mut header = read_chunk_header(&mut reader)?;
+    header.version = stored_version;
+    let payload = reader.take_slice(header.payload_len as usize).ok_or(FormatError::Truncated)?;
+
+    let (width, height, depth) = (header.dims.0 as usize, header.dims.1 as usize, header.dims.2 as usize);
+    let mut storage = decode_voxels(header.codec, payload, width * height * depth)?;
+    if !ids.is_identity() {
+        storage.remap(|block| ids.to_current(block));
+    }
+
+    let mut chunk = Chunk::from_storage(width, height, depth, storage);
+    if reader.remaining() > 0 {
+        let count = reader.read_u16().ok_or(FormatError::Truncated)?;
+        for _ in 0..count {
+            let pos = read_local_pos(&mut reader, (width, height, depth))?;
+            let data = BlockEntityData::decode(&mut reader).ok_or(FormatError::CorruptPayload)?;
+            // Entries for blocks that no longer carry state are stale
+            if block::has_block_entity(chunk.get_block(pos.0, pos.1, pos.2)) {
+                chunk.block_entities.insert(pos, data);
+            }
+        }
+    }
+    if reader.remaining() > 0 {
+        let count = reader.read_u16().ok_or(FormatError::Truncated)?;
+        for _ in 0..count {
+            let drop = StoredItemDrop::decode(&mut reader).ok_or(FormatError::Truncated)?;
+            chunk.item_drops.push(StoredItemDrop { block: ids.to_current(drop.block), ..drop });
+        }
+    }
+    if reader.remaining() > 0 {
+        chunk.generator_version = reader.read_u32().ok_or(FormatError::Truncated)?;
+        // At most one edit per voxel
+        let count = reader.read_u32().ok_or(FormatError::Truncated)? as usize;
+        if count > width * height * depth {
+            return Err(FormatError::CorruptPayload);
+        }
+        for _ in 0..count {
+            let pos = read_local_pos(&mut reader, (width, height, depth))?;
+            let block = reader.read_u16().ok_or(FormatError::Truncated)?;
+            chunk.edits.insert(pos, ids.to_current(block));
+        }
+    }
+    if reader.remaining() > 0 {
+        // At most one tick per voxel
+        let count = reader.read_u32().ok_or(FormatError::Truncated)? as usize;
+        if count > width * height * depth {
+            return Err(FormatError::CorruptPayload);
+        }
+        for _ in 0..count {
+            let pos = read_local_pos(&mut reader, (width, height, depth))?;
+            let due = reader.read_u64().ok_or(FormatError::Truncated)?;
+            chunk.scheduled_ticks.schedule(pos, due);
+        }
+    }
+    if reader.remaining() > 0 {
+        // At most one state per voxel
+        let count = reader.read_u32().ok_or(FormatError::Truncated)? as usize;
+        if count > width * height * depth {
+            return Err(FormatError::CorruptPayload);
+        }
+        for _ in 0..count {
+            let pos = read_local_pos(&mut reader, (width, height, depth))?;
+            let state = reader.read_u8().ok_or(FormatError::Truncated)?;
+            chunk.set_state(pos.0, pos.1, pos.2, state);
+        }
+    }
+
+    Ok((header, chunk))
+}
+
+// A voxel position inside a chunk of `dims`; anything outside it means the blob is corrupt
+fn read_local_pos(reader: &mut ByteReader, dims: (usize, usize, usize)) -> Result<(usize, usize, usize), FormatError> {
+    let pos = (
+        reader.read_u16().ok_or(FormatError::Truncated)? as usize,
+        reader.read_u16().ok_or(FormatError::Truncated)? as usize,
+        reader.read_u16().ok_or(FormatError::Truncated)? as usize,
+    );
+    if pos.0 >= dims.0 || pos.1 >= dims.1 || pos.2 >= dims.2 {
+        return Err(FormatError::CorruptPayload);
+    }
+    Ok(pos)
+}
+
+pub fn encode_region(chunks: &[ChunkBlob]) -> Vec<u8> {
+    let table_len = 4 + 2 + 4 + chunks.len() * 20;
+    let mut bytes = Vec::with_capacity(table_len + chunks.iter().map(|(_, blob)| blob.len()).sum::<usize>());
+    bytes.extend_from_slice(&REGION_MAGIC);
+    bytes.extend_from_slice(&REGION_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+
+    let mut offset = table_len as u32;
+    for (chunk_key, blob) in chunks {
+        for coord in [chunk_key.0, chunk_key.1, chunk_key.2] {
+            bytes.extend_from_slice(&coord.to_le_bytes());
+        }
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        bytes.extend_from_slice(&(blob.len() as u32).to_le_bytes());
+        offset += blob.len() as u32;
+    }
+
+    for (_, blob) in chunks {
+        bytes.extend_from_slice(blob);
+    }
+    bytes
+}
+
+pub fn read_region(bytes: &[u8]) -> Result<Vec<RegionEntry>, FormatError> {
+    let mut reader = ByteReader::new(bytes);
+    read_magic(&mut reader, REGION_MAGIC, REGION_VERSION)?;
+    let truncated = || FormatError::Truncated;
+
+    let count = reader.read_u32().ok_or_else(truncated)? as usize;
+    // Each entry takes 20 bytes, so a corrupt count can't reserve more than the file could hold
+    let mut entries = Vec::with_capacity(count.min(reader.remaining() / 20));
+    for _ in 0..count {
+        let chunk_key = (
+            reader.read_i32().ok_or_else(truncated)?,
+            reader.read_i32().ok_or_else(truncated)?,
+            reader.read_i32().ok_or_else(truncated)?,
+        );
+        let offset = reader.read_u32().ok_or_else(truncated)?;
+        let length = reader.read_u32().ok_or_else(truncated)?;
+        let entry = RegionEntry { chunk_key, offset, length };
+        if entry.blob(bytes).is_none() {
+            return Err(FormatError::Truncated);
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+pub fn encode_pending(blocks: &[ChunkPendingBlock], ids: &BlockIdMap) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(10 + blocks.len() * PENDING_ENTRY_LEN);
+    bytes.extend_from_slice(&PENDING_MAGIC);
+    bytes.extend_from_slice(&PENDING_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+    for (chunk_key, pending) in blocks {
+        for coord in [chunk_key.0, chunk_key.1, chunk_key.2] {
+            bytes.extend_from_slice(&coord.to_le_bytes());
+        }
+        let (x, y, z) = pending.voxel_pos;
+        for value in [x as u16, y as u16, z as u16, ids.to_saved(pending.block)] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.push(pending.replace as u8);
+    }
+    bytes
+}
+
+pub fn decode_pending(bytes: &[u8], ids: &BlockIdMap) -> Result<Vec<ChunkPendingBlock>, FormatError> {
+    let mut reader = ByteReader::new(bytes);
+    read_magic(&mut reader, PENDING_MAGIC, PENDING_VERSION)?;
+    let truncated = || FormatError::Truncated;
+
+    let count = reader.read_u32().ok_or_else(truncated)? as usize;
+    let mut blocks = Vec::with_capacity(count.min(reader.remaining() / PENDING_ENTRY_LEN));
+    for _ in 0..count {
+        let chunk_key = (
+            reader.read_i32().ok_or_else(truncated)?,
+            reader.read_i32().ok_or_else(truncated)?,
+            reader.read_i32().ok_or_else(truncated)?,
+        );
+        let voxel_pos = (
+            reader.read_u16().ok_or_else(truncated)? as usize,
+            reader.read_u16().ok_or_else(truncated)? as usize,
+            reader.read_u16().ok_or_else(truncated)? as usize,
+        );
+        let block = ids.to_current(reader.read_u16().ok_or_else(truncated)?);
+        let replace = reader.read_u8().ok_or_else(truncated)? != 0;
+        blocks.push((chunk_key, PendingBlock { voxel_pos, block, replace }));
+    }
+    Ok(blocks)
+}
+
+fn describe_chunk(bytes: &[u8], indent: &str) -> Result<(), FormatError> {
+    let (header, chunk) = decode_chunk(bytes)?;
+    println!("{}chunk {:?}", indent, header.chunk_key);
+    println!("{}  version {}, dims {}x{}x{}, codec {:?}", indent, header.version, header.dims.0, header.dims.1, header.dims.2, header.codec);
+    println!("{}  palette {} entries, {} bits/index, payload {} bytes", indent, header.palette_size, header.bits_per_index, header.payload_len);
+    println!("{}  generator version {}, {} edited voxel(s)", indent, chunk.generator_version, chunk.edits.len());
+    for migration in MIGRATIONS.iter().filter(|migration| migration.from >= header.version) {
+        println!("{}  upgraded from version {}: {}", indent, migration.from, migration.description);
+    }
+
+    let palette = chunk.voxels.palette();
+    let mut counts = vec![0usize; palette.len()];
+    for block in chunk.voxels.iter() {
+        if let Some(palette_index) = palette.iter().position(|&b| b == block) {
+            counts[palette_index] += 1;
+        }
+    }
+    for (&block, count) in palette.iter().zip(counts) {
+        println!("{}    {:>3} {:<14} {} voxels", indent, block, block::definition(block).name, count);
+    }
+    Ok(())
+}
+
+// `voxelfun inspect <file>`: dumps a chunk blob or region file
+pub fn inspect(path: &str) -> Result<(), FormatError> {
+    let bytes = fs::read(path)?;
+    println!("{} ({} bytes)", path, bytes.len());
+
+    match bytes.get(0..4) {
+        Some(magic) if magic == REGION_MAGIC => {
+            let entries = read_region(&bytes)?;
+            println!("region, version {}, {} chunk(s)", REGION_VERSION, entries.len());
+            for entry in entries {
+                println!("  entry {:?} at offset {}, {} bytes", entry.chunk_key, entry.offset, entry.length);
+                let blob = entry.blob(&bytes).ok_or(FormatError::Truncated)?;
+                if let Err(err) = describe_chunk(blob, "    ") {
+                    println!("    unreadable: {}", err);
+                }
+            }
+            Ok(())
+        }
+        _ => describe_chunk(&bytes, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{AIR, DIRT, GRASS, STONE};
+
+    const LEN: usize = 16 * 16 * 16;
+
+    fn round_trip(storage: &ChunkStorage, expected_codec: ChunkCodec) {
+        let (codec, payload) = encode_voxels(storage);
+        assert_eq!(codec, expected_codec);
+        let decoded = decode_voxels(codec, &payload, storage.len()).expect("payload decodes");
+        assert!(decoded.iter().eq(storage.iter()));
+    }
+
+    #[test]
+    fn uniform_chunks_stay_raw() {
+        round_trip(&ChunkStorage::filled(LEN, STONE), ChunkCodec::Raw);
+    }
+
+    #[test]
+    fn layered_chunks_use_runs() {
+        let mut storage = ChunkStorage::new(LEN);
+        for index in 0..LEN / 2 {
+            storage.set(index, if index < LEN / 4 { STONE } else { DIRT });
+        }
+        round_trip(&storage, ChunkCodec::RunsZstd);
+    }
+
+    #[test]
+    fn mixed_chunks_use_packed_indices() {
+        let mut storage = ChunkStorage::new(LEN);
+        for index in 0..LEN {
+            storage.set(index, [AIR, STONE, DIRT, GRASS][index % 4]);
+        }
+        round_trip(&storage, ChunkCodec::PackedZstd);
+    }
+
+    #[test]
+    fn zstd_payloads_are_capped_by_the_chunk_size() {
+        let bomb = zstd::bulk::compress(&vec![0; 64 * LEN], ZSTD_LEVEL).unwrap();
+        assert!(decompress(&bomb, LEN).is_err());
+        assert_eq!(decompress(&bomb, 64 * LEN).unwrap().len(), 64 * LEN);
+
+        let (codec, payload) = encode_voxels(&ChunkStorage::filled(LEN, STONE));
+        assert!(decode_voxels(codec, &payload, LEN * 2).is_err());
+    }
+
+    // Offset of the u16 dims, after the magic and the version
+    const DIMS_OFFSET: usize = 6;
+
+    #[test]
+    fn oversized_chunk_headers_are_rejected() {
+        let mut chunk = Chunk::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+        chunk.set_block(1, 2, 3, STONE);
+        let blob = encode_chunk((0, 0, 0), &chunk);
+        let (_, decoded) = decode_chunk(&blob).expect("chunk decodes");
+        assert_eq!(decoded.get_block(1, 2, 3), STONE);
+
+        let mut hostile = blob.clone();
+        hostile[DIMS_OFFSET..DIMS_OFFSET + 6].copy_from_slice(&[0xff; 6]);
+        assert!(matches!(decode_chunk(&hostile), Err(FormatError::WrongDimensions((u16::MAX, u16::MAX, u16::MAX)))));
+
+        let mut flat = blob;
+        flat[DIMS_OFFSET + 2..DIMS_OFFSET + 4].copy_from_slice(&1u16.to_le_bytes());
+        assert!(matches!(decode_chunk(&flat), Err(FormatError::WrongDimensions(_))));
+    }
+
+    // Positions of a chunk's block entities, edits, ticks and states all lie inside it
+    #[test]
+    fn out_of_range_positions_are_rejected() {
+        let mut chunk = Chunk::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+        chunk.set_block(4, 5, 6, STONE);
+        chunk.edits.insert((4, 5, 6), STONE);
+        let blob = encode_chunk((0, 0, 0), &chunk);
+        let (_, decoded) = decode_chunk(&blob).expect("chunk decodes");
+        assert_eq!(decoded.edits.get(&(4, 5, 6)), Some(&STONE));
+
+        // The edit is the last (x, y, z, block) before the empty tick and state counts
+        let edit_offset = blob.len() - 8 - 8;
+        assert_eq!(&blob[edit_offset..edit_offset + 2], &4u16.to_le_bytes());
+        let mut corrupt = blob.clone();
+        corrupt[edit_offset..edit_offset + 2].copy_from_slice(&(CHUNK_SIZE as u16).to_le_bytes());
+        assert!(matches!(decode_chunk(&corrupt), Err(FormatError::CorruptPayload)));
+
+        chunk.set_block(1, 2, 3, block::CHEST);
+        chunk.block_entities.insert((1, 2, 3), BlockEntityData::Chest { items: Vec::new() });
+        let blob = encode_chunk((0, 0, 0), &chunk);
+        let (header, decoded) = decode_chunk(&blob).expect("chunk decodes");
+        assert!(decoded.block_entities.contains_key(&(1, 2, 3)));
+
+        // The first entity's position follows the 32-byte header, the payload and the entity count
+        let entity_offset = 32 + header.payload_len as usize + 2;
+        assert_eq!(&blob[entity_offset..entity_offset + 2], &1u16.to_le_bytes());
+        let mut corrupt = blob;
+        corrupt[entity_offset + 2..entity_offset + 4].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(matches!(decode_chunk(&corrupt), Err(FormatError::CorruptPayload)));
+    }
+}
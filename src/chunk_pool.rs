@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+use crate::terrain::Chunk;
+use crate::CHUNK_POOL_CAPACITY;
+
+// Chunks dropped from World wait here with their contents cleared, and newly generated chunks
+// start from them instead of fresh allocations. Flying around loads and unloads chunks all the
+// time, so this keeps the voxel, palette and instance buffers out of the allocator.
+#[derive(Resource, Default)]
+pub struct ChunkPool {
+    free: Vec<Chunk>,
+    // Chunks handed out from the pool and freshly allocated ones, for the debug overlay
+    pub reused: u64,
+    pub allocated: u64,
+}
+
+impl ChunkPool {
+    // An empty all-air chunk of the given size, recycled if the pool has one
+    pub fn take(&mut self, width: usize, height: usize, depth: usize) -> Chunk {
+        let recycled = self.free.iter()
+            .rposition(|chunk| (chunk.width, chunk.height, chunk.depth) == (width, height, depth));
+        match recycled {
+            Some(index) => {
+                self.reused += 1;
+                self.free.swap_remove(index)
+            }
+            None => {
+                self.allocated += 1;
+                Chunk::new(width, height, depth)
+            }
+        }
+    }
+
+    // Keeps the chunk's buffers for a later take. Once the pool is full the chunk is simply dropped.
+    pub fn recycle(&mut self, mut chunk: Chunk) {
+        if self.free.len() >= CHUNK_POOL_CAPACITY {
+            return;
+        }
+        chunk.clear();
+        self.free.push(chunk);
+    }
+
+    pub fn free_chunks(&self) -> usize {
+        self.free.len()
+    }
+}
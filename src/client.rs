@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+use std::collections::HashSet;
+use std::net::TcpStream;
+use crate::camera_controller::CameraController;
+use crate::chunk_format;
+use crate::net::{ClientMessage, Connection, ServerMessage, DEFAULT_PORT, PROTOCOL_VERSION};
+use crate::notifications::NotificationEvent;
+use crate::player::PlayerBody;
+use crate::voxel_events::VoxelSetEvent;
+use crate::world::World;
+
+// Voxels the camera moves before the server is told its new position
+const POSITION_UPDATE_DISTANCE: f32 = 0.5;
+
+// Plays on a remote server instead of a local world (`voxelfun connect <host[:port]>`). Chunks
+// come from the server rather than worldgen, local edits are applied right away and forwarded,
+// and the server's block changes are applied as they arrive. Nothing is saved locally.
+pub struct ClientPlugin {
+    pub address: String,
+}
+
+impl Plugin for ClientPlugin {
+    // If the server can't be reached the app exits with an error after its first update
+    fn build(&self, app: &mut App) {
+        let address = if self.address.contains(':') {
+            self.address.clone()
+        } else {
+            format!("{}:{}", self.address, DEFAULT_PORT)
+        };
+        let mut connection = match TcpStream::connect(&address).and_then(Connection::new) {
+            Ok(connection) => connection,
+            Err(err) => {
+                eprintln!("Could not connect to {}: {}", address, err);
+                app.world_mut().send_event(AppExit::error());
+                return;
+            }
+        };
+        connection.send(&ClientMessage::Hello { protocol_version: PROTOCOL_VERSION });
+        println!("Connected to {}", address);
+
+        app.insert_resource(Client {
+            connection,
+            welcomed: false,
+            lost: false,
+            requested: HashSet::new(),
+            held: HashSet::new(),
+            sent_position: None,
+        })
+            .add_systems(Startup, disable_local_saves)
+            .add_systems(Update, (
+                receive_server_messages.before(crate::update_chunks),
+                // The server refuses chunks far from the last position it was told
+                request_chunks.after(crate::prioritize_chunks).after(send_position).before(crate::process_chunk_queue),
+                send_position,
+                send_local_edits.after(send_position),
+            ))
+            .add_systems(Last, flush_client);
+    }
+}
+
+#[derive(Resource)]
+pub struct Client {
+    connection: Connection,
+    welcomed: bool,
+    // Reported the closed connection already
+    lost: bool,
+    // Asked for, not received yet
+    requested: HashSet<(i32, i32, i32)>,
+    // Received and subscribed to block changes
+    held: HashSet<(i32, i32, i32)>,
+    // Last eye position told to the server
+    sent_position: Option<Vec3>,
+}
+
+// The server owns the world; local copies of edited chunks are never written
+fn disable_local_saves(mut world: ResMut<World>) {
+    world.save_dir = None;
+}
+
+fn receive_server_messages(
+    mut client: ResMut<Client>,
+    mut world: ResMut<World>,
+    mut notifications: EventWriter<NotificationEvent>,
+    mut player_query: Query<(&mut Transform, &mut PlayerBody, Option<&mut CameraController>)>,
+    mut commands: Commands,
+) {
+    let mut edited = Vec::new();
+
+    for message in client.connection.receive::<ServerMessage>() {
+        match message {
+            ServerMessage::Welcome { chunk_size, spawn, .. } => {
+                if chunk_size as usize != world.chunk_size {
+                    notifications.send(NotificationEvent::error(format!(
+                        "Server uses {}-voxel chunks, this client {}", chunk_size, world.chunk_size,
+                    )));
+                    client.lost = true;
+                    continue;
+                }
+                client.welcomed = true;
+                // The server follows the player from its spawn, not from where the local save left them
+                for (mut transform, mut body, controller) in &mut player_query {
+                    transform.translation = spawn;
+                    body.vertical_velocity = 0.0;
+                    body.last_position = Some(spawn);
+                    if let Some(mut controller) = controller {
+                        controller.velocity = Vec3::ZERO;
+                    }
+                }
+            }
+            ServerMessage::ChunkData { chunk_key, blob } => {
+                if !client.requested.remove(&chunk_key) || world.chunks.contains_key(&chunk_key) {
+                    continue;
+                }
+                match chunk_format::decode_chunk(&blob) {
+                    Ok((header, chunk)) if header.chunk_key == chunk_key => {
+                        world.chunks.insert(chunk_key, chunk);
+                        world.mark_new_chunk(chunk_key);
+                        client.held.insert(chunk_key);
+                    }
+                    _ => {
+                        notifications.send(NotificationEvent::warning(format!("Server sent an unreadable chunk {:?}", chunk_key)));
+                    }
+                }
+            }
+            ServerMessage::BlockChange { pos, block } => {
+                let (chunk_key, voxel_pos) = world.world_to_voxel(pos);
+                if world.set_block(chunk_key, voxel_pos, block).is_some_and(|old| old != block) {
+                    edited.push((chunk_key, voxel_pos));
+                }
+            }
+            // Asked for before the server heard where the player went; asked again while still wanted
+            ServerMessage::ChunkRefused(chunk_key) => {
+                client.requested.remove(&chunk_key);
+            }
+            ServerMessage::Disconnect { reason } => {
+                notifications.send(NotificationEvent::error(format!("Disconnected: {}", reason)));
+                client.lost = true;
+            }
+        }
+    }
+
+    if client.connection.is_closed() && !client.lost {
+        notifications.send(NotificationEvent::error("Lost connection to the server"));
+        client.lost = true;
+    }
+    world.flush_dirty_chunks(&mut commands);
+    if !edited.is_empty() {
+        world.remesh_edited(edited, &mut commands);
+    }
+}
+
+// Turns the local load queue into chunk requests, so nothing is generated locally, and tells
+// the server about chunks that were unloaded
+fn request_chunks(mut client: ResMut<Client>, mut world: ResMut<World>) {
+    let wanted: Vec<_> = world.chunk_load_queue.drain(..).collect();
+    if !client.welcomed || client.lost {
+        return;
+    }
+
+    let client = &mut *client;
+    for chunk_key in wanted {
+        if client.requested.insert(chunk_key) {
+            client.connection.send(&ClientMessage::RequestChunk(chunk_key));
+        }
+    }
+
+    let unloaded: Vec<_> = client.held.iter().filter(|key| !world.chunks.contains_key(key)).copied().collect();
+    for chunk_key in unloaded {
+        client.held.remove(&chunk_key);
+        client.connection.send(&ClientMessage::ForgetChunk(chunk_key));
+    }
+}
+
+// Keeps the server's idea of the player's eye, which it checks edits against, close to the camera
+fn send_position(mut client: ResMut<Client>, camera_query: Query<&Transform, With<CameraController>>) {
+    let Ok(transform) = camera_query.get_single() else {
+        return;
+    };
+    let position = transform.translation;
+    if client.sent_position.is_some_and(|sent| sent.distance(position) < POSITION_UPDATE_DISTANCE) {
+        return;
+    }
+    client.sent_position = Some(position);
+    client.connection.send(&ClientMessage::Position(position));
+}
+
+fn send_local_edits(mut client: ResMut<Client>, mut set_events: EventReader<VoxelSetEvent>, world: Res<World>) {
+    for event in set_events.read() {
+        let pos = world.voxel_to_world(event.chunk_key, event.voxel_pos);
+        client.connection.send(&ClientMessage::SetBlock { pos, block: event.block });
+    }
+}
+
+fn flush_client(mut client: ResMut<Client>) {
+    client.connection.flush();
+}
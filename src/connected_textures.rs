@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+
+// Connected textures: every face looks at its four in-plane neighbours and picks one of 16
+// tiles from a generated atlas. A tile draws a border along each edge whose neighbour is
+// a different block, so a wall of glass only gets a frame around its outline.
+pub const CONNECTED_U_MIN: u8 = 1;
+pub const CONNECTED_U_MAX: u8 = 2;
+pub const CONNECTED_V_MIN: u8 = 4;
+pub const CONNECTED_V_MAX: u8 = 8;
+pub const FULLY_CONNECTED: u8 = 15;
+
+const TILE_SIZE: u32 = 16;
+const BORDER_WIDTH: u32 = 2;
+const ATLAS_TILES_PER_ROW: u32 = 4;
+
+// (u axis, v axis) of each face in mesher order: front, back, left, right, top, bottom
+pub const FACE_AXES: [(usize, usize); 6] = [(0, 1), (0, 1), (1, 2), (1, 2), (0, 2), (0, 2)];
+
+pub fn tile_uv(mask: u8, uv: [f32; 2]) -> [f32; 2] {
+    let tiles = ATLAS_TILES_PER_ROW as f32;
+    let column = (mask as u32 % ATLAS_TILES_PER_ROW) as f32;
+    let row = (mask as u32 / ATLAS_TILES_PER_ROW) as f32;
+    [(column + uv[0]) / tiles, (row + uv[1]) / tiles]
+}
+
+// 4x4 grid of white tiles, darkened along unconnected edges. Block color comes from vertex colors.
+pub fn build_connected_atlas() -> Image {
+    let atlas_size = TILE_SIZE * ATLAS_TILES_PER_ROW;
+    let mut data = vec![0u8; (atlas_size * atlas_size * 4) as usize];
+
+    for mask in 0..16u8 {
+        let tile_x = (mask as u32 % ATLAS_TILES_PER_ROW) * TILE_SIZE;
+        let tile_y = (mask as u32 / ATLAS_TILES_PER_ROW) * TILE_SIZE;
+
+        for py in 0..TILE_SIZE {
+            for px in 0..TILE_SIZE {
+                let border = (mask & CONNECTED_U_MIN == 0 && px < BORDER_WIDTH)
+                    || (mask & CONNECTED_U_MAX == 0 && px >= TILE_SIZE - BORDER_WIDTH)
+                    || (mask & CONNECTED_V_MIN == 0 && py < BORDER_WIDTH)
+                    || (mask & CONNECTED_V_MAX == 0 && py >= TILE_SIZE - BORDER_WIDTH);
+                let shade = if border { 140 } else { 255 };
+
+                let offset = (((tile_y + py) * atlas_size + tile_x + px) * 4) as usize;
+                data[offset..offset + 4].copy_from_slice(&[shade, shade, shade, 255]);
+            }
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d { width: atlas_size, height: atlas_size, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    // Tiles sit right next to each other, linear filtering would bleed borders into neighbours
+    image.sampler = ImageSampler::nearest();
+    image
+}
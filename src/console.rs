@@ -0,0 +1,140 @@
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use crate::keybindings::{Action, KeyBindings};
+#[cfg(feature = "render")]
+use crate::theme::Theme;
+
+const CONSOLE_HISTORY_LINES: usize = 8;
+
+// A submitted console line, e.g. "/gamerule fallDamage false" arrives as
+// name "gamerule" and args ["fallDamage", "false"]. Systems owning a command read these.
+#[derive(Event, Clone, Debug)]
+pub struct ConsoleCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+    pub lines: Vec<String>,
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        let line = line.into();
+        println!("{}", line);
+        self.lines.push(line);
+        if self.lines.len() > CONSOLE_HISTORY_LINES {
+            self.lines.remove(0);
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct ConsoleText;
+
+#[cfg(feature = "render")]
+pub fn spawn_console(mut commands: Commands, theme: Res<Theme>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: theme.hud_text,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(80.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        })
+        .with_background_color(theme.hud_background),
+        Visibility::Hidden,
+        ConsoleText,
+    ));
+}
+
+// Reads typed text while the console is open; the Console action opens it, Enter submits, Escape closes
+pub fn console_input(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut console: ResMut<Console>,
+    mut commands_out: EventWriter<ConsoleCommand>,
+) {
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        if !console.open {
+            if bindings.triggered_by_key(Action::Console, event.key_code, &keyboard_input) {
+                console.open = true;
+                console.input.clear();
+            }
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Enter => {
+                let line = std::mem::take(&mut console.input);
+                console.open = false;
+                let mut words = line.trim().trim_start_matches('/').split_whitespace().map(str::to_string);
+                if let Some(name) = words.next() {
+                    console.print(format!("/{}", line.trim().trim_start_matches('/')));
+                    commands_out.send(ConsoleCommand { name, args: words.collect() });
+                }
+            }
+            Key::Escape => {
+                console.open = false;
+                console.input.clear();
+            }
+            Key::Backspace => {
+                console.input.pop();
+            }
+            Key::Space => console.input.push(' '),
+            Key::Character(text) => console.input.push_str(text),
+            _ => {}
+        }
+    }
+}
+
+// Keeps typing from also flying the camera or triggering hotkeys
+pub fn suppress_game_input_while_typing(
+    console: Res<Console>,
+    mut keyboard_input: ResMut<ButtonInput<KeyCode>>,
+) {
+    if console.open {
+        keyboard_input.reset_all();
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn update_console_text(
+    console: Res<Console>,
+    theme: Res<Theme>,
+    mut text_query: Query<(&mut Text, &mut Visibility, &mut BackgroundColor), With<ConsoleText>>,
+) {
+    if !console.is_changed() && !theme.is_changed() {
+        return;
+    }
+
+    for (mut text, mut visibility, mut background_color) in &mut text_query {
+        *visibility = if console.open { Visibility::Visible } else { Visibility::Hidden };
+        let mut contents = console.lines.join("\n");
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        contents.push_str(&format!("/{}_", console.input));
+        text.sections[0].value = contents;
+        text.sections[0].style.color = theme.hud_text;
+        background_color.0 = theme.hud_background;
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+use crate::keybindings::{Action, Actions};
+use crate::selection::TargetedBlock;
+use crate::theme::Theme;
+
+const CROSSHAIR_SIZE: f32 = 16.0; // pixels
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+
+// Colors the crosshair by whether the block under it can be reached: the primary debug color
+// in reach, the warning color past it, plain HUD text when nothing is hit.
+#[derive(Resource)]
+pub struct ReachIndicator {
+    pub enabled: bool,
+}
+
+impl Default for ReachIndicator {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Component)]
+pub struct CrosshairBar;
+
+// Two bars crossing at the screen center, along the eye ray TargetedBlock is picked with
+pub fn spawn_crosshair(mut commands: Commands, theme: Res<Theme>) {
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(50.0),
+            top: Val::Percent(50.0),
+            width: Val::Px(CROSSHAIR_SIZE),
+            height: Val::Px(CROSSHAIR_SIZE),
+            margin: UiRect {
+                left: Val::Px(-CROSSHAIR_SIZE / 2.0),
+                top: Val::Px(-CROSSHAIR_SIZE / 2.0),
+                ..default()
+            },
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        let offset = (CROSSHAIR_SIZE - CROSSHAIR_THICKNESS) / 2.0;
+        for (width, height, left, top) in [
+            (CROSSHAIR_SIZE, CROSSHAIR_THICKNESS, 0.0, offset),
+            (CROSSHAIR_THICKNESS, CROSSHAIR_SIZE, offset, 0.0),
+        ] {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(left),
+                        top: Val::Px(top),
+                        width: Val::Px(width),
+                        height: Val::Px(height),
+                        ..default()
+                    },
+                    background_color: theme.hud_text.into(),
+                    ..default()
+                },
+                CrosshairBar,
+            ));
+        }
+    });
+}
+
+pub fn toggle_reach_indicator(
+    actions: Actions,
+    mut reach_indicator: ResMut<ReachIndicator>,
+) {
+    if actions.just_pressed(Action::ReachIndicator) {
+        reach_indicator.enabled = !reach_indicator.enabled;
+        println!("Reach indicator {}", if reach_indicator.enabled { "enabled" } else { "disabled" });
+    }
+}
+
+pub fn update_crosshair(
+    target: Res<TargetedBlock>,
+    reach_indicator: Res<ReachIndicator>,
+    theme: Res<Theme>,
+    mut bar_query: Query<&mut BackgroundColor, With<CrosshairBar>>,
+) {
+    if !target.is_changed() && !reach_indicator.is_changed() && !theme.is_changed() {
+        return;
+    }
+
+    let color = if !reach_indicator.enabled {
+        theme.hud_text
+    } else if target.voxel.is_some() {
+        theme.debug_primary
+    } else if target.out_of_reach.is_some() {
+        theme.debug_warning
+    } else {
+        theme.hud_text
+    };
+    for mut background_color in &mut bar_query {
+        background_color.0 = color;
+    }
+}
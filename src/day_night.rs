@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use crate::dimension::Dimension;
+#[cfg(feature = "render")]
+use crate::outline::{ChunkMaterials, OutlinedMaterial};
+#[cfg(feature = "render")]
+use crate::water::TerrainMaterial;
+use crate::DAY_LENGTH_SECONDS;
+#[cfg(feature = "render")]
+use crate::NIGHT_SKYLIGHT;
+
+// Fraction of the day: 0.0 midnight, 0.25 sunrise, 0.5 noon, 0.75 sunset
+#[derive(Resource)]
+pub struct TimeOfDay(pub f32);
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self(0.3)
+    }
+}
+
+// In-game calendar: whole days passed since the world started, advanced with the time of day.
+// `tick` counts fixed-timestep game ticks, which keep running when the day-night cycle is off;
+// scheduled block ticks are due at one, see block_updates.
+#[derive(Resource, Default)]
+pub struct WorldClock {
+    pub day: u32,
+    pub tick: u64,
+}
+
+impl TimeOfDay {
+    // 0 at night, 1 at noon, smooth in between
+    pub fn daylight(&self) -> f32 {
+        let sun_height = (self.0 * std::f32::consts::TAU - std::f32::consts::FRAC_PI_2).sin();
+        (sun_height * 2.0 + 0.5).clamp(0.0, 1.0)
+    }
+}
+
+// Only runs while the dayNightCycle game rule is on, freezing the time otherwise
+pub fn advance_time_of_day(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>, mut clock: ResMut<WorldClock>) {
+    let next = time_of_day.0 + time.delta_seconds() / DAY_LENGTH_SECONDS;
+    if next >= 1.0 {
+        clock.day += 1;
+    }
+    time_of_day.0 = next.fract();
+}
+
+// Dimensions without a sky stay at night levels all day
+#[cfg(feature = "render")]
+pub fn apply_daylight(
+    time_of_day: Res<TimeOfDay>,
+    dimension: Res<Dimension>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+
+    let daylight = if dimension.has_skylight() { time_of_day.daylight() } else { 0.0 };
+    let night_sky = LinearRgba::rgb(0.01, 0.01, 0.04);
+    let day_sky = LinearRgba::rgb(0.35, 0.6, 1.0);
+    clear_color.0 = Color::LinearRgba(night_sky.mix(&day_sky, daylight));
+    ambient_light.brightness = 60.0 + 440.0 * daylight;
+}
+
+// Dims the skylight baked into terrain vertices with the time of day; block light isn't affected
+#[cfg(feature = "render")]
+pub fn apply_skylight_to_materials(
+    time_of_day: Res<TimeOfDay>,
+    dimension: Res<Dimension>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut terrain_materials: ResMut<Assets<TerrainMaterial>>,
+    mut outlined_materials: ResMut<Assets<OutlinedMaterial>>,
+) {
+    if !time_of_day.is_changed() && !dimension.is_changed() {
+        return;
+    }
+
+    let skylight = if dimension.has_skylight() { NIGHT_SKYLIGHT + (1.0 - NIGHT_SKYLIGHT) * time_of_day.daylight() } else { 0.0 };
+    let Some(current) = terrain_materials.get(&chunk_materials.standard).map(|material| material.extension.settings.skylight) else {
+        return;
+    };
+    // Same throttling as weather::apply_wetness_to_materials; time moves every frame
+    let at_limit = skylight == NIGHT_SKYLIGHT || skylight == 1.0;
+    if current == skylight || ((current - skylight).abs() < 0.01 && !at_limit) {
+        return;
+    }
+    for handle in chunk_materials.terrain() {
+        if let Some(material) = terrain_materials.get_mut(handle) {
+            material.extension.settings.skylight = skylight;
+        }
+    }
+    if let Some(material) = outlined_materials.get_mut(&chunk_materials.outlined) {
+        material.extension.settings.skylight = skylight;
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::block_updates::BlockUpdates;
+use crate::keybindings::{Action, Actions};
+use crate::rendering::RenderDiagnostics;
+use crate::system_toggles::SystemToggles;
+use crate::terrain::ChunkDiagnostics;
+use crate::theme::Theme;
+use crate::world::World;
+use crate::VoxelRemover;
+
+#[derive(Component)]
+pub struct DebugOverlayText;
+
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+}
+
+pub fn debug_overlay_visible(overlay: Res<DebugOverlay>) -> bool {
+    overlay.visible
+}
+
+pub fn spawn_debug_overlay(mut commands: Commands, theme: Res<Theme>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: theme.hud_text,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        })
+        .with_background_color(theme.hud_background),
+        Visibility::Hidden,
+        DebugOverlayText,
+    ));
+}
+
+pub fn toggle_debug_overlay(
+    actions: Actions,
+    mut overlay: ResMut<DebugOverlay>,
+    mut text_query: Query<&mut Visibility, With<DebugOverlayText>>,
+) {
+    if !actions.just_pressed(Action::DebugOverlay) {
+        return;
+    }
+
+    overlay.visible = !overlay.visible;
+    for mut visibility in &mut text_query {
+        *visibility = if overlay.visible { Visibility::Visible } else { Visibility::Hidden };
+    }
+}
+
+// The counters the overlay lists
+#[derive(SystemParam)]
+pub struct OverlayStats<'w> {
+    diagnostics: Res<'w, DiagnosticsStore>,
+    chunk_diagnostics: Res<'w, ChunkDiagnostics>,
+    render_diagnostics: Res<'w, RenderDiagnostics>,
+    block_updates: Res<'w, BlockUpdates>,
+}
+
+pub fn update_debug_overlay(
+    stats: OverlayStats,
+    world: Res<World>,
+    toggles: Res<SystemToggles>,
+    theme: Res<Theme>,
+    camera_query: Query<&Transform, With<VoxelRemover>>,
+    mut text_query: Query<(&mut Text, &mut BackgroundColor), With<DebugOverlayText>>,
+) {
+    let OverlayStats { diagnostics, chunk_diagnostics, render_diagnostics, block_updates } = stats;
+    let fps = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or(0.0);
+    let frame_time = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.smoothed())
+        .unwrap_or(0.0);
+
+    let position = camera_query.get_single().map(|transform| transform.translation).unwrap_or_default();
+    let (chunk_key, voxel_pos) = world.world_to_voxel(position.floor().as_ivec3());
+
+    let mut contents = format!(
+        "FPS {:.0} ({:.2} ms)\n\
+         Position {:.1} {:.1} {:.1}\n\
+         Chunk {:?} local {:?}\n\
+         Chunks loaded {} | load queue {} | unload queue {}\n\
+         Meshing tasks {} | pending structures {} | block updates {}\n\
+         Chunk meshes {} ({} coarse) | vertices {} | triangles {}\n\
+         Voxel storage {:.1} KiB\n\
+         Chunk pool {} free | {} reused | {} allocated",
+        fps, frame_time,
+        position.x, position.y, position.z,
+        chunk_key, voxel_pos,
+        chunk_diagnostics.loaded_chunks, chunk_diagnostics.load_queue, chunk_diagnostics.unload_queue,
+        chunk_diagnostics.meshing_tasks, chunk_diagnostics.pending_structures, block_updates.len(),
+        render_diagnostics.chunk_meshes, render_diagnostics.coarse_chunk_meshes, render_diagnostics.vertices, render_diagnostics.triangles,
+        chunk_diagnostics.voxel_storage_bytes as f32 / 1024.0,
+        chunk_diagnostics.pooled_chunks, chunk_diagnostics.reused_chunks, chunk_diagnostics.allocated_chunks,
+    );
+    let disabled = toggles.disabled();
+    if !disabled.is_empty() {
+        contents.push_str(&format!("\nDisabled systems: {}", disabled.join(", ")));
+    }
+
+    for (mut text, mut background_color) in &mut text_query {
+        text.sections[0].value = contents.clone();
+        text.sections[0].style.color = theme.hud_text;
+        background_color.0 = theme.hud_background;
+    }
+}
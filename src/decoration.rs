@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use crate::biome::{ChunkColumns, Column};
+use crate::block::{self, BlockId, AIR, FLOWER, LEAVES, LOG, TALL_GRASS};
+use crate::terrain::Chunk;
+use crate::worldgen::{mix_seed, WorldGenConfig};
+
+const DECORATION_SEED: u32 = 0xdec0_7a7e;
+
+// SplitMix64, seeded per chunk so decoration is reproducible without a rand dependency
+pub struct ChunkRng(u64);
+
+impl ChunkRng {
+    pub fn for_chunk(chunk_key: (i32, i32, i32), seed: u32) -> Self {
+        let hash = block::position_hash(chunk_key.0, chunk_key.1, chunk_key.2, seed);
+        Self(((hash as u64) << 32) | block::position_hash(chunk_key.2, chunk_key.0, chunk_key.1, !seed) as u64)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: i32, max_inclusive: i32) -> i32 {
+        min + (self.next_u64() % (max_inclusive - min + 1) as u64) as i32
+    }
+}
+
+// Topmost solid voxel with air above it inside this chunk. Above the top layer lies the chunk
+// over this one, where the column's height tells whether the ground goes on.
+fn surface_height(chunk: &Chunk, column: &Column, base_y: i32, x: usize, z: usize) -> Option<usize> {
+    (0..chunk.height).rev().find(|&y| {
+        let open_above = if y + 1 < chunk.height {
+            !chunk.get_voxel(x, y + 1, z)
+        } else {
+            base_y + y as i32 + 1 >= column.height as i32
+        };
+        chunk.get_voxel(x, y, z) && open_above
+    })
+}
+
+// Whether anything, water in particular, fills the cell above the surface. Cells in the chunk
+// above are water below sea level, as WaterStage leaves them.
+fn covered(chunk: &Chunk, base_y: i32, sea_level: i32, x: usize, surface_y: usize, z: usize) -> bool {
+    if surface_y + 1 < chunk.height {
+        chunk.get_block(x, surface_y + 1, z) != AIR
+    } else {
+        base_y + surface_y as i32 + 1 < sea_level
+    }
+}
+
+// Turns the top of every exposed column into its biome's surface block over a few filler layers.
+// Filler under a surface in a chunk above reaches down into this one.
+pub fn apply_surface(chunk: &mut Chunk, chunk_y: i32, columns: &ChunkColumns, sea_level: i32) {
+    let base_y = chunk_y * chunk.height as i32;
+    for x in 0..chunk.width {
+        for z in 0..chunk.depth {
+            let column = columns.get(x, z);
+            let Some(surface_y) = surface_height(chunk, column, base_y, x, z) else {
+                let ground_y = column.height as i32 - 1 - base_y;
+                if ground_y >= chunk.height as i32 {
+                    let bottom = (ground_y - column.filler_depth as i32).max(0);
+                    for y in bottom..(chunk.height as i32).min(ground_y) {
+                        if chunk.get_voxel(x, y as usize, z) {
+                            chunk.set_block(x, y as usize, z, column.filler);
+                        }
+                    }
+                }
+                continue;
+            };
+
+            // Sea and lake beds get the filler
+            let top = if covered(chunk, base_y, sea_level, x, surface_y, z) { column.filler } else { column.surface };
+            chunk.set_block(x, surface_y, z, top);
+            for y in surface_y.saturating_sub(column.filler_depth)..surface_y {
+                if chunk.get_voxel(x, y, z) {
+                    chunk.set_block(x, y, z, column.filler);
+                }
+            }
+        }
+    }
+}
+
+// Vegetation pass, run after apply_surface has laid the grass. Writes what fits into `chunk`
+// and returns the structure blocks that spill over into other chunks, in world coordinates.
+pub fn decorate_chunk(chunk: &mut Chunk, chunk_key: (i32, i32, i32), columns: &ChunkColumns, config: &WorldGenConfig) -> Vec<(IVec3, BlockId)> {
+    let mut rng = ChunkRng::for_chunk(chunk_key, mix_seed(DECORATION_SEED, config.seed));
+    let mut overflow = Vec::new();
+    let origin = IVec3::new(
+        chunk_key.0 * chunk.width as i32,
+        chunk_key.1 * chunk.height as i32,
+        chunk_key.2 * chunk.depth as i32,
+    );
+
+    for x in 0..chunk.width {
+        for z in 0..chunk.depth {
+            let column = columns.get(x, z);
+            let Some(surface_y) = surface_height(chunk, column, origin.y, x, z) else {
+                continue;
+            };
+            // Nothing grows under water
+            if covered(chunk, origin.y, config.sea_level, x, surface_y, z) {
+                continue;
+            }
+
+            let world_pos = origin + IVec3::new(x as i32, surface_y as i32 + 1, z as i32);
+            let (tree_chance, grass_chance, flower_chance) = (column.tree_chance, column.grass_chance, column.flower_chance);
+            let roll = rng.next_f32();
+
+            if roll < tree_chance {
+                for (pos, block) in tree_blocks(world_pos, &mut rng) {
+                    place_block(chunk, origin, pos, block, &mut overflow);
+                }
+            } else if roll < tree_chance + grass_chance {
+                place_block(chunk, origin, world_pos, TALL_GRASS, &mut overflow);
+            } else if roll < tree_chance + grass_chance + flower_chance {
+                place_block(chunk, origin, world_pos, FLOWER, &mut overflow);
+            }
+        }
+    }
+
+    overflow
+}
+
+// Trunk plus a rounded leaf blob; leaves never replace the trunk
+fn tree_blocks(base: IVec3, rng: &mut ChunkRng) -> Vec<(IVec3, BlockId)> {
+    let trunk_height = rng.range(4, 6);
+    let top = base + IVec3::Y * (trunk_height - 1);
+    let mut blocks = Vec::new();
+
+    for dy in -2..=1 {
+        let radius: i32 = if dy >= 0 { 1 } else { 2 };
+        for dx in -radius..=radius {
+            for dz in -radius..=radius {
+                // Clip the corners so the canopy reads as round
+                if dx.abs() == radius && dz.abs() == radius && rng.next_f32() < 0.6 {
+                    continue;
+                }
+                if dx == 0 && dz == 0 && dy < 1 {
+                    continue;
+                }
+                blocks.push((top + IVec3::new(dx, dy, dz), LEAVES));
+            }
+        }
+    }
+
+    for dy in 0..trunk_height {
+        blocks.push((base + IVec3::Y * dy, LOG));
+    }
+
+    blocks
+}
+
+fn place_block(chunk: &mut Chunk, origin: IVec3, pos: IVec3, block: BlockId, overflow: &mut Vec<(IVec3, BlockId)>) {
+    let local = pos - origin;
+    let size = IVec3::new(chunk.width as i32, chunk.height as i32, chunk.depth as i32);
+    if local.cmpge(IVec3::ZERO).all() && local.cmplt(size).all() {
+        let (x, y, z) = (local.x as usize, local.y as usize, local.z as usize);
+        if chunk.get_block(x, y, z) == AIR {
+            chunk.set_block(x, y, z, block);
+        }
+    } else {
+        overflow.push((pos, block));
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use crate::terrain::Chunk;
+use crate::world::World;
+use crate::worldgen::OverflowBlock;
+
+// Set to `record:<path>` on the first run and `verify:<path>` on the second. Every chunk the
+// world generates is hashed by key (hash_generated_chunk); record writes the hashes out, verify
+// compares against them and reports the first chunk that differs. Which chunks load and when
+// depends on where the player goes and on frame timing, so only the generator's output is
+// compared, never the loaded set or anything edited since.
+pub const DETERMINISM_AUDIT_ENV: &str = "VOXELFUN_DETERMINISM_AUDIT";
+
+pub enum AuditMode {
+    Record(fs::File),
+    Verify { expected: HashMap<(i32, i32, i32), u64>, diverged: bool },
+}
+
+#[derive(Resource)]
+pub struct DeterminismAudit {
+    pub mode: AuditMode,
+}
+
+impl DeterminismAudit {
+    pub fn from_env() -> Option<Self> {
+        let setting = std::env::var(DETERMINISM_AUDIT_ENV).ok()?;
+        let (mode, path) = setting.split_once(':')?;
+
+        let mode = match mode {
+            "record" => match fs::File::create(path) {
+                Ok(file) => AuditMode::Record(file),
+                Err(err) => {
+                    println!("Determinism audit: could not create {}: {}", path, err);
+                    return None;
+                }
+            },
+            "verify" => match fs::read_to_string(path) {
+                Ok(contents) => AuditMode::Verify { expected: parse_checkpoints(&contents), diverged: false },
+                Err(err) => {
+                    println!("Determinism audit: could not read {}: {}", path, err);
+                    return None;
+                }
+            },
+            _ => {
+                println!("Determinism audit: unknown mode '{}', expected record or verify", mode);
+                return None;
+            }
+        };
+
+        println!("Determinism audit enabled ({})", setting);
+        Some(Self { mode })
+    }
+}
+
+// One "<x> <y> <z> <hash>" line per chunk, hash in hex
+fn parse_checkpoints(contents: &str) -> HashMap<(i32, i32, i32), u64> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split(' ');
+            let chunk_key = (parts.next()?.parse().ok()?, parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+            Some((chunk_key, u64::from_str_radix(parts.next()?, 16).ok()?))
+        })
+        .collect()
+}
+
+// FNV-1a, stable across platforms and compiler versions unlike DefaultHasher
+struct Fnv64(u64);
+
+impl Fnv64 {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+// A freshly generated chunk: its voxels, block states and the structure blocks it spilled into
+// neighbours, in the order generation produced them
+pub fn hash_generated_chunk(chunk: &Chunk, overflow: &[OverflowBlock]) -> u64 {
+    let mut hasher = Fnv64::new();
+    hasher.write(&chunk.voxels.to_bytes());
+
+    let mut states: Vec<_> = chunk.block_states.iter().map(|(&pos, &state)| (pos, state)).collect();
+    states.sort_unstable();
+    for ((x, y, z), state) in states {
+        for coordinate in [x, y, z] {
+            hasher.write(&(coordinate as u32).to_le_bytes());
+        }
+        hasher.write(&[state]);
+    }
+
+    for block in overflow {
+        for coordinate in block.pos.to_array() {
+            hasher.write(&coordinate.to_le_bytes());
+        }
+        hasher.write(&block.block.to_le_bytes());
+        hasher.write(&[block.replace as u8]);
+    }
+
+    hasher.0
+}
+
+// Turns on World::generated_hashes for the audit, before the first chunk generates
+pub fn start_generation_log(audit: Option<Res<DeterminismAudit>>, mut world: ResMut<World>) {
+    if audit.is_some() {
+        world.generated_hashes = Some(Vec::new());
+    }
+}
+
+pub fn run_determinism_audit(
+    audit: Option<ResMut<DeterminismAudit>>,
+    mut world: ResMut<World>,
+) {
+    let Some(mut audit) = audit else {
+        return;
+    };
+    let Some(hashes) = world.generated_hashes.as_mut().map(std::mem::take) else {
+        return;
+    };
+
+    for (chunk_key, hash) in hashes {
+        match &mut audit.mode {
+            AuditMode::Record(file) => {
+                if let Err(err) = writeln!(file, "{} {} {} {:016x}", chunk_key.0, chunk_key.1, chunk_key.2, hash) {
+                    println!("Determinism audit: failed to write checkpoint: {}", err);
+                }
+            }
+            AuditMode::Verify { expected, diverged } => {
+                if *diverged {
+                    return;
+                }
+                // Chunks the recording run never generated have nothing to compare against
+                match expected.get(&chunk_key) {
+                    Some(&expected_hash) if expected_hash != hash => {
+                        *diverged = true;
+                        println!(
+                            "Determinism audit: chunk {:?} generated differently (expected {:016x}, got {:016x})",
+                            chunk_key, expected_hash, hash
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
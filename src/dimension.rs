@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+use crate::caves;
+use crate::worldgen::WorldGenerator;
+
+// The built-in worlds. A dimension picks the generator, whether there is a sky, and where
+// its chunks are saved; one is chosen at startup with `--dimension <name>`.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dimension {
+    #[default]
+    Overworld,
+    // Closed caverns from 3D noise with lava seas and glowstone, lit by block light only
+    Caves,
+}
+
+impl Dimension {
+    pub const NAMES: [&'static str; 2] = ["overworld", "caves"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "overworld" => Some(Dimension::Overworld),
+            "caves" => Some(Dimension::Caves),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dimension::Overworld => "overworld",
+            Dimension::Caves => "caves",
+        }
+    }
+
+    pub fn generator(self, seed: u32) -> WorldGenerator {
+        match self {
+            Dimension::Overworld => WorldGenerator::seeded(seed),
+            Dimension::Caves => caves::cave_generator(seed),
+        }
+    }
+
+    pub fn has_skylight(self) -> bool {
+        self == Dimension::Overworld
+    }
+
+    // The overworld keeps the world's directory to itself so existing saves stay where they are;
+    // other dimensions get a subdirectory of it
+    pub fn save_dir(self, world_dir: &Path) -> PathBuf {
+        match self {
+            Dimension::Overworld => world_dir.to_path_buf(),
+            _ => world_dir.join(self.name()),
+        }
+    }
+
+    // Takes `--dimension <name>` out of the command line, so the positional arguments of the
+    // subcommands stay where they are. The overworld if it isn't given.
+    pub fn take_from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        let Some(index) = args.iter().position(|arg| arg == "--dimension") else {
+            return Ok(Dimension::Overworld);
+        };
+        let name = args.drain(index..(index + 2).min(args.len())).nth(1).unwrap_or_default();
+        Self::parse(&name).ok_or_else(|| format!("unknown dimension '{}', expected one of: {}", name, Self::NAMES.join(", ")))
+    }
+}
@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use crate::block::{self, AIR};
+#[cfg(feature = "render")]
+use crate::camera_controller::CameraController;
+#[cfg(feature = "render")]
+use crate::console::{Console, ConsoleCommand};
+use crate::item_drop::{ItemDrop, SpawnItemDrop};
+use crate::mob::Mob;
+#[cfg(feature = "render")]
+use crate::player::PlayerBody;
+#[cfg(feature = "render")]
+use crate::selection::TargetedBlock;
+use crate::voxel_world::VoxelWorld;
+use crate::{MAX_EXPLOSION_DEBRIS, MAX_EXPLOSION_RADIUS};
+
+const EXPLOSION_SEED: u32 = 0xb0_0b5;
+// How much of the radius the noise can eat into, so craters come out ragged instead of round
+const CRATER_ROUGHNESS: f32 = 0.3;
+// One in this many carved blocks is thrown out as an item, up to MAX_EXPLOSION_DEBRIS
+const DEBRIS_ONE_IN: u32 = 6;
+// Bodies are pushed up to this many radii from the center, harder the closer they are
+const KNOCKBACK_RANGE: f32 = 2.0;
+// Voxels/s of push per unit of power at the center
+const KNOCKBACK_SPEED: f32 = 6.0;
+const DEBRIS_SPEED: f32 = 3.0;
+
+// Blows a crater: voxels within a noisy sphere of `radius` around `center` are removed and
+// bodies around it are pushed away with a strength of `power`. Liquids stay. The removal goes
+// through the usual VoxelSetEvents, so all touched chunks are remeshed together in that frame
+// and the broken blocks burst into particles.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ExplosionEvent {
+    pub center: Vec3,
+    pub radius: f32,
+    pub power: f32,
+}
+
+pub fn handle_explosions(
+    mut explosions: EventReader<ExplosionEvent>,
+    mut voxel_world: VoxelWorld,
+    mut drop_events: EventWriter<SpawnItemDrop>,
+    #[cfg(feature = "render")] mut player_query: Query<(&Transform, &mut PlayerBody, &mut CameraController)>,
+    mut item_drops: Query<(&Transform, &mut ItemDrop)>,
+    mut mobs: Query<(&Transform, &mut Mob)>,
+) {
+    for explosion in explosions.read() {
+        let radius = explosion.radius.clamp(0.0, MAX_EXPLOSION_RADIUS);
+        // A negative power would pull bodies in and make the debris speed NaN
+        let power = explosion.power.max(0.0);
+        let _span = info_span!("explosion", radius).entered();
+        let min = (explosion.center - Vec3::splat(radius)).floor().as_ivec3();
+        let max = (explosion.center + Vec3::splat(radius)).floor().as_ivec3();
+
+        let mut debris = 0;
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let pos = IVec3::new(x, y, z);
+                    let noise = block::position_hash(x, y, z, EXPLOSION_SEED);
+                    let reach = radius * (1.0 - CRATER_ROUGHNESS * (noise as f32 / u32::MAX as f32));
+                    let offset = pos.as_vec3() + Vec3::splat(0.5) - explosion.center;
+                    if offset.length() > reach {
+                        continue;
+                    }
+                    let Some(old) = voxel_world.get_block(pos).filter(|&old| old != AIR && !block::is_liquid(old)) else {
+                        continue;
+                    };
+                    voxel_world.set_block(pos, AIR);
+
+                    if debris < MAX_EXPLOSION_DEBRIS && noise.is_multiple_of(DEBRIS_ONE_IN) {
+                        debris += 1;
+                        drop_events.send(SpawnItemDrop {
+                            position: pos.as_vec3() + Vec3::new(0.5, 0.25, 0.5),
+                            block: old,
+                            count: 1,
+                            velocity: (offset.normalize_or_zero() + Vec3::Y) * DEBRIS_SPEED * power.sqrt(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let push = |position: Vec3| {
+            let offset = position - explosion.center;
+            let falloff = 1.0 - offset.length() / (radius * KNOCKBACK_RANGE).max(f32::EPSILON);
+            (falloff > 0.0).then(|| offset.normalize_or(Vec3::Y) * falloff * power * KNOCKBACK_SPEED)
+        };
+        #[cfg(feature = "render")]
+        for (transform, mut body, mut controller) in &mut player_query {
+            if let Some(impulse) = push(transform.translation) {
+                // Horizontal speed lives in the controller and dies down with its deceleration
+                controller.velocity += impulse.with_y(0.0);
+                body.vertical_velocity += impulse.y;
+            }
+        }
+        for (transform, mut drop) in &mut item_drops {
+            if let Some(impulse) = push(transform.translation) {
+                drop.velocity += impulse;
+            }
+        }
+        for (transform, mut mob) in &mut mobs {
+            if let Some(impulse) = push(transform.translation) {
+                mob.velocity += impulse;
+            }
+        }
+    }
+}
+
+// `explode [radius] [power]` at the targeted block, radius 4 and power 1 by default
+#[cfg(feature = "render")]
+pub fn explode_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    target: Res<TargetedBlock>,
+    mut explosions: EventWriter<ExplosionEvent>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "explode") {
+        let radius = command.args.first().map_or(Ok(4.0), |value| value.parse::<f32>());
+        let power = command.args.get(1).map_or(Ok(1.0), |value| value.parse::<f32>());
+        let (Ok(radius), Ok(power)) = (radius, power) else {
+            console.print("Usage: explode [radius] [power]");
+            continue;
+        };
+        let Some(voxel) = target.voxel else {
+            console.print("No block targeted");
+            continue;
+        };
+        let radius = radius.clamp(0.0, MAX_EXPLOSION_RADIUS);
+        explosions.send(ExplosionEvent { center: voxel.as_vec3() + Vec3::splat(0.5), radius, power: power.max(0.0) });
+        console.print(format!("Explosion of radius {} at {:?}", radius, voxel));
+    }
+}
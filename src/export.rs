@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::block::AIR;
+use crate::console::{Console, ConsoleCommand};
+use crate::region_edit::RegionSelection;
+use crate::settings::DepthDarknessCurve;
+use crate::terrain::Chunk;
+use crate::world::World;
+
+// Meshes of the loaded chunks overlapping an inclusive world-space box, clipped to it.
+// Each is the greedy box mesh with positions already in world space. Depth darkening is
+// left out so the colors are the plain block colors.
+pub fn region_meshes(world: &World, min: IVec3, max: IVec3) -> Vec<Mesh> {
+    let size = world.chunk_size as i32;
+    let no_darkness = DepthDarknessCurve { enabled: false, ..default() };
+    let min_chunk = min.div_euclid(IVec3::splat(size));
+    let max_chunk = max.div_euclid(IVec3::splat(size));
+
+    let mut meshes = Vec::new();
+    for cx in min_chunk.x..=max_chunk.x {
+        for cy in min_chunk.y..=max_chunk.y {
+            for cz in min_chunk.z..=max_chunk.z {
+                let chunk_key = (cx, cy, cz);
+                let Some(source) = world.chunks.get(&chunk_key) else {
+                    continue;
+                };
+                let origin = IVec3::new(cx, cy, cz) * size;
+                let mut chunk = Chunk::from_storage(source.width, source.height, source.depth, source.voxels.clone());
+                for x in 0..chunk.width {
+                    for y in 0..chunk.height {
+                        for z in 0..chunk.depth {
+                            let pos = origin + IVec3::new(x as i32, y as i32, z as i32);
+                            if pos.cmplt(min).any() || pos.cmpgt(max).any() {
+                                chunk.set_block(x, y, z, AIR);
+                            }
+                        }
+                    }
+                }
+
+                let mut mesh = chunk.generate_mesh(chunk_key, &no_darkness).combined();
+                if mesh.count_vertices() == 0 {
+                    continue;
+                }
+                mesh.translate_by(origin.as_vec3());
+                meshes.push(mesh);
+            }
+        }
+    }
+    meshes
+}
+
+fn float3(mesh: &Mesh, attribute: impl Into<bevy::render::mesh::MeshVertexAttributeId>) -> &[[f32; 3]] {
+    match mesh.attribute(attribute) {
+        Some(VertexAttributeValues::Float32x3(values)) => values,
+        _ => &[],
+    }
+}
+
+// Wavefront OBJ with per-vertex colors (the `v x y z r g b` extension Blender and MeshLab read),
+// normals and atlas UVs. Returns the number of triangles written.
+pub fn write_obj(meshes: &[Mesh], out: &mut impl Write) -> io::Result<usize> {
+    writeln!(out, "# RustVoxelfun region export")?;
+    writeln!(out, "o region")?;
+
+    let mut vertex_offset = 1;
+    let mut triangles = 0;
+    for mesh in meshes {
+        let positions = float3(mesh, Mesh::ATTRIBUTE_POSITION);
+        let normals = float3(mesh, Mesh::ATTRIBUTE_NORMAL);
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+            continue;
+        };
+        let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR) else {
+            continue;
+        };
+        let Some(Indices::U32(indices)) = mesh.indices() else {
+            continue;
+        };
+
+        for (position, color) in positions.iter().zip(colors) {
+            // Vertex colors are linear, OBJ viewers expect sRGB
+            let srgb = Color::linear_rgb(color[0], color[1], color[2]).to_srgba();
+            writeln!(out, "v {} {} {} {:.4} {:.4} {:.4}", position[0], position[1], position[2], srgb.red, srgb.green, srgb.blue)?;
+        }
+        for normal in normals {
+            writeln!(out, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+        }
+        for uv in uvs {
+            // OBJ puts v = 0 at the bottom of the texture
+            writeln!(out, "vt {} {}", uv[0], 1.0 - uv[1])?;
+        }
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| index as usize + vertex_offset);
+            writeln!(out, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+
+        vertex_offset += positions.len();
+        triangles += indices.len() / 3;
+    }
+    Ok(triangles)
+}
+
+// Writes the voxels inside an inclusive world-space box to an OBJ file, for taking builds into
+// Blender. Only loaded chunks are included. Returns the number of triangles written.
+pub fn export_region(world: &World, min: IVec3, max: IVec3, path: &Path) -> io::Result<usize> {
+    let meshes = region_meshes(world, min.min(max), min.max(max));
+    let mut out = BufWriter::new(File::create(path)?);
+    let triangles = write_obj(&meshes, &mut out)?;
+    out.flush()?;
+    Ok(triangles)
+}
+
+// `export <file.obj> [x1 y1 z1 x2 y2 z2]`: exports the given box, or the region selection
+pub fn export_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    world: Res<World>,
+    selection: Res<RegionSelection>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "export") {
+        let Some(path) = command.args.first() else {
+            console.print("usage: export <file.obj> [x1 y1 z1 x2 y2 z2]");
+            continue;
+        };
+        let coords: Vec<i32> = command.args[1..].iter().filter_map(|arg| arg.parse().ok()).collect();
+        let bounds = match coords[..] {
+            [] => selection.bounds(),
+            [x1, y1, z1, x2, y2, z2] if command.args.len() == 7 => Some((IVec3::new(x1, y1, z1), IVec3::new(x2, y2, z2))),
+            _ => {
+                console.print("Expected six integer coordinates");
+                continue;
+            }
+        };
+        let Some((min, max)) = bounds else {
+            console.print("Select a region or pass its corners");
+            continue;
+        };
+
+        match export_region(&world, min, max, Path::new(path)) {
+            Ok(triangles) => console.print(format!("Exported {} triangle(s) to {}", triangles, path)),
+            Err(err) => console.print(format!("Export failed: {}", err)),
+        }
+    }
+}
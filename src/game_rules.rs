@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::Path;
+use bevy::prelude::*;
+use crate::console::{Console, ConsoleCommand};
+use crate::world::World;
+
+pub const GAME_RULES_FILE: &str = "gamerules.txt";
+
+// Per-world gameplay switches, stored next to the world's region files as `name value` lines
+// and changed in game with `/gamerule <name> [true|false]`
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct GameRules {
+    pub mob_spawning: bool,
+    pub day_night_cycle: bool,
+    pub keep_inventory: bool,
+    pub seasons: bool,
+    pub weather_cycle: bool,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            mob_spawning: true,
+            day_night_cycle: true,
+            keep_inventory: false,
+            seasons: true,
+            weather_cycle: true,
+        }
+    }
+}
+
+impl GameRules {
+    pub const NAMES: [&'static str; 5] = ["mobSpawning", "dayNightCycle", "keepInventory", "seasons", "weatherCycle"];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "mobSpawning" => Some(self.mob_spawning),
+            "dayNightCycle" => Some(self.day_night_cycle),
+            "keepInventory" => Some(self.keep_inventory),
+            "seasons" => Some(self.seasons),
+            "weatherCycle" => Some(self.weather_cycle),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "mobSpawning" => Some(&mut self.mob_spawning),
+            "dayNightCycle" => Some(&mut self.day_night_cycle),
+            "keepInventory" => Some(&mut self.keep_inventory),
+            "seasons" => Some(&mut self.seasons),
+            "weatherCycle" => Some(&mut self.weather_cycle),
+            _ => None,
+        }
+    }
+
+    // Unknown names and malformed lines are skipped so older or newer files still load
+    pub fn load(save_dir: &Path) -> Self {
+        let mut rules = Self::default();
+        let Ok(contents) = fs::read_to_string(save_dir.join(GAME_RULES_FILE)) else {
+            return rules;
+        };
+        for line in contents.lines() {
+            let mut parts = line.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                if let (Some(rule), Ok(value)) = (rules.get_mut(name), value.parse()) {
+                    *rule = value;
+                }
+            }
+        }
+        rules
+    }
+
+    pub fn save(&self, save_dir: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(save_dir)?;
+        let contents: String = Self::NAMES.iter()
+            .map(|name| format!("{} {}\n", name, self.get(name).unwrap()))
+            .collect();
+        fs::write(save_dir.join(GAME_RULES_FILE), contents)
+    }
+}
+
+pub fn load_game_rules(mut commands: Commands, world: Res<World>) {
+    let rules = world.save_dir.as_deref().map(GameRules::load).unwrap_or_default();
+    commands.insert_resource(rules);
+}
+
+pub fn gamerule_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut rules: ResMut<GameRules>,
+    world: Res<World>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "gamerule") {
+        let Some(name) = command.args.first() else {
+            for name in GameRules::NAMES {
+                console.print(format!("{} = {}", name, rules.get(name).unwrap()));
+            }
+            continue;
+        };
+        let Some(current) = rules.get(name) else {
+            console.print(format!("Unknown game rule '{}', expected one of {}", name, GameRules::NAMES.join(", ")));
+            continue;
+        };
+
+        match command.args.get(1).map(|value| value.parse::<bool>()) {
+            None => console.print(format!("{} = {}", name, current)),
+            Some(Ok(value)) => {
+                *rules.get_mut(name).unwrap() = value;
+                console.print(format!("{} set to {}", name, value));
+                if let Some(save_dir) = world.save_dir.as_deref() {
+                    if let Err(err) = rules.save(save_dir) {
+                        console.print(format!("Could not save game rules: {}", err));
+                    }
+                }
+            }
+            Some(Err(_)) => console.print(format!("Game rule values are true or false, got '{}'", command.args[1])),
+        }
+    }
+}
+
+// Run conditions for systems governed by a rule
+pub fn day_night_cycle_enabled(rules: Res<GameRules>) -> bool {
+    rules.day_night_cycle
+}
+
+pub fn mob_spawning_enabled(rules: Res<GameRules>) -> bool {
+    rules.mob_spawning
+}
+
+pub fn weather_cycle_enabled(rules: Res<GameRules>) -> bool {
+    rules.weather_cycle
+}
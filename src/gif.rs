@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+// Animated GIF writing for `/record`. Every frame is mapped onto one fixed palette of 6 red,
+// 7 green and 6 blue levels, which keeps the encoder small and is plenty for flat-shaded voxels.
+
+const RED_LEVELS: usize = 6;
+const GREEN_LEVELS: usize = 7;
+const BLUE_LEVELS: usize = 6;
+// 252 colors in use; the table is padded to the 256 its header announces
+const PALETTE_BITS: u8 = 8;
+const MAX_CODE_BITS: u8 = 12;
+
+// One frame, 8-bit sRGB without alpha, row by row
+pub struct GifFrame {
+    pub width: u16,
+    pub height: u16,
+    pub rgb: Vec<u8>,
+}
+
+fn level(value: u8, levels: usize) -> usize {
+    (value as usize * (levels - 1) + 127) / 255
+}
+
+fn palette_index(r: u8, g: u8, b: u8) -> u8 {
+    ((level(r, RED_LEVELS) * GREEN_LEVELS + level(g, GREEN_LEVELS)) * BLUE_LEVELS + level(b, BLUE_LEVELS)) as u8
+}
+
+fn palette() -> Vec<u8> {
+    let mut table = Vec::with_capacity(3 << PALETTE_BITS);
+    let channel = |level: usize, levels: usize| (level * 255 / (levels - 1)) as u8;
+    for r in 0..RED_LEVELS {
+        for g in 0..GREEN_LEVELS {
+            for b in 0..BLUE_LEVELS {
+                table.extend([channel(r, RED_LEVELS), channel(g, GREEN_LEVELS), channel(b, BLUE_LEVELS)]);
+            }
+        }
+    }
+    table.resize(3 << PALETTE_BITS, 0);
+    table
+}
+
+// Packs variable-width codes least significant bit first, as GIF wants
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    // The decoder adds each table entry one code after the encoder does, so codes widen once
+    // the next free code no longer fits, not once the table holds one that doesn't
+    fn write_widening(&mut self, code: u16, next_code: u16, width: &mut u8) {
+        self.write(code, *width);
+        if next_code >= 1 << *width && *width < MAX_CODE_BITS {
+            *width += 1;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// Variable-width LZW over palette indices, starting over once the code table is full
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << PALETTE_BITS;
+    let end = clear + 1;
+    let mut writer = BitWriter { bytes: Vec::new(), buffer: 0, bits: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut width = PALETTE_BITS + 1;
+
+    writer.write(clear, width);
+    let Some((&first, rest)) = indices.split_first() else {
+        writer.write(end, width);
+        return writer.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        writer.write_widening(prefix, next_code, &mut width);
+        if next_code == 1 << MAX_CODE_BITS {
+            writer.write(clear, width);
+            table.clear();
+            next_code = end + 1;
+            width = PALETTE_BITS + 1;
+        } else {
+            table.insert((prefix, index), next_code);
+            next_code += 1;
+        }
+        prefix = index as u16;
+    }
+    writer.write_widening(prefix, next_code, &mut width);
+    writer.write(end, width);
+    writer.finish()
+}
+
+// A looping animation showing each frame for `delay_centiseconds`. The canvas is the size of
+// the first frame; frames of another size are skipped.
+pub fn encode(frames: &[GifFrame], delay_centiseconds: u16) -> Vec<u8> {
+    let (width, height) = frames.first().map_or((1, 1), |frame| (frame.width, frame.height));
+    let mut out = b"GIF89a".to_vec();
+    out.extend(width.to_le_bytes());
+    out.extend(height.to_le_bytes());
+    // Global color table of 2^(7 + 1) entries, 8 bits per channel
+    out.extend([0xf7, 0, 0]);
+    out.extend(palette());
+    // Loop forever
+    out.extend([0x21, 0xff, 11]);
+    out.extend(b"NETSCAPE2.0");
+    out.extend([3, 1, 0, 0, 0]);
+
+    for frame in frames.iter().filter(|frame| frame.width == width && frame.height == height) {
+        out.extend([0x21, 0xf9, 4, 0]);
+        out.extend(delay_centiseconds.to_le_bytes());
+        out.extend([0, 0]);
+
+        out.push(0x2c);
+        out.extend([0, 0, 0, 0]);
+        out.extend(width.to_le_bytes());
+        out.extend(height.to_le_bytes());
+        out.push(0);
+
+        let indices: Vec<u8> = frame.rgb.chunks_exact(3).map(|rgb| palette_index(rgb[0], rgb[1], rgb[2])).collect();
+        out.push(PALETTE_BITS);
+        for block in lzw(&indices).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend(block);
+        }
+        out.push(0);
+    }
+    out.push(0x3b);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LZW decoding as a GIF viewer does it, kept apart from the encoder above so the two can't
+    // share a mistake
+    fn decode_lzw(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let reset = |table: &mut Vec<Vec<u8>>| {
+            table.clear();
+            table.extend((0..clear).map(|index| vec![index as u8]));
+            // Clear and end take up the next two codes
+            table.extend([Vec::new(), Vec::new()]);
+        };
+        let mut table = Vec::new();
+        reset(&mut table);
+        let mut width = min_code_size + 1;
+        let mut previous: Option<usize> = None;
+        let mut bit = 0;
+        let mut out = Vec::new();
+
+        loop {
+            let mut code = 0;
+            for i in 0..width {
+                let byte = data[bit / 8];
+                code |= (((byte >> (bit % 8)) & 1) as usize) << i;
+                bit += 1;
+            }
+            if code == clear {
+                reset(&mut table);
+                width = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+
+            let entry = match (table.get(code), previous) {
+                (Some(entry), _) => entry.clone(),
+                // The code being defined by this very step
+                (None, Some(previous)) => {
+                    let mut entry = table[previous].clone();
+                    entry.push(entry[0]);
+                    entry
+                }
+                (None, None) => panic!("code {} used before it was defined", code),
+            };
+            if let Some(previous) = previous {
+                if table.len() < 1 << MAX_CODE_BITS {
+                    let mut added = table[previous].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(code);
+            if table.len() == 1 << width && width < MAX_CODE_BITS {
+                width += 1;
+            }
+        }
+    }
+
+    // Palette indices of every image in a GIF, walking its blocks like a viewer would
+    fn decode_frames(gif: &[u8]) -> Vec<Vec<u8>> {
+        assert_eq!(&gif[..6], b"GIF89a");
+        let flags = gif[10];
+        let mut pos = 13 + if flags & 0x80 != 0 { 3 << ((flags & 7) + 1) } else { 0 };
+        let mut frames = Vec::new();
+        loop {
+            match gif[pos] {
+                0x21 => {
+                    pos += 2;
+                    while gif[pos] != 0 {
+                        pos += gif[pos] as usize + 1;
+                    }
+                    pos += 1;
+                }
+                0x2c => {
+                    assert_eq!(gif[pos + 9] & 0x80, 0, "no local color table");
+                    let min_code_size = gif[pos + 10];
+                    pos += 11;
+                    let mut data = Vec::new();
+                    while gif[pos] != 0 {
+                        let len = gif[pos] as usize;
+                        data.extend_from_slice(&gif[pos + 1..pos + 1 + len]);
+                        pos += len + 1;
+                    }
+                    pos += 1;
+                    frames.push(decode_lzw(&data, min_code_size));
+                }
+                0x3b => return frames,
+                block => panic!("unexpected block {:#04x} at {}", block, pos),
+            }
+        }
+    }
+
+    // Deterministic noise over the 252 palette entries
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            ((state >> 16) % 252) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn lzw_output_decodes_to_its_input() {
+        // Noise fills the code table several times over, so this covers the table resets
+        let inputs = [Vec::new(), vec![7], vec![0; 10_000], (0..=251).cycle().take(50_000).collect(), noise(200_000)];
+        for indices in inputs {
+            assert_eq!(decode_lzw(&lzw(&indices), PALETTE_BITS), indices, "{} indices", indices.len());
+        }
+    }
+
+    #[test]
+    fn encoded_frames_decode_to_their_palette_indices() {
+        let frame = |width: u16, height: u16, shift: u8| {
+            let rgb = (0..width as usize * height as usize)
+                .flat_map(|i| [(i as u8).wrapping_mul(3), (i as u8).wrapping_add(shift), shift])
+                .collect();
+            GifFrame { width, height, rgb }
+        };
+        // The odd-sized frame is skipped
+        let frames = [frame(40, 30, 0), frame(40, 30, 90), frame(8, 8, 0)];
+        let decoded = decode_frames(&encode(&frames, 10));
+
+        assert_eq!(decoded.len(), 2);
+        for (frame, indices) in frames.iter().zip(decoded) {
+            let expected: Vec<u8> = frame.rgb.chunks_exact(3).map(|rgb| palette_index(rgb[0], rgb[1], rgb[2])).collect();
+            assert_eq!(indices, expected);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use crate::determinism::hash_generated_chunk;
+use crate::dimension::Dimension;
+use crate::worldgen::GENERATOR_VERSION;
+use crate::{CHUNK_SIZE, GOLDEN_WORLDGEN_FILE};
+
+// Golden worldgen hashes. `voxelfun --golden` generates a fixed set of chunks in every dimension
+// from a fixed seed and compares their hashes (determinism::hash_generated_chunk) against the
+// ones committed in GOLDEN_WORLDGEN_FILE, exiting non-zero on any difference. A refactor that
+// changes no terrain passes untouched. A change meant to give existing chunk keys different
+// blocks bumps worldgen::GENERATOR_VERSION and re-records with `voxelfun --golden record`.
+
+const GOLDEN_SEED: u32 = 0x601d_5eed;
+
+// Near the origin through every layer of its column, then scattered further out and on both
+// sides of zero, so biomes, caves, ores and structures all show up somewhere
+const GOLDEN_CHUNKS: [(i32, i32, i32); 16] = [
+    (0, -3, 0),
+    (0, -2, 0),
+    (0, -1, 0),
+    (0, 0, 0),
+    (0, 1, 0),
+    (0, 2, 0),
+    (1, 0, 0),
+    (-1, 0, -1),
+    (7, 0, -3),
+    (-12, 1, 5),
+    (25, 0, 25),
+    (-40, -1, 18),
+    (64, 0, -64),
+    (-250, 0, 130),
+    (1000, -1, -1000),
+    (-4096, 0, 4096),
+];
+
+type GoldenKey = (String, (i32, i32, i32));
+
+fn generate_hashes() -> BTreeMap<GoldenKey, u64> {
+    let mut hashes = BTreeMap::new();
+    for name in Dimension::NAMES {
+        let generator = Dimension::parse(name).unwrap().generator(GOLDEN_SEED);
+        for chunk_key in GOLDEN_CHUNKS {
+            let (chunk, overflow) = generator.generate(chunk_key, CHUNK_SIZE);
+            hashes.insert((name.to_string(), chunk_key), hash_generated_chunk(&chunk, &overflow));
+        }
+    }
+    hashes
+}
+
+// A "version <generator version>" line, then one "<dimension> <x> <y> <z> <hash>" line per
+// chunk with the hash in hex
+fn format_hashes(hashes: &BTreeMap<GoldenKey, u64>) -> String {
+    let mut contents = format!("version {}\n", GENERATOR_VERSION);
+    for ((dimension, (x, y, z)), hash) in hashes {
+        contents.push_str(&format!("{} {} {} {} {:016x}\n", dimension, x, y, z, hash));
+    }
+    contents
+}
+
+fn parse_hashes(contents: &str) -> (Option<u32>, BTreeMap<GoldenKey, u64>) {
+    let mut version = None;
+    let mut hashes = BTreeMap::new();
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["version", value] => version = value.parse().ok(),
+            [dimension, x, y, z, hash] => {
+                let (Ok(x), Ok(y), Ok(z), Ok(hash)) = (x.parse(), y.parse(), z.parse(), u64::from_str_radix(hash, 16)) else {
+                    continue;
+                };
+                hashes.insert((dimension.to_string(), (x, y, z)), hash);
+            }
+            _ => {}
+        }
+    }
+    (version, hashes)
+}
+
+// `voxelfun --golden [record]`. Returns whether every chunk matched (or the hashes were written).
+pub fn run(record: bool) -> bool {
+    let path = Path::new(GOLDEN_WORLDGEN_FILE);
+    let hashes = generate_hashes();
+
+    if record {
+        let written = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, format_hashes(&hashes)));
+        return match written {
+            Ok(()) => {
+                println!("Recorded {} golden chunk hashes to {}", hashes.len(), path.display());
+                true
+            }
+            Err(err) => {
+                eprintln!("Could not write {}: {}", path.display(), err);
+                false
+            }
+        };
+    }
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("Could not read {}: {} (record it with --golden record)", path.display(), err);
+            return false;
+        }
+    };
+    let (version, expected) = parse_hashes(&contents);
+    if version != Some(GENERATOR_VERSION) {
+        eprintln!(
+            "{} was recorded for generator version {}, this is version {}; re-record it with --golden record",
+            path.display(),
+            version.map_or("?".to_string(), |version| version.to_string()),
+            GENERATOR_VERSION,
+        );
+        return false;
+    }
+
+    let mut mismatches = 0;
+    for (key, hash) in &hashes {
+        let (dimension, chunk_key) = key;
+        match expected.get(key) {
+            Some(expected_hash) if expected_hash == hash => {}
+            Some(expected_hash) => {
+                mismatches += 1;
+                println!("{} chunk {:?} changed: expected {:016x}, got {:016x}", dimension, chunk_key, expected_hash, hash);
+            }
+            None => {
+                mismatches += 1;
+                println!("{} chunk {:?} has no golden hash", dimension, chunk_key);
+            }
+        }
+    }
+
+    if mismatches > 0 {
+        println!(
+            "{} of {} golden chunks differ. If the change is intended, bump GENERATOR_VERSION and re-record with --golden record",
+            mismatches,
+            hashes.len(),
+        );
+        return false;
+    }
+    println!("All {} golden chunks match", hashes.len());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cargo test runs from the crate root, where GOLDEN_WORLDGEN_FILE is relative to
+    #[test]
+    fn worldgen_matches_the_golden_hashes() {
+        assert!(run(false), "worldgen drifted from {}, see the output above", GOLDEN_WORLDGEN_FILE);
+    }
+
+    #[test]
+    fn recorded_hashes_parse_back() {
+        let hashes = generate_hashes();
+        assert_eq!(parse_hashes(&format_hashes(&hashes)), (Some(GENERATOR_VERSION), hashes));
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use crate::block::BlockId;
+
+#[derive(Clone, Copy, Debug)]
+pub struct VoxelEdit {
+    pub chunk_key: (i32, i32, i32),
+    pub voxel_pos: (usize, usize, usize),
+    pub old: BlockId,
+    pub new: BlockId,
+}
+
+// Undo/redo stacks of voxel edit batches. One batch is one user action.
+#[derive(Resource)]
+pub struct EditHistory {
+    undo_stack: Vec<Vec<VoxelEdit>>,
+    redo_stack: Vec<Vec<VoxelEdit>>,
+    pub max_batches: usize,
+}
+
+impl EditHistory {
+    pub fn new(max_batches: usize) -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            max_batches,
+        }
+    }
+
+    pub fn record(&mut self, batch: Vec<VoxelEdit>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        self.undo_stack.push(batch);
+        // A fresh edit invalidates everything that was undone before it
+        self.redo_stack.clear();
+
+        if self.undo_stack.len() > self.max_batches {
+            let overflow = self.undo_stack.len() - self.max_batches;
+            self.undo_stack.drain(..overflow);
+        }
+    }
+
+    // The batch undo would revert, without reverting it
+    pub fn peek_undo(&self) -> Option<&[VoxelEdit]> {
+        self.undo_stack.last().map(Vec::as_slice)
+    }
+
+    pub fn peek_redo(&self) -> Option<&[VoxelEdit]> {
+        self.redo_stack.last().map(Vec::as_slice)
+    }
+
+    pub fn undo(&mut self) -> Option<Vec<VoxelEdit>> {
+        let batch = self.undo_stack.pop()?;
+        self.redo_stack.push(batch.clone());
+        Some(batch)
+    }
+
+    pub fn redo(&mut self) -> Option<Vec<VoxelEdit>> {
+        let batch = self.redo_stack.pop()?;
+        self.undo_stack.push(batch.clone());
+        Some(batch)
+    }
+}
@@ -0,0 +1,182 @@
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use crate::block::{self, BlockId, CHEST, DIRT, GLASS, GRASS, LOG, SIGN, STONE, STONE_BRICKS, TORCH};
+use crate::inventory::{CreativeMode, Inventory};
+use crate::keybindings::{Action, Actions};
+use crate::theme::Theme;
+
+pub const HOTBAR_SLOTS: usize = 9;
+
+#[derive(Resource)]
+pub struct Hotbar {
+    pub slots: [BlockId; HOTBAR_SLOTS],
+    pub selected: usize,
+}
+
+impl Default for Hotbar {
+    fn default() -> Self {
+        Self {
+            slots: [STONE, DIRT, GRASS, LOG, TORCH, GLASS, STONE_BRICKS, CHEST, SIGN],
+            selected: 0,
+        }
+    }
+}
+
+// Block the placement system puts down, kept in sync with the hotbar selection
+#[derive(Resource)]
+pub struct SelectedBlock(pub BlockId);
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        Self(STONE)
+    }
+}
+
+#[derive(Component)]
+pub struct HotbarSlot(pub usize);
+
+#[derive(Component)]
+pub struct HotbarIcon(pub usize);
+
+// How many of the slot's block the inventory holds, empty in creative mode
+#[derive(Component)]
+pub struct HotbarCount(pub usize);
+
+fn count_label(inventory: &Inventory, creative: &CreativeMode, block: BlockId) -> String {
+    if creative.enabled {
+        String::new()
+    } else {
+        inventory.count(block).to_string()
+    }
+}
+
+pub fn block_icon_color(block: BlockId) -> Color {
+    // Same linear values the mesher writes into vertex colors
+    let [r, g, b] = block::variant_color(block, 0);
+    Color::linear_rgb(r, g, b)
+}
+
+pub fn spawn_hotbar(mut commands: Commands, hotbar: Res<Hotbar>, theme: Res<Theme>) {
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(16.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            column_gap: Val::Px(4.0),
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        for (index, &block) in hotbar.slots.iter().enumerate() {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(48.0),
+                        height: Val::Px(48.0),
+                        border: UiRect::all(Val::Px(3.0)),
+                        padding: UiRect::all(Val::Px(6.0)),
+                        ..default()
+                    },
+                    background_color: theme.hud_background.into(),
+                    border_color: theme.hud_background.into(),
+                    ..default()
+                },
+                HotbarSlot(index),
+            )).with_children(|slot| {
+                slot.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: block_icon_color(block).into(),
+                        ..default()
+                    },
+                    HotbarIcon(index),
+                ));
+                slot.spawn((
+                    TextBundle {
+                        text: Text::from_section(String::new(), TextStyle {
+                            font_size: 14.0,
+                            color: theme.hud_text,
+                            ..default()
+                        }),
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            right: Val::Px(3.0),
+                            bottom: Val::Px(1.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    HotbarCount(index),
+                ));
+            });
+        }
+    });
+}
+
+pub fn select_hotbar_slot(
+    actions: Actions,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut hotbar: ResMut<Hotbar>,
+    mut selected_block: ResMut<SelectedBlock>,
+) {
+    let mut selected = hotbar.selected;
+
+    if let Some(index) = Action::HOTBAR.iter().position(|&action| actions.just_pressed(action)) {
+        selected = index;
+    }
+
+    // Modifier + wheel changes the camera speed instead
+    let scrolling_speed = actions.pressed(Action::SpeedModifier);
+    for event in mouse_wheel_events.read().filter(|_| !scrolling_speed) {
+        // Scrolling down moves right, like most games
+        if event.y < 0.0 {
+            selected = (selected + 1) % HOTBAR_SLOTS;
+        } else if event.y > 0.0 {
+            selected = (selected + HOTBAR_SLOTS - 1) % HOTBAR_SLOTS;
+        }
+    }
+
+    if selected != hotbar.selected {
+        hotbar.selected = selected;
+    }
+    let block = hotbar.slots[hotbar.selected];
+    if selected_block.0 != block {
+        selected_block.0 = block;
+    }
+}
+
+pub fn update_hotbar_ui(
+    hotbar: Res<Hotbar>,
+    theme: Res<Theme>,
+    inventory: Res<Inventory>,
+    creative: Res<CreativeMode>,
+    mut slot_query: Query<(&HotbarSlot, &mut BorderColor, &mut BackgroundColor), Without<HotbarIcon>>,
+    mut icon_query: Query<(&HotbarIcon, &mut BackgroundColor), Without<HotbarSlot>>,
+    mut count_query: Query<(&HotbarCount, &mut Text)>,
+) {
+    if !hotbar.is_changed() && !theme.is_changed() && !inventory.is_changed() && !creative.is_changed() {
+        return;
+    }
+
+    for (slot, mut border_color, mut background_color) in &mut slot_query {
+        border_color.0 = if slot.0 == hotbar.selected { theme.selection_highlight } else { theme.hud_background };
+        background_color.0 = theme.hud_background;
+    }
+
+    for (icon, mut background_color) in &mut icon_query {
+        // Blocks that have run out are greyed out
+        let block = hotbar.slots[icon.0];
+        let available = creative.enabled || inventory.count(block) > 0;
+        background_color.0 = block_icon_color(block).with_alpha(if available { 1.0 } else { 0.3 });
+    }
+
+    for (count, mut text) in &mut count_query {
+        text.sections[0].value = count_label(&inventory, &creative, hotbar.slots[count.0]);
+        text.sections[0].style.color = theme.hud_text;
+    }
+}
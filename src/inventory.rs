@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use crate::block::{self, BlockId};
+use crate::console::{Console, ConsoleCommand};
+
+// Blocks the player has picked up, by block
+#[derive(Resource, Default, Debug)]
+pub struct Inventory {
+    pub counts: HashMap<BlockId, u32>,
+}
+
+impl Inventory {
+    pub fn count(&self, block: BlockId) -> u32 {
+        self.counts.get(&block).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, block: BlockId, count: u32) {
+        *self.counts.entry(block).or_insert(0) += count;
+    }
+
+    // Takes one block out, false if there is none left
+    pub fn take_one(&mut self, block: BlockId) -> bool {
+        match self.counts.get_mut(&block) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&block);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Settles replaying voxel edits as (from, to) pairs, like an undo or redo does outside
+    // creative mode: every block put back costs one, every block taken away is given back.
+    // Returns the first block the player has too few of, leaving the inventory untouched.
+    pub fn charge_edits(&mut self, edits: impl IntoIterator<Item = (BlockId, BlockId)>) -> Result<(), BlockId> {
+        let mut net: HashMap<BlockId, i64> = HashMap::new();
+        for (from, to) in edits {
+            // Air, liquids and thin blocks are never items
+            if !block::is_replaceable(to) {
+                *net.entry(to).or_default() += 1;
+            }
+            if !block::is_replaceable(from) {
+                *net.entry(from).or_default() -= 1;
+            }
+        }
+        if let Some((&short, _)) = net.iter().find(|&(&block, &cost)| cost > self.count(block) as i64) {
+            return Err(short);
+        }
+        for (block, cost) in net {
+            if cost > 0 {
+                let count = self.counts.entry(block).or_insert(0);
+                *count -= cost as u32;
+                if *count == 0 {
+                    self.counts.remove(&block);
+                }
+            } else if cost < 0 {
+                self.add(block, (-cost) as u32);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Creative mode: placing never runs out and uses nothing up, and broken blocks don't drop.
+// Toggled with `/creative [on|off]`.
+#[derive(Resource, Default, Debug)]
+pub struct CreativeMode {
+    pub enabled: bool,
+}
+
+pub fn creative_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut creative: ResMut<CreativeMode>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "creative") {
+        let enabled = match command.args.first().map(String::as_str) {
+            None => !creative.enabled,
+            Some("on" | "true") => true,
+            Some("off" | "false") => false,
+            Some(value) => {
+                console.print(format!("Expected on or off, got '{}'", value));
+                continue;
+            }
+        };
+        creative.enabled = enabled;
+        console.print(format!("Creative mode {}", if enabled { "on" } else { "off" }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{AIR, STONE, WATER};
+
+    #[test]
+    fn undoing_edits_moves_blocks_through_the_inventory() {
+        let mut inventory = Inventory::default();
+        inventory.add(STONE, 1);
+
+        // Place the stone, then undo the placement: it comes back
+        assert!(inventory.take_one(STONE));
+        inventory.charge_edits([(STONE, AIR)]).unwrap();
+        assert_eq!(inventory.count(STONE), 1);
+
+        // Redoing it takes the stone again, and a second redo can't be paid for
+        inventory.charge_edits([(AIR, STONE)]).unwrap();
+        assert_eq!(inventory.count(STONE), 0);
+        assert_eq!(inventory.charge_edits([(WATER, STONE)]), Err(STONE));
+
+        // Undoing a removal re-creates the block, so it costs one
+        inventory.add(STONE, 1);
+        inventory.charge_edits([(AIR, STONE), (AIR, STONE)]).unwrap_err();
+        assert_eq!(inventory.count(STONE), 1);
+        inventory.charge_edits([(AIR, STONE)]).unwrap();
+        assert!(inventory.counts.is_empty());
+    }
+}
@@ -0,0 +1,352 @@
+use bevy::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use crate::block::{self, BlockId};
+#[cfg(feature = "render")]
+use crate::blob_shadow::BlobShadow;
+#[cfg(feature = "render")]
+use crate::hotbar::block_icon_color;
+use crate::inventory::Inventory;
+use crate::player::{PlayerBody, EYE_HEIGHT, GRAVITY, TERMINAL_VELOCITY};
+use crate::storage::ByteReader;
+use crate::voxel_world::VoxelWorld;
+use crate::world::World;
+
+#[cfg(feature = "render")]
+const ITEM_SIZE: f32 = 0.25;
+pub const MAX_STACK: u32 = 64;
+// Identical drops closer than this combine into one stack
+const MERGE_RADIUS: f32 = 0.75;
+// Drops within this distance of the player fly towards them
+const MAGNET_RADIUS: f32 = 3.0;
+const MAGNET_ACCELERATION: f32 = 40.0;
+const PICKUP_RADIUS: f32 = 0.6;
+// Fresh drops pop out of the broken block before they can be collected
+const PICKUP_DELAY: f32 = 0.4;
+pub const POP_SPEED: f32 = 4.0;
+const GROUND_FRICTION: f32 = 8.0;
+// Drops on the ground sliding slower than this stop, so resting drops stay where they are saved
+const REST_SPEED: f32 = 0.01;
+#[cfg(feature = "render")]
+const BOB_HEIGHT: f32 = 0.08;
+#[cfg(feature = "render")]
+const BOB_SPEED: f32 = 2.5;
+#[cfg(feature = "render")]
+const SPIN_SPEED: f32 = 1.2;
+
+// A stack of blocks lying in the world. The entity sits at the bottom centre of the stack;
+// its ItemDropModel child, added by attach_item_drop_models, does the bobbing and spinning.
+#[derive(Component)]
+pub struct ItemDrop {
+    pub block: BlockId,
+    pub count: u32,
+    pub velocity: Vec3,
+    pub age: f32,
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct ItemDropModel;
+
+// Saved form of a drop, owned by the chunk it lies in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StoredItemDrop {
+    pub position: Vec3,
+    pub block: BlockId,
+    pub count: u32,
+}
+
+impl StoredItemDrop {
+    // Serialized form used inside chunk blobs, see chunk_format
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        for coord in self.position.to_array() {
+            bytes.extend_from_slice(&coord.to_bits().to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.block.to_le_bytes());
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Option<Self> {
+        let mut coord = || reader.read_u32().map(f32::from_bits);
+        let position = Vec3::new(coord()?, coord()?, coord()?);
+        Some(Self { position, block: reader.read_u16()?, count: reader.read_u32()? })
+    }
+}
+
+#[derive(Event)]
+pub struct SpawnItemDrop {
+    pub position: Vec3,
+    pub block: BlockId,
+    pub count: u32,
+    // Straight up at POP_SPEED for a dropped block
+    pub velocity: Vec3,
+}
+
+#[cfg(feature = "render")]
+#[derive(Resource)]
+pub struct ItemDropAssets {
+    mesh: Handle<Mesh>,
+    materials: HashMap<BlockId, Handle<StandardMaterial>>,
+}
+
+// Chunks whose stored drops have been spawned as entities
+#[derive(Resource, Default)]
+pub struct LoadedItemDrops {
+    chunks: HashSet<(i32, i32, i32)>,
+}
+
+#[cfg(feature = "render")]
+pub fn setup_item_drops(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ItemDropAssets {
+        mesh: meshes.add(Cuboid::from_length(ITEM_SIZE)),
+        materials: HashMap::new(),
+    });
+}
+
+fn spawn_drop(commands: &mut Commands, drop: StoredItemDrop, velocity: Vec3, age: f32) {
+    commands.spawn((
+        ItemDrop { block: drop.block, count: drop.count, velocity, age },
+        TransformBundle::from_transform(Transform::from_translation(drop.position)),
+        Name::new(format!("{} x{}", block::definition(drop.block).name, drop.count)),
+    ));
+}
+
+// Gives every new drop its cube and shadow; the simulation above runs without them
+#[cfg(feature = "render")]
+pub fn attach_item_drop_models(
+    mut commands: Commands,
+    mut assets: ResMut<ItemDropAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    drops: Query<(Entity, &ItemDrop), Added<ItemDrop>>,
+) {
+    for (entity, drop) in &drops {
+        let material = assets.materials.entry(drop.block)
+            .or_insert_with(|| materials.add(StandardMaterial {
+                base_color: block_icon_color(drop.block),
+                perceptual_roughness: 0.9,
+                ..default()
+            }))
+            .clone();
+        commands.entity(entity)
+            .insert((VisibilityBundle::default(), BlobShadow { radius: ITEM_SIZE }))
+            .with_children(|parent| {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: assets.mesh.clone(),
+                        material,
+                        transform: Transform::from_xyz(0.0, ITEM_SIZE * 0.5, 0.0),
+                        ..default()
+                    },
+                    ItemDropModel,
+                ));
+            });
+    }
+}
+
+pub fn spawn_item_drops(
+    mut commands: Commands,
+    mut spawn_events: EventReader<SpawnItemDrop>,
+) {
+    for event in spawn_events.read() {
+        let drop = StoredItemDrop { position: event.position, block: event.block, count: event.count };
+        spawn_drop(&mut commands, drop, event.velocity, 0.0);
+    }
+}
+
+// Spawns the stored drops of newly loaded chunks and despawns drops whose chunk went away.
+// Unloading saves the chunk first, so those drops are already on disk.
+pub fn sync_item_drops(
+    mut commands: Commands,
+    world: Res<World>,
+    mut loaded: ResMut<LoadedItemDrops>,
+    drops: Query<(Entity, &Transform), With<ItemDrop>>,
+) {
+    loaded.chunks.retain(|chunk_key| world.chunks.contains_key(chunk_key));
+    for (entity, transform) in &drops {
+        let (chunk_key, _) = world.world_to_voxel(transform.translation.floor().as_ivec3());
+        if !world.chunks.contains_key(&chunk_key) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for (&chunk_key, chunk) in &world.chunks {
+        if !loaded.chunks.insert(chunk_key) {
+            continue;
+        }
+        for &drop in &chunk.item_drops {
+            spawn_drop(&mut commands, drop, Vec3::ZERO, PICKUP_DELAY);
+        }
+    }
+}
+
+// Writes the current drops back into their chunks so they are saved with them
+pub fn store_item_drops(
+    mut world: ResMut<World>,
+    drops: Query<(&ItemDrop, &Transform)>,
+) {
+    let mut by_chunk: HashMap<(i32, i32, i32), Vec<StoredItemDrop>> = HashMap::new();
+    for (drop, transform) in &drops {
+        let (chunk_key, _) = world.world_to_voxel(transform.translation.floor().as_ivec3());
+        by_chunk.entry(chunk_key).or_default().push(StoredItemDrop {
+            position: transform.translation,
+            block: drop.block,
+            count: drop.count,
+        });
+    }
+
+    // Query order shifts as drops spawn and despawn elsewhere; sort so it doesn't count as a change
+    for stored in by_chunk.values_mut() {
+        stored.sort_by_key(|drop| (drop.block, drop.count, drop.position.to_array().map(f32::to_bits)));
+    }
+
+    // Only touch chunks whose list changed, so World change detection stays quiet and idle
+    // drops don't get their chunk saved again
+    let changed: Vec<_> = world.chunks.iter()
+        .filter(|(chunk_key, chunk)| {
+            let current = by_chunk.get(*chunk_key).map(Vec::as_slice).unwrap_or_default();
+            chunk.item_drops != current
+        })
+        .map(|(&chunk_key, _)| chunk_key)
+        .collect();
+    for chunk_key in changed {
+        let current = by_chunk.remove(&chunk_key).unwrap_or_default();
+        world.chunks.get_mut(&chunk_key).unwrap().item_drops = current;
+        world.modified_chunks.insert(chunk_key);
+    }
+}
+
+fn solid_at(voxel_world: &VoxelWorld, position: Vec3) -> bool {
+    voxel_world.is_solid(voxel_world.voxel_at(position))
+}
+
+// Gravity, ground friction and the pull towards the player, plus pickup on contact
+pub fn update_item_drops(
+    mut commands: Commands,
+    time: Res<Time>,
+    voxel_world: VoxelWorld,
+    mut inventory: ResMut<Inventory>,
+    player_query: Query<&Transform, (With<PlayerBody>, Without<ItemDrop>)>,
+    mut drops: Query<(Entity, &mut ItemDrop, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+    let player = player_query.get_single().ok().map(|transform| transform.translation);
+
+    for (entity, mut drop, mut transform) in &mut drops {
+        drop.age += dt;
+        let position = transform.translation;
+
+        // Closest point on the player's body, from the feet up to the eyes
+        let target = player.map(|eye| {
+            let feet = eye - Vec3::Y * EYE_HEIGHT;
+            Vec3::new(eye.x, position.y.clamp(feet.y, eye.y), eye.z)
+        });
+        let distance = target.map(|target| target.distance(position));
+        let collectable = drop.age >= PICKUP_DELAY;
+
+        if collectable && distance.is_some_and(|distance| distance < PICKUP_RADIUS) {
+            inventory.add(drop.block, drop.count);
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        if let (true, Some(target), Some(distance)) = (collectable, target, distance) {
+            if distance < MAGNET_RADIUS {
+                // Pulled straight in, through anything in the way
+                let direction = (target - position).normalize_or_zero();
+                drop.velocity += direction * MAGNET_ACCELERATION * dt;
+                drop.velocity = drop.velocity.project_onto(direction).clamp_length_max(MAGNET_RADIUS * 4.0);
+                transform.translation += drop.velocity * dt;
+                continue;
+            }
+        }
+
+        // Drops stay put at the edge of loaded terrain rather than fall out of the world
+        if voxel_world.get_block(voxel_world.voxel_at(position)).is_none() {
+            continue;
+        }
+
+        drop.velocity.y = (drop.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+        let target = position + drop.velocity * dt;
+        let mut moved = position;
+        for axis in 0..3 {
+            let mut candidate = moved;
+            candidate[axis] = target[axis];
+            if !solid_at(&voxel_world, candidate) && voxel_world.get_block(voxel_world.voxel_at(candidate)).is_some() {
+                moved = candidate;
+            } else {
+                drop.velocity[axis] = 0.0;
+            }
+        }
+        transform.translation = moved;
+
+        let grounded = solid_at(&voxel_world, moved - Vec3::Y * 0.01);
+        if grounded {
+            let friction = (1.0 - GROUND_FRICTION * dt).max(0.0);
+            drop.velocity.x *= friction;
+            drop.velocity.z *= friction;
+            if drop.velocity.xz().length() < REST_SPEED {
+                drop.velocity.x = 0.0;
+                drop.velocity.z = 0.0;
+            }
+        }
+    }
+}
+
+// Folds identical nearby drops into the larger stack, up to MAX_STACK
+pub fn merge_item_drops(
+    mut commands: Commands,
+    mut drops: Query<(Entity, &mut ItemDrop, &Transform, &mut Name)>,
+) {
+    let mut stacks: Vec<(Entity, BlockId, u32, Vec3)> = drops.iter()
+        .map(|(entity, drop, transform, _)| (entity, drop.block, drop.count, transform.translation))
+        .collect();
+    // Larger stacks absorb smaller ones
+    stacks.sort_by_key(|stack| Reverse(stack.2));
+
+    let mut merged = HashSet::new();
+    for i in 0..stacks.len() {
+        if merged.contains(&stacks[i].0) {
+            continue;
+        }
+        for j in i + 1..stacks.len() {
+            let (into, from) = (stacks[i], stacks[j]);
+            if merged.contains(&from.0) || from.1 != into.1 || into.3.distance(from.3) > MERGE_RADIUS {
+                continue;
+            }
+            if into.2 + from.2 > MAX_STACK {
+                continue;
+            }
+            stacks[i].2 += from.2;
+            merged.insert(from.0);
+            commands.entity(from.0).despawn_recursive();
+        }
+    }
+
+    for (entity, block, count, _) in stacks {
+        if merged.contains(&entity) {
+            continue;
+        }
+        let Ok((_, mut drop, _, mut name)) = drops.get_mut(entity) else {
+            continue;
+        };
+        if drop.count != count {
+            drop.count = count;
+            *name = Name::new(format!("{} x{}", block::definition(block).name, count));
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn animate_item_drops(
+    drops: Query<&ItemDrop>,
+    mut models: Query<(&Parent, &mut Transform), With<ItemDropModel>>,
+) {
+    for (parent, mut transform) in &mut models {
+        let Ok(drop) = drops.get(parent.get()) else {
+            continue;
+        };
+        let bob = (drop.age * BOB_SPEED).sin() * 0.5 + 0.5;
+        transform.translation.y = ITEM_SIZE * 0.5 + bob * BOB_HEIGHT;
+        transform.rotation = Quat::from_rotation_y(drop.age * SPIN_SPEED);
+    }
+}
@@ -0,0 +1,567 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use crate::console::{Console, ConsoleCommand};
+
+// Everything the player can do with a key or mouse button. Systems ask for actions instead of
+// keys, so every binding can be changed in the keybindings file or with `/bind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Ascend,
+    Descend,
+    // Held while scrolling to change the flying speed instead of the hotbar slot
+    SpeedModifier,
+    // Walking: sprint; flying: fast-fly
+    Sprint,
+    BreakBlock,
+    PlaceBlock,
+    Undo,
+    Redo,
+    Hotbar1,
+    Hotbar2,
+    Hotbar3,
+    Hotbar4,
+    Hotbar5,
+    Hotbar6,
+    Hotbar7,
+    Hotbar8,
+    Hotbar9,
+    Pause,
+    Console,
+    DebugOverlay,
+    Noclip,
+    GameMode,
+    LaserTool,
+    ToonMode,
+    CycleTheme,
+    SmoothLighting,
+    DepthDarkness,
+    PixelArt,
+    Anisotropy,
+    ReachIndicator,
+    RegionCornerA,
+    RegionCornerB,
+    RegionFill,
+    RegionReplace,
+    RegionHollow,
+    RegionSphere,
+    RegionCylinder,
+    Symmetry,
+    SymmetryOrigin,
+    Blueprint,
+    BlueprintCapture,
+    BlueprintRotate,
+    BlueprintPlace,
+    Screenshot,
+    TuningPanel,
+    TuneNext,
+    TunePrevious,
+    TuneIncrease,
+    TuneDecrease,
+    ChunkSeams,
+}
+
+impl Action {
+    pub const ALL: [Action; 54] = [
+        Action::MoveForward, Action::MoveBack, Action::MoveLeft, Action::MoveRight,
+        Action::Ascend, Action::Descend, Action::SpeedModifier, Action::Sprint,
+        Action::BreakBlock, Action::PlaceBlock, Action::Undo, Action::Redo,
+        Action::Hotbar1, Action::Hotbar2, Action::Hotbar3, Action::Hotbar4, Action::Hotbar5,
+        Action::Hotbar6, Action::Hotbar7, Action::Hotbar8, Action::Hotbar9,
+        Action::Pause, Action::Console, Action::DebugOverlay, Action::Noclip, Action::GameMode,
+        Action::LaserTool, Action::ToonMode, Action::CycleTheme, Action::SmoothLighting,
+        Action::DepthDarkness, Action::PixelArt, Action::Anisotropy, Action::ReachIndicator,
+        Action::RegionCornerA, Action::RegionCornerB, Action::RegionFill, Action::RegionReplace,
+        Action::RegionHollow, Action::RegionSphere, Action::RegionCylinder,
+        Action::Symmetry, Action::SymmetryOrigin,
+        Action::Blueprint, Action::BlueprintCapture, Action::BlueprintRotate, Action::BlueprintPlace,
+        Action::Screenshot, Action::TuningPanel,
+        Action::TuneNext, Action::TunePrevious, Action::TuneIncrease, Action::TuneDecrease,
+        Action::ChunkSeams,
+    ];
+
+    pub const HOTBAR: [Action; 9] = [
+        Action::Hotbar1, Action::Hotbar2, Action::Hotbar3,
+        Action::Hotbar4, Action::Hotbar5, Action::Hotbar6,
+        Action::Hotbar7, Action::Hotbar8, Action::Hotbar9,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBack => "move_back",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Ascend => "ascend",
+            Action::Descend => "descend",
+            Action::SpeedModifier => "speed_modifier",
+            Action::Sprint => "sprint",
+            Action::BreakBlock => "break_block",
+            Action::PlaceBlock => "place_block",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Hotbar1 => "hotbar_1",
+            Action::Hotbar2 => "hotbar_2",
+            Action::Hotbar3 => "hotbar_3",
+            Action::Hotbar4 => "hotbar_4",
+            Action::Hotbar5 => "hotbar_5",
+            Action::Hotbar6 => "hotbar_6",
+            Action::Hotbar7 => "hotbar_7",
+            Action::Hotbar8 => "hotbar_8",
+            Action::Hotbar9 => "hotbar_9",
+            Action::Pause => "pause",
+            Action::Console => "console",
+            Action::DebugOverlay => "debug_overlay",
+            Action::Noclip => "noclip",
+            Action::GameMode => "game_mode",
+            Action::LaserTool => "laser_tool",
+            Action::ToonMode => "toon_mode",
+            Action::CycleTheme => "cycle_theme",
+            Action::SmoothLighting => "smooth_lighting",
+            Action::DepthDarkness => "depth_darkness",
+            Action::PixelArt => "pixel_art",
+            Action::Anisotropy => "anisotropy",
+            Action::ReachIndicator => "reach_indicator",
+            Action::RegionCornerA => "region_corner_a",
+            Action::RegionCornerB => "region_corner_b",
+            Action::RegionFill => "region_fill",
+            Action::RegionReplace => "region_replace",
+            Action::RegionHollow => "region_hollow",
+            Action::RegionSphere => "region_sphere",
+            Action::RegionCylinder => "region_cylinder",
+            Action::Symmetry => "symmetry",
+            Action::SymmetryOrigin => "symmetry_origin",
+            Action::Blueprint => "blueprint",
+            Action::BlueprintCapture => "blueprint_capture",
+            Action::BlueprintRotate => "blueprint_rotate",
+            Action::BlueprintPlace => "blueprint_place",
+            Action::Screenshot => "screenshot",
+            Action::TuningPanel => "tuning_panel",
+            Action::TuneNext => "tune_next",
+            Action::TunePrevious => "tune_previous",
+            Action::TuneIncrease => "tune_increase",
+            Action::TuneDecrease => "tune_decrease",
+            Action::ChunkSeams => "chunk_seams",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    fn default_bindings(self) -> &'static [&'static str] {
+        match self {
+            Action::MoveForward => &["KeyW"],
+            Action::MoveBack => &["KeyS"],
+            Action::MoveLeft => &["KeyA"],
+            Action::MoveRight => &["KeyD"],
+            Action::Ascend => &["Space"],
+            Action::Descend => &["ShiftLeft"],
+            Action::SpeedModifier => &["AltLeft"],
+            Action::Sprint => &["ControlLeft"],
+            Action::BreakBlock => &["MouseLeft"],
+            Action::PlaceBlock => &["MouseRight"],
+            Action::Undo => &["Ctrl+KeyZ"],
+            Action::Redo => &["Ctrl+KeyY"],
+            Action::Hotbar1 => &["Digit1"],
+            Action::Hotbar2 => &["Digit2"],
+            Action::Hotbar3 => &["Digit3"],
+            Action::Hotbar4 => &["Digit4"],
+            Action::Hotbar5 => &["Digit5"],
+            Action::Hotbar6 => &["Digit6"],
+            Action::Hotbar7 => &["Digit7"],
+            Action::Hotbar8 => &["Digit8"],
+            Action::Hotbar9 => &["Digit9"],
+            Action::Pause => &["Escape"],
+            Action::Console => &["Slash"],
+            Action::DebugOverlay => &["F3"],
+            Action::Noclip => &["F6"],
+            Action::GameMode => &["KeyG"],
+            Action::LaserTool => &["KeyL"],
+            // Was F2 until screenshots took it
+            Action::ToonMode => &["F12"],
+            Action::CycleTheme => &["F7"],
+            Action::SmoothLighting => &["F9"],
+            Action::DepthDarkness => &["F10"],
+            Action::PixelArt => &["F4"],
+            Action::Anisotropy => &["F8"],
+            Action::ReachIndicator => &["F5"],
+            Action::RegionCornerA => &["BracketLeft"],
+            Action::RegionCornerB => &["BracketRight"],
+            Action::RegionFill => &["Alt+KeyF"],
+            Action::RegionReplace => &["Alt+KeyR"],
+            Action::RegionHollow => &["Alt+KeyH"],
+            Action::RegionSphere => &["Alt+KeyO"],
+            Action::RegionCylinder => &["Alt+KeyC"],
+            Action::Symmetry => &["KeyM"],
+            Action::SymmetryOrigin => &["Shift+KeyM"],
+            Action::Blueprint => &["KeyB"],
+            Action::BlueprintCapture => &["Alt+KeyB"],
+            Action::BlueprintRotate => &["KeyR"],
+            Action::BlueprintPlace => &["Enter"],
+            Action::Screenshot => &["F2"],
+            Action::TuningPanel => &["F1"],
+            // Only while the tuning panel is open
+            Action::TuneNext => &["ArrowDown"],
+            Action::TunePrevious => &["ArrowUp"],
+            Action::TuneIncrease => &["ArrowRight"],
+            Action::TuneDecrease => &["ArrowLeft"],
+            Action::ChunkSeams => &["F11"],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+}
+
+impl Modifier {
+    const ALL: [Modifier; 3] = [Modifier::Ctrl, Modifier::Shift, Modifier::Alt];
+
+    fn name(self) -> &'static str {
+        match self {
+            Modifier::Ctrl => "Ctrl",
+            Modifier::Shift => "Shift",
+            Modifier::Alt => "Alt",
+        }
+    }
+
+    // Either side counts
+    fn held(self, keys: &ButtonInput<KeyCode>) -> bool {
+        let (left, right) = match self {
+            Modifier::Ctrl => (KeyCode::ControlLeft, KeyCode::ControlRight),
+            Modifier::Shift => (KeyCode::ShiftLeft, KeyCode::ShiftRight),
+            Modifier::Alt => (KeyCode::AltLeft, KeyCode::AltRight),
+        };
+        keys.any_pressed([left, right])
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+// Keys that can be bound, by their KeyCode name
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF,
+    KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL,
+    KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR,
+    KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX,
+    KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Escape, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight,
+    KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Slash, KeyCode::Backslash,
+    KeyCode::Backquote, KeyCode::Minus, KeyCode::Equal, KeyCode::Comma, KeyCode::Period,
+    KeyCode::Semicolon, KeyCode::Quote,
+];
+
+const MOUSE_BUTTONS: [(MouseButton, &str); 3] = [
+    (MouseButton::Left, "MouseLeft"),
+    (MouseButton::Right, "MouseRight"),
+    (MouseButton::Middle, "MouseMiddle"),
+];
+
+impl Input {
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(&(button, _)) = MOUSE_BUTTONS.iter().find(|(_, button_name)| *button_name == name) {
+            return Some(Input::Mouse(button));
+        }
+        BINDABLE_KEYS.iter().find(|key| format!("{:?}", key) == name).map(|&key| Input::Key(key))
+    }
+
+    fn pressed(self, keys: &ButtonInput<KeyCode>, mouse: &ButtonInput<MouseButton>) -> bool {
+        match self {
+            Input::Key(key) => keys.pressed(key),
+            Input::Mouse(button) => mouse.pressed(button),
+        }
+    }
+
+    fn just_pressed(self, keys: &ButtonInput<KeyCode>, mouse: &ButtonInput<MouseButton>) -> bool {
+        match self {
+            Input::Key(key) => keys.just_pressed(key),
+            Input::Mouse(button) => mouse.just_pressed(button),
+        }
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Key(key) => write!(f, "{:?}", key),
+            Input::Mouse(button) => {
+                let name = MOUSE_BUTTONS.iter().find(|(b, _)| b == button).map_or("MouseOther", |(_, name)| name);
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+// A key or mouse button, optionally with a modifier that has to be held: "KeyW", "Ctrl+KeyZ"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub modifier: Option<Modifier>,
+    pub input: Input,
+}
+
+impl Binding {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.split_once('+') {
+            Some((modifier, input)) => Some(Self {
+                modifier: Some(Modifier::ALL.into_iter().find(|modifier_kind| modifier_kind.name() == modifier)?),
+                input: Input::parse(input)?,
+            }),
+            None => Some(Self { modifier: None, input: Input::parse(text)? }),
+        }
+    }
+
+    fn modifier_held(&self, keys: &ButtonInput<KeyCode>) -> bool {
+        self.modifier.is_none_or(|modifier| modifier.held(keys))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.modifier {
+            Some(modifier) => write!(f, "{}+{}", modifier.name(), self.input),
+            None => write!(f, "{}", self.input),
+        }
+    }
+}
+
+fn default_bindings(action: Action) -> Vec<Binding> {
+    action.default_bindings().iter().map(|text| Binding::parse(text).expect("invalid default binding")).collect()
+}
+
+// Bindings of every action, loaded from KEYBINDINGS_FILE. Actions missing from the file keep
+// their defaults; unknown actions and unparsable bindings are skipped with a message.
+#[derive(Resource, Debug)]
+pub struct KeyBindings {
+    bindings: HashMap<Action, Vec<Binding>>,
+    path: Option<PathBuf>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL.into_iter().map(|action| (action, default_bindings(action))).collect(),
+            path: None,
+        }
+    }
+}
+
+impl KeyBindings {
+    // Reads the file, writing one with the defaults if there is none yet so players can find it
+    pub fn load(path: &Path) -> Self {
+        let mut bindings = Self { path: Some(path.to_path_buf()), ..default() };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                if let Err(err) = bindings.save() {
+                    println!("Could not write default keybindings to {}: {}", path.display(), err);
+                }
+                return bindings;
+            }
+            Err(err) => {
+                println!("Could not read keybindings from {}: {}", path.display(), err);
+                return bindings;
+            }
+        };
+
+        let document = match contents.parse::<DocumentMut>() {
+            Ok(document) => document,
+            Err(err) => {
+                println!("{} is not valid TOML, using default keybindings: {}", path.display(), err);
+                return bindings;
+            }
+        };
+        let Some(table) = document.get("keybindings").and_then(|item| item.as_table_like()) else {
+            return bindings;
+        };
+
+        for (name, item) in table.iter() {
+            let Some(action) = Action::from_name(name) else {
+                println!("{}: unknown action '{}'", path.display(), name);
+                continue;
+            };
+            let texts: Vec<&str> = match (item.as_str(), item.as_array()) {
+                (Some(text), _) => vec![text],
+                (_, Some(array)) => array.iter().filter_map(|value| value.as_str()).collect(),
+                _ => {
+                    println!("{}: bindings of '{}' must be a string or a list of strings", path.display(), name);
+                    continue;
+                }
+            };
+            let mut parsed = Vec::new();
+            for text in texts {
+                match Binding::parse(text) {
+                    Some(binding) => parsed.push(binding),
+                    None => println!("{}: unknown key '{}' for '{}'", path.display(), text, name),
+                }
+            }
+            bindings.bindings.insert(action, parsed);
+        }
+        bindings
+    }
+
+    // Writes every action's bindings back to the file the bindings were loaded from
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = String::from(
+            "# Key and mouse bindings. Keys use their KeyCode names (KeyW, Digit1, F3, Space, ShiftLeft, ...),\n\
+             # mouse buttons are MouseLeft, MouseRight and MouseMiddle. Ctrl+, Shift+ or Alt+ in front makes a\n\
+             # chord. An action can have several bindings; an empty list unbinds it.\n\
+             [keybindings]\n",
+        );
+        for action in Action::ALL {
+            let bindings: Vec<String> = self.get(action).iter().map(|binding| format!("\"{}\"", binding)).collect();
+            contents.push_str(&format!("{} = [{}]\n", action.name(), bindings.join(", ")));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    // Runtime rebind; call save() to keep the change
+    pub fn rebind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    pub fn reset(&mut self) {
+        let path = self.path.take();
+        *self = Self { path, ..default() };
+    }
+
+    pub fn pressed(&self, action: Action, keys: &ButtonInput<KeyCode>, mouse: &ButtonInput<MouseButton>) -> bool {
+        self.get(action).iter().any(|binding| binding.modifier_held(keys) && binding.input.pressed(keys, mouse))
+    }
+
+    // A plain binding doesn't fire while another action's chord on the same input does, so
+    // KeyR (rotate the blueprint) stays quiet when Alt+KeyR (region replace) is pressed
+    pub fn just_pressed(&self, action: Action, keys: &ButtonInput<KeyCode>, mouse: &ButtonInput<MouseButton>) -> bool {
+        self.get(action).iter().any(|binding| {
+            binding.modifier_held(keys)
+                && binding.input.just_pressed(keys, mouse)
+                && (binding.modifier.is_some() || !self.chord_active(binding.input, keys))
+        })
+    }
+
+    // Whether `key` was this action's key, for systems reading raw keyboard events
+    pub fn triggered_by_key(&self, action: Action, key: KeyCode, keys: &ButtonInput<KeyCode>) -> bool {
+        self.get(action).iter().any(|binding| {
+            binding.input == Input::Key(key)
+                && binding.modifier_held(keys)
+                && (binding.modifier.is_some() || !self.chord_active(binding.input, keys))
+        })
+    }
+
+    fn chord_active(&self, input: Input, keys: &ButtonInput<KeyCode>) -> bool {
+        self.bindings.values().flatten().any(|binding| {
+            binding.input == input && binding.modifier.is_some_and(|modifier| modifier.held(keys))
+        })
+    }
+}
+
+// Action state for systems: `actions.just_pressed(Action::Undo)` instead of key checks
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    bindings: Res<'w, KeyBindings>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+}
+
+impl<'w> Actions<'w> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings.pressed(action, &self.keys, &self.mouse)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.bindings.just_pressed(action, &self.keys, &self.mouse)
+    }
+
+    // 1 while `positive` is held, -1 for `negative`, 0 for both or neither
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.pressed(positive) as i32 as f32 - self.pressed(negative) as i32 as f32
+    }
+}
+
+// `/bind` lists every binding, `/bind <action>` shows one, `/bind <action> <binding>...` rebinds it
+// (`none` unbinds), and `/bind reset` restores the defaults. Changes are saved right away.
+pub fn bind_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    let describe = |bindings: &KeyBindings, action: Action| {
+        let list: Vec<String> = bindings.get(action).iter().map(Binding::to_string).collect();
+        format!("{}: {}", action.name(), if list.is_empty() { "unbound".to_string() } else { list.join(", ") })
+    };
+
+    for command in console_commands.read().filter(|command| command.name == "bind") {
+        let Some(name) = command.args.first() else {
+            for action in Action::ALL {
+                console.print(describe(&bindings, action));
+            }
+            continue;
+        };
+        if name == "reset" {
+            bindings.reset();
+            console.print("Keybindings reset to defaults".to_string());
+        } else {
+            let Some(action) = Action::from_name(name) else {
+                console.print(format!("Unknown action '{}'", name));
+                continue;
+            };
+            if command.args.len() == 1 {
+                console.print(describe(&bindings, action));
+                continue;
+            }
+
+            let mut parsed = Vec::new();
+            for text in command.args[1..].iter().filter(|text| *text != "none") {
+                match Binding::parse(text) {
+                    Some(binding) => parsed.push(binding),
+                    None => {
+                        console.print(format!("Unknown key '{}'", text));
+                        parsed.clear();
+                        break;
+                    }
+                }
+            }
+            if parsed.is_empty() && command.args[1..].iter().any(|text| text != "none") {
+                continue;
+            }
+            bindings.rebind(action, parsed);
+            console.print(describe(&bindings, action));
+        }
+        if let Err(err) = bindings.save() {
+            console.print(format!("Could not save keybindings: {}", err));
+        }
+    }
+}
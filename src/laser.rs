@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use crate::block::{self, BlockId, AIR};
+use crate::history::{EditHistory, VoxelEdit};
+use crate::hotbar::SelectedBlock;
+use crate::keybindings::{Action, Actions};
+use crate::notifications::NotificationEvent;
+use crate::player::GameMode;
+use crate::theme::Theme;
+use crate::voxel_world::VoxelWorld;
+use crate::world::VoxelRay;
+use crate::{VoxelRemover, LASER_DISTANCE, LASER_EDITS_PER_FRAME};
+
+// Creative tool that edits every voxel along the view ray: left click bores a tunnel,
+// right click fills the empty cells along the ray with the selected block.
+// Edits are queued and applied a budget at a time so a long beam doesn't stall a frame.
+#[derive(Resource)]
+pub struct LaserTool {
+    pub enabled: bool,
+    pub distance: f32,
+    pub edits_per_frame: usize,
+    pending: VecDeque<(IVec3, BlockId)>,
+    // Everything applied for the current beam, recorded as one undo batch when the queue drains
+    applied: Vec<VoxelEdit>,
+}
+
+impl Default for LaserTool {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            distance: LASER_DISTANCE,
+            edits_per_frame: LASER_EDITS_PER_FRAME,
+            pending: VecDeque::new(),
+            applied: Vec::new(),
+        }
+    }
+}
+
+impl LaserTool {
+    pub fn is_busy(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+// Voxels a ray passes through, in order, traversed like World::raycast
+fn voxels_along_ray(ray: Ray3d, max_distance: f32) -> Vec<IVec3> {
+    VoxelRay::new(ray.origin, ray.direction, max_distance).map(|(voxel, _)| voxel).collect()
+}
+
+pub fn toggle_laser_tool(
+    actions: Actions,
+    game_mode: Res<GameMode>,
+    mut laser: ResMut<LaserTool>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    // Leaving creative flight puts the laser away
+    if *game_mode != GameMode::Flying {
+        laser.enabled = false;
+        return;
+    }
+
+    if actions.just_pressed(Action::LaserTool) {
+        laser.enabled = !laser.enabled;
+        notifications.send(NotificationEvent::info(format!("Laser tool {}", if laser.enabled { "on" } else { "off" })));
+    }
+}
+
+pub fn fire_laser(
+    actions: Actions,
+    camera_query: Query<&Transform, With<VoxelRemover>>,
+    selected_block: Res<SelectedBlock>,
+    voxel_world: VoxelWorld,
+    mut laser: ResMut<LaserTool>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if !laser.enabled || laser.is_busy() {
+        return;
+    }
+    let block = if actions.just_pressed(Action::BreakBlock) {
+        AIR
+    } else if actions.just_pressed(Action::PlaceBlock) {
+        selected_block.0
+    } else {
+        return;
+    };
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+
+    let ray = Ray3d::new(camera_transform.translation, *camera_transform.forward());
+    let eye_voxel = voxel_world.voxel_at(camera_transform.translation);
+    let targets: VecDeque<_> = voxels_along_ray(ray, laser.distance)
+        .into_iter()
+        // Never build inside the camera
+        .filter(|&pos| block == AIR || pos != eye_voxel)
+        .filter(|&pos| match voxel_world.get_block(pos) {
+            Some(current) if block == AIR => block::is_solid(current),
+            Some(current) => current == AIR,
+            None => false,
+        })
+        .map(|pos| (pos, block))
+        .collect();
+
+    notifications.send(NotificationEvent::info(format!("Laser queued {} voxel edit(s)", targets.len())));
+    laser.pending = targets;
+}
+
+pub fn apply_laser_edits(
+    mut voxel_world: VoxelWorld,
+    mut laser: ResMut<LaserTool>,
+    mut history: ResMut<EditHistory>,
+) {
+    if !laser.is_busy() {
+        return;
+    }
+
+    for _ in 0..laser.edits_per_frame {
+        let Some((pos, block)) = laser.pending.pop_front() else {
+            break;
+        };
+        // The voxel may have changed or unloaded since the beam was queued
+        let Some(old) = voxel_world.get_block(pos) else {
+            continue;
+        };
+        if old == block {
+            continue;
+        }
+
+        voxel_world.set_block(pos, block);
+        let (chunk_key, voxel_pos) = voxel_world.to_chunk_local(pos);
+        laser.applied.push(VoxelEdit { chunk_key, voxel_pos, old, new: block });
+    }
+
+    if !laser.is_busy() {
+        history.record(std::mem::take(&mut laser.applied));
+    }
+}
+
+pub fn draw_laser_beam(
+    laser: Res<LaserTool>,
+    camera_query: Query<&Transform, With<VoxelRemover>>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    if !laser.enabled {
+        return;
+    }
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+
+    // Start slightly below the eye so the beam is visible instead of a single point
+    let start = camera_transform.translation - camera_transform.up() * 0.2;
+    let end = camera_transform.translation + camera_transform.forward() * laser.distance;
+    gizmos.line(start, end, theme.debug_warning);
+}
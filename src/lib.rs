@@ -0,0 +1,865 @@
+#[cfg(feature = "render")]
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use crate::world::{AnchorMotion, ChunkScoped, ChunkUpdateBudget, StreamingAnchor, World};
+#[cfg(feature = "render")]
+use crate::chunk_pool::ChunkPool;
+#[cfg(feature = "render")]
+use crate::worldgen::WorldGenerator;
+#[cfg(feature = "render")]
+use crate::terrain::Chunk;
+#[cfg(feature = "render")]
+use crate::meshing::{ChunkMeshes, ChunkMeshingTask, ChunkTransparentSection, TerrainMeshes};
+#[cfg(feature = "render")]
+use crate::rendering::ChunkLod;
+#[cfg(feature = "render")]
+use crate::history::{EditHistory, VoxelEdit};
+#[cfg(feature = "render")]
+use crate::outline::{ChunkMaterials, OutlinedMaterial, ToonMode, VoxelOutline, VoxelOutlineSettings};
+#[cfg(feature = "render")]
+use bevy::pbr::ExtendedMaterial;
+#[cfg(feature = "render")]
+use bevy::ecs::system::SystemParam;
+#[cfg(feature = "render")]
+use crate::theme::Theme;
+#[cfg(feature = "render")]
+use crate::voxel_events::VoxelSetEvent;
+#[cfg(feature = "render")]
+use crate::block::{Transparency, AIR};
+#[cfg(feature = "render")]
+use crate::voxel_world::VoxelWorld;
+#[cfg(feature = "render")]
+use crate::hotbar::SelectedBlock;
+#[cfg(feature = "render")]
+use crate::tuning::LightingTuning;
+#[cfg(feature = "render")]
+use crate::worlds::WorldMeta;
+#[cfg(feature = "render")]
+use crate::player::{GameMode, Noclip, PlayerBody, PlayerReach};
+#[cfg(feature = "render")]
+use crate::laser::LaserTool;
+#[cfg(feature = "render")]
+use crate::symmetry::Symmetry;
+#[cfg(feature = "render")]
+use crate::water::{ChunkWater, TerrainMaterial, WaterMaterial};
+#[cfg(feature = "render")]
+use crate::blob_shadow::BlobShadow;
+#[cfg(feature = "render")]
+use crate::item_drop::SpawnItemDrop;
+#[cfg(feature = "render")]
+use crate::inventory::{CreativeMode, Inventory};
+#[cfg(feature = "render")]
+use crate::notifications::NotificationEvent;
+#[cfg(feature = "render")]
+use crate::keybindings::{Action, Actions};
+#[cfg(feature = "render")]
+use crate::camera_controller::CameraController;
+#[cfg(feature = "render")]
+use crate::selection::TargetedBlock;
+#[cfg(feature = "render")]
+use bevy::core_pipeline::Skybox;
+#[cfg(all(feature = "render", feature = "physics"))]
+use bevy_xpbd_3d::prelude::{Collider, RigidBody};
+#[cfg(feature = "render")]
+use std::pin::Pin;
+#[cfg(feature = "render")]
+use futures::FutureExt;
+#[cfg(feature = "render")]
+use std::task::{Context, Poll};
+#[cfg(feature = "render")]
+use std::collections::{BinaryHeap, HashMap};
+#[cfg(feature = "render")]
+use std::cmp::Ordering;
+#[cfg(feature = "render")]
+use std::path::Path;
+
+// Voxel engine as a library. VoxelEnginePlugins (see plugins.rs) adds everything to a Bevy app;
+// the modules stay public for games that embed the engine and want to reach into the world,
+// add blocks or replace one of the plugins. The `voxelfun` binary (main.rs) is one such game.
+//
+// Cargo features pick the subsystems: `render` (the windowed game and the plugins below),
+// `physics` (chunk colliders), `persistence` (autosave and the saved player) and `net` (the
+// server, and with `render` the client). A headless server or tool builds with
+// `--no-default-features` and keeps worldgen, chunk storage, the region format and World itself.
+pub mod terrain;
+#[cfg(feature = "render")]
+pub mod meshing;
+pub mod world;
+pub mod history;
+pub mod block;
+pub mod storage;
+#[cfg(feature = "render")]
+pub mod outline;
+#[cfg(feature = "render")]
+pub mod theme;
+pub mod voxel_events;
+#[cfg(feature = "render")]
+pub mod region_edit;
+#[cfg(feature = "render")]
+pub mod connected_textures;
+pub mod lighting;
+pub mod voxel_world;
+pub mod decoration;
+pub mod settings;
+pub mod worldgen;
+pub mod determinism;
+#[cfg(feature = "render")]
+pub mod hotbar;
+pub mod chunk_format;
+#[cfg(feature = "render")]
+pub mod rendering;
+#[cfg(feature = "render")]
+pub mod placeholders;
+#[cfg(feature = "render")]
+pub mod debug_overlay;
+pub mod save;
+pub mod player;
+#[cfg(feature = "render")]
+pub mod laser;
+#[cfg(feature = "render")]
+pub mod symmetry;
+#[cfg(feature = "render")]
+pub mod blueprint;
+pub mod console;
+pub mod game_rules;
+pub mod day_night;
+pub mod notifications;
+#[cfg(feature = "render")]
+pub mod sky;
+#[cfg(feature = "render")]
+pub mod water;
+pub mod block_entity;
+pub mod weather;
+#[cfg(feature = "render")]
+pub mod precipitation;
+// The browser build has no sockets to serve or connect over
+#[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+pub mod net;
+#[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+pub mod server;
+#[cfg(all(feature = "net", feature = "render", not(target_arch = "wasm32")))]
+pub mod client;
+#[cfg(feature = "render")]
+pub mod seasons;
+#[cfg(feature = "render")]
+pub mod blob_shadow;
+pub mod vox;
+pub mod inventory;
+pub mod item_drop;
+#[cfg(feature = "render")]
+pub mod export;
+#[cfg(feature = "render")]
+pub mod camera_controller;
+pub mod system_toggles;
+pub mod structures;
+#[cfg(feature = "render")]
+pub mod selection;
+pub mod torch;
+#[cfg(feature = "render")]
+pub mod particles;
+#[cfg(feature = "render")]
+pub mod block_textures;
+#[cfg(feature = "render")]
+pub mod bench;
+#[cfg(feature = "render")]
+pub mod crosshair;
+#[cfg(feature = "persistence")]
+pub mod upgrade;
+pub mod biome;
+pub mod keybindings;
+pub mod dimension;
+pub mod caves;
+#[cfg(feature = "render")]
+pub mod soak;
+pub mod chunk_pool;
+pub mod tasks;
+#[cfg(feature = "persistence")]
+pub mod autosave;
+pub mod worlds;
+pub mod gif;
+#[cfg(feature = "render")]
+pub mod capture;
+#[cfg(feature = "render")]
+pub mod tuning;
+#[cfg(feature = "render")]
+pub mod seams;
+pub mod block_updates;
+pub mod explosion;
+pub mod mob;
+pub mod spawn;
+#[cfg(feature = "render")]
+pub mod spectator;
+#[cfg(feature = "render")]
+pub mod audio;
+pub mod golden;
+pub mod migration;
+pub mod block_ids;
+pub mod scripting;
+#[cfg(feature = "render")]
+mod plugins;
+
+#[cfg(feature = "render")]
+pub use plugins::{InteractionPlugin, RenderingPlugin, VoxelEnginePlugins, WorldPlugin};
+#[cfg(all(feature = "render", feature = "persistence"))]
+pub use plugins::PersistencePlugin;
+
+pub const CHUNK_SIZE: usize = 16;
+pub const DEFAULT_RENDER_DISTANCE: i32 = 4; // chunks; changed at runtime with `renderdistance`
+pub const MIN_RENDER_DISTANCE: i32 = 1;
+pub const MAX_RENDER_DISTANCE: i32 = 12;
+pub const UNLOAD_GRACE_PERIOD: f32 = 5.0; // seconds
+pub const LOD_CELL_SIZE: usize = 2; // voxels per side merged into one cell of a chunk's coarse mesh
+pub const LOD_DISTANCE: f32 = 80.0; // voxels from the camera to a chunk's bounds before it draws coarse
+pub const LOD_HYSTERESIS: f32 = 8.0; // voxels closer than LOD_DISTANCE before it switches back
+pub const MAX_CHUNK_LOADS_PER_FRAME: usize = 4;
+pub const MAX_MESH_UPLOADS_PER_FRAME: usize = 8;
+pub const MAX_CHUNK_UNLOADS_PER_FRAME: usize = 32;
+pub const CHUNK_POOL_CAPACITY: usize = 64; // unloaded chunks kept for reuse
+pub const CHUNK_GENERATION_BUDGET_MS: f32 = 4.0;
+pub const WASM_MAX_CHUNK_LOADS_PER_FRAME: usize = 1;
+pub const WASM_MAX_MESH_UPLOADS_PER_FRAME: usize = 2;
+pub const WASM_MAX_CHUNK_UNLOADS_PER_FRAME: usize = 8;
+pub const WASM_CHUNK_GENERATION_BUDGET_MS: f32 = 2.0;
+pub const VIEW_DIRECTION_WEIGHT: f32 = 0.5; // how strongly loading favours chunks in view, 0..1
+pub const PREFETCH_MIN_SPEED: f32 = 10.0; // voxels/s; slower than this nothing is prefetched
+pub const PREFETCH_LOOKAHEAD_SECONDS: f32 = 3.0; // how far ahead of the camera chunks are prefetched
+pub const PREFETCH_MAX_RINGS: i32 = 3; // chunk rings past the render distance
+pub const PREFETCH_YAW_WEIGHT: f32 = 0.5; // how much the view yaw bends the prefetch direction
+pub const VOXEL_REMOVAL_RANGE: f32 = 20.0; // Increased from 5.0 to 20.0
+pub const FLY_REACH: f32 = 100.0; // pick range while flying or in noclip
+pub const MAX_UNDO_HISTORY: usize = 256; // edit batches
+pub const TOON_OUTLINE_WIDTH: f32 = 0.03; // in voxels
+pub const TRANSLUCENT_ALPHA: f32 = 0.45; // opacity of glass and other blended blocks
+pub const SELECTION_LINE_WIDTH: f32 = 2.0; // in pixels
+pub const REACH_INDICATOR_RANGE: f32 = 128.0; // voxels; targets up to here show as out of reach
+pub const WORLDS_DIRECTORY: &str = "saves"; // one directory per world, see worlds.rs
+pub const DEFAULT_WORLD_NAME: &str = "world"; // played without --world; the single save from before worlds existed
+pub const SCREENSHOT_DIRECTORY: &str = "screenshots"; // screenshots and `record` GIFs
+pub const GIF_FRAME_RATE: f32 = 10.0; // frames per second recorded by `record`
+pub const GIF_MAX_WIDTH: usize = 480; // in pixels; recorded frames are scaled down to fit
+pub const MAX_RECORDING_SECONDS: f32 = 30.0;
+pub const AUTOSAVE_INTERVAL_SECONDS: f32 = 60.0; // changed at runtime with `autosave`
+pub const SAVE_RETRY_SECONDS: f32 = 1.0; // wait before writing a region again after a failed write, doubling with each failure
+pub const SAVE_RETRY_MAX_SECONDS: f32 = 60.0;
+pub const KEYBINDINGS_FILE: &str = "keybindings.toml"; // action bindings, written with defaults on first run
+// Each pack is a folder; block textures go in <pack>/blocks/<block name>.png, sounds in <pack>/sounds (see audio.rs),
+// WASM mods in <pack>/scripts (see scripting.rs)
+pub const CONTENT_PACK_DIRECTORY: &str = "content_packs";
+pub const CHUNK_FADE_OUT_SECONDS: f32 = 0.4;
+pub const DAY_LENGTH_SECONDS: f32 = 600.0;
+pub const NIGHT_SKYLIGHT: f32 = 0.4; // Fraction of skylight levels left at midnight (moonlight)
+pub const TOAST_SECONDS: f32 = 4.0;
+pub const MAX_TOASTS: usize = 5;
+pub const LASER_DISTANCE: f32 = 64.0; // voxels
+pub const HEIGHTMAP_CACHE_COLUMNS: usize = 1024; // chunk columns the world generator keeps sampled
+pub const MAX_PENDING_STRUCTURE_CHUNKS: usize = 4096; // chunks with structure overflow kept in memory, the farthest beyond go to disk
+pub const SEAM_DEBUG_RADIUS: i32 = 2; // chunks around the camera the seam visualizer draws
+pub const LASER_EDITS_PER_FRAME: usize = 64;
+pub const BLOCK_UPDATES_PER_TICK: usize = 256; // neighbour reactions handled per fixed tick
+pub const SCHEDULED_TICKS_PER_TICK: usize = 256; // due block ticks run per fixed tick, the rest wait
+pub const MAX_EXPLOSION_RADIUS: f32 = 16.0; // voxels
+pub const MAX_EXPLOSION_DEBRIS: usize = 32; // item drops thrown out per explosion
+pub const MAX_MOBS: usize = 16;
+pub const MOB_SPAWN_RADIUS: i32 = 48; // voxels around the player mobs spawn within
+pub const MOB_DESPAWN_DISTANCE: f32 = 96.0; // voxels
+pub const SPAWN_SEARCH_RADIUS: i32 = 256; // voxels from the origin a new world looks for dry land to spawn on
+pub const VOID_Y: f32 = -512.0; // walking players falling below this respawn
+pub const MAX_PRECIPITATION_DROPS: usize = 1024; // raindrops or snowflakes falling at once in a storm
+pub const GOLDEN_WORLDGEN_FILE: &str = "golden/worldgen.txt"; // chunk hashes `--golden` checks worldgen against
+pub const SCRIPT_FUEL_PER_CALL: u64 = 1_000_000; // roughly WASM instructions a script hook may run before it's stopped
+pub const SCRIPT_MEMORY_LIMIT: usize = 16 * 1024 * 1024; // bytes of linear memory a script may grow to
+pub const SCRIPT_TABLE_LIMIT: u32 = 10_000; // elements of a script's function tables
+pub const MAX_SCRIPT_WRITES_PER_CALL: usize = 4096; // block writes, and separately ticks, one hook call may queue
+pub const MAX_REGION_VOLUME: i64 = 1_000_000; // voxels per region operation
+pub const PLAYER_SHADOW_RADIUS: f32 = 0.45; // voxels
+pub const FLY_SPEED_TIERS: [f32; 5] = [4.0, 12.0, 24.0, 48.0, 96.0]; // voxels/s
+pub const MOVEMENT_SMOOTHING: f32 = 12.0; // 1/s, default acceleration of the camera velocity
+pub const MOVEMENT_SETTINGS_FILE: &str = "movement.txt"; // speeds changed with `movement`, all worlds share them
+pub const AUDIO_SETTINGS_FILE: &str = "audio.txt"; // volumes changed with `volume`, all worlds share them
+pub const EAR_GAP: f32 = 0.3; // voxels between the listener's ears for spatial sound
+pub const CAMERA_SENSITIVITY: f32 = 0.0015; // radians per pixel
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct CameraLight;
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct VoxelRemover;
+
+// Lower priority loads first
+// Added to a prefetched chunk's priority, more than any chunk in render distance can have
+#[cfg(feature = "render")]
+const PREFETCH_PRIORITY_OFFSET: f32 = 1.0e6;
+
+#[cfg(feature = "render")]
+#[derive(Clone, PartialEq)]
+struct PrioritizedChunk {
+    priority: f32,
+    chunk_key: (i32, i32, i32),
+}
+
+#[cfg(feature = "render")]
+impl Eq for PrioritizedChunk {}
+
+#[cfg(feature = "render")]
+impl Ord for PrioritizedChunk {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so BinaryHeap pops the lowest priority first
+        other.priority.total_cmp(&self.priority)
+    }
+}
+
+#[cfg(feature = "render")]
+impl PartialOrd for PrioritizedChunk {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The material assets ChunkMaterials are added to
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+struct ChunkMaterialAssets<'w> {
+    terrain: ResMut<'w, Assets<TerrainMaterial>>,
+    outlined: ResMut<'w, Assets<OutlinedMaterial>>,
+    water: ResMut<'w, Assets<WaterMaterial>>,
+}
+
+#[cfg(feature = "render")]
+fn setup(
+    mut commands: Commands,
+    materials: ChunkMaterialAssets,
+    mut images: ResMut<Assets<Image>>,
+    theme: Res<Theme>,
+    world: Res<World>,
+    world_meta: Res<WorldMeta>,
+) {
+    let atlas = images.add(connected_textures::build_connected_atlas());
+    let block_textures = images.add(block_textures::load_block_textures(
+        Path::new(CONTENT_PACK_DIRECTORY),
+        world.graphics.texture_filtering,
+    ));
+    commands.insert_resource(block_textures::BlockTextures(block_textures.clone()));
+    let ChunkMaterialAssets { terrain: mut terrain_materials, outlined: mut outlined_materials, water: mut water_materials } = materials;
+    commands.insert_resource(ChunkMaterials {
+        standard: terrain_materials.add(ExtendedMaterial {
+            base: outline::chunk_base_material(atlas.clone()),
+            extension: water::terrain_extension(block_textures.clone()),
+        }),
+        cutout: terrain_materials.add(ExtendedMaterial {
+            base: outline::section_base_material(atlas.clone(), Transparency::Cutout),
+            extension: water::terrain_extension(block_textures.clone()),
+        }),
+        translucent: terrain_materials.add(ExtendedMaterial {
+            base: outline::section_base_material(atlas.clone(), Transparency::Translucent),
+            extension: water::terrain_extension(block_textures),
+        }),
+        outlined: outlined_materials.add(ExtendedMaterial {
+            base: outline::chunk_base_material(atlas),
+            extension: VoxelOutline {
+                settings: VoxelOutlineSettings {
+                    color: theme.voxel_outline.to_linear(),
+                    width: TOON_OUTLINE_WIDTH,
+                    ao_strength: LightingTuning::default().ao_strength,
+                    skylight: 1.0,
+                },
+            },
+        }),
+        water: water_materials.add(water::water_material()),
+    });
+
+    // Enhanced lighting with CameraLight component
+    commands.spawn(PointLightBundle {
+        point_light: PointLight {
+            intensity: 10000.0,
+            shadows_enabled: true,
+            range: 500.0,
+            ..Default::default()
+        },
+        transform: Transform::from_xyz(10.0, 20.0, 10.0),
+        ..Default::default()
+    }).insert(CameraLight);
+
+    // Spawn the player camera at the world spawn; a saved player position replaces it once loaded
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_translation(spawn::spawn_eye(&world_meta, world.dimension)),
+            ..default()
+        },
+        CameraController::default(),
+        VoxelRemover,
+        PlayerBody::default(),
+        StreamingAnchor::default(),
+        SpatialListener::new(EAR_GAP),
+        BlobShadow { radius: PLAYER_SHADOW_RADIUS },
+        Skybox {
+            image: images.add(sky::build_sky_cubemap()),
+            brightness: sky::SKY_BRIGHTNESS,
+        },
+        sky::fog_settings(&world),
+    ));
+}
+
+#[cfg(feature = "render")]
+fn update_chunks(
+    mut world: ResMut<World>,
+    query: Query<(Entity, &Transform), With<StreamingAnchor>>,
+    time: Res<Time>,
+    mut last_positions: Local<HashMap<Entity, Vec3>>,
+) {
+    // Nothing to stream around; keep what is loaded
+    if query.is_empty() {
+        return;
+    }
+
+    // Anchor velocities from their movement since last frame, whatever moved them
+    let dt = time.delta_seconds();
+    let chunk_size = world.chunk_size as f32;
+    let mut positions = HashMap::new();
+    let mut motions = Vec::new();
+    let mut anchor_chunks = Vec::new();
+    for (entity, transform) in &query {
+        let position = transform.translation;
+        let velocity = match last_positions.get(&entity) {
+            Some(&last) if dt > 0.0 => (position - last) / dt,
+            _ => Vec3::ZERO,
+        };
+        positions.insert(entity, position);
+        motions.push(AnchorMotion { position, velocity, forward: *transform.forward() });
+        let chunk = (position / chunk_size).floor().as_ivec3();
+        anchor_chunks.push((chunk.x, chunk.y, chunk.z));
+    }
+    // Despawned anchors are forgotten
+    *last_positions = positions;
+
+    world.predict_prefetch(&motions);
+    world.update_chunks(&anchor_chunks);
+}
+
+// Orders the load queue nearest-first, favouring chunks in front of an anchor so terrain fills
+// in where the player is looking before it fills in behind them. Every anchor ranks each chunk
+// by its own distance and view direction, divided by its weight, and the chunk takes the best
+// of those. Prefetched chunks come after everything within the render distance.
+#[cfg(feature = "render")]
+fn prioritize_chunks(
+    mut world: ResMut<World>,
+    query: Query<(&Transform, &StreamingAnchor)>,
+) {
+    if query.is_empty() {
+        return;
+    }
+    let chunk_size = world.chunk_size as f32;
+    let anchors: Vec<(Vec3, Vec3, f32)> = query.iter()
+        .map(|(transform, anchor)| ((transform.translation / chunk_size).floor(), *transform.forward(), anchor.weight.max(f32::EPSILON)))
+        .collect();
+
+    let mut priority_queue = BinaryHeap::new();
+    let world = &mut *world;
+
+    for chunk_key in world.chunk_load_queue.drain(..) {
+        let chunk = Vec3::new(chunk_key.0 as f32, chunk_key.1 as f32, chunk_key.2 as f32);
+        let mut priority = anchors.iter()
+            .map(|&(anchor_chunk, view_direction, weight)| {
+                let offset = chunk - anchor_chunk;
+                let distance = offset.length();
+                let alignment = if distance > 0.0 { offset.dot(view_direction) / distance } else { 1.0 };
+                distance * (1.0 - VIEW_DIRECTION_WEIGHT * alignment) / weight
+            })
+            .fold(f32::INFINITY, f32::min);
+        if world.prefetch_chunks.contains(&chunk_key) {
+            priority += PREFETCH_PRIORITY_OFFSET;
+        }
+        priority_queue.push(PrioritizedChunk { priority, chunk_key });
+    }
+
+    while let Some(prioritized) = priority_queue.pop() {
+        world.chunk_load_queue.push_back(prioritized.chunk_key);
+    }
+}
+
+#[cfg(feature = "render")]
+fn process_chunk_queue(
+    mut world: ResMut<World>,
+    generator: Res<WorldGenerator>,
+    mut pool: ResMut<ChunkPool>,
+    budget: Res<ChunkUpdateBudget>,
+    mut commands: Commands,
+) {
+    world.process_queue(&generator, &mut pool, &budget, &mut commands);
+}
+
+// The camera, and the light that follows it
+#[cfg(feature = "render")]
+type CameraAndLight<'w, 's> = ParamSet<'w, 's, (
+    Query<'static, 'static, &'static Transform, With<Camera>>,
+    Query<'static, 'static, &'static mut Transform, (With<PointLight>, With<CameraLight>)>,
+)>;
+
+#[cfg(feature = "render")]
+fn sync_light_with_camera(mut param_set: CameraAndLight) {
+    let camera_translation = {
+        if let Ok(camera_transform) = param_set.p0().get_single() {
+            Some(camera_transform.translation)
+        } else {
+            None
+        }
+    };
+
+    if let Some(translation) = camera_translation {
+        if let Ok(mut light_transform) = param_set.p1().get_single_mut() {
+            light_transform.translation = translation;
+        }
+    }
+}
+
+// Where finished chunk meshes go: the mesh assets, and the materials for new chunk entities
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+struct ChunkMeshAssets<'w> {
+    meshes: ResMut<'w, Assets<Mesh>>,
+    chunk_materials: Res<'w, ChunkMaterials>,
+    toon_mode: Res<'w, ToonMode>,
+}
+
+// A chunk's water surface and see-through sections, the children of its entity
+#[cfg(feature = "render")]
+type ChunkSectionQuery<'w, 's> = Query<
+    'w,
+    's,
+    (&'static mut Handle<Mesh>, Has<ChunkWater>),
+    (Or<(With<ChunkWater>, With<ChunkTransparentSection>)>, Without<Chunk>),
+>;
+
+// The entities drawing loaded chunks, whose meshes a finished task replaces
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+struct ChunkMeshEntities<'w, 's> {
+    chunk_entities: Query<'w, 's, (&'static mut Handle<Mesh>, &'static mut ChunkLod), With<Chunk>>,
+    chunk_children: Query<'w, 's, &'static Children, With<Chunk>>,
+    section_query: ChunkSectionQuery<'w, 's>,
+}
+
+#[cfg(feature = "render")]
+fn handle_meshing_tasks(
+    mut commands: Commands,
+    assets: ChunkMeshAssets,
+    mut meshing_tasks: Query<(Entity, &mut ChunkMeshingTask)>,
+    mut world: ResMut<World>,
+    entities: ChunkMeshEntities,
+    budget: Res<ChunkUpdateBudget>,
+) {
+    let ChunkMeshAssets { mut meshes, chunk_materials, toon_mode } = assets;
+    let ChunkMeshEntities { mut chunk_entities, chunk_children, mut section_query } = entities;
+
+    // Tasks for chunks that unloaded since, or that a newer task for the same chunk replaces,
+    // are dropped before they finish, which cancels them. Otherwise every chunk load queues
+    // meshes for its neighbours faster than they upload, and the backlog grows without end.
+    let mut newest = HashMap::new();
+    for (_, task) in &meshing_tasks {
+        let sequence = newest.entry(task.1).or_insert(task.2);
+        *sequence = (*sequence).max(task.2);
+    }
+    for (entity, task) in &meshing_tasks {
+        if !world.chunks.contains_key(&task.1) || newest[&task.1] != task.2 {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let mut context = Context::from_waker(futures::task::noop_waker_ref());
+    let mut uploads = 0;
+
+    for (entity, mut task) in &mut meshing_tasks {
+        if !world.chunks.contains_key(&task.1) || newest[&task.1] != task.2 {
+            continue;
+        }
+        // Finished tasks left over stay ready for the next frame; don't poll them again now
+        if uploads >= budget.max_mesh_uploads_per_frame {
+            break;
+        }
+        if let Poll::Ready(ChunkMeshes { terrain, mut water, #[cfg(feature = "physics")] collider, lod, missing_borders }) = Pin::new(&mut task.0).poll_unpin(&mut context) {
+            let TerrainMeshes { opaque: mesh, cutout, translucent } = terrain;
+            let chunk_key = task.1;
+            let _span = info_span!("chunk_mesh_upload", ?chunk_key).entered();
+            uploads += 1;
+
+            // Check if the chunk entity already exists
+            let chunk_entity = if let Some(&existing_entity) = world.chunk_entities.get(&chunk_key) {
+                // Update existing chunk entity
+                if let Ok((mut mesh_handle, mut chunk_lod)) = chunk_entities.get_mut(existing_entity) {
+                    // Update both meshes in place and keep drawing whichever update_chunk_lod picked
+                    rendering::replace_chunk_mesh(&mut meshes, &mut chunk_lod.full, mesh);
+                    rendering::replace_chunk_mesh(&mut meshes, &mut chunk_lod.coarse, lod);
+                    if *mesh_handle != *chunk_lod.active() {
+                        *mesh_handle = chunk_lod.active().clone();
+                    }
+                    #[cfg(feature = "physics")]
+                    match collider {
+                        Some(collider) => commands.entity(existing_entity).insert(collider),
+                        None => commands.entity(existing_entity).remove::<Collider>(),
+                    };
+                }
+                // The water surface keeps its entity and mesh asset like the terrain; the see-through
+                // sections are replaced wholesale below
+                for &child in chunk_children.get(existing_entity).into_iter().flatten() {
+                    let Ok((mut child_mesh, is_water)) = section_query.get_mut(child) else {
+                        continue;
+                    };
+                    match water.take() {
+                        Some(surface) if is_water => rendering::replace_chunk_mesh(&mut meshes, &mut child_mesh, surface),
+                        surface => {
+                            water = surface;
+                            commands.entity(child).despawn();
+                        }
+                    }
+                }
+                existing_entity
+            } else {
+                // Create new chunk entity, drawn in full detail until update_chunk_lod says otherwise
+                let chunk_lod = ChunkLod { full: meshes.add(mesh), coarse: meshes.add(lod), coarse_active: false };
+                let mesh_handle = chunk_lod.full.clone();
+                let transform = Transform::from_xyz(
+                    (chunk_key.0 * CHUNK_SIZE as i32) as f32,
+                    (chunk_key.1 * CHUNK_SIZE as i32) as f32,
+                    (chunk_key.2 * CHUNK_SIZE as i32) as f32
+                );
+
+                let chunk_entity = if toon_mode.enabled {
+                    commands.spawn((
+                        MaterialMeshBundle {
+                            mesh: mesh_handle,
+                            material: chunk_materials.outlined.clone(),
+                            transform,
+                            ..default()
+                        },
+                        Chunk::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE),
+                    ))
+                        .id()
+                } else {
+                    commands.spawn((
+                        MaterialMeshBundle {
+                            mesh: mesh_handle,
+                            material: chunk_materials.standard.clone(),
+                            transform,
+                            ..default()
+                        },
+                        Chunk::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE), // Assuming Chunk::new takes dimensions
+                    ))
+                        .id()
+                };
+
+                commands.entity(chunk_entity).insert(chunk_lod);
+                #[cfg(feature = "physics")]
+                {
+                    commands.entity(chunk_entity).insert(RigidBody::Static);
+                    if let Some(collider) = collider {
+                        commands.entity(chunk_entity).insert(collider);
+                    }
+                }
+
+                // Store the new entity in the world
+                world.chunk_entities.insert(chunk_key, chunk_entity);
+                chunk_entity
+            };
+
+            commands.entity(chunk_entity).insert(seams::ChunkSeams(missing_borders));
+
+            let sections = [(cutout, &chunk_materials.cutout), (translucent, &chunk_materials.translucent)];
+            for (section, material) in sections {
+                let Some(section) = section else {
+                    continue;
+                };
+                let section_entity = commands.spawn((
+                    MaterialMeshBundle {
+                        mesh: meshes.add(section),
+                        material: material.clone(),
+                        ..default()
+                    },
+                    ChunkTransparentSection,
+                )).id();
+                commands.entity(chunk_entity).add_child(section_entity);
+            }
+
+            if let Some(water) = water {
+                let water_entity = commands.spawn((
+                    MaterialMeshBundle {
+                        mesh: meshes.add(water),
+                        material: chunk_materials.water.clone(),
+                        ..default()
+                    },
+                    ChunkWater,
+                )).id();
+                commands.entity(chunk_entity).add_child(water_entity);
+            }
+
+            // Remove the meshing task entity
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+fn attach_chunk_scoped_entities(
+    world: Res<World>,
+    mut commands: Commands,
+    scoped_query: Query<(Entity, &ChunkScoped), Without<Parent>>,
+) {
+    for (entity, scoped) in &scoped_query {
+        if let Some(chunk_entity) = world.chunk_entities.get(&scoped.0) {
+            // Keep the world-space placement the entity was spawned with
+            commands.entity(entity).set_parent_in_place(*chunk_entity);
+        } else if !world.chunks.contains_key(&scoped.0) {
+            // Owning chunk is gone, nothing will ever clean this entity up
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+fn undo_redo_system(
+    actions: Actions,
+    mut history: ResMut<EditHistory>,
+    creative: Res<CreativeMode>,
+    mut inventory: ResMut<Inventory>,
+    mut voxel_set_events: EventWriter<VoxelSetEvent>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    let undo = actions.just_pressed(Action::Undo);
+    if !undo && !actions.just_pressed(Action::Redo) {
+        return;
+    }
+    let Some(batch) = (if undo { history.peek_undo() } else { history.peek_redo() }) else {
+        return;
+    };
+
+    // Outside creative mode blocks come back from and go into the inventory, like placing and
+    // breaking them would, so undo can't make items out of nothing
+    if !creative.enabled {
+        let replayed = batch.iter().map(|edit| if undo { (edit.new, edit.old) } else { (edit.old, edit.new) });
+        if let Err(short) = inventory.charge_edits(replayed) {
+            let verb = if undo { "undo" } else { "redo" };
+            notifications.send(NotificationEvent::warning(format!("Not enough {} to {} that", block::definition(short).name, verb)));
+            return;
+        }
+    }
+
+    if undo {
+        let batch = history.undo().unwrap_or_default();
+        // Revert in reverse order so overlapping edits in one batch unwind correctly
+        voxel_set_events.send_batch(batch.iter().rev().map(|edit| VoxelSetEvent {
+            chunk_key: edit.chunk_key,
+            voxel_pos: edit.voxel_pos,
+            block: edit.old,
+            state: 0,
+        }));
+        notifications.send(NotificationEvent::info(format!("Undid {} voxel edit(s)", batch.len())));
+    } else {
+        let batch = history.redo().unwrap_or_default();
+        voxel_set_events.send_batch(batch.iter().map(|edit| VoxelSetEvent {
+            chunk_key: edit.chunk_key,
+            voxel_pos: edit.voxel_pos,
+            block: edit.new,
+            state: 0,
+        }));
+        notifications.send(NotificationEvent::info(format!("Redid {} voxel edit(s)", batch.len())));
+    }
+}
+
+// What breaking and placing blocks by hand both go by: the click, the targeted block, the tools
+// that change what a click does and the undo history the edits go into
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+struct HandEdits<'w> {
+    actions: Actions<'w>,
+    target: Res<'w, TargetedBlock>,
+    laser: Res<'w, LaserTool>,
+    symmetry: Res<'w, Symmetry>,
+    creative: Res<'w, CreativeMode>,
+    history: ResMut<'w, EditHistory>,
+}
+
+#[cfg(feature = "render")]
+fn voxel_removal_system(
+    mut voxel_world: VoxelWorld,
+    edits: HandEdits,
+    reach: PlayerReach,
+    game_mode: Res<GameMode>,
+    mut drop_events: EventWriter<SpawnItemDrop>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    let HandEdits { actions, target, laser, symmetry, creative, mut history } = edits;
+    // The laser tool owns the mouse buttons while it is out
+    if laser.enabled || !actions.just_pressed(Action::BreakBlock) {
+        return;
+    }
+
+    let Some(hit) = target.voxel else {
+        notifications.send(NotificationEvent::warning(format!("No voxel within range of {}", reach.get())));
+        return;
+    };
+    // Symmetric copies are part of the same undo step
+    let mut batch = Vec::new();
+    for pos in symmetry.images(hit) {
+        if let Some(old) = voxel_world.get_block(pos).filter(|&old| old != AIR) {
+            voxel_world.set_block(pos, AIR);
+            let (chunk_key, voxel_pos) = voxel_world.to_chunk_local(pos);
+            batch.push(VoxelEdit { chunk_key, voxel_pos, old, new: AIR });
+            // Builders flying around don't leave a trail of items behind
+            if *game_mode == GameMode::Walking && !creative.enabled {
+                drop_events.send(SpawnItemDrop {
+                    position: pos.as_vec3() + Vec3::new(0.5, 0.25, 0.5),
+                    block: old,
+                    count: 1,
+                    velocity: Vec3::Y * item_drop::POP_SPEED,
+                });
+            }
+        }
+    }
+    history.record(batch);
+}
+
+#[cfg(feature = "render")]
+fn voxel_placement_system(
+    mut voxel_world: VoxelWorld,
+    edits: HandEdits,
+    player_query: Query<&Transform, With<PlayerBody>>,
+    noclip: Res<Noclip>,
+    selected_block: Res<SelectedBlock>,
+    mut inventory: ResMut<Inventory>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    let HandEdits { actions, target, laser, symmetry, creative, mut history } = edits;
+    if laser.enabled || !actions.just_pressed(Action::PlaceBlock) {
+        return;
+    }
+
+    // Solid blocks never go where the player stands, unless noclip lets them walk out again
+    let eye = player_query.get_single().ok().filter(|_| !noclip.enabled).map(|transform| transform.translation);
+    let blocks_player = |pos: IVec3| block::is_solid(selected_block.0) && eye.is_some_and(|eye| player::overlaps_player(eye, pos));
+
+    if let Some(target) = target.place_at {
+        // Only place into loaded, empty space; liquids and thin blocks are displaced
+        if voxel_world.get_block(target).is_some_and(block::is_replaceable) && !blocks_player(target) {
+            let mut batch = Vec::new();
+            for pos in symmetry.images(target) {
+                if blocks_player(pos) {
+                    continue;
+                }
+                if let Some(old) = voxel_world.get_block(pos).filter(|&old| block::is_replaceable(old)) {
+                    // Outside creative mode every placed block, symmetric copies included, comes
+                    // out of the inventory; placing stops once it runs out
+                    if !creative.enabled && !inventory.take_one(selected_block.0) {
+                        break;
+                    }
+                    voxel_world.set_block(pos, selected_block.0);
+                    let (chunk_key, voxel_pos) = voxel_world.to_chunk_local(pos);
+                    batch.push(VoxelEdit { chunk_key, voxel_pos, old, new: selected_block.0 });
+                }
+            }
+            if batch.is_empty() {
+                notifications.send(NotificationEvent::warning(format!("No {} left to place", block::definition(selected_block.0).name)));
+            }
+            history.record(batch);
+        }
+    }
+}
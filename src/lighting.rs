@@ -0,0 +1,264 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use crate::block;
+#[cfg(feature = "render")]
+use crate::keybindings::{Action, Actions};
+use crate::terrain::{Chunk, ChunkBorders};
+#[cfg(feature = "render")]
+use crate::world::World;
+
+pub const MAX_LIGHT: u8 = 15;
+
+// A block giving off light, in the local coordinates of the chunk being lit. Sources in
+// neighbouring chunks lie outside 0..size. Emission stays below the chunk size, so only
+// the 26 surrounding chunks can reach a chunk.
+#[derive(Clone, Copy, Debug)]
+pub struct LightSource {
+    pub pos: IVec3,
+    pub level: u8,
+}
+
+// Per-voxel light levels (0..=15) of one chunk, kept apart so the terrain shader can dim
+// skylight with the time of day while block light stays as it is
+pub struct ChunkLight {
+    pub sky: Vec<u8>,
+    pub block: Vec<u8>,
+}
+
+// Skylight falls straight down every column until it hits an opaque block, then floods sideways
+// losing one level per step. Glass and leaves let it through unchanged.
+// Dimensions without a sky (`chunk.borders.skylight` off) have none.
+// Block light from `chunk.borders.lights` floods the same way from each source, as does light
+// from emitting blocks without a block entity (lava, glowstone) in the chunk and in the
+// neighbouring layers it borders on.
+pub fn compute_light(chunk: &Chunk) -> ChunkLight {
+    let (width, height, depth) = (chunk.width, chunk.height, chunk.depth);
+    let index = |x: usize, y: usize, z: usize| x + y * width + z * width * height;
+    let mut sky = vec![0u8; width * height * depth];
+    let mut sky_queue = VecDeque::new();
+    let mut light = vec![0u8; width * height * depth];
+    let mut queue = VecDeque::new();
+    let seed = |light: &mut Vec<u8>, queue: &mut VecDeque<(usize, usize, usize)>, (x, y, z): (usize, usize, usize), level: u8| {
+        if light[index(x, y, z)] < level {
+            light[index(x, y, z)] = level;
+            queue.push_back((x, y, z));
+        }
+    };
+
+    if chunk.borders.skylight {
+        for x in 0..width {
+            for z in 0..depth {
+                for y in (0..height).rev() {
+                    if chunk.is_opaque(x, y, z) {
+                        break;
+                    }
+                    seed(&mut sky, &mut sky_queue, (x, y, z), MAX_LIGHT);
+                }
+            }
+        }
+    }
+
+    // Lava seas would be far too many block entities, so emitting blocks are found by scanning.
+    // Torches inside the chunk are seeded here as well as from the sources below, which is harmless.
+    for x in 0..width {
+        for y in 0..height {
+            for z in 0..depth {
+                let level = block::light_emission(chunk.get_block(x, y, z));
+                if level > 0 {
+                    seed(&mut light, &mut queue, (x, y, z), level);
+                }
+            }
+        }
+    }
+    // Only the facing layer of each neighbour is known, so light from emitters deeper inside
+    // a neighbour stops at the border
+    let dims = [width, height, depth];
+    for (direction, layer) in chunk.borders.layers.iter().enumerate() {
+        let Some(layer) = layer else {
+            continue;
+        };
+        let axis = direction / 2;
+        let (u_axis, v_axis) = ChunkBorders::layer_axes(axis);
+        for (i, &neighbour_block) in layer.iter().enumerate() {
+            let level = block::light_emission(neighbour_block);
+            if level <= 1 {
+                continue;
+            }
+            let mut pos = [0; 3];
+            pos[axis] = if direction % 2 == 0 { 0 } else { dims[axis] - 1 };
+            pos[u_axis] = i % dims[u_axis];
+            pos[v_axis] = i / dims[u_axis];
+            if !chunk.is_opaque(pos[0], pos[1], pos[2]) {
+                seed(&mut light, &mut queue, (pos[0], pos[1], pos[2]), level - 1);
+            }
+        }
+    }
+
+    let size = IVec3::new(width as i32, height as i32, depth as i32);
+    let (inside, outside): (Vec<&LightSource>, Vec<_>) = chunk.borders.lights.iter()
+        .partition(|source| source.pos.cmpge(IVec3::ZERO).all() && source.pos.cmplt(size).all());
+    for source in inside {
+        let pos = source.pos.as_uvec3();
+        seed(&mut light, &mut queue, (pos.x as usize, pos.y as usize, pos.z as usize), source.level);
+    }
+    if !outside.is_empty() {
+        for (pos, level) in entering_light(chunk, &outside) {
+            seed(&mut light, &mut queue, pos, level);
+        }
+    }
+
+    {
+        let _span = info_span!("light_flood", channel = "sky").entered();
+        flood(chunk, &mut sky, sky_queue);
+    }
+    {
+        let _span = info_span!("light_flood", channel = "block").entered();
+        flood(chunk, &mut light, queue);
+    }
+    ChunkLight { sky, block: light }
+}
+
+// Light from sources outside the chunk, flooded through the neighbours' blocks over every cell
+// it can reach the chunk from. Returns the border cells of the chunk the flood lit from outside,
+// at the level of their brightest outside neighbour minus one.
+fn entering_light(chunk: &Chunk, sources: &[&LightSource]) -> Vec<((usize, usize, usize), u8)> {
+    let size = IVec3::new(chunk.width as i32, chunk.height as i32, chunk.depth as i32);
+    // Light fades out before it gets further than this
+    let reach = IVec3::splat(MAX_LIGHT as i32 - 1);
+    let extent = size + reach * 2;
+    let contains = |pos: IVec3| pos.cmpge(-reach).all() && pos.cmplt(size + reach).all();
+    let index = |pos: IVec3| {
+        let pos = pos + reach;
+        (pos.x + pos.y * extent.x + pos.z * extent.x * extent.y) as usize
+    };
+    let mut light = vec![0u8; (extent.x * extent.y * extent.z) as usize];
+    let mut queue = VecDeque::new();
+    for source in sources {
+        if contains(source.pos) && light[index(source.pos)] < source.level {
+            light[index(source.pos)] = source.level;
+            queue.push_back(source.pos);
+        }
+    }
+
+    {
+        let _span = info_span!("light_flood", channel = "outside").entered();
+        while let Some(pos) = queue.pop_front() {
+            let level = light[index(pos)];
+            if level <= 1 {
+                continue;
+            }
+            for direction in ChunkBorders::DIRECTIONS {
+                let next = pos + direction;
+                if !contains(next) || is_opaque_around(chunk, next) || light[index(next)] >= level - 1 {
+                    continue;
+                }
+                light[index(next)] = level - 1;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut entering = Vec::new();
+    for x in 0..chunk.width {
+        for y in 0..chunk.height {
+            for z in 0..chunk.depth {
+                let on_border = x == 0 || y == 0 || z == 0 || x == chunk.width - 1 || y == chunk.height - 1 || z == chunk.depth - 1;
+                if !on_border || chunk.is_opaque(x, y, z) {
+                    continue;
+                }
+                let pos = IVec3::new(x as i32, y as i32, z as i32);
+                let level = ChunkBorders::DIRECTIONS.iter()
+                    .map(|&direction| pos + direction)
+                    .filter(|outside| !(outside.cmpge(IVec3::ZERO).all() && outside.cmplt(size).all()))
+                    .map(|outside| light[index(outside)])
+                    .max()
+                    .unwrap_or(0);
+                if level > 1 {
+                    entering.push(((x, y, z), level - 1));
+                }
+            }
+        }
+    }
+    entering
+}
+
+// Whether the block at `pos`, in the chunk's local coordinates, stops light. Blocks of
+// neighbours that weren't handed over count as opaque.
+fn is_opaque_around(chunk: &Chunk, pos: IVec3) -> bool {
+    let size = IVec3::new(chunk.width as i32, chunk.height as i32, chunk.depth as i32);
+    let offset = pos.div_euclid(size);
+    let local = pos.rem_euclid(size).as_uvec3();
+    let (x, y, z) = (local.x as usize, local.y as usize, local.z as usize);
+    if offset == IVec3::ZERO {
+        return chunk.is_opaque(x, y, z);
+    }
+    match &chunk.borders.neighbours[ChunkBorders::neighbour_index(offset)] {
+        Some(voxels) => block::is_opaque(voxels.get(x + y * chunk.width + z * chunk.width * chunk.height)),
+        None => true,
+    }
+}
+
+// Spreads light from the queued cells through everything that isn't opaque, one level less per step
+fn flood(chunk: &Chunk, light: &mut [u8], mut queue: VecDeque<(usize, usize, usize)>) {
+    let (width, height, depth) = (chunk.width, chunk.height, chunk.depth);
+    let index = |x: usize, y: usize, z: usize| x + y * width + z * width * height;
+    while let Some((x, y, z)) = queue.pop_front() {
+        let level = light[index(x, y, z)];
+        if level <= 1 {
+            continue;
+        }
+
+        let neighbors = [
+            (x.wrapping_sub(1), y, z), (x + 1, y, z),
+            (x, y.wrapping_sub(1), z), (x, y + 1, z),
+            (x, y, z.wrapping_sub(1)), (x, y, z + 1),
+        ];
+        for (nx, ny, nz) in neighbors {
+            if nx >= width || ny >= height || nz >= depth || chunk.is_opaque(nx, ny, nz) {
+                continue;
+            }
+            let neighbor_index = index(nx, ny, nz);
+            if light[neighbor_index] < level - 1 {
+                light[neighbor_index] = level - 1;
+                queue.push_back((nx, ny, nz));
+            }
+        }
+    }
+}
+
+// Fractional part of a terrain shader flag carrying a light level, see
+// block_textures::pack_shader_flag. Levels sit in the middle of their 1/16 step so
+// interpolation across a face never rounds a flag to the next integer.
+pub fn pack_light_level(level: f32) -> f32 {
+    (level + 0.5) / (MAX_LIGHT as f32 + 1.0)
+}
+
+#[cfg(feature = "render")]
+pub fn toggle_smooth_lighting(
+    actions: Actions,
+    mut world: ResMut<World>,
+    mut commands: Commands,
+) {
+    if !actions.just_pressed(Action::SmoothLighting) {
+        return;
+    }
+
+    world.graphics.smooth_lighting = !world.graphics.smooth_lighting;
+    println!("Smooth lighting {}", if world.graphics.smooth_lighting { "enabled" } else { "disabled" });
+    world.remesh_all(&mut commands);
+}
+
+#[cfg(feature = "render")]
+pub fn toggle_depth_darkness(
+    actions: Actions,
+    mut world: ResMut<World>,
+    mut commands: Commands,
+) {
+    if !actions.just_pressed(Action::DepthDarkness) {
+        return;
+    }
+
+    world.graphics.depth_darkness.enabled = !world.graphics.depth_darkness.enabled;
+    println!("Depth darkness {}", if world.graphics.depth_darkness.enabled { "enabled" } else { "disabled" });
+    world.remesh_all(&mut commands);
+}
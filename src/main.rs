@@ -1,274 +1,217 @@
+#[cfg(any(feature = "render", feature = "net"))]
 use bevy::prelude::*;
-use bevy::input::mouse::MouseButton;
-use bevy_flycam::{FlyCam, PlayerPlugin};
-use crate::terrain::{Chunk, ChunkMeshingTask};
-use crate::world::{World};
-use std::future::Future;
-use bevy::tasks::Task;
-use std::pin::Pin;
-use futures::FutureExt;
-use std::task::{Context, Poll};
-use std::collections::BinaryHeap;
-use std::cmp::Ordering;
+use std::path::Path;
+use voxelfun::dimension::Dimension;
+use voxelfun::{chunk_format, golden, save, scripting, worlds, CONTENT_PACK_DIRECTORY};
+#[cfg(feature = "render")]
+use voxelfun::determinism::DeterminismAudit;
+#[cfg(feature = "render")]
+use voxelfun::{bench, soak, VoxelEnginePlugins};
+#[cfg(all(feature = "net", feature = "render", not(target_arch = "wasm32")))]
+use voxelfun::client::ClientPlugin;
+#[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+use voxelfun::{net, server::ServerPlugin};
+#[cfg(feature = "persistence")]
+use voxelfun::{upgrade, worldgen};
 
-mod terrain;
-mod world;
-
-pub const CHUNK_SIZE: usize = 16;
-pub const RENDER_DISTANCE: i32 = 4;
-pub const UNLOAD_GRACE_PERIOD: f32 = 5.0; // seconds
-pub const VOXEL_REMOVAL_RANGE: f32 = 20.0; // Increased from 5.0 to 20.0
-
-#[derive(Component)]
-struct CameraLight;
-
-#[derive(Component)]
-struct VoxelRemover;
-
-use bevy_flycam::NoCameraPlayerPlugin;
-
-#[derive(Clone, Eq, PartialEq)]
-struct PrioritizedChunk {
-    distance: i32,
-    chunk_key: (i32, i32, i32),
-}
-
-impl Ord for PrioritizedChunk {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.distance.cmp(&self.distance)
+fn main() {
+    // `voxelfun inspect <file>` (`cargo run -- inspect <file>`) dumps a saved chunk or region
+    // instead of starting the game
+    let mut args: Vec<String> = std::env::args().collect();
+    // `--dimension <name>` anywhere on the command line picks the world to play, upgrade or bench
+    let dimension = match Dimension::take_from_args(&mut args) {
+        Ok(dimension) => dimension,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    // `--world <name>` picks which saved world, the default one without it
+    let world_name = worlds::take_from_args(&mut args);
+    // Script mods go first, since the blocks they register belong in every world's ID table
+    let scripts = scripting::load_scripts(Path::new(CONTENT_PACK_DIRECTORY));
+    // `voxelfun worlds [create <name> [seed] | delete <name>]` lists or manages the saved worlds
+    if args.get(1).map(String::as_str) == Some("worlds") {
+        std::process::exit(worlds::run_command(&args[0], &args[2..]));
     }
-}
-
-impl PartialOrd for PrioritizedChunk {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+    if args.get(1).map(String::as_str) == Some("inspect") {
+        let Some(path) = args.get(2) else {
+            eprintln!("usage: {} inspect <chunk or region file>", args[0]);
+            std::process::exit(2);
+        };
+        if let Err(err) = chunk_format::inspect(path) {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+        return;
     }
-}
-
-fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugins(NoCameraPlayerPlugin)
-        .insert_resource(World::new(CHUNK_SIZE, RENDER_DISTANCE))
-        .add_systems(Startup, setup)
-        .add_systems(Update, (
-            update_chunks,
-            process_chunk_queue,
-            sync_light_with_camera,
-            handle_meshing_tasks,
-            voxel_removal_system,
-            prioritize_chunks,
-        ))
-        .run();
-}
-
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // Enhanced lighting with CameraLight component
-    commands.spawn(PointLightBundle {
-        point_light: PointLight {
-            intensity: 10000.0,
-            shadows_enabled: true,
-            range: 500.0,
-            ..Default::default()
-        },
-        transform: Transform::from_xyz(10.0, 20.0, 10.0),
-        ..Default::default()
-    }).insert(CameraLight);
-
-    // Spawn a new camera with FlyCam and VoxelRemover components
-    commands.spawn((
-        Camera3dBundle {
-            transform: Transform::from_xyz(0.0, 2.0, 0.5),
-            ..default()
-        },
-        FlyCam,
-        VoxelRemover
-    ));
-}
-
-fn update_chunks(
-    mut world: ResMut<World>,
-    query: Query<&Transform, With<Camera>>,
-    time: Res<Time>,
-) {
-    if let Ok(player_transform) = query.get_single() {
-        let player_chunk_x = (player_transform.translation.x / world.chunk_size as f32).floor() as i32;
-        let player_chunk_y = (player_transform.translation.y / world.chunk_size as f32).floor() as i32;
-        let player_chunk_z = (player_transform.translation.z / world.chunk_size as f32).floor() as i32;
-
-        let current_chunk = (player_chunk_x, player_chunk_y, player_chunk_z);
-        world.update_chunks(current_chunk.0, current_chunk.1, current_chunk.2);
+    // `voxelfun compact [save dir]` prunes and rewrites the region files of a save
+    if args.get(1).map(String::as_str) == Some("compact") {
+        let default_dir = dimension.save_dir(&worlds::world_directory(&world_name)).to_string_lossy().into_owned();
+        let save_dir = args.get(2).map(String::as_str).unwrap_or(&default_dir);
+        // Chunks are read and rewritten under the block IDs of the world given with --world
+        let ids = worlds::WorldMeta::load(&world_name).map(|meta| meta.block_ids()).unwrap_or_default();
+        match save::compact(std::path::Path::new(save_dir), &ids) {
+            Ok(report) => {
+                println!(
+                    "{}: {} region(s), {} removed; kept {} chunk(s), dropped {}",
+                    save_dir, report.regions, report.regions_removed, report.chunks_kept, report.chunks_dropped,
+                );
+                println!(
+                    "{} -> {} bytes, {} reclaimed",
+                    report.bytes_before, report.bytes_after, report.bytes_before.saturating_sub(report.bytes_after),
+                );
+            }
+            Err(err) => {
+                eprintln!("{}: {}", save_dir, err);
+                std::process::exit(1);
+            }
+        }
+        return;
     }
-}
-
-fn prioritize_chunks(
-    mut world: ResMut<World>,
-    query: Query<&Transform, With<Camera>>,
-) {
-    if let Ok(player_transform) = query.get_single() {
-        let player_chunk_x = (player_transform.translation.x / world.chunk_size as f32).floor() as i32;
-        let player_chunk_y = (player_transform.translation.y / world.chunk_size as f32).floor() as i32;
-        let player_chunk_z = (player_transform.translation.z / world.chunk_size as f32).floor() as i32;
-
-        let mut priority_queue = BinaryHeap::new();
-
-        for x in -RENDER_DISTANCE..=RENDER_DISTANCE {
-            for y in -RENDER_DISTANCE..=RENDER_DISTANCE {
-                for z in -RENDER_DISTANCE..=RENDER_DISTANCE {
-                    let chunk_key = (player_chunk_x + x, player_chunk_y + y, player_chunk_z + z);
-                    let distance = x.abs() + y.abs() + z.abs();
-
-                    priority_queue.push(PrioritizedChunk {
-                        distance,
-                        chunk_key,
-                    });
+    // `voxelfun upgrade [save dir] [keep|blend|regenerate]` reports saved chunks from older world
+    // generators and, given a mode, upgrades them
+    #[cfg(feature = "persistence")]
+    if args.get(1).map(String::as_str) == Some("upgrade") {
+        let default_dir = dimension.save_dir(&worlds::world_directory(&world_name)).to_string_lossy().into_owned();
+        let save_dir = args.get(2).map(String::as_str).unwrap_or(&default_dir);
+        let mode = match args.get(3) {
+            None => None,
+            Some(name) => match upgrade::UpgradeMode::parse(name) {
+                Some(mode) => Some(mode),
+                None => {
+                    eprintln!("usage: {} upgrade [save dir] [{}]", args[0], upgrade::UpgradeMode::NAMES.join("|"));
+                    std::process::exit(2);
+                }
+            },
+        };
+        // The current generator is seeded like the world given with --world, and its blocks saved under the world's IDs
+        let meta = worlds::WorldMeta::load(&world_name);
+        let seed = meta.as_ref().map_or(0, |meta| meta.seed);
+        let ids = meta.as_ref().map(worlds::WorldMeta::block_ids).unwrap_or_default();
+        match upgrade::upgrade(std::path::Path::new(save_dir), mode, dimension, seed, &ids) {
+            Ok(report) => {
+                println!("{}: {} saved chunk(s), {} unreadable", save_dir, report.chunks, report.unreadable);
+                for (version, count) in &report.versions {
+                    let note = match *version {
+                        worldgen::GENERATOR_VERSION => " (current)",
+                        0 => " (unknown, saved without an edit log)",
+                        _ => "",
+                    };
+                    println!("  generator version {}{}: {} chunk(s)", version, note, count);
                 }
+                if mode.is_none() && report.outdated() > 0 {
+                    println!(
+                        "{} chunk(s) come from an older generator; rerun with one of: {}",
+                        report.outdated(), upgrade::UpgradeMode::NAMES.join(", "),
+                    );
+                } else if mode.is_some() {
+                    println!(
+                        "blended {}, regenerated {}, dropped {} unedited, kept {}",
+                        report.blended, report.regenerated, report.dropped, report.kept,
+                    );
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}", save_dir, err);
+                std::process::exit(1);
             }
         }
-
-        // Update the world's chunk loading queue with the prioritized chunks
-        world.chunk_loading_queue = priority_queue.into_iter().map(|pc| pc.chunk_key).collect();
+        return;
     }
-}
-
-fn process_chunk_queue(
-    mut world: ResMut<World>,
-    mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
-    world.process_queue(&mut commands, &mut materials, &mut meshes);
-}
-
-fn sync_light_with_camera(
-    mut param_set: ParamSet<(
-        Query<&Transform, With<Camera>>,
-        Query<&mut Transform, (With<PointLight>, With<CameraLight>)>,
-    )>,
-) {
-    let camera_translation = {
-        if let Ok(camera_transform) = param_set.p0().get_single() {
-            Some(camera_transform.translation)
-        } else {
-            None
-        }
-    };
-
-    if let Some(translation) = camera_translation {
-        if let Ok(mut light_transform) = param_set.p1().get_single_mut() {
-            light_transform.translation = translation;
+    // `voxelfun --bench [chunks]` times worldgen and meshing headlessly and prints JSON
+    #[cfg(feature = "render")]
+    if args.get(1).map(String::as_str) == Some("--bench") {
+        let chunks = match args.get(2).map(|count| count.parse::<usize>()) {
+            None => bench::DEFAULT_BENCH_CHUNKS,
+            Some(Ok(count)) if count > 0 => count,
+            Some(_) => {
+                eprintln!("usage: {} --bench [chunk count]", args[0]);
+                std::process::exit(2);
+            }
+        };
+        bench::run(chunks, dimension);
+        return;
+    }
+    // `voxelfun --soak [crossings]` streams chunks around a long loop headlessly and exits
+    // non-zero if entity counts, memory or chunk bookkeeping keep growing
+    #[cfg(feature = "render")]
+    if args.get(1).map(String::as_str) == Some("--soak") {
+        let crossings = match args.get(2).map(|count| count.parse::<usize>()) {
+            None => soak::DEFAULT_SOAK_CROSSINGS,
+            Some(Ok(count)) if count > 0 => count,
+            Some(_) => {
+                eprintln!("usage: {} --soak [chunk crossings]", args[0]);
+                std::process::exit(2);
+            }
+        };
+        if !soak::run(crossings, dimension) {
+            std::process::exit(1);
         }
+        return;
     }
-}
-
-fn handle_meshing_tasks(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshing_tasks: Query<(Entity, &mut ChunkMeshingTask)>,
-    mut world: ResMut<World>,
-    mut chunk_entities: Query<(Entity, &mut Handle<Mesh>), With<Chunk>>,
-) {
-    let mut context = Context::from_waker(futures::task::noop_waker_ref());
-
-    for (entity, mut task) in &mut meshing_tasks {
-        if let Poll::Ready(mesh) = Pin::new(&mut task.0).poll_unpin(&mut context) {
-            let chunk_key = task.1;
-
-            // Check if the chunk entity already exists
-            if let Some(existing_entity) = world.chunk_entities.get(&chunk_key) {
-                // Update existing chunk entity
-                if let Ok((_, mut mesh_handle)) = chunk_entities.get_mut(*existing_entity) {
-                    // Update the mesh
-                    *mesh_handle = meshes.add(mesh);
-                }
-            } else {
-                // Create new chunk entity
-                let material = materials.add(StandardMaterial {
-                    base_color: Color::rgb(0.8, 0.7, 0.6),
-                    ..default()
-                });
-                let mesh_handle = meshes.add(mesh);
-
-                let chunk_entity = commands.spawn((
-                    PbrBundle {
-                        mesh: mesh_handle,
-                        material: material.clone(),
-                        transform: Transform::from_xyz(
-                            (chunk_key.0 * CHUNK_SIZE as i32) as f32,
-                            (chunk_key.1 * CHUNK_SIZE as i32) as f32,
-                            (chunk_key.2 * CHUNK_SIZE as i32) as f32
-                        ),
-                        ..default()
-                    },
-                    Chunk::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE), // Assuming Chunk::new takes dimensions
-                ))
-                    .id();
-
-                // Store the new entity in the world
-                world.chunk_entities.insert(chunk_key, chunk_entity);
+    // `voxelfun --golden` exits non-zero if worldgen no longer reproduces the committed chunk
+    // hashes; `voxelfun --golden record` rewrites them
+    if args.get(1).map(String::as_str) == Some("--golden") {
+        let record = match args.get(2).map(String::as_str) {
+            None => false,
+            Some("record") => true,
+            Some(_) => {
+                eprintln!("usage: {} --golden [record]", args[0]);
+                std::process::exit(2);
             }
-
-            // Remove the meshing task entity
-            commands.entity(entity).despawn();
+        };
+        if !golden::run(record) {
+            std::process::exit(1);
         }
+        return;
+    }
+    let world_meta = match worlds::open(&world_name) {
+        Ok(meta) => meta,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    // `voxelfun server [port]` runs a headless server for `voxelfun connect <host[:port]>` clients
+    #[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+    if args.get(1).map(String::as_str) == Some("server") {
+        let port = args.get(2).and_then(|port| port.parse().ok()).unwrap_or(net::DEFAULT_PORT);
+        if let AppExit::Error(code) = App::new().add_plugins(ServerPlugin { port, world: world_meta }).run() {
+            std::process::exit(code.get().into());
+        }
+        return;
+    }
+    // Without `render` there's no game to start, only the subcommands above
+    #[cfg(not(feature = "render"))]
+    {
+        let _ = (world_meta, scripts);
+        eprintln!("{} was built without the `render` feature; see `{} server`", args[0], args[0]);
+        std::process::exit(2);
     }
-}
-
-fn voxel_removal_system(
-    mut world: ResMut<World>,
-    camera_query: Query<&Transform, With<VoxelRemover>>,
-    mouse_button_input: Res<ButtonInput<MouseButton>>,
-    mut commands: Commands,
-) {
-    if mouse_button_input.just_pressed(MouseButton::Left) {
-        if let Ok(camera_transform) = camera_query.get_single() {
-            let ray_origin = camera_transform.translation;
-            let ray_direction = camera_transform.forward();
-
-            println!("Attempting to remove voxel. Ray origin: {:?}, direction: {:?}", ray_origin, ray_direction);
-
-            // Debug: Print chunk and voxel at camera position
-            let chunk_x = (ray_origin.x / world.chunk_size as f32).floor() as i32;
-            let chunk_y = (ray_origin.y / world.chunk_size as f32).floor() as i32;
-            let chunk_z = (ray_origin.z / world.chunk_size as f32).floor() as i32;
-            println!("Camera is in chunk: ({}, {}, {})", chunk_x, chunk_y, chunk_z);
-
-            if let Some((chunk_key, voxel_pos)) = world.raycast(ray_origin, ray_direction, VOXEL_REMOVAL_RANGE) {
-                println!("Raycast hit: Chunk {:?}, Voxel position {:?}", chunk_key, voxel_pos);
-                world.remove_voxel(chunk_key, voxel_pos, &mut commands);
-            } else {
-                println!("Raycast did not hit any voxel within range of {}", VOXEL_REMOVAL_RANGE);
-
-                // Debug: Check voxels along the ray
-                let mut current_pos = ray_origin;
-                let steps = (VOXEL_REMOVAL_RANGE / 0.1) as i32; // Adjust step size if needed
-                for i in 0..steps {
-                    let chunk_x = (current_pos.x / world.chunk_size as f32).floor() as i32;
-                    let chunk_y = (current_pos.y / world.chunk_size as f32).floor() as i32;
-                    let chunk_z = (current_pos.z / world.chunk_size as f32).floor() as i32;
-                    let chunk_key = (chunk_x, chunk_y, chunk_z);
-
-                    if let Some(chunk) = world.chunks.get(&chunk_key) {
-                        let local_x = (current_pos.x.rem_euclid(world.chunk_size as f32) as usize).min(world.chunk_size - 1);
-                        let local_y = (current_pos.y.rem_euclid(world.chunk_size as f32) as usize).min(world.chunk_size - 1);
-                        let local_z = (current_pos.z.rem_euclid(world.chunk_size as f32) as usize).min(world.chunk_size - 1);
 
-                        if chunk.get_voxel(local_x, local_y, local_z) {
-                            println!("Found voxel at step {}: Chunk {:?}, Local pos ({}, {}, {})", i, chunk_key, local_x, local_y, local_z);
-                            break;
-                        }
-                    }
+    #[cfg(feature = "render")]
+    {
+        let mut app = App::new();
+        #[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+        if args.get(1).map(String::as_str) == Some("connect") {
+            let Some(address) = args.get(2) else {
+                eprintln!("usage: {} connect <host[:port]>", args[0]);
+                std::process::exit(2);
+            };
+            app.add_plugins(ClientPlugin { address: address.clone() });
+        }
+        if let Some(audit) = DeterminismAudit::from_env() {
+            app.insert_resource(audit);
+        }
 
-                    current_pos += ray_direction * 0.1;
-                }
-            }
-        } else {
-            println!("Could not find camera transform");
+        let exit = app
+            .add_plugins(DefaultPlugins)
+            .insert_resource(scripts)
+            .add_plugins(VoxelEnginePlugins { world: world_meta, dimension })
+            .run();
+        if let AppExit::Error(code) = exit {
+            std::process::exit(code.get().into());
         }
     }
-}
\ No newline at end of file
+}
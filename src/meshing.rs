@@ -0,0 +1,819 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+#[cfg(feature = "physics")]
+use bevy_xpbd_3d::prelude::Collider;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::block::{self, BlockId, Transparency, AIR, WATER};
+use crate::block_textures;
+use crate::block_updates::ScheduledTicks;
+use crate::connected_textures;
+use crate::lighting;
+use crate::settings::{DepthDarknessCurve, GraphicsSettings};
+use crate::tasks::{self, BackgroundTask};
+use crate::terrain::{Chunk, ChunkBorders};
+use crate::LOD_CELL_SIZE;
+
+// Chunk meshes, built on the async compute pool from a copy of the chunk and its borders (see
+// World::mesh_task). Only built with the `render` feature; headless builds keep chunks as voxels.
+
+// Terrain vertex alpha is 1 on foliage plus this per opaque cell around the corner (0-3), which
+// voxel_terrain.wgsl darkens by LightingTuning::ao_strength each
+pub const OCCLUSION_ALPHA_STEP: f32 = 0.25;
+
+// One cube face for the per-voxel meshers: normal, corner offsets and triangle winding
+type FaceTemplate = ([i32; 3], [[i32; 3]; 4], [u32; 6]);
+
+// Terrain of one chunk, split by block Transparency so each section gets a material that
+// draws it correctly
+pub struct TerrainMeshes {
+    pub opaque: Mesh,
+    // Alpha-tested and blended sections, None if the chunk has no such blocks
+    pub cutout: Option<Mesh>,
+    pub translucent: Option<Mesh>,
+}
+
+impl TerrainMeshes {
+    fn from_sections([opaque, cutout, translucent]: [MeshSection; 3]) -> Self {
+        Self {
+            opaque: opaque.into_mesh(),
+            cutout: (!cutout.indices.is_empty()).then(|| cutout.into_mesh()),
+            translucent: (!translucent.indices.is_empty()).then(|| translucent.into_mesh()),
+        }
+    }
+
+    // Every section in one mesh, for the collider and exports
+    pub fn combined(&self) -> Mesh {
+        let mut mesh = self.opaque.clone();
+        for section in self.cutout.iter().chain(&self.translucent) {
+            mesh.merge(section);
+        }
+        mesh
+    }
+}
+
+// Vertex data of one terrain section while the mesher fills it in. The meshers keep one per
+// Transparency class, indexed by `Transparency as usize`.
+#[derive(Default)]
+struct MeshSection {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    // Terrain shader flags, see generate_face_mesh
+    shader_flags: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl MeshSection {
+    fn vertex_count(&self) -> u32 {
+        self.positions.len() as u32
+    }
+
+    fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, Default::default());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, self.shader_flags);
+        mesh.insert_indices(Indices::U32(self.indices));
+        mesh
+    }
+}
+
+// Child of a chunk entity holding its cutout or translucent terrain section
+#[derive(Component)]
+pub struct ChunkTransparentSection;
+
+// Everything a meshing task produces for one chunk
+pub struct ChunkMeshes {
+    pub terrain: TerrainMeshes,
+    // Water surface, None if the chunk has no exposed water
+    pub water: Option<Mesh>,
+    // Static collider built from the same exposed faces, None for empty chunks
+    #[cfg(feature = "physics")]
+    pub collider: Option<Collider>,
+    // Downsampled opaque terrain drawn instead of `terrain.opaque` far away, see generate_lod_mesh
+    pub lod: Mesh,
+    // Bit per ChunkBorders::DIRECTIONS entry whose neighbour wasn't loaded, so the faces
+    // towards it were kept whatever lies there; see seams
+    pub missing_borders: u8,
+}
+
+// Numbers meshing tasks in the order they were started, so a newer task for a chunk can
+// replace older ones still running
+static NEXT_MESHING_TASK: AtomicU64 = AtomicU64::new(0);
+
+// The task, the chunk it meshes and its sequence number from NEXT_MESHING_TASK
+#[derive(Component)]
+pub struct ChunkMeshingTask(pub BackgroundTask<ChunkMeshes>, pub (i32, i32, i32), pub u64);
+
+// Trimesh over the chunk's exposed faces. Built on the meshing task so remeshing after an edit
+// refreshes the collider too.
+#[cfg(feature = "physics")]
+fn chunk_collider(mesh: &Mesh) -> Option<Collider> {
+    let _span = info_span!("chunk_collider").entered();
+    if mesh.indices().is_none_or(|indices| indices.is_empty()) {
+        return None;
+    }
+    Collider::trimesh_from_mesh(mesh)
+}
+
+impl Chunk {
+    // See World::mesh_task, which gathers the borders from the loaded neighbours
+    pub fn generate_mesh_task(&self, chunk_key: (i32, i32, i32), graphics: GraphicsSettings, borders: ChunkBorders) -> ChunkMeshingTask {
+        let missing_borders = borders.missing_mask();
+        let voxels = self.voxels.clone();
+        let block_states = self.block_states.clone();
+        let width = self.width;
+        let height = self.height;
+        let depth = self.depth;
+
+        let task = tasks::spawn_compute(async move {
+            let _span = info_span!("chunk_meshing", ?chunk_key).entered();
+            let mut chunk = Chunk {
+                voxels,
+                width,
+                height,
+                depth,
+                last_accessed: 0.0,
+                boxified: vec![false; width * height * depth],
+                block_entities: HashMap::new(),
+                item_drops: Vec::new(),
+                generator_version: 0,
+                edits: BTreeMap::new(),
+                scheduled_ticks: ScheduledTicks::default(),
+                block_states,
+                borders,
+            };
+            let terrain = if graphics.smooth_lighting {
+                chunk.generate_face_mesh(chunk_key, &graphics.depth_darkness)
+            } else {
+                chunk.generate_mesh(chunk_key, &graphics.depth_darkness)
+            };
+            // Glass and leaves collide like any other block
+            #[cfg(feature = "physics")]
+            let collider = if terrain.cutout.is_none() && terrain.translucent.is_none() {
+                chunk_collider(&terrain.opaque)
+            } else {
+                chunk_collider(&terrain.combined())
+            };
+            let water = chunk.generate_water_mesh(chunk_key, &graphics.depth_darkness);
+            let lod = chunk.generate_lod_mesh(chunk_key, &graphics.depth_darkness);
+            ChunkMeshes {
+                terrain,
+                water,
+                #[cfg(feature = "physics")]
+                collider,
+                lod,
+                missing_borders,
+            }
+        });
+
+        ChunkMeshingTask(task, chunk_key, NEXT_MESHING_TASK.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // Inverse of the voxel index used by get_block
+    fn voxel_position(&self, index: usize) -> (usize, usize, usize) {
+        (index % self.width, index / self.width % self.height, index / (self.width * self.height))
+    }
+
+    fn get_box_index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + y * self.width + z * self.width * self.height
+    }
+
+    fn is_boxified(&self, x: usize, y: usize, z: usize) -> bool {
+        self.boxified[self.get_box_index(x, y, z)]
+    }
+
+    fn set_boxified(&mut self, x: usize, y: usize, z: usize, value: bool) {
+        let index: usize = self.get_box_index(x, y, z);
+        self.boxified[index] = value;
+    }
+
+    // Voxels only merge into one box when they share block type and color variant
+    fn merge_key(&self, x: usize, y: usize, z: usize, chunk_key: (i32, i32, i32)) -> Option<(BlockId, usize)> {
+        let block = self.get_block(x, y, z);
+        if !block::is_visible(block) {
+            return None;
+        }
+
+        let world_x = chunk_key.0 * self.width as i32 + x as i32;
+        let world_y = chunk_key.1 * self.height as i32 + y as i32;
+        let world_z = chunk_key.2 * self.depth as i32 + z as i32;
+        Some((block, block::variant_index(block, world_x, world_y, world_z)))
+    }
+
+    // Bitmask of in-plane neighbours that are the same block, see connected_textures
+    fn connection_mask(&self, x: usize, y: usize, z: usize, block: BlockId, u_axis: usize, v_axis: usize) -> u8 {
+        let pos = [x as i32, y as i32, z as i32];
+        let same = |axis: usize, offset: i32| {
+            let mut neighbor = pos;
+            neighbor[axis] += offset;
+            // Neighbours in other chunks aren't visible from the meshing task, so chunk borders show a seam
+            neighbor.iter().all(|&c| c >= 0)
+                && self.get_block(neighbor[0] as usize, neighbor[1] as usize, neighbor[2] as usize) == block
+        };
+
+        let mut mask = 0;
+        if same(u_axis, -1) { mask |= connected_textures::CONNECTED_U_MIN; }
+        if same(u_axis, 1) { mask |= connected_textures::CONNECTED_U_MAX; }
+        if same(v_axis, -1) { mask |= connected_textures::CONNECTED_V_MIN; }
+        if same(v_axis, 1) { mask |= connected_textures::CONNECTED_V_MAX; }
+        mask
+    }
+
+    pub fn merge_voxels(&mut self, chunk_key: (i32, i32, i32)) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+        let mut boxes = Vec::new();
+        self.boxified.fill(false);
+
+        for x in 0..self.width {
+            for y in 0..self.height {
+                for z in 0..self.depth {
+                    let key = self.merge_key(x, y, z, chunk_key);
+                    if let Some((block, _)) = key.filter(|_| !self.is_boxified(x, y, z)) {
+                        let mut nx = 1;
+                        let mut ny = 1;
+                        let mut nz = 1;
+
+                        // Connected-texture blocks pick a tile per voxel face, so they stay 1x1x1
+                        let (x_end, y_end, z_end) = if block::definition(block).connected_texture {
+                            (x + 1, y + 1, z + 1)
+                        } else {
+                            (self.width, self.height, self.depth)
+                        };
+
+                        // Merge in x direction
+                        for i in x + 1..x_end {
+                            if self.merge_key(i, y, z, chunk_key) == key && !self.is_boxified(i, y, z) {
+                                nx += 1;
+                            } else {
+                                break;
+                            }
+                        }
+
+                        // Merge in y direction
+                        for j in y + 1..y_end {
+                            let mut valid = true;
+                            for i in x..x + nx {
+                                if self.merge_key(i, j, z, chunk_key) != key || self.is_boxified(i, j, z) {
+                                    valid = false;
+                                    break;
+                                }
+                            }
+                            if valid {
+                                ny += 1;
+                            } else {
+                                break;
+                            }
+                        }
+
+                        // Merge in z direction
+                        for k in z + 1..z_end {
+                            let mut valid = true;
+                            for i in x..x + nx {
+                                for j in y..y + ny {
+                                    if self.merge_key(i, j, k, chunk_key) != key || self.is_boxified(i, j, k) {
+                                        valid = false;
+                                        break;
+                                    }
+                                }
+                                if !valid {
+                                    break;
+                                }
+                            }
+                            if valid {
+                                nz += 1;
+                            } else {
+                                break;
+                            }
+                        }
+
+                        // Mark voxels as boxified
+                        for i in x..x + nx {
+                            for j in y..y + ny {
+                                for k in z..z + nz {
+                                    self.set_boxified(i, j, k, true);
+                                }
+                            }
+                        }
+
+                        boxes.push((x, y, z, nx, ny, nz));
+                    }
+                }
+            }
+        }
+        boxes
+    }
+
+    pub fn generate_mesh(&mut self, chunk_key: (i32, i32, i32), depth_darkness: &DepthDarknessCurve) -> TerrainMeshes {
+        // The merged boxes don't know which of their faces touch water or sky, so no caustics
+        // or wetness here, only the texture layer. Thin blocks are drawn as full boxes as well.
+        let mut sections: [MeshSection; 3] = Default::default();
+
+        for (x, y, z, nx, ny, nz) in self.merge_voxels(chunk_key) {
+            // Every voxel in the box shares the same block and variant
+            let (block, variant) = self.merge_key(x, y, z, chunk_key).unwrap();
+            let [r, g, b] = block::face_color(block, variant);
+            let connected_texture = block::definition(block).connected_texture;
+            let foliage = if block::is_foliage(block) { 1.0 } else { 0.0 };
+            let transparency = block::transparency(block);
+            let section = &mut sections[transparency as usize];
+
+            // Vertices for each face of the box
+            let face_vertices = [
+                // Front face
+                [x as f32, y as f32, z as f32],
+                [(x + nx) as f32, y as f32, z as f32],
+                [x as f32, (y + ny) as f32, z as f32],
+                [(x + nx) as f32, (y + ny) as f32, z as f32],
+
+                // Back face
+                [x as f32, y as f32, (z + nz) as f32],
+                [(x + nx) as f32, y as f32, (z + nz) as f32],
+                [x as f32, (y + ny) as f32, (z + nz) as f32],
+                [(x + nx) as f32, (y + ny) as f32, (z + nz) as f32],
+
+                // Left face
+                [x as f32, y as f32, z as f32],
+                [x as f32, (y + ny) as f32, z as f32],
+                [x as f32, y as f32, (z + nz) as f32],
+                [x as f32, (y + ny) as f32, (z + nz) as f32],
+
+                // Right face
+                [(x + nx) as f32, y as f32, z as f32],
+                [(x + nx) as f32, (y + ny) as f32, z as f32],
+                [(x + nx) as f32, y as f32, (z + nz) as f32],
+                [(x + nx) as f32, (y + ny) as f32, (z + nz) as f32],
+
+                // Top face
+                [x as f32, (y + ny) as f32, z as f32],
+                [(x + nx) as f32, (y + ny) as f32, z as f32],
+                [x as f32, (y + ny) as f32, (z + nz) as f32],
+                [(x + nx) as f32, (y + ny) as f32, (z + nz) as f32],
+
+                // Bottom face
+                [x as f32, y as f32, z as f32],
+                [(x + nx) as f32, y as f32, z as f32],
+                [x as f32, y as f32, (z + nz) as f32],
+                [(x + nx) as f32, y as f32, (z + nz) as f32],
+            ];
+
+            // Indices for each face of the box
+            let face_indices = [
+                // Correct winding order for each face
+                0, 2, 1, 2, 3, 1,   // Front face
+                4, 5, 6, 5, 7, 6,   // Back face
+                8, 10, 9, 10, 11, 9, // Left face
+                12, 13, 14, 13, 15, 14, // Right face
+                16, 18, 17, 18, 19, 17, // Top face
+                20, 21, 22, 21, 23, 22, // Bottom face
+            ];
+
+            // Normals for each face of the box
+            let face_normals = [
+                [0.0f32, 0.0f32, -1.0f32], // Front face
+                [0.0f32, 0.0f32, -1.0f32],
+                [0.0f32, 0.0f32, -1.0f32],
+                [0.0f32, 0.0f32, -1.0f32],
+
+                [0.0f32, 0.0f32, 1.0f32],  // Back face
+                [0.0f32, 0.0f32, 1.0f32],
+                [0.0f32, 0.0f32, 1.0f32],
+                [0.0f32, 0.0f32, 1.0f32],
+
+                [-1.0f32, 0.0f32, 0.0f32], // Left face
+                [-1.0f32, 0.0f32, 0.0f32],
+                [-1.0f32, 0.0f32, 0.0f32],
+                [-1.0f32, 0.0f32, 0.0f32],
+
+                [1.0f32, 0.0f32, 0.0f32],  // Right face
+                [1.0f32, 0.0f32, 0.0f32],
+                [1.0f32, 0.0f32, 0.0f32],
+                [1.0f32, 0.0f32, 0.0f32],
+
+                [0.0f32, 1.0f32, 0.0f32],  // Top face
+                [0.0f32, 1.0f32, 0.0f32],
+                [0.0f32, 1.0f32, 0.0f32],
+                [0.0f32, 1.0f32, 0.0f32],
+
+                [0.0f32, -1.0f32, 0.0f32], // Bottom face
+                [0.0f32, -1.0f32, 0.0f32],
+                [0.0f32, -1.0f32, 0.0f32],
+                [0.0f32, -1.0f32, 0.0f32],
+            ];
+
+            // UV coordinates for each face of the box
+            let face_uvs = [
+                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Front face
+                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Back face
+                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Left face
+                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Right face
+                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Top face
+                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Bottom face
+            ];
+
+            let [block_light, sky_light] = self.full_light_flags();
+            let layer_flags = block_textures::pack_shader_flag(0.0, block::texture_layer(block)) + block_light;
+            for face in 0..6 {
+                // Opaque boxes keep every face, the depth test hides the covered ones. See-through
+                // boxes would show them, so faces covered across the whole box side are dropped.
+                if transparency != Transparency::Opaque && self.box_face_hidden((x, y, z), (nx, ny, nz), face, block) {
+                    continue;
+                }
+                let corners = face * 4..face * 4 + 4;
+                let base = section.vertex_count();
+                section.positions.extend_from_slice(&face_vertices[corners.clone()]);
+                section.indices.extend(face_indices[face * 6..face * 6 + 6].iter().map(|&i| base + i - face as u32 * 4));
+                section.normals.extend_from_slice(&face_normals[corners.clone()]);
+                // Map the face into its atlas tile; non-connected blocks use the borderless tile
+                let (u_axis, v_axis) = connected_textures::FACE_AXES[face];
+                let mask = if connected_texture {
+                    self.connection_mask(x, y, z, block, u_axis, v_axis)
+                } else {
+                    connected_textures::FULLY_CONNECTED
+                };
+                section.uvs.extend(face_uvs[corners.clone()].iter().map(|&uv| connected_textures::tile_uv(mask, uv)));
+                section.colors.extend(face_vertices[corners].iter().map(|vertex| {
+                    let depth_shade = depth_darkness.brightness_at(chunk_key.1 as f32 * self.height as f32 + vertex[1]);
+                    [r * depth_shade, g * depth_shade, b * depth_shade, foliage]
+                }));
+                section.shader_flags.extend([[layer_flags, sky_light]; 4]);
+            }
+        }
+
+        TerrainMeshes::from_sections(sections)
+    }
+
+    // Whether every cell touching one side of a merged box covers it. `face` indexes the
+    // face order of generate_mesh.
+    fn box_face_hidden(&self, (x, y, z): (usize, usize, usize), (nx, ny, nz): (usize, usize, usize), face: usize, block: BlockId) -> bool {
+        // (axis, whether the face is on the max side) per face
+        const FACE_SIDES: [(usize, bool); 6] = [(2, false), (2, true), (0, false), (0, true), (1, true), (1, false)];
+        let (axis, max_side) = FACE_SIDES[face];
+        let start = [x as i32, y as i32, z as i32];
+        let size = [nx as i32, ny as i32, nz as i32];
+        let (u_axis, v_axis) = ChunkBorders::layer_axes(axis);
+        (0..size[u_axis]).all(|u| (0..size[v_axis]).all(|v| {
+            let mut front = start;
+            front[axis] = if max_side { start[axis] + size[axis] } else { start[axis] - 1 };
+            front[u_axis] += u;
+            front[v_axis] += v;
+            self.block_at(front[0], front[1], front[2]).is_some_and(|front_block| block::hides_face(front_block, block))
+        }))
+    }
+
+    fn is_solid_at(&self, x: i32, y: i32, z: i32) -> bool {
+        self.block_at(x, y, z).is_some_and(block::is_solid)
+    }
+
+    fn is_opaque_at(&self, x: i32, y: i32, z: i32) -> bool {
+        self.block_at(x, y, z).is_some_and(block::is_opaque)
+    }
+
+    // Block at a chunk-local position, reaching one layer into the neighbouring chunks through
+    // `borders`. None further out, or where that neighbour isn't loaded.
+    fn block_at(&self, x: i32, y: i32, z: i32) -> Option<BlockId> {
+        let pos = [x, y, z];
+        let dims = [self.width as i32, self.height as i32, self.depth as i32];
+        // Called for every face of every voxel, nearly always inside the chunk
+        if (0..3).all(|axis| (0..dims[axis]).contains(&pos[axis])) {
+            return Some(self.get_block(x as usize, y as usize, z as usize));
+        }
+        let mut outside = (0..3).filter(|&axis| pos[axis] < 0 || pos[axis] >= dims[axis]);
+        let axis = outside.next()?;
+        // Only the six face neighbours' layers are known, not edges or corners
+        if outside.next().is_some() || (pos[axis] != -1 && pos[axis] != dims[axis]) {
+            return None;
+        }
+        let direction = axis * 2 + (pos[axis] == dims[axis]) as usize;
+        let layer = self.borders.layers[direction].as_ref()?;
+        let (u_axis, v_axis) = ChunkBorders::layer_axes(axis);
+        layer.get((pos[u_axis] + pos[v_axis] * dims[u_axis]) as usize).copied()
+    }
+
+    // Light fractions of the shader flags for meshers without per-voxel light: full skylight,
+    // or full block light in dimensions without a sky so caves aren't black
+    fn full_light_flags(&self) -> [f32; 2] {
+        let full = lighting::MAX_LIGHT as f32;
+        let (block_light, sky_light) = if self.borders.skylight { (0.0, full) } else { (full, 0.0) };
+        [lighting::pack_light_level(block_light), lighting::pack_light_level(sky_light)]
+    }
+
+    // Light level used for smooth lighting samples: opaque cells count as dark, cells outside
+    // the chunk as `outside`
+    fn sample_light(&self, light: &[u8], outside: u8, x: i32, y: i32, z: i32) -> f32 {
+        if x < 0 || y < 0 || z < 0 || x >= self.width as i32 || y >= self.height as i32 || z >= self.depth as i32 {
+            return outside as f32;
+        }
+        if self.is_opaque_at(x, y, z) {
+            return 0.0;
+        }
+        light[self.get_box_index(x as usize, y as usize, z as usize)] as f32
+    }
+
+    // Per-voxel mesher with culled faces and Minecraft-style smooth lighting: every face corner
+    // averages the light of the four cells touching it in front of the face. Faces can't be
+    // greedy-merged here since each corner carries its own light value.
+    pub fn generate_face_mesh(&mut self, chunk_key: (i32, i32, i32), depth_darkness: &DepthDarknessCurve) -> TerrainMeshes {
+        // (normal, corner offsets, winding) per face, in the same order and winding as generate_mesh
+        const FACES: [FaceTemplate; 6] = [
+            ([0, 0, -1], [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]], [0, 2, 1, 2, 3, 1]), // Front face
+            ([0, 0, 1], [[0, 0, 1], [1, 0, 1], [0, 1, 1], [1, 1, 1]], [0, 1, 2, 1, 3, 2]),  // Back face
+            ([-1, 0, 0], [[0, 0, 0], [0, 1, 0], [0, 0, 1], [0, 1, 1]], [0, 2, 1, 2, 3, 1]), // Left face
+            ([1, 0, 0], [[1, 0, 0], [1, 1, 0], [1, 0, 1], [1, 1, 1]], [0, 1, 2, 1, 3, 2]),  // Right face
+            ([0, 1, 0], [[0, 1, 0], [1, 1, 0], [0, 1, 1], [1, 1, 1]], [0, 2, 1, 2, 3, 1]),  // Top face
+            ([0, -1, 0], [[0, 0, 0], [1, 0, 0], [0, 0, 1], [1, 0, 1]], [0, 1, 2, 1, 3, 2]), // Bottom face
+        ];
+        const FACE_UVS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+
+        let light = {
+            let _span = info_span!("chunk_lighting").entered();
+            lighting::compute_light(self)
+        };
+
+        // Terrain shader flags: x = 1 on faces looking into water (caustics), packed with the
+        // texture layer; y = 1 on top faces with nothing solid above them in this chunk (rain wetness).
+        // The corner's block light goes in the fraction of x and its skylight in that of y.
+        // Space outside the chunk is open sky since the neighbours' light isn't known here.
+        let outside_sky = if self.borders.skylight { lighting::MAX_LIGHT } else { 0 };
+        let mut sections: [MeshSection; 3] = Default::default();
+        let mut column_top = vec![-1i32; self.width * self.depth];
+        for x in 0..self.width {
+            for z in 0..self.depth {
+                if let Some(y) = (0..self.height).rev().find(|&y| self.get_voxel(x, y, z)) {
+                    column_top[x + z * self.width] = y as i32;
+                }
+            }
+        }
+
+        for (index, _) in self.voxels.iter_except(AIR) {
+            let (x, y, z) = self.voxel_position(index);
+            let Some((block, variant)) = self.merge_key(x, y, z, chunk_key) else {
+                continue;
+            };
+            let section = &mut sections[block::transparency(block) as usize];
+            let [r, g, b] = block::face_color(block, variant);
+            let connected_texture = block::definition(block).connected_texture;
+            // Opacity comes from the section's material, so vertex alpha is free to flag
+            // foliage for the seasonal tint
+            let foliage = if block::is_foliage(block) { 1.0 } else { 0.0 };
+            let texture_layer = block::texture_layer(block);
+            let pos = [x as i32, y as i32, z as i32];
+            let top_height = block::top_height(block, self.get_state(x, y, z));
+            let sky_exposed = if y as i32 >= column_top[x + z * self.width] { 1.0 } else { 0.0 };
+
+            for (face, (normal, corners, winding)) in FACES.iter().enumerate() {
+                let front = [pos[0] + normal[0], pos[1] + normal[1], pos[2] + normal[2]];
+                if self.block_at(front[0], front[1], front[2]).is_some_and(|front_block| block::hides_face(front_block, block)) {
+                    continue;
+                }
+                let in_water = if self.is_water_at(front[0], front[1], front[2]) { 1.0 } else { 0.0 };
+                let exposed = if normal[1] == 1 { sky_exposed } else { 0.0 };
+
+                let (u_axis, v_axis) = connected_textures::FACE_AXES[face];
+                let mask = if connected_texture {
+                    self.connection_mask(x, y, z, block, u_axis, v_axis)
+                } else {
+                    connected_textures::FULLY_CONNECTED
+                };
+
+                for (corner, uv) in corners.iter().zip(FACE_UVS) {
+                    // Step towards the corner along both in-plane axes
+                    let mut side_u = front;
+                    side_u[u_axis] += corner[u_axis] * 2 - 1;
+                    let mut side_v = front;
+                    side_v[v_axis] += corner[v_axis] * 2 - 1;
+                    let mut diagonal = side_u;
+                    diagonal[v_axis] = side_v[v_axis];
+
+                    // Opaque cells around the corner darken it in the shader (ambient occlusion), so
+                    // they stand in with the face's own light here. A corner enclosed by both sides
+                    // counts as fully occluded, and light can't leak through it.
+                    let side_u_opaque = self.is_opaque_at(side_u[0], side_u[1], side_u[2]);
+                    let side_v_opaque = self.is_opaque_at(side_v[0], side_v[1], side_v[2]);
+                    let diagonal_opaque = (side_u_opaque && side_v_opaque) || self.is_opaque_at(diagonal[0], diagonal[1], diagonal[2]);
+                    let occlusion = [side_u_opaque, side_v_opaque, diagonal_opaque].into_iter().filter(|&opaque| opaque).count();
+                    let corner_light = |light: &[u8], outside: u8| {
+                        let front_light = self.sample_light(light, outside, front[0], front[1], front[2]);
+                        let sample = |opaque: bool, cell: [i32; 3]| {
+                            if opaque { front_light } else { self.sample_light(light, outside, cell[0], cell[1], cell[2]) }
+                        };
+                        (front_light + sample(side_u_opaque, side_u) + sample(side_v_opaque, side_v) + sample(diagonal_opaque, diagonal)) / 4.0
+                    };
+                    let sky_light = corner_light(&light.sky, outside_sky);
+                    let block_light = corner_light(&light.block, 0);
+                    let world_y = chunk_key.1 as f32 * self.height as f32 + (pos[1] + corner[1]) as f32;
+                    let shade = depth_darkness.brightness_at(world_y);
+
+                    section.positions.push([
+                        (pos[0] + corner[0]) as f32,
+                        pos[1] as f32 + corner[1] as f32 * top_height,
+                        (pos[2] + corner[2]) as f32,
+                    ]);
+                    section.normals.push([normal[0] as f32, normal[1] as f32, normal[2] as f32]);
+                    section.uvs.push(connected_textures::tile_uv(mask, uv));
+                    section.colors.push([r * shade, g * shade, b * shade, foliage + occlusion as f32 * OCCLUSION_ALPHA_STEP]);
+                    section.shader_flags.push([
+                        block_textures::pack_shader_flag(in_water, texture_layer) + lighting::pack_light_level(block_light),
+                        exposed + lighting::pack_light_level(sky_light),
+                    ]);
+                }
+
+                let base = section.vertex_count() - 4;
+                section.indices.extend(winding.iter().map(|&i| i + base));
+            }
+        }
+
+        TerrainMeshes::from_sections(sections)
+    }
+
+    // Stand-in for the opaque terrain at a distance: the chunk downsampled to cells of
+    // LOD_CELL_SIZE³ voxels, each filled with its most common opaque block when at least half
+    // of it is opaque. Flat shaded like generate_mesh; see-through blocks and water keep their
+    // own full detail sections.
+    pub fn generate_lod_mesh(&self, chunk_key: (i32, i32, i32), depth_darkness: &DepthDarknessCurve) -> Mesh {
+        // Same face table as generate_face_mesh
+        const FACES: [FaceTemplate; 6] = [
+            ([0, 0, -1], [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]], [0, 2, 1, 2, 3, 1]), // Front face
+            ([0, 0, 1], [[0, 0, 1], [1, 0, 1], [0, 1, 1], [1, 1, 1]], [0, 1, 2, 1, 3, 2]),  // Back face
+            ([-1, 0, 0], [[0, 0, 0], [0, 1, 0], [0, 0, 1], [0, 1, 1]], [0, 2, 1, 2, 3, 1]), // Left face
+            ([1, 0, 0], [[1, 0, 0], [1, 1, 0], [1, 0, 1], [1, 1, 1]], [0, 1, 2, 1, 3, 2]),  // Right face
+            ([0, 1, 0], [[0, 1, 0], [1, 1, 0], [0, 1, 1], [1, 1, 1]], [0, 2, 1, 2, 3, 1]),  // Top face
+            ([0, -1, 0], [[0, 0, 0], [1, 0, 0], [0, 0, 1], [1, 0, 1]], [0, 1, 2, 1, 3, 2]), // Bottom face
+        ];
+        const FACE_UVS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let cell = LOD_CELL_SIZE as i32;
+        let cells = [self.width / LOD_CELL_SIZE, self.height / LOD_CELL_SIZE, self.depth / LOD_CELL_SIZE];
+        let _span = info_span!("chunk_lod_mesh", ?chunk_key).entered();
+
+        let mut cell_blocks = vec![None; cells[0] * cells[1] * cells[2]];
+        let mut counts: Vec<(BlockId, usize)> = Vec::new();
+        for cx in 0..cells[0] {
+            for cy in 0..cells[1] {
+                for cz in 0..cells[2] {
+                    counts.clear();
+                    for x in cx * LOD_CELL_SIZE..(cx + 1) * LOD_CELL_SIZE {
+                        for y in cy * LOD_CELL_SIZE..(cy + 1) * LOD_CELL_SIZE {
+                            for z in cz * LOD_CELL_SIZE..(cz + 1) * LOD_CELL_SIZE {
+                                let block = self.get_block(x, y, z);
+                                if !block::is_opaque(block) {
+                                    continue;
+                                }
+                                match counts.iter_mut().find(|(counted, _)| *counted == block) {
+                                    Some((_, count)) => *count += 1,
+                                    None => counts.push((block, 1)),
+                                }
+                            }
+                        }
+                    }
+                    let opaque: usize = counts.iter().map(|&(_, count)| count).sum();
+                    if opaque * 2 >= LOD_CELL_SIZE.pow(3) {
+                        cell_blocks[cx + cy * cells[0] + cz * cells[0] * cells[1]] = counts.iter().max_by_key(|&&(_, count)| count).map(|&(block, _)| block);
+                    }
+                }
+            }
+        }
+        let cell_block = |pos: [i32; 3]| -> Option<BlockId> {
+            cell_blocks[pos[0] as usize + pos[1] as usize * cells[0] + pos[2] as usize * cells[0] * cells[1]]
+        };
+
+        let [block_light, sky_light] = self.full_light_flags();
+        let mut section = MeshSection::default();
+        for cx in 0..cells[0] as i32 {
+            for cy in 0..cells[1] as i32 {
+                for cz in 0..cells[2] as i32 {
+                    let pos = [cx, cy, cz];
+                    let Some(block) = cell_block(pos) else {
+                        continue;
+                    };
+                    let [r, g, b] = block::face_color(block, 0);
+                    let foliage = if block::is_foliage(block) { 1.0 } else { 0.0 };
+                    let layer_flags = block_textures::pack_shader_flag(0.0, block::texture_layer(block)) + block_light;
+
+                    for (face, (normal, corners, winding)) in FACES.iter().enumerate() {
+                        let front = [pos[0] + normal[0], pos[1] + normal[1], pos[2] + normal[2]];
+                        let inside = (0..3).all(|axis| front[axis] >= 0 && front[axis] < cells[axis] as i32);
+                        let hidden = if inside {
+                            cell_block(front).is_some()
+                        } else {
+                            // Across the border only the one voxel layer of the neighbour is
+                            // known; the face goes if that layer covers the whole cell side
+                            let axis = face / 2;
+                            let (u_axis, v_axis) = ChunkBorders::layer_axes(axis);
+                            (0..cell).all(|u| (0..cell).all(|v| {
+                                let mut voxel = [pos[0] * cell, pos[1] * cell, pos[2] * cell];
+                                voxel[axis] = if normal[axis] > 0 { voxel[axis] + cell } else { voxel[axis] - 1 };
+                                voxel[u_axis] += u;
+                                voxel[v_axis] += v;
+                                self.is_opaque_at(voxel[0], voxel[1], voxel[2])
+                            }))
+                        };
+                        if hidden {
+                            continue;
+                        }
+
+                        for (corner, uv) in corners.iter().zip(FACE_UVS) {
+                            let position = [(pos[0] + corner[0]) * cell, (pos[1] + corner[1]) * cell, (pos[2] + corner[2]) * cell];
+                            let depth_shade = depth_darkness.brightness_at(chunk_key.1 as f32 * self.height as f32 + position[1] as f32);
+                            section.positions.push(position.map(|coordinate| coordinate as f32));
+                            section.normals.push(normal.map(|component| component as f32));
+                            section.uvs.push(connected_textures::tile_uv(connected_textures::FULLY_CONNECTED, uv));
+                            section.colors.push([r * depth_shade, g * depth_shade, b * depth_shade, foliage]);
+                            section.shader_flags.push([layer_flags, sky_light]);
+                        }
+
+                        let base = section.vertex_count() - 4;
+                        section.indices.extend(winding.iter().map(|&i| i + base));
+                    }
+                }
+            }
+        }
+
+        section.into_mesh()
+    }
+
+    fn is_water_at(&self, x: i32, y: i32, z: i32) -> bool {
+        self.block_at(x, y, z) == Some(WATER)
+    }
+
+    // Water surface, drawn with its own transparent material. Only faces between water and air
+    // are emitted; faces against solid blocks are hidden by the block and faces across the chunk
+    // border are left out (neighbours aren't visible here), except the top so the sea is closed.
+    // UV_1.x carries shoreline foam: 1 at corners touching a solid block in the water's plane.
+    pub fn generate_water_mesh(&self, chunk_key: (i32, i32, i32), depth_darkness: &DepthDarknessCurve) -> Option<Mesh> {
+        // Same face table as generate_face_mesh
+        const FACES: [FaceTemplate; 6] = [
+            ([0, 0, -1], [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]], [0, 2, 1, 2, 3, 1]), // Front face
+            ([0, 0, 1], [[0, 0, 1], [1, 0, 1], [0, 1, 1], [1, 1, 1]], [0, 1, 2, 1, 3, 2]),  // Back face
+            ([-1, 0, 0], [[0, 0, 0], [0, 1, 0], [0, 0, 1], [0, 1, 1]], [0, 2, 1, 2, 3, 1]), // Left face
+            ([1, 0, 0], [[1, 0, 0], [1, 1, 0], [1, 0, 1], [1, 1, 1]], [0, 1, 2, 1, 3, 2]),  // Right face
+            ([0, 1, 0], [[0, 1, 0], [1, 1, 0], [0, 1, 1], [1, 1, 1]], [0, 2, 1, 2, 3, 1]),  // Top face
+            ([0, -1, 0], [[0, 0, 0], [1, 0, 0], [0, 0, 1], [1, 0, 1]], [0, 1, 2, 1, 3, 2]), // Bottom face
+        ];
+        const TOP_FACE: usize = 4;
+        const WATER_ALPHA: f32 = 0.7;
+
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        let mut normals = Vec::new();
+        let mut colors: Vec<[f32; 4]> = Vec::new();
+        let mut foam: Vec<[f32; 2]> = Vec::new();
+
+        let [r, g, b] = block::variant_color(WATER, 0);
+        let mut index_count = 0;
+        for (index, block) in self.voxels.iter_except(AIR) {
+            if block != WATER {
+                continue;
+            }
+            let (x, y, z) = self.voxel_position(index);
+            let pos = [x as i32, y as i32, z as i32];
+
+            for (face, (normal, corners, winding)) in FACES.iter().enumerate() {
+                let front = [pos[0] + normal[0], pos[1] + normal[1], pos[2] + normal[2]];
+                let outside = front[0] < 0 || front[1] < 0 || front[2] < 0
+                    || front[0] >= self.width as i32 || front[1] >= self.height as i32 || front[2] >= self.depth as i32;
+                if outside && face != TOP_FACE {
+                    continue;
+                }
+                // Across the top seam the chunk above decides, through its border layer. Water
+                // under an unloaded chunk gets its surface until that chunk loads and remeshes this one.
+                if self.block_at(front[0], front[1], front[2]).is_some_and(|block| block != AIR) {
+                    continue;
+                }
+
+                for corner in corners {
+                    let vertex = [pos[0] + corner[0], pos[1] + corner[1], pos[2] + corner[2]];
+                    // Any of the four columns sharing this corner solid at the water's level
+                    let shore = face == TOP_FACE && (-1..=0).any(|dx| {
+                        (-1..=0).any(|dz| self.is_solid_at(vertex[0] + dx, pos[1], vertex[2] + dz))
+                    });
+                    let world_y = chunk_key.1 as f32 * self.height as f32 + vertex[1] as f32;
+                    let shade = depth_darkness.brightness_at(world_y);
+
+                    positions.push([vertex[0] as f32, vertex[1] as f32, vertex[2] as f32]);
+                    normals.push([normal[0] as f32, normal[1] as f32, normal[2] as f32]);
+                    colors.push([r * shade, g * shade, b * shade, WATER_ALPHA]);
+                    foam.push([if shore { 1.0 } else { 0.0 }, 0.0]);
+                }
+
+                indices.extend(winding.iter().map(|&i| i + index_count));
+                index_count += 4;
+            }
+        }
+
+        if indices.is_empty() {
+            return None;
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, Default::default());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, foam);
+        mesh.insert_indices(Indices::U32(indices));
+
+        Some(mesh)
+    }
+}
@@ -0,0 +1,60 @@
+use std::borrow::Cow;
+use crate::chunk_format::{FormatError, CHUNK_MAGIC, FORMAT_VERSION};
+
+// Upgrades for chunk blobs saved by older builds. Every change to the chunk layout or to what
+// stored block IDs mean bumps chunk_format::FORMAT_VERSION and adds a migration from the
+// previous version here, so old saves are rewritten step by step on load rather than
+// rejected. Migrations work on whole blobs (see the layout in chunk_format.rs) and may assume
+// their input is exactly version `from`; the version field is rewritten for them.
+
+pub struct Migration {
+    pub from: u16,
+    pub description: &'static str,
+    pub migrate: fn(&[u8]) -> Result<Vec<u8>, FormatError>,
+}
+
+// Sorted by `from`, one per version below FORMAT_VERSION
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "voxel payloads may be zstd-compressed (codecs 1 and 2)",
+        // Version 1 blobs only ever used the raw codec, which version 2 still reads
+        migrate: |blob| Ok(blob.to_vec()),
+    },
+];
+
+// Offset of the u16 version right after the magic
+const VERSION_OFFSET: usize = 4;
+
+// The version a chunk blob was written with
+pub fn blob_version(blob: &[u8]) -> Result<u16, FormatError> {
+    let magic: [u8; 4] = blob.get(..4).ok_or(FormatError::Truncated)?.try_into().unwrap();
+    if magic != CHUNK_MAGIC {
+        return Err(FormatError::BadMagic(magic));
+    }
+    let version = blob.get(VERSION_OFFSET..VERSION_OFFSET + 2).ok_or(FormatError::Truncated)?;
+    Ok(u16::from_le_bytes([version[0], version[1]]))
+}
+
+// `blob` brought up to FORMAT_VERSION, borrowed as-is when it's current already. Blobs from a
+// newer build than this one are refused rather than guessed at.
+pub fn migrate_blob(blob: &[u8]) -> Result<Cow<'_, [u8]>, FormatError> {
+    let mut version = blob_version(blob)?;
+    if version > FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+
+    let mut blob = Cow::Borrowed(blob);
+    while version < FORMAT_VERSION {
+        let migration = MIGRATIONS.iter()
+            .find(|migration| migration.from == version)
+            .ok_or(FormatError::UnsupportedVersion(version))?;
+        let mut migrated = (migration.migrate)(&blob)?;
+        version += 1;
+        migrated.get_mut(VERSION_OFFSET..VERSION_OFFSET + 2)
+            .ok_or(FormatError::Truncated)?
+            .copy_from_slice(&version.to_le_bytes());
+        blob = Cow::Owned(migrated);
+    }
+    Ok(blob)
+}
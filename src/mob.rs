@@ -0,0 +1,367 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use crate::blob_shadow::BlobShadow;
+use crate::block::{self, AIR};
+use crate::day_night::TimeOfDay;
+use crate::decoration::ChunkRng;
+use crate::player::{PlayerBody, EYE_HEIGHT, GRAVITY, TERMINAL_VELOCITY};
+use crate::voxel_world::VoxelWorld;
+use crate::{MAX_MOBS, MOB_DESPAWN_DISTANCE, MOB_SPAWN_RADIUS};
+
+const MOB_SEED: u32 = 0x0b_5eed;
+const MOB_HALF_WIDTH: f32 = 0.3;
+const MOB_HEIGHT: f32 = 1.7;
+const MOB_SPEED: f32 = 2.5; // voxels/s
+// Enough to clear one block
+const MOB_JUMP_SPEED: f32 = 8.0;
+// Mobs only spawn while the daylight is below this, see TimeOfDay::daylight
+const MOB_SPAWN_DAYLIGHT: f32 = 0.2;
+const MOB_SPAWN_SECONDS: f32 = 2.0;
+// Closest to the player a mob spawns, in voxels
+const MOB_SPAWN_MIN_DISTANCE: i32 = 16;
+// How far above and below the player a spawn column is searched for its surface
+const SPAWN_SEARCH_HEIGHT: i32 = 24;
+// Mobs within this many voxels of the player walk towards them, the others wander
+const CHASE_RANGE: f32 = 24.0;
+const WANDER_RANGE: i32 = 8;
+const REPATH_SECONDS: f32 = 1.0;
+// Path searches started per frame, the rest of the mobs keep their old path until a later frame
+const PATHS_PER_FRAME: usize = 4;
+// Cells the A* search may expand before it settles for the closest one it found
+const MAX_PATH_NODES: usize = 600;
+// Deepest drop a path steps down without jumping
+const MAX_DROP: i32 = 3;
+// Hashable voxel position for the path search
+type Cell = (i32, i32, i32);
+
+const HORIZONTAL_STEPS: [IVec3; 4] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z];
+
+// A simple hostile creature. The entity's translation is the bottom center of its box; the
+// MobModel child added by attach_mob_models draws it. Mobs aren't saved: they despawn once far
+// from the player or when the chunk under them unloads.
+#[derive(Component, Default)]
+pub struct Mob {
+    pub velocity: Vec3,
+    // Feet cells still to walk through, the next one last
+    path: Vec<IVec3>,
+    repath_in: f32,
+    on_ground: bool,
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct MobModel;
+
+#[cfg(feature = "render")]
+#[derive(Resource)]
+pub struct MobAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+#[derive(Resource)]
+pub struct MobSpawner {
+    timer: Timer,
+    rng: ChunkRng,
+}
+
+impl Default for MobSpawner {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(MOB_SPAWN_SECONDS, TimerMode::Repeating),
+            rng: ChunkRng::for_chunk((0, 0, 0), MOB_SEED),
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn setup_mobs(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(MobAssets {
+        mesh: meshes.add(Cuboid::new(MOB_HALF_WIDTH * 2.0, MOB_HEIGHT, MOB_HALF_WIDTH * 2.0)),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgb(0.3, 0.55, 0.3),
+            perceptual_roughness: 0.9,
+            ..default()
+        }),
+    });
+}
+
+// Feet cells a mob can stand in: solid ground below and room for its body above
+fn walkable(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    voxel_world.is_solid(pos - IVec3::Y) && is_open(voxel_world, pos) && is_open(voxel_world, pos + IVec3::Y)
+}
+
+// Loaded and not solid
+fn is_open(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    voxel_world.get_block(pos).is_some_and(|block| !block::is_solid(block))
+}
+
+// Where a mob standing at `from` gets to with one step in `direction`, and what it costs:
+// level ground, a jump up one block, or a drop of up to MAX_DROP blocks
+fn step(voxel_world: &VoxelWorld, from: IVec3, direction: IVec3) -> Option<(IVec3, u32)> {
+    let next = from + direction;
+    if walkable(voxel_world, next) {
+        return Some((next, 10));
+    }
+    if walkable(voxel_world, next + IVec3::Y) && is_open(voxel_world, from + IVec3::Y * 2) {
+        return Some((next + IVec3::Y, 20));
+    }
+    if !is_open(voxel_world, next) || !is_open(voxel_world, next + IVec3::Y) {
+        return None;
+    }
+    (1..=MAX_DROP)
+        .take_while(|&drop| drop == 1 || is_open(voxel_world, next - IVec3::Y * (drop - 1)))
+        .find(|&drop| walkable(voxel_world, next - IVec3::Y * drop))
+        .map(|drop| (next - IVec3::Y * drop, 10 + 5 * drop as u32))
+}
+
+// A* over feet cells from `start` towards `goal`. When the goal can't be reached within
+// MAX_PATH_NODES expanded cells the path leads to the explored cell closest to it instead, so
+// mobs still make progress towards far targets. The returned cells exclude `start`, next one last.
+pub fn find_path(voxel_world: &VoxelWorld, start: IVec3, goal: IVec3) -> Vec<IVec3> {
+    let _span = info_span!("mob_pathfinding").entered();
+    let heuristic = |pos: IVec3| {
+        let distance = (goal - pos).abs();
+        (distance.x + distance.y + distance.z) as u32 * 10
+    };
+    let key = |pos: IVec3| -> Cell { (pos.x, pos.y, pos.z) };
+
+    let mut open = BinaryHeap::from([Reverse((heuristic(start), 0, key(start)))]);
+    let mut came_from: HashMap<Cell, (Cell, u32)> = HashMap::from([(key(start), (key(start), 0))]);
+    let mut closest = (heuristic(start), key(start));
+    let mut expanded = 0;
+
+    while let Some(Reverse((_, cost, current))) = open.pop() {
+        if current == key(goal) {
+            closest = (0, current);
+            break;
+        }
+        // Stale entry; the cell was reached more cheaply since
+        if came_from[&current].1 < cost {
+            continue;
+        }
+        expanded += 1;
+        if expanded > MAX_PATH_NODES {
+            break;
+        }
+
+        let pos = IVec3::from(current);
+        for direction in HORIZONTAL_STEPS {
+            let Some((next, step_cost)) = step(voxel_world, pos, direction) else {
+                continue;
+            };
+            let next_cost = cost + step_cost;
+            if came_from.get(&key(next)).is_some_and(|&(_, known)| known <= next_cost) {
+                continue;
+            }
+            came_from.insert(key(next), (current, next_cost));
+            let estimate = heuristic(next);
+            closest = closest.min((estimate, key(next)));
+            open.push(Reverse((next_cost + estimate, next_cost, key(next))));
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut cell = closest.1;
+    while cell != key(start) {
+        path.push(IVec3::from(cell));
+        cell = came_from[&cell].0;
+    }
+    path
+}
+
+// Topmost cell a mob could stand in within SPAWN_SEARCH_HEIGHT of `around_y`, only if nothing
+// but air is above it, so mobs spawn on the surface rather than in caves
+fn spawn_cell(voxel_world: &VoxelWorld, x: i32, z: i32, around_y: i32) -> Option<IVec3> {
+    for y in (around_y - SPAWN_SEARCH_HEIGHT..=around_y + SPAWN_SEARCH_HEIGHT).rev() {
+        let pos = IVec3::new(x, y, z);
+        if voxel_world.get_block(pos)? != AIR {
+            let feet = pos + IVec3::Y;
+            return walkable(voxel_world, feet).then_some(feet);
+        }
+    }
+    None
+}
+
+// Tries one column around the player every MOB_SPAWN_SECONDS while it's dark, up to MAX_MOBS.
+// Runs only while the mobSpawning game rule is on.
+pub fn spawn_mobs(
+    mut commands: Commands,
+    time: Res<Time>,
+    time_of_day: Res<TimeOfDay>,
+    mut spawner: ResMut<MobSpawner>,
+    voxel_world: VoxelWorld,
+    player_query: Query<&Transform, With<PlayerBody>>,
+    mobs: Query<(), With<Mob>>,
+) {
+    if !spawner.timer.tick(time.delta()).just_finished() || time_of_day.daylight() >= MOB_SPAWN_DAYLIGHT {
+        return;
+    }
+    if mobs.iter().count() >= MAX_MOBS {
+        return;
+    }
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+
+    let center = voxel_world.voxel_at(player.translation);
+    let offset = IVec2::new(spawner.rng.range(-MOB_SPAWN_RADIUS, MOB_SPAWN_RADIUS), spawner.rng.range(-MOB_SPAWN_RADIUS, MOB_SPAWN_RADIUS));
+    if offset.abs().max_element() < MOB_SPAWN_MIN_DISTANCE {
+        return;
+    }
+    let Some(feet) = spawn_cell(&voxel_world, center.x + offset.x, center.z + offset.y, center.y) else {
+        return;
+    };
+
+    let position = feet.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+    commands.spawn((
+        Mob::default(),
+        TransformBundle::from_transform(Transform::from_translation(position)),
+        Name::new("Mob"),
+    ));
+}
+
+// Gives every new mob its box and shadow
+#[cfg(feature = "render")]
+pub fn attach_mob_models(mut commands: Commands, assets: Res<MobAssets>, mobs: Query<Entity, Added<Mob>>) {
+    for entity in &mobs {
+        commands.entity(entity)
+            .insert((VisibilityBundle::default(), BlobShadow { radius: MOB_HALF_WIDTH * 1.5 }))
+            .with_children(|parent| {
+                parent.spawn((
+                    PbrBundle {
+                        mesh: assets.mesh.clone(),
+                        material: assets.material.clone(),
+                        transform: Transform::from_xyz(0.0, MOB_HEIGHT * 0.5, 0.0),
+                        ..default()
+                    },
+                    MobModel,
+                ));
+            });
+    }
+}
+
+// Every REPATH_SECONDS a mob looks for a new path: to the player when close enough, otherwise
+// to a random cell nearby. At most PATHS_PER_FRAME searches run per frame.
+pub fn update_mob_paths(
+    time: Res<Time>,
+    voxel_world: VoxelWorld,
+    mut spawner: ResMut<MobSpawner>,
+    player_query: Query<&Transform, (With<PlayerBody>, Without<Mob>)>,
+    mut mobs: Query<(&Transform, &mut Mob)>,
+) {
+    let player_feet = player_query.get_single().ok().map(|player| player.translation - Vec3::Y * EYE_HEIGHT);
+    let mut searches = 0;
+
+    for (transform, mut mob) in &mut mobs {
+        mob.repath_in -= time.delta_seconds();
+        if mob.repath_in > 0.0 || !mob.on_ground || searches >= PATHS_PER_FRAME {
+            continue;
+        }
+        searches += 1;
+        mob.repath_in = REPATH_SECONDS;
+
+        let start = voxel_world.voxel_at(transform.translation + Vec3::Y * 0.1);
+        let goal = match player_feet {
+            Some(feet) if feet.distance(transform.translation) < CHASE_RANGE => voxel_world.voxel_at(feet + Vec3::Y * 0.1),
+            _ => start + IVec3::new(spawner.rng.range(-WANDER_RANGE, WANDER_RANGE), 0, spawner.rng.range(-WANDER_RANGE, WANDER_RANGE)),
+        };
+        mob.path = find_path(&voxel_world, start, goal);
+    }
+}
+
+// Steers each mob along its path, jumping where the next cell is higher, then applies gravity
+// and moves it axis by axis against the voxel grid like the player
+pub fn move_mobs(
+    time: Res<Time>,
+    voxel_world: VoxelWorld,
+    mut mobs: Query<(&mut Transform, &mut Mob)>,
+) {
+    let dt = time.delta_seconds();
+    for (mut transform, mut mob) in &mut mobs {
+        let position = transform.translation;
+
+        // Waypoints count as reached once the mob stands roughly in their cell
+        while let Some(&next) = mob.path.last() {
+            let center = next.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+            if (center - position).xz().length() < 0.25 && (position.y - center.y).abs() < 0.6 {
+                mob.path.pop();
+            } else {
+                break;
+            }
+        }
+
+        let mut steering = Vec2::ZERO;
+        if let Some(&next) = mob.path.last() {
+            let center = next.as_vec3() + Vec3::new(0.5, 0.0, 0.5);
+            steering = (center - position).xz().normalize_or_zero() * MOB_SPEED;
+            if mob.on_ground && next.y as f32 > position.y + 0.5 {
+                mob.velocity.y = MOB_JUMP_SPEED;
+            }
+        }
+        // Steering takes over from knockback gradually
+        let blend = 1.0 - (-8.0 * dt).exp();
+        let horizontal = mob.velocity.xz().lerp(steering, blend);
+        mob.velocity.x = horizontal.x;
+        mob.velocity.z = horizontal.y;
+        mob.velocity.y = (mob.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+
+        let target = position + mob.velocity * dt;
+        let mut moved = position;
+        mob.on_ground = false;
+        for axis in [1, 0, 2] {
+            let mut candidate = moved;
+            candidate[axis] = target[axis];
+            if !mob_collides(&voxel_world, candidate) {
+                moved = candidate;
+                continue;
+            }
+            if axis == 1 && mob.velocity.y <= 0.0 {
+                mob.on_ground = true;
+            }
+            mob.velocity[axis] = 0.0;
+        }
+        transform.translation = moved;
+    }
+}
+
+// Whether the mob box standing at `feet` overlaps a solid voxel. Unloaded chunks count as solid
+// so mobs wait at the edge of the loaded world instead of falling through it.
+fn mob_collides(voxel_world: &VoxelWorld, feet: Vec3) -> bool {
+    let min = (feet - Vec3::new(MOB_HALF_WIDTH, 0.0, MOB_HALF_WIDTH)).floor().as_ivec3();
+    let max = (feet + Vec3::new(MOB_HALF_WIDTH, MOB_HEIGHT, MOB_HALF_WIDTH) - Vec3::splat(f32::EPSILON)).floor().as_ivec3();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                if voxel_world.get_block(IVec3::new(x, y, z)).is_none_or(block::is_solid) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+// Mobs further than MOB_DESPAWN_DISTANCE from the player, or standing in an unloaded chunk, go away
+pub fn despawn_mobs(
+    mut commands: Commands,
+    voxel_world: VoxelWorld,
+    player_query: Query<&Transform, (With<PlayerBody>, Without<Mob>)>,
+    mobs: Query<(Entity, &Transform), With<Mob>>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    for (entity, transform) in &mobs {
+        let far = transform.translation.distance(player.translation) > MOB_DESPAWN_DISTANCE;
+        if far || voxel_world.get_block(voxel_world.voxel_at(transform.translation)).is_none() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
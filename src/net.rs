@@ -0,0 +1,303 @@
+use bevy::prelude::*;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use crate::block::BlockId;
+use crate::storage::ByteReader;
+
+// Wire protocol between the server and game clients, over plain TCP. Every message is framed as
+//   length  u32  size of the rest of the frame
+//   tag     u8   message kind
+//   fields  ...  little-endian, see encode()
+// Chunks travel as chunk_format blobs (zstd-compressed voxels, see chunk_format::encode_voxels,
+// plus block entities).
+pub const PROTOCOL_VERSION: u16 = 4;
+pub const DEFAULT_PORT: u16 = 24680;
+// Frames larger than this are treated as a broken connection
+const MAX_FRAME_LEN: usize = 4 * 1024 * 1024;
+// Output queued for a peer that stopped reading; past this the connection is dropped
+const MAX_OUTGOING_LEN: usize = 32 * 1024 * 1024;
+// Input buffered by one receive call. Always room for a whole frame; the rest waits in the socket.
+const MAX_INCOMING_LEN: usize = 2 * MAX_FRAME_LEN;
+
+type ChunkKey = (i32, i32, i32);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    Hello { protocol_version: u16 },
+    // Stream this chunk and keep sending its block changes
+    RequestChunk(ChunkKey),
+    // The client unloaded the chunk and no longer wants updates for it
+    ForgetChunk(ChunkKey),
+    SetBlock { pos: IVec3, block: BlockId },
+    // Where the player's eye is, sent as it moves; edits out of reach of it are rejected
+    Position(Vec3),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerMessage {
+    // `spawn` is the eye position the player starts at; the server checks movement from there
+    Welcome { protocol_version: u16, chunk_size: u16, spawn: Vec3 },
+    ChunkData { chunk_key: ChunkKey, blob: Vec<u8> },
+    // Authoritative value of a block, broadcast after every accepted edit and sent back to the
+    // editing client to undo an edit the server rejected
+    BlockChange { pos: IVec3, block: BlockId },
+    Disconnect { reason: String },
+    // The requested chunk is too far from the player's reported position to be sent
+    ChunkRefused(ChunkKey),
+}
+
+pub trait Message: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(reader: &mut ByteReader) -> Option<Self>;
+}
+
+fn write_key(out: &mut Vec<u8>, key: ChunkKey) {
+    for coord in [key.0, key.1, key.2] {
+        out.extend_from_slice(&coord.to_le_bytes());
+    }
+}
+
+fn read_key(reader: &mut ByteReader) -> Option<ChunkKey> {
+    Some((reader.read_i32()?, reader.read_i32()?, reader.read_i32()?))
+}
+
+fn write_pos(out: &mut Vec<u8>, pos: IVec3) {
+    write_key(out, (pos.x, pos.y, pos.z));
+}
+
+fn read_pos(reader: &mut ByteReader) -> Option<IVec3> {
+    let (x, y, z) = read_key(reader)?;
+    Some(IVec3::new(x, y, z))
+}
+
+fn write_vec3(out: &mut Vec<u8>, vec: Vec3) {
+    for coord in vec.to_array() {
+        out.extend_from_slice(&coord.to_bits().to_le_bytes());
+    }
+}
+
+fn read_vec3(reader: &mut ByteReader) -> Option<Vec3> {
+    let mut coords = [0.0; 3];
+    for coord in &mut coords {
+        *coord = f32::from_bits(reader.read_u32()?);
+    }
+    Some(Vec3::from_array(coords))
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn read_bytes(reader: &mut ByteReader) -> Option<Vec<u8>> {
+    let len = reader.read_u32()?;
+    Some(reader.take_slice(len as usize)?.to_vec())
+}
+
+impl Message for ClientMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ClientMessage::Hello { protocol_version } => {
+                out.push(0);
+                out.extend_from_slice(&protocol_version.to_le_bytes());
+            }
+            ClientMessage::RequestChunk(key) => {
+                out.push(1);
+                write_key(out, *key);
+            }
+            ClientMessage::ForgetChunk(key) => {
+                out.push(2);
+                write_key(out, *key);
+            }
+            ClientMessage::SetBlock { pos, block } => {
+                out.push(3);
+                write_pos(out, *pos);
+                out.extend_from_slice(&block.to_le_bytes());
+            }
+            ClientMessage::Position(position) => {
+                out.push(4);
+                write_vec3(out, *position);
+            }
+        }
+    }
+
+    fn decode(reader: &mut ByteReader) -> Option<Self> {
+        match reader.read_u8()? {
+            0 => Some(ClientMessage::Hello { protocol_version: reader.read_u16()? }),
+            1 => Some(ClientMessage::RequestChunk(read_key(reader)?)),
+            2 => Some(ClientMessage::ForgetChunk(read_key(reader)?)),
+            3 => Some(ClientMessage::SetBlock { pos: read_pos(reader)?, block: reader.read_u16()? }),
+            4 => Some(ClientMessage::Position(read_vec3(reader)?)),
+            _ => None,
+        }
+    }
+}
+
+impl Message for ServerMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ServerMessage::Welcome { protocol_version, chunk_size, spawn } => {
+                out.push(0);
+                out.extend_from_slice(&protocol_version.to_le_bytes());
+                out.extend_from_slice(&chunk_size.to_le_bytes());
+                write_vec3(out, *spawn);
+            }
+            ServerMessage::ChunkData { chunk_key, blob } => {
+                out.push(1);
+                write_key(out, *chunk_key);
+                write_bytes(out, blob);
+            }
+            ServerMessage::BlockChange { pos, block } => {
+                out.push(2);
+                write_pos(out, *pos);
+                out.extend_from_slice(&block.to_le_bytes());
+            }
+            ServerMessage::Disconnect { reason } => {
+                out.push(3);
+                write_bytes(out, reason.as_bytes());
+            }
+            ServerMessage::ChunkRefused(key) => {
+                out.push(4);
+                write_key(out, *key);
+            }
+        }
+    }
+
+    fn decode(reader: &mut ByteReader) -> Option<Self> {
+        match reader.read_u8()? {
+            0 => Some(ServerMessage::Welcome { protocol_version: reader.read_u16()?, chunk_size: reader.read_u16()?, spawn: read_vec3(reader)? }),
+            1 => Some(ServerMessage::ChunkData { chunk_key: read_key(reader)?, blob: read_bytes(reader)? }),
+            2 => Some(ServerMessage::BlockChange { pos: read_pos(reader)?, block: reader.read_u16()? }),
+            3 => Some(ServerMessage::Disconnect { reason: String::from_utf8(read_bytes(reader)?).ok()? }),
+            4 => Some(ServerMessage::ChunkRefused(read_key(reader)?)),
+            _ => None,
+        }
+    }
+}
+
+// Non-blocking framed stream, polled from systems every frame. Outgoing frames are buffered
+// until the socket accepts them; a read or write error, a malformed frame, or more than
+// MAX_OUTGOING_LEN of unsent output closes it for good.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+    // Closed because the peer stopped reading
+    overflowed: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, incoming: Vec::new(), outgoing: Vec::new(), closed: false, overflowed: false })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    pub fn has_pending_output(&self) -> bool {
+        !self.outgoing.is_empty()
+    }
+
+    pub fn send(&mut self, message: &impl Message) {
+        let mut frame = Vec::new();
+        message.encode(&mut frame);
+        if self.closed {
+            return;
+        }
+        if self.outgoing.len() + 4 + frame.len() > MAX_OUTGOING_LEN {
+            self.closed = true;
+            self.overflowed = true;
+            self.outgoing = Vec::new();
+            return;
+        }
+        self.outgoing.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.outgoing.extend_from_slice(&frame);
+    }
+
+    // Writes as much buffered output as the socket takes right now
+    pub fn flush(&mut self) {
+        while !self.closed && !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => self.closed = true,
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => self.closed = true,
+            }
+        }
+    }
+
+    // Every complete message received since the last call
+    pub fn receive<M: Message>(&mut self) -> Vec<M> {
+        let mut buffer = [0u8; 16 * 1024];
+        while !self.closed && self.incoming.len() < MAX_INCOMING_LEN {
+            let room = buffer.len().min(MAX_INCOMING_LEN - self.incoming.len());
+            match self.stream.read(&mut buffer[..room]) {
+                Ok(0) => self.closed = true,
+                Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => self.closed = true,
+            }
+        }
+
+        let mut messages = Vec::new();
+        let mut consumed = 0;
+        while let Some(len_bytes) = self.incoming.get(consumed..consumed + 4) {
+            let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+            if len > MAX_FRAME_LEN {
+                self.closed = true;
+                break;
+            }
+            let Some(frame) = self.incoming.get(consumed + 4..consumed + 4 + len) else {
+                break;
+            };
+            let mut reader = ByteReader::new(frame);
+            match M::decode(&mut reader) {
+                Some(message) if reader.remaining() == 0 => messages.push(message),
+                _ => {
+                    self.closed = true;
+                    break;
+                }
+            }
+            consumed += 4 + len;
+        }
+        self.incoming.drain(..consumed);
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<M: Message + PartialEq + std::fmt::Debug>(message: M) {
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes);
+        let mut reader = ByteReader::new(&bytes);
+        assert_eq!(M::decode(&mut reader).as_ref(), Some(&message));
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn messages_decode_to_what_was_encoded() {
+        round_trip(ClientMessage::Hello { protocol_version: PROTOCOL_VERSION });
+        round_trip(ClientMessage::RequestChunk((-3, 0, i32::MAX)));
+        round_trip(ClientMessage::Position(Vec3::new(0.5, -64.0, 1.0e6)));
+        round_trip(ClientMessage::SetBlock { pos: IVec3::new(-1, 2, -3), block: 7 });
+        round_trip(ServerMessage::Welcome { protocol_version: PROTOCOL_VERSION, chunk_size: 16, spawn: Vec3::new(8.5, 70.6, -3.5) });
+        round_trip(ServerMessage::ChunkData { chunk_key: (1, 2, 3), blob: vec![1, 2, 3] });
+        round_trip(ServerMessage::ChunkRefused((i32::MIN, 0, 0)));
+        round_trip(ServerMessage::Disconnect { reason: "bye".to_string() });
+    }
+}
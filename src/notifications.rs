@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use crate::theme::Theme;
+#[cfg(feature = "render")]
+use crate::{MAX_TOASTS, TOAST_SECONDS};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+// A message for the player, shown as a toast for a few seconds
+#[derive(Event, Clone, Debug)]
+pub struct NotificationEvent {
+    pub message: String,
+    pub level: NotificationLevel,
+}
+
+impl NotificationEvent {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self { message: message.into(), level: NotificationLevel::Info }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self { message: message.into(), level: NotificationLevel::Warning }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self { message: message.into(), level: NotificationLevel::Error }
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct ToastStack;
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct Toast {
+    pub timer: Timer,
+    pub level: NotificationLevel,
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_toast_stack(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.0),
+                right: Val::Px(8.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::FlexEnd,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            ..default()
+        },
+        ToastStack,
+    ));
+}
+
+#[cfg(feature = "render")]
+fn toast_text_color(theme: &Theme, level: NotificationLevel) -> Color {
+    match level {
+        NotificationLevel::Info => theme.hud_text,
+        NotificationLevel::Warning => theme.debug_secondary,
+        NotificationLevel::Error => theme.debug_warning,
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn show_notifications(
+    mut commands: Commands,
+    mut notifications: EventReader<NotificationEvent>,
+    theme: Res<Theme>,
+    stack_query: Query<(Entity, Option<&Children>), With<ToastStack>>,
+) {
+    let Ok((stack, children)) = stack_query.get_single() else {
+        return;
+    };
+    let notifications: Vec<_> = notifications.read().collect();
+    for notification in &notifications {
+        match notification.level {
+            NotificationLevel::Info => info!("{}", notification.message),
+            NotificationLevel::Warning => warn!("{}", notification.message),
+            NotificationLevel::Error => error!("{}", notification.message),
+        }
+    }
+
+    // Oldest toasts make room for new ones, counting every one arriving this frame. A burst of
+    // more than MAX_TOASTS only shows its newest, the rest are in the log above.
+    let existing = children.map_or(&[][..], |children| &children[..]);
+    let excess = (existing.len() + notifications.len()).saturating_sub(MAX_TOASTS);
+    for oldest in existing.iter().take(excess) {
+        commands.entity(*oldest).despawn_recursive();
+    }
+    let skipped = excess.saturating_sub(existing.len());
+
+    for notification in notifications.into_iter().skip(skipped) {
+        let toast = commands.spawn((
+            TextBundle::from_section(
+                notification.message.clone(),
+                TextStyle {
+                    font_size: 16.0,
+                    color: toast_text_color(&theme, notification.level),
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                ..default()
+            })
+            .with_background_color(theme.hud_background),
+            Toast {
+                timer: Timer::from_seconds(TOAST_SECONDS, TimerMode::Once),
+                level: notification.level,
+            },
+        )).id();
+        commands.entity(stack).add_child(toast);
+    }
+}
+
+// Fades toasts out over their last second and removes them when they expire
+#[cfg(feature = "render")]
+pub fn update_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    theme: Res<Theme>,
+    mut toast_query: Query<(Entity, &mut Toast, &mut Text, &mut BackgroundColor)>,
+) {
+    for (entity, mut toast, mut text, mut background_color) in &mut toast_query {
+        toast.timer.tick(time.delta());
+        if toast.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let opacity = toast.timer.remaining_secs().min(1.0);
+        let text_color = toast_text_color(&theme, toast.level);
+        text.sections[0].style.color = text_color.with_alpha(text_color.alpha() * opacity);
+        background_color.0 = theme.hud_background.with_alpha(theme.hud_background.alpha() * opacity);
+    }
+}
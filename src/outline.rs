@@ -0,0 +1,119 @@
+// The ShaderType derive (encase) emits `check` functions it never calls, which rustc reports as
+// dead code on every uniform field
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use crate::block::Transparency;
+use crate::keybindings::{Action, Actions};
+use crate::terrain::Chunk;
+use crate::TRANSLUCENT_ALPHA;
+use crate::water::{TerrainMaterial, WaterMaterial};
+
+pub type OutlinedMaterial = ExtendedMaterial<StandardMaterial, VoxelOutline>;
+
+// Draws thin lines along every voxel edge in the fragment shader, the "blueprint" look.
+// Edges are derived from the fractional world position, so merged boxes from the mesher
+// still show the individual voxel grid.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct VoxelOutline {
+    // Bindings 0-99 belong to the base StandardMaterial
+    #[uniform(100)]
+    pub settings: VoxelOutlineSettings,
+}
+
+#[derive(ShaderType, Reflect, Debug, Clone)]
+pub struct VoxelOutlineSettings {
+    pub color: LinearRgba,
+    pub width: f32,
+    // Same as the terrain shader's, see LightingTuning and day_night
+    pub ao_strength: f32,
+    pub skylight: f32,
+}
+
+impl MaterialExtension for VoxelOutline {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/voxel_outline.wgsl".into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        "shaders/voxel_outline.wgsl".into()
+    }
+}
+
+// Shared chunk materials, one per render style
+#[derive(Resource)]
+pub struct ChunkMaterials {
+    pub standard: Handle<TerrainMaterial>,
+    pub outlined: Handle<OutlinedMaterial>,
+    // Glass and leaves sections of all chunks, whatever the render style, see section_base_material
+    pub cutout: Handle<TerrainMaterial>,
+    pub translucent: Handle<TerrainMaterial>,
+    // Shared by the water surfaces of all chunks, whatever the render style
+    pub water: Handle<WaterMaterial>,
+}
+
+impl ChunkMaterials {
+    // Every material drawn with the terrain shader, for settings that apply to all of them
+    pub fn terrain(&self) -> [&Handle<TerrainMaterial>; 3] {
+        [&self.standard, &self.cutout, &self.translucent]
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ToonMode {
+    pub enabled: bool,
+}
+
+// Block colors come from the mesh's vertex colors, the atlas only adds connected-texture borders
+pub fn chunk_base_material(atlas: Handle<Image>) -> StandardMaterial {
+    StandardMaterial {
+        base_color: Color::WHITE,
+        base_color_texture: Some(atlas),
+        ..default()
+    }
+}
+
+// Base for the see-through terrain sections. Vertex alpha flags foliage there like on opaque
+// terrain, so the terrain shader takes coverage from this alpha and the block texture instead.
+pub fn section_base_material(atlas: Handle<Image>, transparency: Transparency) -> StandardMaterial {
+    let (alpha_mode, alpha) = match transparency {
+        Transparency::Opaque => (AlphaMode::Opaque, 1.0),
+        Transparency::Cutout => (AlphaMode::Mask(0.5), 1.0),
+        Transparency::Translucent => (AlphaMode::Blend, TRANSLUCENT_ALPHA),
+    };
+    StandardMaterial {
+        alpha_mode,
+        base_color: Color::WHITE.with_alpha(alpha),
+        ..chunk_base_material(atlas)
+    }
+}
+
+pub fn toggle_toon_mode(
+    actions: Actions,
+    mut toon_mode: ResMut<ToonMode>,
+    chunk_materials: Res<ChunkMaterials>,
+    chunk_query: Query<Entity, With<Chunk>>,
+    mut commands: Commands,
+) {
+    if !actions.just_pressed(Action::ToonMode) {
+        return;
+    }
+
+    toon_mode.enabled = !toon_mode.enabled;
+    println!("Toon mode {}", if toon_mode.enabled { "enabled" } else { "disabled" });
+
+    // Swap the material on every existing chunk; new chunks pick the style up when spawned
+    for entity in &chunk_query {
+        if toon_mode.enabled {
+            commands.entity(entity)
+                .remove::<Handle<TerrainMaterial>>()
+                .insert(chunk_materials.outlined.clone());
+        } else {
+            commands.entity(entity)
+                .remove::<Handle<OutlinedMaterial>>()
+                .insert(chunk_materials.standard.clone());
+        }
+    }
+}
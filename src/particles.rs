@@ -0,0 +1,160 @@
+// For the ShaderType derive, see outline.rs
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use bevy::pbr::NotShadowCaster;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::render::view::NoFrustumCulling;
+use std::collections::HashMap;
+use crate::block::{self, BlockId};
+use crate::decoration::ChunkRng;
+use crate::player::{GRAVITY, TERMINAL_VELOCITY};
+use crate::voxel_events::{VoxelBrokenEvent, VoxelPlacedEvent};
+use crate::voxel_world::VoxelWorld;
+
+// Upper bound on live particles; events past it spawn nothing, so a large region edit
+// doesn't flood the scene
+const MAX_PARTICLES: usize = 512;
+const BREAK_PARTICLES: usize = 12;
+const PLACE_PARTICLES: usize = 5;
+const BREAK_PARTICLE_SIZE: f32 = 0.12;
+const PLACE_PARTICLE_SIZE: f32 = 0.08;
+const BREAK_SPEED: f32 = 3.0;
+const PLACE_SPEED: f32 = 1.5;
+const PARTICLE_LIFETIME: f32 = 0.8;
+const PARTICLE_SEED: u32 = 0x00de_b715;
+
+// Camera-facing square drawn by particle.wgsl. The vertex shader builds the quad from the
+// view's right and up vectors, so the entity transform only needs translation and scale.
+// All particles of one block color share a material and the quad mesh, which lets the
+// renderer batch them into a single instanced draw.
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+pub struct ParticleMaterial {
+    #[uniform(0)]
+    pub settings: ParticleSettings,
+}
+
+#[derive(ShaderType, Debug, Clone)]
+pub struct ParticleSettings {
+    pub color: LinearRgba,
+}
+
+impl Material for ParticleMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/particle.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/particle.wgsl".into()
+    }
+}
+
+#[derive(Component)]
+pub struct Particle {
+    pub velocity: Vec3,
+    pub age: f32,
+    pub size: f32,
+}
+
+#[derive(Resource)]
+pub struct ParticleAssets {
+    quad: Handle<Mesh>,
+    // One material per block color variant, created on first use
+    materials: HashMap<(BlockId, usize), Handle<ParticleMaterial>>,
+    rng: ChunkRng,
+}
+
+pub fn setup_particles(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ParticleAssets {
+        quad: meshes.add(Rectangle::new(1.0, 1.0)),
+        materials: HashMap::new(),
+        rng: ChunkRng::for_chunk((0, 0, 0), PARTICLE_SEED),
+    });
+}
+
+// Debris bursting out of broken blocks and a small puff around placed ones, each particle
+// taking one of the block's color variants
+pub fn spawn_block_particles(
+    mut commands: Commands,
+    mut assets: ResMut<ParticleAssets>,
+    mut materials: ResMut<Assets<ParticleMaterial>>,
+    voxel_world: VoxelWorld,
+    mut broken_events: EventReader<VoxelBrokenEvent>,
+    mut placed_events: EventReader<VoxelPlacedEvent>,
+    particles: Query<(), With<Particle>>,
+) {
+    let bursts = broken_events.read()
+        .map(|event| (event.chunk_key, event.voxel_pos, event.block, BREAK_PARTICLES, BREAK_PARTICLE_SIZE, BREAK_SPEED))
+        .chain(placed_events.read()
+            .map(|event| (event.chunk_key, event.voxel_pos, event.block, PLACE_PARTICLES, PLACE_PARTICLE_SIZE, PLACE_SPEED)))
+        .collect::<Vec<_>>();
+
+    let mut live = particles.iter().count();
+    for (chunk_key, voxel_pos, block, count, size, speed) in bursts {
+        if live + count > MAX_PARTICLES {
+            break;
+        }
+        live += count;
+
+        let center = voxel_world.to_world(chunk_key, voxel_pos).as_vec3() + Vec3::splat(0.5);
+        let variants = block::definition(block).variants.len();
+        for _ in 0..count {
+            let assets = &mut *assets;
+            let variant = assets.rng.range(0, variants as i32 - 1) as usize;
+            let offset = Vec3::new(assets.rng.next_f32(), assets.rng.next_f32(), assets.rng.next_f32()) - Vec3::splat(0.5);
+            let material = assets.materials.entry((block, variant)).or_insert_with(|| {
+                let [r, g, b] = block::variant_color(block, variant);
+                materials.add(ParticleMaterial {
+                    settings: ParticleSettings { color: Color::srgb(r, g, b).to_linear() },
+                })
+            });
+
+            commands.spawn((
+                MaterialMeshBundle {
+                    mesh: assets.quad.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_translation(center + offset * 0.8).with_scale(Vec3::splat(size)),
+                    ..default()
+                },
+                Particle {
+                    // Outwards from the block's center with a little upward kick
+                    velocity: (offset.normalize_or_zero() + Vec3::Y * 0.5) * speed,
+                    age: 0.0,
+                    size,
+                },
+                NotShadowCaster,
+                // The mesh bounds don't follow the billboard rotation done on the GPU
+                NoFrustumCulling,
+            ));
+        }
+    }
+}
+
+// Gravity, resting on solid ground, and shrinking away towards the end of the lifetime
+pub fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    voxel_world: VoxelWorld,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut particle, mut transform) in &mut particles {
+        particle.age += dt;
+        if particle.age >= PARTICLE_LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        particle.velocity.y = (particle.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+        let next = transform.translation + particle.velocity * dt;
+        if voxel_world.is_solid(voxel_world.voxel_at(next - Vec3::Y * particle.size * 0.5)) {
+            // Landed (or hit a wall): stop and let it fade where it is
+            particle.velocity = Vec3::ZERO;
+        } else {
+            transform.translation = next;
+        }
+
+        let remaining = 1.0 - particle.age / PARTICLE_LIFETIME;
+        transform.scale = Vec3::splat(particle.size * remaining.sqrt());
+    }
+}
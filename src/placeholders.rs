@@ -0,0 +1,110 @@
+use std::collections::{HashMap, HashSet};
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use crate::block::{BlockId, WATER};
+use crate::dimension::Dimension;
+use crate::hotbar::block_icon_color;
+use crate::world::World;
+use crate::worldgen::WorldGenerator;
+
+// Flat stand-ins for chunks that are queued or generated but not meshed yet, so flying fast
+// shows rough ground ahead instead of holes. Each chunk column gets one chunk-wide quad at its
+// ground height (or the sea surface over water), in the color of its surface block, drawn in
+// the chunk holding that height. The height is sampled once per column from the generator and
+// cached. A placeholder goes in the same frame its chunk's entity is spawned, so the real mesh
+// replaces it without a gap. Overworld only: the other dimensions have no single ground height.
+#[derive(Resource)]
+pub struct ChunkPlaceholders {
+    mesh: Handle<Mesh>,
+    materials: HashMap<BlockId, Handle<StandardMaterial>>,
+    // Height of the quad and the block it shows, by chunk column (x, z)
+    columns: HashMap<(i32, i32), (i32, BlockId)>,
+    entities: HashMap<(i32, i32, i32), Entity>,
+}
+
+#[derive(Component)]
+pub struct ChunkPlaceholder;
+
+pub fn setup_chunk_placeholders(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, world: Res<World>) {
+    let size = world.chunk_size as f32;
+    commands.insert_resource(ChunkPlaceholders {
+        mesh: meshes.add(Plane3d::default().mesh().size(size, size)),
+        materials: HashMap::new(),
+        columns: HashMap::new(),
+        entities: HashMap::new(),
+    });
+}
+
+pub fn update_chunk_placeholders(
+    mut commands: Commands,
+    mut placeholders: ResMut<ChunkPlaceholders>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    world: Res<World>,
+    generator: Res<WorldGenerator>,
+    dimension: Res<Dimension>,
+) {
+    if *dimension != Dimension::Overworld {
+        return;
+    }
+    let placeholders = &mut *placeholders;
+    let size = world.chunk_size as i32;
+    let sea_level = generator.config.sea_level;
+
+    // Chunks still waiting for their mesh: queued for loading, or loaded without an entity yet
+    let waiting = world.chunk_load_queue.iter()
+        .chain(world.chunks.keys().filter(|key| !world.chunk_entities.contains_key(key)));
+    let mut wanted = HashSet::new();
+    let mut waiting_columns = HashSet::new();
+    for &chunk_key in waiting {
+        waiting_columns.insert((chunk_key.0, chunk_key.2));
+        let (height, _) = *placeholders.columns.entry((chunk_key.0, chunk_key.2)).or_insert_with(|| {
+            let column = generator.column(chunk_key.0 * size + size / 2, chunk_key.2 * size + size / 2);
+            let ground = column.height as i32;
+            if ground < sea_level { (sea_level, WATER) } else { (ground, column.surface) }
+        });
+        // The quad lies on top of the highest solid voxel, in the chunk holding that voxel
+        if (height - 1).div_euclid(size) == chunk_key.1 {
+            wanted.insert(chunk_key);
+        }
+    }
+
+    placeholders.entities.retain(|chunk_key, entity| {
+        let keep = wanted.contains(chunk_key);
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+    for chunk_key in wanted {
+        if placeholders.entities.contains_key(&chunk_key) {
+            continue;
+        }
+        let (height, block) = placeholders.columns[&(chunk_key.0, chunk_key.2)];
+        let material = placeholders.materials.entry(block)
+            .or_insert_with(|| materials.add(StandardMaterial {
+                base_color: block_icon_color(block),
+                perceptual_roughness: 1.0,
+                ..default()
+            }))
+            .clone();
+        let center = Vec3::new(
+            (chunk_key.0 * size) as f32 + size as f32 * 0.5,
+            height as f32,
+            (chunk_key.2 * size) as f32 + size as f32 * 0.5,
+        );
+        let entity = commands.spawn((
+            PbrBundle {
+                mesh: placeholders.mesh.clone(),
+                material,
+                transform: Transform::from_translation(center),
+                ..default()
+            },
+            ChunkPlaceholder,
+            NotShadowCaster,
+        )).id();
+        placeholders.entities.insert(chunk_key, entity);
+    }
+
+    // Columns nothing waits on any more are sampled again if they come back
+    placeholders.columns.retain(|column, _| waiting_columns.contains(column));
+}
@@ -0,0 +1,200 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use crate::block;
+#[cfg(feature = "render")]
+use crate::camera_controller::CameraController;
+use crate::keybindings::{Action, Actions};
+use crate::notifications::NotificationEvent;
+#[cfg(feature = "render")]
+use crate::voxel_world::VoxelWorld;
+use crate::{FLY_REACH, VOXEL_REMOVAL_RANGE};
+
+pub const GRAVITY: f32 = 25.0; // voxels/s^2
+pub const TERMINAL_VELOCITY: f32 = 50.0;
+// Player box around the camera; the camera sits EYE_HEIGHT above the feet
+pub const PLAYER_HALF_WIDTH: f32 = 0.3;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+pub const EYE_HEIGHT: f32 = 1.6;
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameMode {
+    // Gravity pulls the player down onto the terrain
+    #[default]
+    Walking,
+    // No gravity; builders can hover and edit far away voxels
+    Flying,
+}
+
+impl GameMode {
+    pub fn next(self) -> Self {
+        match self {
+            GameMode::Walking => GameMode::Flying,
+            GameMode::Flying => GameMode::Walking,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Walking => "walking",
+            GameMode::Flying => "flying",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "walking" => Some(GameMode::Walking),
+            "flying" => Some(GameMode::Flying),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PlayerStats {
+    // How far away voxels can be picked, in voxels
+    pub reach: f32,
+    // Reach while flying or in noclip
+    pub fly_reach: f32,
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        Self {
+            reach: VOXEL_REMOVAL_RANGE,
+            fly_reach: FLY_REACH,
+        }
+    }
+}
+
+// Current interaction reach for ray picks, derived from game mode, noclip and player stats
+#[derive(SystemParam)]
+pub struct PlayerReach<'w> {
+    game_mode: Res<'w, GameMode>,
+    noclip: Res<'w, Noclip>,
+    stats: Res<'w, PlayerStats>,
+}
+
+impl<'w> PlayerReach<'w> {
+    pub fn get(&self) -> f32 {
+        if *self.game_mode == GameMode::Flying || self.noclip.enabled {
+            self.stats.fly_reach
+        } else {
+            self.stats.reach
+        }
+    }
+}
+
+// Debug cheat: when enabled the player passes through voxels and ignores gravity.
+// Chunk streaming follows the camera either way.
+#[derive(Resource, Default)]
+pub struct Noclip {
+    pub enabled: bool,
+}
+
+// Collision state for the camera. Movement applied by the camera controller since the last frame
+// is replayed axis by axis against the voxel grid.
+#[derive(Component, Default)]
+pub struct PlayerBody {
+    pub vertical_velocity: f32,
+    pub last_position: Option<Vec3>,
+}
+
+pub fn toggle_noclip(
+    actions: Actions,
+    mut noclip: ResMut<Noclip>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if actions.just_pressed(Action::Noclip) {
+        noclip.enabled = !noclip.enabled;
+        notifications.send(NotificationEvent::info(format!("Noclip {}", if noclip.enabled { "on" } else { "off" })));
+    }
+}
+
+pub fn cycle_game_mode(
+    actions: Actions,
+    mut game_mode: ResMut<GameMode>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if actions.just_pressed(Action::GameMode) {
+        *game_mode = game_mode.next();
+        notifications.send(NotificationEvent::info(format!("Game mode: {:?}", *game_mode)));
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn apply_player_physics(
+    time: Res<Time>,
+    noclip: Res<Noclip>,
+    game_mode: Res<GameMode>,
+    voxel_world: VoxelWorld,
+    mut player_query: Query<(&mut Transform, &mut PlayerBody, Option<&mut CameraController>)>,
+) {
+    for (mut transform, mut body, mut controller) in &mut player_query {
+        let target = transform.translation;
+        let start = body.last_position.unwrap_or(target);
+
+        // A player already stuck inside terrain moves freely until it is out
+        if noclip.enabled || collides(&voxel_world, start) {
+            body.vertical_velocity = 0.0;
+            body.last_position = Some(target);
+            continue;
+        }
+
+        if *game_mode == GameMode::Walking {
+            body.vertical_velocity = (body.vertical_velocity - GRAVITY * time.delta_seconds()).max(-TERMINAL_VELOCITY);
+        } else {
+            body.vertical_velocity = 0.0;
+        }
+        let target = target + Vec3::Y * body.vertical_velocity * time.delta_seconds();
+
+        let mut position = start;
+        for axis in 0..3 {
+            let mut candidate = position;
+            candidate[axis] = target[axis];
+            if !collides(&voxel_world, candidate) {
+                position = candidate;
+                continue;
+            }
+            if axis == 1 {
+                body.vertical_velocity = 0.0;
+            }
+            // Stop pushing into the wall instead of carrying speed through it
+            if let Some(controller) = controller.as_mut() {
+                controller.velocity[axis] = 0.0;
+            }
+        }
+
+        transform.translation = position;
+        body.last_position = Some(position);
+    }
+}
+
+// Whether the player box with the camera at `eye` overlaps the voxel at `pos`; placing a block
+// there would trap the player
+pub fn overlaps_player(eye: Vec3, pos: IVec3) -> bool {
+    let min = eye - Vec3::new(PLAYER_HALF_WIDTH, EYE_HEIGHT, PLAYER_HALF_WIDTH);
+    let max = eye + Vec3::new(PLAYER_HALF_WIDTH, PLAYER_HEIGHT - EYE_HEIGHT, PLAYER_HALF_WIDTH);
+    let voxel_min = pos.as_vec3();
+    (min.cmplt(voxel_min + Vec3::ONE) & max.cmpgt(voxel_min)).all()
+}
+
+// Whether the player box with the camera at `eye` overlaps a solid voxel. Unloaded chunks count
+// as solid so the player can't fall out of the world before terrain streams in.
+#[cfg(feature = "render")]
+fn collides(voxel_world: &VoxelWorld, eye: Vec3) -> bool {
+    let min = eye - Vec3::new(PLAYER_HALF_WIDTH, EYE_HEIGHT, PLAYER_HALF_WIDTH);
+    let max = eye + Vec3::new(PLAYER_HALF_WIDTH, PLAYER_HEIGHT - EYE_HEIGHT, PLAYER_HALF_WIDTH);
+    let min_voxel = min.floor().as_ivec3();
+    let max_voxel = (max - Vec3::splat(f32::EPSILON)).floor().as_ivec3();
+
+    for x in min_voxel.x..=max_voxel.x {
+        for y in min_voxel.y..=max_voxel.y {
+            for z in min_voxel.z..=max_voxel.z {
+                if voxel_world.get_block(IVec3::new(x, y, z)).is_none_or(block::is_solid) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
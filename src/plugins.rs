@@ -0,0 +1,434 @@
+use bevy::app::PluginGroupBuilder;
+use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+#[cfg(feature = "physics")]
+use bevy_xpbd_3d::prelude::PhysicsPlugins;
+use std::path::Path;
+#[cfg(feature = "persistence")]
+use crate::autosave::Autosave;
+use crate::block_entity::BlockEntities;
+use crate::block_updates::BlockUpdates;
+use crate::blueprint::BlueprintPlacement;
+use crate::camera_controller::PauseMenu;
+use crate::capture::Recorder;
+use crate::chunk_pool::ChunkPool;
+use crate::console::{Console, ConsoleCommand};
+use crate::crosshair::ReachIndicator;
+use crate::day_night::{TimeOfDay, WorldClock};
+use crate::debug_overlay::DebugOverlay;
+use crate::dimension::Dimension;
+use crate::explosion::ExplosionEvent;
+use crate::history::EditHistory;
+use crate::hotbar::{Hotbar, SelectedBlock};
+use crate::inventory::{CreativeMode, Inventory};
+use crate::item_drop::{LoadedItemDrops, SpawnItemDrop};
+use crate::keybindings::KeyBindings;
+use crate::laser::LaserTool;
+use crate::mob::MobSpawner;
+use crate::notifications::NotificationEvent;
+use crate::outline::{OutlinedMaterial, ToonMode};
+use crate::particles::ParticleMaterial;
+use crate::player::{Noclip, PlayerStats};
+use crate::region_edit::RegionSelection;
+use crate::rendering::RenderDiagnostics;
+use crate::scripting::Scripts;
+use crate::seams::SeamDebug;
+use crate::selection::{SelectionMaterial, TargetedBlock};
+use crate::settings::{AudioSettings, MovementSettings};
+use crate::spawn::RespawnEvent;
+use crate::spectator::Spectator;
+use crate::symmetry::Symmetry;
+use crate::system_toggles::SystemToggles;
+use crate::terrain::ChunkDiagnostics;
+use crate::theme::Theme;
+use crate::tuning::{LightingTuning, TuningPanel};
+use crate::vox::{VoxImport, VoxLoader, VoxelModel};
+use crate::voxel_events::{VoxelBrokenEvent, VoxelPlacedEvent, VoxelSetEvent};
+use crate::water::{TerrainMaterial, WaterMaterial};
+use crate::precipitation::PrecipitationMaterial;
+use crate::weather::Weather;
+use crate::world::{ChunkUpdateBudget, World};
+use crate::worlds::WorldMeta;
+use crate::*;
+
+// The engine as Bevy plugins, added after DefaultPlugins:
+//
+//     App::new()
+//         .add_plugins(DefaultPlugins)
+//         .add_plugins(VoxelEnginePlugins { world: worlds::open("world")?, dimension: Dimension::Overworld })
+//         .run();
+//
+// WorldPlugin and RenderingPlugin are the engine proper. InteractionPlugin (the first-person
+// player, editing tools and HUD) and PersistencePlugin (autosave and the saved player state) can
+// be disabled through the group's builder and replaced with a game's own; PersistencePlugin is
+// only in the group with the `persistence` feature. Blocks registered by script mods have to be
+// in place before the world is opened (see scripting::load_scripts); the loaded Scripts go in as
+// a resource, otherwise the world runs without scripts.
+pub struct VoxelEnginePlugins {
+    pub world: WorldMeta,
+    pub dimension: Dimension,
+}
+
+impl PluginGroup for VoxelEnginePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let group = PluginGroupBuilder::start::<Self>()
+            .add(WorldPlugin { world: self.world, dimension: self.dimension })
+            .add(RenderingPlugin)
+            .add(InteractionPlugin);
+        #[cfg(feature = "persistence")]
+        let group = group.add(PersistencePlugin);
+        group
+    }
+}
+
+// Chunk streaming, worldgen, voxel edits and everything that simulates the world around the
+// player: block updates, weather, day and night, item drops, mobs, explosions and scripts
+pub struct WorldPlugin {
+    pub world: WorldMeta,
+    pub dimension: Dimension,
+}
+
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "physics")]
+        app.add_plugins(PhysicsPlugins::default());
+        app
+            .insert_resource(World::for_dimension(CHUNK_SIZE, DEFAULT_RENDER_DISTANCE, self.dimension, &self.world))
+            .insert_resource(self.dimension.generator(self.world.seed))
+            .insert_resource(self.dimension)
+            .insert_resource(self.world.game_mode)
+            .insert_resource(self.world.clone())
+            .init_resource::<Scripts>()
+            .add_event::<VoxelSetEvent>()
+            .add_event::<VoxelBrokenEvent>()
+            .add_event::<VoxelPlacedEvent>()
+            .add_event::<ExplosionEvent>()
+            .add_event::<SpawnItemDrop>()
+            .add_event::<ConsoleCommand>()
+            .add_event::<NotificationEvent>()
+            .init_resource::<Console>()
+            .init_resource::<BlockUpdates>()
+            .init_resource::<Noclip>()
+            .init_resource::<PlayerStats>()
+            .init_resource::<ChunkUpdateBudget>()
+            .init_resource::<ChunkPool>()
+            .init_resource::<TimeOfDay>()
+            .init_resource::<WorldClock>()
+            .init_resource::<BlockEntities>()
+            .init_resource::<Weather>()
+            .init_resource::<Inventory>()
+            .init_resource::<CreativeMode>()
+            .init_resource::<SystemToggles>()
+            .init_resource::<LoadedItemDrops>()
+            .init_resource::<MobSpawner>()
+            .add_systems(Startup, (
+                determinism::start_generation_log,
+                game_rules::load_game_rules,
+                item_drop::setup_item_drops,
+                mob::setup_mobs,
+                precipitation::setup_precipitation,
+            ))
+            .add_systems(Update, (
+                update_chunks.run_if(system_toggles::streaming_enabled),
+                prioritize_chunks.after(update_chunks).run_if(system_toggles::streaming_enabled),
+                process_chunk_queue.after(prioritize_chunks).run_if(system_toggles::streaming_enabled),
+                determinism::run_determinism_audit.after(process_chunk_queue),
+                attach_chunk_scoped_entities,
+                world::forward_world_notifications,
+                (
+                    system_toggles::system_toggle_command,
+                    structures::locate_command,
+                    world::render_distance_command,
+                    game_rules::gamerule_command,
+                ),
+                (
+                    weather::weather_command,
+                    weather::advance_weather.after(weather::weather_command).run_if(game_rules::weather_cycle_enabled),
+                    (
+                        weather::update_precipitation.after(weather::advance_weather).after(player::apply_player_physics),
+                        weather::update_wetness.after(weather::update_precipitation),
+                        weather::apply_wetness_to_materials.after(weather::update_wetness),
+                        weather::update_surface_snow.after(weather::update_precipitation),
+                        precipitation::update_precipitation_drops.after(weather::update_precipitation),
+                    ).run_if(system_toggles::weather_enabled),
+                ),
+                day_night::advance_time_of_day.run_if(game_rules::day_night_cycle_enabled),
+                explosion::handle_explosions.after(explosion::explode_command),
+                block_entity::store_block_entity_data,
+                block_entity::sync_block_entities
+                    .after(block_entity::store_block_entity_data)
+                    .after(voxel_events::apply_voxel_events)
+                    .after(process_chunk_queue),
+                (
+                    item_drop::spawn_item_drops.after(voxel_removal_system).after(explosion::handle_explosions),
+                    item_drop::sync_item_drops.after(process_chunk_queue),
+                    item_drop::update_item_drops
+                        .after(item_drop::spawn_item_drops)
+                        .after(item_drop::sync_item_drops)
+                        .after(player::apply_player_physics),
+                    item_drop::merge_item_drops.after(item_drop::update_item_drops),
+                    item_drop::store_item_drops.after(item_drop::merge_item_drops),
+                    item_drop::attach_item_drop_models.after(item_drop::spawn_item_drops).after(item_drop::sync_item_drops),
+                    item_drop::animate_item_drops.after(item_drop::update_item_drops),
+                ).run_if(system_toggles::item_drops_enabled),
+                (
+                    mob::spawn_mobs.run_if(game_rules::mob_spawning_enabled),
+                    mob::attach_mob_models.after(mob::spawn_mobs),
+                    mob::update_mob_paths.after(mob::spawn_mobs).after(player::apply_player_physics),
+                    mob::move_mobs.after(mob::update_mob_paths).after(explosion::handle_explosions),
+                    mob::despawn_mobs.after(mob::move_mobs).after(process_chunk_queue),
+                ),
+            ))
+            .add_systems(FixedUpdate, (block_updates::run_scheduled_ticks, scripting::run_tick_hooks, block_updates::process_block_updates)
+                .chain()
+                .run_if(system_toggles::block_updates_enabled))
+            .add_systems(Update, voxel_events::apply_voxel_events
+                .after(voxel_removal_system)
+                .after(voxel_placement_system)
+                .after(undo_redo_system)
+                .after(region_edit::apply_region_operations)
+                .after(laser::apply_laser_edits)
+                .after(blueprint::blueprint_input)
+                .after(weather::update_surface_snow)
+                .after(vox::finish_vox_import)
+                .after(explosion::handle_explosions))
+            .add_systems(Update, scripting::run_voxel_hooks.after(voxel_events::apply_voxel_events));
+    }
+}
+
+// Chunk meshes, materials, lighting, sky, particles, shadows, sound and the debug views, plus the
+// camera every one of them follows
+pub struct RenderingPlugin;
+
+impl Plugin for RenderingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugins(MaterialPlugin::<OutlinedMaterial>::default())
+            .add_plugins(MaterialPlugin::<TerrainMaterial>::default())
+            .add_plugins(MaterialPlugin::<WaterMaterial>::default())
+            .add_plugins(MaterialPlugin::<SelectionMaterial>::default())
+            .add_plugins(MaterialPlugin::<ParticleMaterial>::default())
+            .add_plugins(MaterialPlugin::<PrecipitationMaterial>::default())
+            .add_plugins(FrameTimeDiagnosticsPlugin)
+            .insert_resource(AudioSettings::load(Path::new(AUDIO_SETTINGS_FILE)))
+            .init_resource::<ToonMode>()
+            .init_resource::<Theme>()
+            .init_resource::<DebugOverlay>()
+            .init_resource::<TuningPanel>()
+            .init_resource::<LightingTuning>()
+            .init_resource::<SeamDebug>()
+            .init_resource::<Recorder>()
+            .init_resource::<ChunkDiagnostics>()
+            .init_resource::<RenderDiagnostics>()
+            .add_systems(Startup, (
+                setup,
+                debug_overlay::spawn_debug_overlay,
+                tuning::spawn_tuning_panel,
+                blob_shadow::setup_blob_shadows,
+                placeholders::setup_chunk_placeholders,
+                torch::setup_torches,
+                particles::setup_particles,
+                audio::load_sounds,
+            ))
+            .add_systems(Update, (
+                sync_light_with_camera.run_if(system_toggles::lighting_enabled),
+                handle_meshing_tasks.run_if(system_toggles::meshing_enabled),
+                (
+                    rendering::update_chunk_lod,
+                    placeholders::update_chunk_placeholders
+                        .after(process_chunk_queue)
+                        .run_if(system_toggles::meshing_enabled),
+                ).after(handle_meshing_tasks),
+                world::fade_out_chunks,
+                outline::toggle_toon_mode,
+                theme::cycle_theme,
+                theme::apply_theme_to_materials,
+                (
+                    particles::spawn_block_particles.after(voxel_events::apply_voxel_events),
+                    particles::update_particles.after(particles::spawn_block_particles),
+                ).run_if(system_toggles::particles_enabled),
+                (
+                    seasons::season_command,
+                    seasons::apply_season_tint
+                        .after(seasons::season_command)
+                        .after(day_night::advance_time_of_day)
+                        .run_if(system_toggles::seasons_enabled),
+                ),
+                (day_night::apply_daylight, day_night::apply_skylight_to_materials)
+                    .after(day_night::advance_time_of_day)
+                    .run_if(system_toggles::lighting_enabled),
+                blob_shadow::update_blob_shadows
+                    .after(player::apply_player_physics)
+                    .run_if(system_toggles::shadows_enabled),
+                (
+                    lighting::toggle_smooth_lighting,
+                    lighting::toggle_depth_darkness,
+                ),
+                (
+                    block_textures::toggle_pixel_art,
+                    block_textures::cycle_anisotropy,
+                    block_textures::apply_texture_filtering
+                        .after(block_textures::toggle_pixel_art)
+                        .after(block_textures::cycle_anisotropy),
+                ),
+                (
+                    capture::record_command,
+                    capture::take_screenshot,
+                    capture::capture_gif_frames.after(capture::record_command),
+                ),
+                torch::attach_torch_meshes.after(block_entity::sync_block_entities),
+                debug_overlay::toggle_debug_overlay,
+                (
+                    terrain::update_chunk_diagnostics,
+                    rendering::update_render_diagnostics,
+                    debug_overlay::update_debug_overlay,
+                )
+                    .chain()
+                    .run_if(debug_overlay::debug_overlay_visible),
+                (
+                    tuning::toggle_tuning_panel,
+                    tuning::adjust_tuning.after(tuning::toggle_tuning_panel).run_if(tuning::tuning_panel_visible),
+                    tuning::update_tuning_panel.after(tuning::adjust_tuning),
+                    tuning::apply_lighting_tuning.after(tuning::adjust_tuning),
+                    sky::update_sky_and_fog.after(day_night::advance_time_of_day).after(tuning::adjust_tuning).after(weather::update_precipitation),
+                ),
+                (
+                    seams::toggle_seam_debug,
+                    seams::draw_chunk_seams.after(seams::toggle_seam_debug).run_if(seams::seam_debug_enabled),
+                ),
+                (
+                    audio::volume_command,
+                    audio::play_block_sounds.after(voxel_events::apply_voxel_events),
+                    audio::play_footsteps.after(player::apply_player_physics),
+                    audio::update_ambient.after(day_night::advance_time_of_day).after(audio::volume_command),
+                ),
+            ));
+    }
+}
+
+// The first-person player and everything they do to the world: movement and physics, picking,
+// breaking and placing, the editing tools, the hotbar, console and pause menu
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(EditHistory::new(MAX_UNDO_HISTORY))
+            .insert_resource(KeyBindings::load(Path::new(KEYBINDINGS_FILE)))
+            .insert_resource(MovementSettings::load(Path::new(MOVEMENT_SETTINGS_FILE)))
+            .add_event::<RespawnEvent>()
+            .init_resource::<TargetedBlock>()
+            .init_resource::<ReachIndicator>()
+            .init_resource::<RegionSelection>()
+            .init_resource::<Hotbar>()
+            .init_resource::<SelectedBlock>()
+            .init_resource::<LaserTool>()
+            .init_resource::<Symmetry>()
+            .init_resource::<BlueprintPlacement>()
+            .init_resource::<VoxImport>()
+            .init_resource::<PauseMenu>()
+            .init_resource::<Spectator>()
+            .init_asset::<VoxelModel>()
+            .init_asset_loader::<VoxLoader>()
+            .add_systems(Startup, (
+                hotbar::spawn_hotbar,
+                console::spawn_console,
+                notifications::spawn_toast_stack,
+                camera_controller::spawn_pause_menu,
+                selection::spawn_selection_highlight,
+                crosshair::spawn_crosshair,
+            ))
+            .add_systems(PreUpdate, (
+                camera_controller::toggle_pause_menu.before(console::console_input),
+                console::console_input,
+                console::suppress_game_input_while_typing.after(console::console_input),
+                camera_controller::suppress_game_input_while_paused.after(camera_controller::toggle_pause_menu),
+            ).after(InputSystem))
+            .add_systems(Update, (
+                voxel_removal_system.after(selection::update_targeted_block),
+                undo_redo_system,
+                (
+                    crosshair::toggle_reach_indicator,
+                    selection::update_targeted_block
+                        .after(crosshair::toggle_reach_indicator)
+                        .after(player::apply_player_physics),
+                    selection::update_selection_highlight.after(selection::update_targeted_block),
+                    crosshair::update_crosshair.after(selection::update_targeted_block),
+                ),
+                selection::apply_theme_to_selection,
+                (
+                    region_edit::select_region_corners.after(selection::update_targeted_block),
+                    region_edit::apply_region_operations,
+                    region_edit::draw_region_selection,
+                ),
+                (
+                    vox::vox_command,
+                    vox::finish_vox_import.after(vox::vox_command),
+                    explosion::explode_command.after(selection::update_targeted_block),
+                ),
+                (export::export_command, inventory::creative_command, keybindings::bind_command),
+                hotbar::select_hotbar_slot,
+                hotbar::update_hotbar_ui.after(hotbar::select_hotbar_slot),
+                voxel_placement_system.after(hotbar::select_hotbar_slot).after(selection::update_targeted_block),
+                (
+                    camera_controller::apply_cursor_grab,
+                    camera_controller::camera_look,
+                    camera_controller::change_fly_speed,
+                    camera_controller::camera_move.after(camera_controller::change_fly_speed),
+                    camera_controller::movement_command,
+                    spectator::spectator_command.before(camera_controller::camera_move),
+                ),
+                (
+                    player::toggle_noclip,
+                    player::cycle_game_mode,
+                    player::apply_player_physics
+                        .after(player::toggle_noclip)
+                        .after(player::cycle_game_mode)
+                        .after(camera_controller::camera_move),
+                    spawn::spawn_command,
+                    spawn::respawn_player.after(spawn::spawn_command).after(player::apply_player_physics),
+                ),
+                (
+                    laser::toggle_laser_tool.after(player::cycle_game_mode),
+                    laser::fire_laser.after(laser::toggle_laser_tool),
+                    laser::apply_laser_edits.after(laser::fire_laser),
+                    laser::draw_laser_beam,
+                ),
+                (
+                    symmetry::configure_symmetry.after(selection::update_targeted_block),
+                    symmetry::draw_symmetry_guides,
+                ),
+                (
+                    blueprint::update_blueprint_ghost.after(selection::update_targeted_block),
+                    blueprint::blueprint_input.after(blueprint::update_blueprint_ghost),
+                ),
+                (
+                    console::update_console_text,
+                    notifications::show_notifications.after(world::forward_world_notifications),
+                    notifications::update_toasts,
+                ),
+            ));
+    }
+}
+
+// Autosaving the world and the player (position, inventory, time of day) and restoring the player
+// when the world opens
+#[cfg(feature = "persistence")]
+pub struct PersistencePlugin;
+
+#[cfg(feature = "persistence")]
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Autosave>()
+            .add_systems(Startup, autosave::load_player_state.after(setup))
+            .add_systems(Update, (
+                autosave::autosave,
+                autosave::autosave_command,
+                worlds::record_game_mode,
+            ))
+            // AppExit from closing the window is only sent in PostUpdate
+            .add_systems(Last, autosave::save_on_exit);
+    }
+}
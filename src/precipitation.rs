@@ -0,0 +1,192 @@
+// For the ShaderType derive, see outline.rs
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use bevy::pbr::NotShadowCaster;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::render::view::NoFrustumCulling;
+use crate::block::AIR;
+use crate::decoration::ChunkRng;
+use crate::player::PlayerBody;
+use crate::voxel_world::VoxelWorld;
+use crate::weather::{Precipitation, Weather, WEATHER_SEED};
+use crate::MAX_PRECIPITATION_DROPS;
+
+// Rain and snow drops falling around the player while Weather says it precipitates. Drawing
+// only; Weather, the overcast sky and settling snow are all in weather.rs.
+
+// Drops fall in a box this many voxels either side of the player, from this high above their
+// eyes down to the ground or DROP_DEPTH below them
+const DROP_RADIUS: f32 = 16.0;
+const DROP_HEIGHT: f32 = 16.0;
+const DROP_DEPTH: f32 = 8.0;
+const RAIN_SPEED: f32 = 14.0; // voxels/s
+const SNOW_SPEED: f32 = 1.5;
+// Snowflakes drift sideways up to this fast
+const SNOW_SWAY: f32 = 0.4;
+// Width and height of the drop quads
+const RAIN_SIZE: Vec2 = Vec2::new(0.02, 0.45);
+const SNOW_SIZE: Vec2 = Vec2::new(0.08, 0.08);
+
+// Upright camera-facing quad drawn by precipitation.wgsl. All drops share the quad mesh and
+// one of two materials, so they render as a single instanced draw each for rain and snow.
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+pub struct PrecipitationMaterial {
+    #[uniform(0)]
+    pub settings: PrecipitationSettings,
+}
+
+#[derive(ShaderType, Debug, Clone)]
+pub struct PrecipitationSettings {
+    pub color: LinearRgba,
+}
+
+impl Material for PrecipitationMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/precipitation.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/precipitation.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+// One raindrop or snowflake of the fixed pool spawned at startup. The first
+// intensity * MAX_PRECIPITATION_DROPS of the pool fall, the rest stay hidden.
+#[derive(Component, Default)]
+pub struct PrecipitationDrop {
+    falling: bool,
+    // Height of the ground under the drop, where it is recycled to the top of the box
+    floor: f32,
+}
+
+#[derive(Resource)]
+pub struct PrecipitationAssets {
+    rain: Handle<PrecipitationMaterial>,
+    snow: Handle<PrecipitationMaterial>,
+    rng: ChunkRng,
+}
+
+pub fn setup_precipitation(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<PrecipitationMaterial>>,
+) {
+    let quad = meshes.add(Rectangle::new(1.0, 1.0));
+    let rain = materials.add(PrecipitationMaterial {
+        settings: PrecipitationSettings { color: LinearRgba::new(0.6, 0.7, 0.85, 0.45) },
+    });
+    let snow = materials.add(PrecipitationMaterial {
+        settings: PrecipitationSettings { color: LinearRgba::new(0.95, 0.97, 1.0, 0.9) },
+    });
+
+    for _ in 0..MAX_PRECIPITATION_DROPS {
+        commands.spawn((
+            MaterialMeshBundle {
+                mesh: quad.clone(),
+                material: rain.clone(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            PrecipitationDrop::default(),
+            NotShadowCaster,
+            // The mesh bounds don't follow the billboard rotation done on the GPU
+            NoFrustumCulling,
+        ));
+    }
+    commands.insert_resource(PrecipitationAssets { rain, snow, rng: ChunkRng::for_chunk((0, 0, 0), WEATHER_SEED ^ 1) });
+}
+
+// Highest cell a drop starting at `top` lands on, scanning down to `bottom`. None if the column
+// runs into an unloaded chunk.
+fn landing_height(voxel_world: &VoxelWorld, x: i32, z: i32, top: i32, bottom: i32) -> Option<i32> {
+    for y in (bottom..=top).rev() {
+        if voxel_world.get_block(IVec3::new(x, y, z))? != AIR {
+            return Some(y + 1);
+        }
+    }
+    Some(bottom)
+}
+
+// Moves the falling drops down and recycles the ones that reached the ground or were left
+// behind by the player to a random spot of the box above them. Each drop looks up the ground
+// of its column once when recycled, so the cost is a column scan per landed drop rather than
+// a voxel lookup per drop per frame, and roofs keep the rain off whoever stands under them.
+pub fn update_precipitation_drops(
+    time: Res<Time>,
+    weather: Res<Weather>,
+    voxel_world: VoxelWorld,
+    mut assets: ResMut<PrecipitationAssets>,
+    player_query: Query<&Transform, (With<PlayerBody>, Without<PrecipitationDrop>)>,
+    mut drops: Query<(&mut Transform, &mut Visibility, &mut Handle<PrecipitationMaterial>, &mut PrecipitationDrop)>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let snowing = weather.precipitation == Precipitation::Snow;
+    let falling = match weather.precipitation {
+        Precipitation::Clear => 0,
+        _ => (weather.state.intensity() * MAX_PRECIPITATION_DROPS as f32) as usize,
+    };
+    let (speed, size, material) = if snowing {
+        (SNOW_SPEED, SNOW_SIZE, &assets.snow)
+    } else {
+        (RAIN_SPEED, RAIN_SIZE, &assets.rain)
+    };
+    let material = material.clone();
+    let dt = time.delta_seconds();
+    let eye = player.translation;
+    let elapsed = time.elapsed_seconds();
+
+    for (index, (mut transform, mut visibility, mut handle, mut drop)) in drops.iter_mut().enumerate() {
+        if index >= falling {
+            if drop.falling {
+                drop.falling = false;
+                *visibility = Visibility::Hidden;
+            }
+            continue;
+        }
+
+        let position = transform.translation;
+        let strayed = (position - eye).xz().abs().max_element() > DROP_RADIUS;
+        if drop.falling && position.y > drop.floor && !strayed {
+            let mut next = position - Vec3::Y * speed * dt;
+            if snowing {
+                let phase = elapsed + index as f32;
+                next += Vec3::new(phase.sin(), 0.0, (phase * 0.7).cos()) * SNOW_SWAY * dt;
+            }
+            transform.translation = next;
+            continue;
+        }
+
+        // Recycle: a random spot of the box, if it's open to the sky above the ground there
+        let rng = &mut assets.rng;
+        let x = eye.x + (rng.next_f32() * 2.0 - 1.0) * DROP_RADIUS;
+        let z = eye.z + (rng.next_f32() * 2.0 - 1.0) * DROP_RADIUS;
+        let y = eye.y - DROP_DEPTH + rng.next_f32() * (DROP_HEIGHT + DROP_DEPTH);
+        let top = (eye.y + DROP_HEIGHT).floor() as i32;
+        let bottom = (eye.y - DROP_DEPTH).floor() as i32;
+        let floor = landing_height(&voxel_world, x.floor() as i32, z.floor() as i32, top, bottom);
+        match floor {
+            Some(floor) if y > floor as f32 => {
+                drop.falling = true;
+                drop.floor = floor as f32;
+                transform.translation = Vec3::new(x, y, z);
+                transform.scale = size.extend(1.0);
+                if *handle != material {
+                    *handle = material.clone();
+                }
+                *visibility = Visibility::Visible;
+            }
+            // Under cover or over unloaded terrain; try another spot next frame
+            _ => {
+                drop.falling = false;
+                *visibility = Visibility::Hidden;
+            }
+        }
+    }
+}
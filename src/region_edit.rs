@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+use crate::selection::TargetedBlock;
+use crate::block::{BlockId, AIR, STONE};
+use crate::history::{EditHistory, VoxelEdit};
+use crate::keybindings::{Action, Actions};
+use crate::notifications::NotificationEvent;
+use crate::theme::Theme;
+use crate::voxel_world::VoxelWorld;
+use crate::MAX_REGION_VOLUME;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionOperation {
+    // Every cell becomes the brush block
+    Fill,
+    // Only cells holding `replace_target` become the brush block
+    Replace,
+    // Shell of the selection becomes the brush block, the inside is cleared
+    Hollow,
+    // Ellipsoid inscribed in the selection
+    Sphere,
+    // Vertical cylinder inscribed in the selection
+    Cylinder,
+}
+
+// Two-corner selection in world voxel coordinates, WorldEdit style
+#[derive(Resource)]
+pub struct RegionSelection {
+    pub corner_a: Option<IVec3>,
+    pub corner_b: Option<IVec3>,
+    pub brush_block: BlockId,
+    pub replace_target: BlockId,
+}
+
+impl Default for RegionSelection {
+    fn default() -> Self {
+        Self {
+            corner_a: None,
+            corner_b: None,
+            brush_block: STONE,
+            replace_target: AIR,
+        }
+    }
+}
+
+impl RegionSelection {
+    // Inclusive min/max corners once both are set
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        let (a, b) = (self.corner_a?, self.corner_b?);
+        Some((a.min(b), a.max(b)))
+    }
+}
+
+// Block the operation wants at `pos`, None to leave the cell untouched
+pub fn region_block(operation: RegionOperation, pos: IVec3, min: IVec3, max: IVec3, current: BlockId, brush: BlockId, replace_target: BlockId) -> Option<BlockId> {
+    // Work in voxel centers so odd and even sized selections stay symmetric
+    let center = (min.as_vec3() + max.as_vec3() + Vec3::ONE) * 0.5;
+    let radii = (max - min + IVec3::ONE).as_vec3() * 0.5;
+    let offset = (pos.as_vec3() + Vec3::splat(0.5) - center) / radii;
+
+    match operation {
+        RegionOperation::Fill => Some(brush),
+        RegionOperation::Replace => (current == replace_target).then_some(brush),
+        RegionOperation::Hollow => {
+            let on_shell = pos.cmpeq(min).any() || pos.cmpeq(max).any();
+            Some(if on_shell { brush } else { AIR })
+        }
+        RegionOperation::Sphere => (offset.length_squared() <= 1.0).then_some(brush),
+        RegionOperation::Cylinder => (offset.x * offset.x + offset.z * offset.z <= 1.0).then_some(brush),
+    }
+}
+
+pub fn select_region_corners(
+    actions: Actions,
+    target: Res<TargetedBlock>,
+    mut selection: ResMut<RegionSelection>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    let set_a = actions.just_pressed(Action::RegionCornerA);
+    let set_b = actions.just_pressed(Action::RegionCornerB);
+    if !set_a && !set_b {
+        return;
+    }
+
+    let Some(corner) = target.voxel else {
+        notifications.send(NotificationEvent::warning("No voxel in range to select"));
+        return;
+    };
+
+    if set_a {
+        selection.corner_a = Some(corner);
+        notifications.send(NotificationEvent::info(format!("Region corner A set to {}", corner)));
+    } else {
+        selection.corner_b = Some(corner);
+        notifications.send(NotificationEvent::info(format!("Region corner B set to {}", corner)));
+    }
+}
+
+pub fn apply_region_operations(
+    actions: Actions,
+    mut voxel_world: VoxelWorld,
+    selection: Res<RegionSelection>,
+    mut history: ResMut<EditHistory>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    let operation = if actions.just_pressed(Action::RegionFill) {
+        RegionOperation::Fill
+    } else if actions.just_pressed(Action::RegionReplace) {
+        RegionOperation::Replace
+    } else if actions.just_pressed(Action::RegionHollow) {
+        RegionOperation::Hollow
+    } else if actions.just_pressed(Action::RegionSphere) {
+        RegionOperation::Sphere
+    } else if actions.just_pressed(Action::RegionCylinder) {
+        RegionOperation::Cylinder
+    } else {
+        return;
+    };
+
+    let Some((min, max)) = selection.bounds() else {
+        notifications.send(NotificationEvent::warning(format!("Select both region corners before running {:?}", operation)));
+        return;
+    };
+
+    let size = (max - min + IVec3::ONE).as_i64vec3();
+    let volume = size.x * size.y * size.z;
+    if volume > MAX_REGION_VOLUME {
+        notifications.send(NotificationEvent::warning(format!("Selection of {} voxels exceeds the limit of {}", volume, MAX_REGION_VOLUME)));
+        return;
+    }
+
+    // Everything goes out as one event batch, so apply_voxel_events remeshes each chunk once
+    // and the whole operation is a single undo step
+    let mut edits = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let pos = IVec3::new(x, y, z);
+                let Some(current) = voxel_world.get_block(pos) else {
+                    continue;
+                };
+
+                if let Some(block) = region_block(operation, pos, min, max, current, selection.brush_block, selection.replace_target) {
+                    if block != current {
+                        voxel_world.set_block(pos, block);
+                        let (chunk_key, voxel_pos) = voxel_world.to_chunk_local(pos);
+                        edits.push(VoxelEdit { chunk_key, voxel_pos, old: current, new: block });
+                    }
+                }
+            }
+        }
+    }
+
+    notifications.send(NotificationEvent::info(format!("{:?} changed {} voxel(s)", operation, edits.len())));
+    history.record(edits);
+}
+
+pub fn draw_region_selection(
+    selection: Res<RegionSelection>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    if let Some((min, max)) = selection.bounds() {
+        let size = (max - min + IVec3::ONE).as_vec3();
+        gizmos.cuboid(
+            Transform::from_translation(min.as_vec3() + size * 0.5).with_scale(size + Vec3::splat(0.02)),
+            theme.debug_secondary,
+        );
+    } else {
+        for corner in [selection.corner_a, selection.corner_b].into_iter().flatten() {
+            gizmos.cuboid(
+                Transform::from_translation(corner.as_vec3() + Vec3::splat(0.5)).with_scale(Vec3::splat(1.02)),
+                theme.debug_secondary,
+            );
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use crate::terrain::Chunk;
+use crate::world::World;
+use crate::{LOD_DISTANCE, LOD_HYSTERESIS};
+
+// Geometry currently on the GPU for chunk meshes
+#[derive(Resource, Default)]
+pub struct RenderDiagnostics {
+    pub chunk_meshes: usize,
+    // Of those, chunks drawn with their coarse mesh
+    pub coarse_chunk_meshes: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+}
+
+pub fn update_render_diagnostics(
+    mut diagnostics: ResMut<RenderDiagnostics>,
+    meshes: Res<Assets<Mesh>>,
+    chunk_query: Query<(&Handle<Mesh>, Option<&ChunkLod>), With<Chunk>>,
+) {
+    let mut stats = RenderDiagnostics::default();
+
+    for (mesh_handle, lod) in &chunk_query {
+        let Some(mesh) = meshes.get(mesh_handle) else {
+            continue;
+        };
+        stats.chunk_meshes += 1;
+        if lod.is_some_and(|lod| lod.coarse_active) {
+            stats.coarse_chunk_meshes += 1;
+        }
+        if let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            stats.vertices += positions.len();
+        }
+        if let Some(indices) = mesh.indices() {
+            stats.triangles += indices.len() / 3;
+        }
+    }
+
+    *diagnostics = stats;
+}
+
+// Swaps new geometry into a chunk's existing mesh asset instead of adding a fresh asset per
+// remesh. The asset id, and with it the chunk's GPU-side mesh slot, stays the same across edits,
+// so render assets are updated in place rather than allocated and torn down on every edit.
+pub fn replace_chunk_mesh(meshes: &mut Assets<Mesh>, handle: &mut Handle<Mesh>, mesh: Mesh) {
+    match meshes.get_mut(&*handle) {
+        Some(existing) => *existing = mesh,
+        None => *handle = meshes.add(mesh),
+    }
+}
+
+// Both meshes of a chunk. The chunk's own Handle<Mesh> points at one of them; the collider
+// always comes from the full one.
+#[derive(Component)]
+pub struct ChunkLod {
+    pub full: Handle<Mesh>,
+    pub coarse: Handle<Mesh>,
+    pub coarse_active: bool,
+}
+
+impl ChunkLod {
+    pub fn active(&self) -> &Handle<Mesh> {
+        if self.coarse_active { &self.coarse } else { &self.full }
+    }
+}
+
+// Draws chunks far from the camera with their coarse mesh. The distance is to the chunk's
+// bounding box rather than its corner, so big chunks right next to the camera never go coarse,
+// and switching back needs LOD_HYSTERESIS closer so chunks on the line don't flicker.
+pub fn update_chunk_lod(
+    world: Res<World>,
+    camera_query: Query<&Transform, With<Camera>>,
+    mut chunk_query: Query<(&Transform, &mut ChunkLod, &mut Handle<Mesh>), With<Chunk>>,
+) {
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+    let size = Vec3::splat(world.chunk_size as f32);
+    for (transform, mut lod, mut mesh_handle) in &mut chunk_query {
+        let min = transform.translation;
+        let distance = camera.translation.distance(camera.translation.clamp(min, min + size));
+        let coarse = if lod.coarse_active {
+            distance > LOD_DISTANCE - LOD_HYSTERESIS
+        } else {
+            distance > LOD_DISTANCE
+        };
+        if coarse != lod.coarse_active {
+            lod.coarse_active = coarse;
+            *mesh_handle = lod.active().clone();
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use bevy::log::info_span;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::block_ids::BlockIdMap;
+use crate::chunk_format::{self, ChunkHeader, FormatError};
+use crate::terrain::Chunk;
+use crate::world::{ChunkPendingBlock, PendingBlock};
+
+// Chunks are grouped into region files of REGION_SIZE^3 chunks, named by region coordinate
+pub const REGION_SIZE: i32 = 8;
+pub const REGION_EXTENSION: &str = "vxr";
+// Structure overflow waiting for a region's chunks, see chunk_format's pending structure file
+pub const PENDING_EXTENSION: &str = "vxp";
+
+pub fn region_key(chunk_key: (i32, i32, i32)) -> (i32, i32, i32) {
+    (
+        chunk_key.0.div_euclid(REGION_SIZE),
+        chunk_key.1.div_euclid(REGION_SIZE),
+        chunk_key.2.div_euclid(REGION_SIZE),
+    )
+}
+
+pub fn region_path(save_dir: &Path, region_key: (i32, i32, i32)) -> PathBuf {
+    save_dir.join(format!("r.{}.{}.{}.{}", region_key.0, region_key.1, region_key.2, REGION_EXTENSION))
+}
+
+// The encoded chunks of a region by chunk key
+pub type RegionBlobs = BTreeMap<(i32, i32, i32), Vec<u8>>;
+
+// All chunk blobs stored in a region file, empty if the region hasn't been written yet
+pub fn read_region_blobs(path: &Path) -> Result<RegionBlobs, FormatError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut blobs = BTreeMap::new();
+    for entry in chunk_format::read_region(&bytes)? {
+        let blob = entry.blob(&bytes).ok_or(FormatError::Truncated)?;
+        blobs.insert(entry.chunk_key, blob.to_vec());
+    }
+    Ok(blobs)
+}
+
+pub fn write_region_blobs(path: &Path, blobs: &RegionBlobs) -> Result<(), FormatError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let entries: Vec<_> = blobs.iter().map(|(key, blob)| (*key, blob.clone())).collect();
+
+    // Write next to the target and rename so a crash never leaves a half-written region
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, chunk_format::encode_region(&entries))?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// An encoded chunk ready to be written, see chunk_format::encode_chunk
+pub type ChunkBlob = ((i32, i32, i32), Vec<u8>);
+// The chunks of a region that could not be written, and why
+pub type FailedRegion = (Vec<(i32, i32, i32)>, FormatError);
+
+// Held across every read-modify-write of a region, so a background autosave and a chunk saved
+// on unload can't both rewrite the same region from the same old contents
+static REGION_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+// Writes already encoded chunks, each region once. Returns the chunks of every region that
+// failed to write, with the error.
+pub fn save_chunk_blobs(save_dir: &Path, blobs: Vec<ChunkBlob>) -> Vec<FailedRegion> {
+    let mut regions: BTreeMap<(i32, i32, i32), Vec<ChunkBlob>> = BTreeMap::new();
+    for (chunk_key, blob) in blobs {
+        regions.entry(region_key(chunk_key)).or_default().push((chunk_key, blob));
+    }
+
+    let _lock = REGION_WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut failed = Vec::new();
+    for (region, chunks) in regions {
+        let _span = info_span!("region_write", ?region, chunks = chunks.len()).entered();
+        let path = region_path(save_dir, region);
+        let chunk_keys = chunks.iter().map(|(chunk_key, _)| *chunk_key).collect();
+        let result = read_region_blobs(&path).and_then(|mut region_blobs| {
+            region_blobs.extend(chunks);
+            write_region_blobs(&path, &region_blobs)
+        });
+        if let Err(err) = result {
+            failed.push((chunk_keys, err));
+        }
+    }
+    failed
+}
+
+// The saved copy of a chunk and the header it was stored with, None if it was never modified and saved
+pub fn load_chunk(save_dir: &Path, chunk_key: (i32, i32, i32), ids: &BlockIdMap) -> Result<Option<(ChunkHeader, Chunk)>, FormatError> {
+    let blobs = read_region_blobs(&region_path(save_dir, region_key(chunk_key)))?;
+    let Some(blob) = blobs.get(&chunk_key) else {
+        return Ok(None);
+    };
+    chunk_format::decode_chunk_with_ids(blob, ids).map(Some)
+}
+
+pub fn pending_path(save_dir: &Path, region_key: (i32, i32, i32)) -> PathBuf {
+    save_dir.join(format!("r.{}.{}.{}.{}", region_key.0, region_key.1, region_key.2, PENDING_EXTENSION))
+}
+
+fn read_pending(path: &Path, ids: &BlockIdMap) -> Result<Vec<ChunkPendingBlock>, FormatError> {
+    match fs::read(path) {
+        Ok(bytes) => chunk_format::decode_pending(&bytes, ids),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Writes the blocks back, or removes the file once nothing is left waiting in the region
+fn write_pending(path: &Path, blocks: &[ChunkPendingBlock], ids: &BlockIdMap) -> Result<(), FormatError> {
+    if blocks.is_empty() {
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension(format!("{}.tmp", PENDING_EXTENSION));
+    fs::write(&tmp_path, chunk_format::encode_pending(blocks, ids))?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Appends structure overflow for chunks that aren't loaded to their regions' pending files,
+// after whatever was already waiting there
+pub fn save_pending_blocks(save_dir: &Path, blocks: Vec<((i32, i32, i32), Vec<PendingBlock>)>, ids: &BlockIdMap) -> Result<(), FormatError> {
+    let mut regions: BTreeMap<(i32, i32, i32), Vec<ChunkPendingBlock>> = BTreeMap::new();
+    for (chunk_key, pending) in blocks {
+        regions.entry(region_key(chunk_key)).or_default().extend(pending.into_iter().map(|block| (chunk_key, block)));
+    }
+
+    let _lock = REGION_WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (region, new_blocks) in regions {
+        let path = pending_path(save_dir, region);
+        let mut region_blocks = read_pending(&path, ids)?;
+        region_blocks.extend(new_blocks);
+        write_pending(&path, &region_blocks, ids)?;
+    }
+    Ok(())
+}
+
+// Removes the overflow saved for `chunk_key` from its region's pending file and returns it,
+// oldest first
+pub fn take_pending_blocks(save_dir: &Path, chunk_key: (i32, i32, i32), ids: &BlockIdMap) -> Result<Vec<PendingBlock>, FormatError> {
+    let path = pending_path(save_dir, region_key(chunk_key));
+    // Most chunks have nothing waiting; only take the lock, which an autosave may hold for a
+    // while, when there is something to remove
+    if !read_pending(&path, ids)?.iter().any(|(key, _)| *key == chunk_key) {
+        return Ok(Vec::new());
+    }
+    let _lock = REGION_WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let (taken, rest): (Vec<_>, Vec<_>) = read_pending(&path, ids)?.into_iter().partition(|(key, _)| *key == chunk_key);
+    write_pending(&path, &rest, ids)?;
+    Ok(taken.into_iter().map(|(_, block)| block).collect())
+}
+
+#[derive(Default, Debug)]
+pub struct CompactionReport {
+    pub regions: usize,
+    pub regions_removed: usize,
+    pub chunks_kept: usize,
+    pub chunks_dropped: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+// Rewrites every region in a save directory: entries that belong to another region or no longer
+// decode are dropped, the rest are re-encoded with the current codec, and empty regions are
+// deleted along with leftover temp files from interrupted writes. `ids` is the world's block ID
+// table, which both reading and rewriting the chunks go through.
+pub fn compact(save_dir: &Path, ids: &BlockIdMap) -> Result<CompactionReport, FormatError> {
+    let mut report = CompactionReport::default();
+
+    for dir_entry in fs::read_dir(save_dir)? {
+        let path = dir_entry?.path();
+        let extension = path.extension().and_then(|ext| ext.to_str());
+
+        if extension == Some("tmp") {
+            report.bytes_before += fs::metadata(&path)?.len();
+            fs::remove_file(&path)?;
+            continue;
+        }
+        if extension != Some(REGION_EXTENSION) {
+            continue;
+        }
+        let Some(expected_region) = parse_region_name(&path) else {
+            continue;
+        };
+
+        report.regions += 1;
+        report.bytes_before += fs::metadata(&path)?.len();
+
+        let bytes = fs::read(&path)?;
+        let entries = match chunk_format::read_region(&bytes) {
+            Ok(entries) => entries,
+            Err(err) => {
+                println!("{}: unreadable region left in place: {}", path.display(), err);
+                report.bytes_after += bytes.len() as u64;
+                continue;
+            }
+        };
+
+        let mut blobs = BTreeMap::new();
+        for entry in entries {
+            let decoded = entry.blob(&bytes).ok_or(FormatError::Truncated).and_then(|blob| chunk_format::decode_chunk_with_ids(blob, ids));
+            match decoded {
+                Ok((header, chunk)) if header.chunk_key == entry.chunk_key && region_key(entry.chunk_key) == expected_region => {
+                    // A later entry for the same chunk supersedes an earlier one
+                    if blobs.insert(entry.chunk_key, chunk_format::encode_chunk_with_ids(entry.chunk_key, &chunk, ids)).is_some() {
+                        report.chunks_dropped += 1;
+                    }
+                }
+                _ => report.chunks_dropped += 1,
+            }
+        }
+        report.chunks_kept += blobs.len();
+
+        if blobs.is_empty() {
+            fs::remove_file(&path)?;
+            report.regions_removed += 1;
+        } else {
+            write_region_blobs(&path, &blobs)?;
+            report.bytes_after += fs::metadata(&path)?.len();
+        }
+    }
+
+    Ok(report)
+}
+
+pub fn parse_region_name(path: &Path) -> Option<(i32, i32, i32)> {
+    let stem = path.file_stem()?.to_str()?;
+    let mut parts = stem.strip_prefix("r.")?.split('.');
+    let key = (parts.next()?.parse().ok()?, parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+    parts.next().is_none().then_some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockId, CHEST, DIRT, STONE};
+    use crate::block_entity::BlockEntityData;
+    use crate::block_ids;
+    use crate::CHUNK_SIZE;
+
+    fn chunk_with(block: BlockId, column: usize) -> Chunk {
+        let mut chunk = Chunk::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+        for y in 0..CHUNK_SIZE {
+            chunk.set_block(column, y, column, block);
+        }
+        chunk
+    }
+
+    #[test]
+    fn compact_drops_stale_entries_and_keeps_the_chunks() {
+        let save_dir = std::env::temp_dir().join(format!("voxelfun-compact-{}", std::process::id()));
+        fs::create_dir_all(&save_dir).unwrap();
+
+        let kept = chunk_with(STONE, 3);
+        let superseded = chunk_with(DIRT, 5);
+        let neighbour = chunk_with(DIRT, 0);
+        // A chunk saved twice, one that moved to another region and a blob that no longer decodes
+        let region = chunk_format::encode_region(&[
+            ((0, 0, 0), chunk_format::encode_chunk((0, 0, 0), &superseded)),
+            ((1, 0, 0), chunk_format::encode_chunk((1, 0, 0), &neighbour)),
+            ((0, 0, 0), chunk_format::encode_chunk((0, 0, 0), &kept)),
+            ((9, 0, 0), chunk_format::encode_chunk((9, 0, 0), &kept)),
+            ((2, 0, 0), vec![0xff; 12]),
+        ]);
+        fs::write(region_path(&save_dir, (0, 0, 0)), region).unwrap();
+        // A region left with nothing valid in it, and a temp file from an interrupted write
+        fs::write(region_path(&save_dir, (1, 0, 0)), chunk_format::encode_region(&[((3, 0, 0), chunk_format::encode_chunk((3, 0, 0), &kept))])).unwrap();
+        fs::write(region_path(&save_dir, (2, 0, 0)).with_extension("tmp"), b"partial").unwrap();
+
+        let report = compact(&save_dir, &BlockIdMap::default()).unwrap();
+        assert_eq!(report.regions, 2);
+        assert_eq!(report.regions_removed, 1);
+        assert_eq!(report.chunks_kept, 2);
+        assert_eq!(report.chunks_dropped, 4);
+        assert!(!region_path(&save_dir, (1, 0, 0)).exists());
+        assert!(!region_path(&save_dir, (2, 0, 0)).with_extension("tmp").exists());
+
+        let ids = BlockIdMap::default();
+        let (_, read_back) = load_chunk(&save_dir, (0, 0, 0), &ids).unwrap().expect("kept chunk is still saved");
+        assert!(read_back.voxels.iter().eq(kept.voxels.iter()));
+        let (_, read_back) = load_chunk(&save_dir, (1, 0, 0), &ids).unwrap().expect("neighbour is still saved");
+        assert!(read_back.voxels.iter().eq(neighbour.voxels.iter()));
+        assert!(load_chunk(&save_dir, (2, 0, 0), &ids).unwrap().is_none());
+        assert!(read_region_blobs(&region_path(&save_dir, (0, 0, 0))).unwrap().keys().eq([(0, 0, 0), (1, 0, 0)].iter()));
+
+        fs::remove_dir_all(&save_dir).unwrap();
+    }
+
+    #[test]
+    fn compact_keeps_the_blocks_of_a_remapped_world() {
+        let save_dir = std::env::temp_dir().join(format!("voxelfun-compact-remapped-{}", std::process::id()));
+        fs::create_dir_all(&save_dir).unwrap();
+
+        // A world that saved the registry in reverse, so no block keeps its ID
+        let mut names = block_ids::registered_names();
+        names.reverse();
+        let ids = BlockIdMap::new(&names);
+        assert!(!ids.is_identity());
+
+        let mut chunk = chunk_with(STONE, 3);
+        chunk.set_block(1, 2, 3, CHEST);
+        chunk.block_entities.insert((1, 2, 3), BlockEntityData::Chest { items: Vec::new() });
+        let region = chunk_format::encode_region(&[((0, 0, 0), chunk_format::encode_chunk_with_ids((0, 0, 0), &chunk, &ids))]);
+        fs::write(region_path(&save_dir, (0, 0, 0)), region).unwrap();
+
+        let report = compact(&save_dir, &ids).unwrap();
+        assert_eq!((report.chunks_kept, report.chunks_dropped), (1, 0));
+
+        let (_, read_back) = load_chunk(&save_dir, (0, 0, 0), &ids).unwrap().expect("chunk is still saved");
+        assert!(read_back.voxels.iter().eq(chunk.voxels.iter()));
+        // Read back under the identity table the chest would be some other block, and lose its entity
+        assert!(read_back.block_entities.contains_key(&(1, 2, 3)));
+
+        fs::remove_dir_all(&save_dir).unwrap();
+    }
+}
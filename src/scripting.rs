@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+use bevy::prelude::*;
+use wasmi::{Caller, Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc, WasmParams};
+use crate::block::{self, BlockDefinition, BlockId, Transparency};
+use crate::block_updates::BlockUpdates;
+use crate::day_night::WorldClock;
+use crate::terrain::Chunk;
+use crate::voxel_events::{VoxelBrokenEvent, VoxelPlacedEvent, VoxelSetEvent};
+use crate::world::{self, World};
+use crate::{MAX_SCRIPT_WRITES_PER_CALL, SCRIPT_FUEL_PER_CALL, SCRIPT_MEMORY_LIMIT, SCRIPT_TABLE_LIMIT};
+
+// Gameplay mods as WebAssembly modules. Every `.wasm` file in a content pack's `scripts` folder
+// is loaded at startup, packs and files in name order. A script imports what it needs from the
+// "voxelfun" module:
+//   get_block(x: i32, y: i32, z: i32) -> i32         block at a voxel, -1 if it isn't loaded
+//   set_block(x: i32, y: i32, z: i32, block: i32)    written once the hook returns
+//   schedule_tick(x: i32, y: i32, z: i32, delay: i64) see BlockUpdates::schedule
+//   register_block(name_ptr: i32, name_len: i32, color: i32, transparency: i32) -> i32
+//       new block named by a UTF-8 string in the script's exported "memory", colored 0xRRGGBB,
+//       0 opaque / 1 cutout / 2 translucent. Returns its ID, or -1 outside `init` or if the name
+//       is taken.
+//   log(ptr: i32, len: i32)                          prints a UTF-8 string
+// and subscribes to events by exporting any of:
+//   init()                                            once after loading
+//   on_block_placed(x: i32, y: i32, z: i32, block: i32)
+//   on_block_broken(x: i32, y: i32, z: i32, block: i32)
+//   on_block_tick(x: i32, y: i32, z: i32, block: i32) a scheduled tick came up
+//   on_tick(tick: i64)                                every fixed tick, with the WorldClock tick
+// Every call gets SCRIPT_FUEL_PER_CALL fuel. A script that traps or runs out is disabled for the
+// rest of the session. Memory and tables are capped by SCRIPT_MEMORY_LIMIT and SCRIPT_TABLE_LIMIT,
+// and writes or ticks past MAX_SCRIPT_WRITES_PER_CALL in one call are ignored.
+
+type ChunkKey = (i32, i32, i32);
+type VoxelHook = TypedFunc<(i32, i32, i32, i32), ()>;
+// Block writes and (voxel, delay) ticks scripts asked for
+type ScriptWrites = (Vec<(IVec3, BlockId)>, Vec<(IVec3, u64)>);
+
+// What host functions see of the game while a hook runs
+#[derive(Default)]
+struct HostState {
+    script: String,
+    // The world's chunks, lent to the store for the length of a call (see Scripts::call_all)
+    chunks: HashMap<ChunkKey, Chunk>,
+    chunk_size: usize,
+    edits: Vec<(IVec3, BlockId)>,
+    ticks: Vec<(IVec3, u64)>,
+    // Writes and ticks queued by the running hook call, against MAX_SCRIPT_WRITES_PER_CALL
+    call_edits: usize,
+    call_ticks: usize,
+    // Only while `init` runs
+    registering: bool,
+    // Blocks registered so far by this and earlier scripts, numbered after the built-in ones
+    registered: Vec<BlockDefinition>,
+    limits: StoreLimits,
+}
+
+impl HostState {
+    fn locate(&self, pos: IVec3) -> (ChunkKey, (usize, usize, usize)) {
+        world::split_world_pos(pos, self.chunk_size)
+    }
+
+    fn get_block(&self, pos: IVec3) -> Option<BlockId> {
+        let (chunk_key, (x, y, z)) = self.locate(pos);
+        self.chunks.get(&chunk_key).map(|chunk| chunk.get_block(x, y, z))
+    }
+}
+
+// `len` bytes at `ptr` in the calling script's memory as a string
+fn read_string(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller.get_export("memory")?.into_memory()?;
+    let start = ptr as u32 as usize;
+    let bytes = memory.data(caller).get(start..start.checked_add(len as u32 as usize)?)?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+fn transparency(value: i32) -> Option<Transparency> {
+    match value {
+        0 => Some(Transparency::Opaque),
+        1 => Some(Transparency::Cutout),
+        2 => Some(Transparency::Translucent),
+        _ => None,
+    }
+}
+
+fn register_block(caller: &mut Caller<'_, HostState>, name_ptr: i32, name_len: i32, color: i32, transparency_value: i32) -> i32 {
+    let name = read_string(caller, name_ptr, name_len);
+    let state = caller.data_mut();
+    if !state.registering {
+        println!("Script {}: blocks can only be registered in init", state.script);
+        return -1;
+    }
+    let (Some(name), Some(transparency)) = (name, transparency(transparency_value)) else {
+        println!("Script {}: invalid register_block arguments", state.script);
+        return -1;
+    };
+    let taken = block::definitions().any(|definition| definition.name == name)
+        || state.registered.iter().any(|definition| definition.name == name);
+    let id = block::block_count() + state.registered.len();
+    if taken || id > BlockId::MAX as usize {
+        println!("Script {}: can't register block {}", state.script, name);
+        return -1;
+    }
+
+    let channel = |shift: u32| ((color as u32 >> shift) & 0xff) as f32 / 255.0;
+    state.registered.push(BlockDefinition {
+        name: Box::leak(name.into_boxed_str()),
+        variants: Box::leak(Box::new([[channel(16), channel(8), channel(0)]])),
+        connected_texture: false,
+        transparency,
+    });
+    id as i32
+}
+
+fn host_linker(engine: &Engine) -> Result<Linker<HostState>, wasmi::Error> {
+    let mut linker = Linker::new(engine);
+    linker
+        .func_wrap("voxelfun", "get_block", |caller: Caller<'_, HostState>, x: i32, y: i32, z: i32| -> i32 {
+            caller.data().get_block(IVec3::new(x, y, z)).map_or(-1, i32::from)
+        })?
+        .func_wrap("voxelfun", "set_block", |mut caller: Caller<'_, HostState>, x: i32, y: i32, z: i32, block: i32| {
+            let state = caller.data_mut();
+            if (0..block::block_count() as i32).contains(&block) && state.call_edits < MAX_SCRIPT_WRITES_PER_CALL {
+                state.call_edits += 1;
+                state.edits.push((IVec3::new(x, y, z), block as BlockId));
+            }
+        })?
+        .func_wrap("voxelfun", "schedule_tick", |mut caller: Caller<'_, HostState>, x: i32, y: i32, z: i32, delay: i64| {
+            let state = caller.data_mut();
+            if state.call_ticks < MAX_SCRIPT_WRITES_PER_CALL {
+                state.call_ticks += 1;
+                state.ticks.push((IVec3::new(x, y, z), delay.max(1) as u64));
+            }
+        })?
+        .func_wrap("voxelfun", "register_block", |mut caller: Caller<'_, HostState>, name_ptr: i32, name_len: i32, color: i32, transparency: i32| -> i32 {
+            register_block(&mut caller, name_ptr, name_len, color, transparency)
+        })?
+        .func_wrap("voxelfun", "log", |caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            if let Some(message) = read_string(&caller, ptr, len) {
+                println!("[{}] {}", caller.data().script, message);
+            }
+        })?;
+    Ok(linker)
+}
+
+struct Script {
+    store: Store<HostState>,
+    enabled: bool,
+    on_block_placed: Option<VoxelHook>,
+    on_block_broken: Option<VoxelHook>,
+    on_block_tick: Option<VoxelHook>,
+    on_tick: Option<TypedFunc<i64, ()>>,
+}
+
+impl Script {
+    // Instantiates the module and runs its `init`. `registered` carries the blocks registered
+    // so far from one script to the next.
+    fn load(engine: &Engine, linker: &Linker<HostState>, path: &Path, registered: Vec<BlockDefinition>) -> Result<(Self, Vec<BlockDefinition>), wasmi::Error> {
+        let bytes = std::fs::read(path).map_err(|err| wasmi::Error::new(err.to_string()))?;
+        let module = Module::new(engine, &bytes)?;
+        let state = HostState {
+            script: path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned()),
+            registered,
+            registering: true,
+            limits: StoreLimitsBuilder::new()
+                .memory_size(SCRIPT_MEMORY_LIMIT)
+                .table_elements(SCRIPT_TABLE_LIMIT)
+                .instances(1)
+                .tables(1)
+                .memories(1)
+                .build(),
+            ..default()
+        };
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(SCRIPT_FUEL_PER_CALL)?;
+        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        if let Ok(init) = instance.get_typed_func::<(), ()>(&store, "init") {
+            init.call(&mut store, ())?;
+        }
+
+        let state = store.data_mut();
+        state.registering = false;
+        let registered = mem::take(&mut state.registered);
+        let voxel_hook = |name| hook::<(i32, i32, i32, i32)>(&instance, &store, name);
+        let script = Script {
+            on_block_placed: voxel_hook("on_block_placed"),
+            on_block_broken: voxel_hook("on_block_broken"),
+            on_block_tick: voxel_hook("on_block_tick"),
+            on_tick: hook::<i64>(&instance, &store, "on_tick"),
+            enabled: true,
+            store,
+        };
+        Ok((script, registered))
+    }
+
+    fn name(&self) -> &str {
+        &self.store.data().script
+    }
+
+    // Runs one hook with fresh fuel, disabling the script if it fails
+    fn call<P: WasmParams>(&mut self, func: TypedFunc<P, ()>, params: P) {
+        let state = self.store.data_mut();
+        state.call_edits = 0;
+        state.call_ticks = 0;
+        let result = self.store.set_fuel(SCRIPT_FUEL_PER_CALL)
+            .and_then(|()| func.call(&mut self.store, params));
+        if let Err(err) = result {
+            println!("Script {} stopped and disabled: {}", self.name(), err);
+            self.enabled = false;
+        }
+    }
+}
+
+fn hook<P: WasmParams>(instance: &Instance, store: &Store<HostState>, name: &str) -> Option<TypedFunc<P, ()>> {
+    instance.get_typed_func(store, name).ok()
+}
+
+// Loaded scripts in load order. Dropped scripts stay in the list, disabled.
+#[derive(Resource, Default)]
+pub struct Scripts {
+    scripts: Vec<Script>,
+}
+
+impl Scripts {
+    // Calls `call` for every enabled script with the world's chunks lent to it, and returns the
+    // block writes and ticks the scripts asked for
+    fn call_all(&mut self, world: &mut World, mut call: impl FnMut(&mut Script)) -> ScriptWrites {
+        let mut edits = Vec::new();
+        let mut ticks = Vec::new();
+        for script in self.scripts.iter_mut().filter(|script| script.enabled) {
+            let state = script.store.data_mut();
+            state.chunk_size = world.chunk_size;
+            mem::swap(&mut state.chunks, &mut world.chunks);
+            call(script);
+            let state = script.store.data_mut();
+            mem::swap(&mut state.chunks, &mut world.chunks);
+            edits.append(&mut state.edits);
+            ticks.append(&mut state.ticks);
+        }
+        (edits, ticks)
+    }
+}
+
+// Loads every script of the content packs under `pack_dir` and registers the blocks they add.
+// Scripts that fail to load are skipped with a message.
+pub fn load_scripts(pack_dir: &Path) -> Scripts {
+    let mut packs: Vec<PathBuf> = std::fs::read_dir(pack_dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect())
+        .unwrap_or_default();
+    packs.sort();
+    let mut paths = Vec::new();
+    for pack in packs {
+        let mut pack_scripts: Vec<PathBuf> = std::fs::read_dir(pack.join("scripts"))
+            .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|path| path.extension().is_some_and(|ext| ext == "wasm")).collect())
+            .unwrap_or_default();
+        pack_scripts.sort();
+        paths.extend(pack_scripts);
+    }
+    if paths.is_empty() {
+        return Scripts::default();
+    }
+
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let linker = match host_linker(&engine) {
+        Ok(linker) => linker,
+        Err(err) => {
+            println!("Scripting unavailable: {}", err);
+            return Scripts::default();
+        }
+    };
+
+    let mut scripts = Vec::new();
+    let mut registered = Vec::new();
+    for path in paths {
+        // A script failing halfway through init keeps none of its blocks
+        match Script::load(&engine, &linker, &path, registered.clone()) {
+            Ok((script, blocks)) => {
+                println!("Loaded script {}", path.display());
+                scripts.push(script);
+                registered = blocks;
+            }
+            Err(err) => println!("Skipping script {}: {}", path.display(), err),
+        }
+    }
+    block::set_script_blocks(registered);
+    Scripts { scripts }
+}
+
+fn queue_script_writes((edits, ticks): ScriptWrites, world: &World, set_events: &mut EventWriter<VoxelSetEvent>, updates: &mut BlockUpdates) {
+    for (pos, block) in edits {
+        let (chunk_key, voxel_pos) = world.world_to_voxel(pos);
+        set_events.send(VoxelSetEvent { chunk_key, voxel_pos, block, state: 0 });
+    }
+    for (pos, delay) in ticks {
+        updates.schedule(pos, delay);
+    }
+}
+
+// Hands this frame's placed and broken blocks to the scripts subscribed to them
+pub fn run_voxel_hooks(
+    mut scripts: ResMut<Scripts>,
+    mut world: ResMut<World>,
+    mut updates: ResMut<BlockUpdates>,
+    mut placed_events: EventReader<VoxelPlacedEvent>,
+    mut broken_events: EventReader<VoxelBrokenEvent>,
+    mut set_events: EventWriter<VoxelSetEvent>,
+) {
+    let placed: Vec<(IVec3, BlockId)> = placed_events.read()
+        .map(|event| (world.voxel_to_world(event.chunk_key, event.voxel_pos), event.block))
+        .collect();
+    let broken: Vec<(IVec3, BlockId)> = broken_events.read()
+        .map(|event| (world.voxel_to_world(event.chunk_key, event.voxel_pos), event.block))
+        .collect();
+    if scripts.scripts.is_empty() || (placed.is_empty() && broken.is_empty()) {
+        return;
+    }
+
+    let writes = scripts.call_all(&mut world, |script| {
+        for (hook, events) in [(script.on_block_placed, &placed), (script.on_block_broken, &broken)] {
+            let Some(hook) = hook else {
+                continue;
+            };
+            for &(pos, block) in events {
+                if script.enabled {
+                    script.call(hook, (pos.x, pos.y, pos.z, block as i32));
+                }
+            }
+        }
+    });
+    queue_script_writes(writes, &world, &mut set_events, &mut updates);
+}
+
+// Runs on_block_tick for the scheduled ticks that came up this tick, then on_tick. Sits between
+// block_updates::run_scheduled_ticks and process_block_updates.
+pub fn run_tick_hooks(
+    mut scripts: ResMut<Scripts>,
+    mut world: ResMut<World>,
+    mut updates: ResMut<BlockUpdates>,
+    clock: Res<WorldClock>,
+    mut set_events: EventWriter<VoxelSetEvent>,
+) {
+    if scripts.scripts.is_empty() {
+        return;
+    }
+
+    let due: Vec<(IVec3, BlockId)> = updates.due().iter()
+        .filter_map(|&pos| {
+            let (chunk_key, voxel_pos) = world.world_to_voxel(pos);
+            world.get_block(chunk_key, voxel_pos).map(|block| (pos, block))
+        })
+        .collect();
+    let tick = clock.tick as i64;
+    let writes = scripts.call_all(&mut world, |script| {
+        if let Some(hook) = script.on_block_tick {
+            for &(pos, block) in &due {
+                if script.enabled {
+                    script.call(hook, (pos.x, pos.y, pos.z, block as i32));
+                }
+            }
+        }
+        if let Some(hook) = script.on_tick.filter(|_| script.enabled) {
+            script.call(hook, tick);
+        }
+    });
+    queue_script_writes(writes, &world, &mut set_events, &mut updates);
+}
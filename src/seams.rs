@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+use crate::block::{self, AIR};
+use crate::camera_controller::CameraController;
+use crate::keybindings::{Action, Actions};
+use crate::terrain::ChunkBorders;
+use crate::theme::Theme;
+use crate::world::World;
+use crate::SEAM_DEBUG_RADIUS;
+
+// Which neighbours were missing when the chunk's current mesh was built, see ChunkMeshes
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ChunkSeams(pub u8);
+
+// Chunk boundaries and border faces that were meshed without their neighbour, for tracking
+// down seams left by generation order. Not saved.
+#[derive(Resource, Default)]
+pub struct SeamDebug {
+    pub enabled: bool,
+}
+
+pub fn toggle_seam_debug(actions: Actions, mut seam_debug: ResMut<SeamDebug>) {
+    if actions.just_pressed(Action::ChunkSeams) {
+        seam_debug.enabled = !seam_debug.enabled;
+        println!("Chunk seam debugging {}", if seam_debug.enabled { "enabled" } else { "disabled" });
+    }
+}
+
+pub fn seam_debug_enabled(seam_debug: Res<SeamDebug>) -> bool {
+    seam_debug.enabled
+}
+
+// Chunks within SEAM_DEBUG_RADIUS of the camera get a box: primary when every neighbour was
+// there at meshing, secondary while one is still missing, warning once a missing one has
+// loaded but the chunk hasn't been remeshed. Faces towards a missing neighbour are outlined in
+// secondary, in warning when the neighbour has loaded since and covers them (a visible seam).
+pub fn draw_chunk_seams(
+    world: Res<World>,
+    theme: Res<Theme>,
+    camera_query: Query<&Transform, With<CameraController>>,
+    seams_query: Query<&ChunkSeams>,
+    mut gizmos: Gizmos,
+) {
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+    let (center, _) = world.world_to_voxel(camera.translation.floor().as_ivec3());
+    let size = world.chunk_size;
+    let dims = [size; 3];
+
+    for (&chunk_key, &entity) in &world.chunk_entities {
+        let offset = IVec3::new(chunk_key.0 - center.0, chunk_key.1 - center.1, chunk_key.2 - center.2);
+        if offset.abs().max_element() > SEAM_DEBUG_RADIUS {
+            continue;
+        }
+        let (Ok(&ChunkSeams(missing)), Some(chunk)) = (seams_query.get(entity), world.chunks.get(&chunk_key)) else {
+            continue;
+        };
+
+        let origin = world.voxel_to_world(chunk_key, (0, 0, 0));
+        let mut stale = false;
+        for (direction, step) in ChunkBorders::DIRECTIONS.iter().enumerate() {
+            if missing & 1 << direction == 0 {
+                continue;
+            }
+            let neighbour_key = (chunk_key.0 + step.x, chunk_key.1 + step.y, chunk_key.2 + step.z);
+            let neighbour = world.chunks.get(&neighbour_key);
+            stale |= neighbour.is_some();
+
+            let axis = direction / 2;
+            let (u_axis, v_axis) = ChunkBorders::layer_axes(axis);
+            let rotation = Quat::from_rotation_arc(Vec3::Z, step.as_vec3());
+            for v in 0..dims[v_axis] {
+                for u in 0..dims[u_axis] {
+                    let mut pos = [0; 3];
+                    pos[axis] = if direction % 2 == 0 { 0 } else { dims[axis] - 1 };
+                    pos[u_axis] = u;
+                    pos[v_axis] = v;
+                    let block = chunk.get_block(pos[0], pos[1], pos[2]);
+                    if block == AIR {
+                        continue;
+                    }
+                    // The voxel across the border, in the neighbour's own coordinates
+                    let mut front = pos;
+                    front[axis] = if direction % 2 == 0 { dims[axis] - 1 } else { 0 };
+                    let covered = neighbour.is_some_and(|neighbour| {
+                        block::hides_face(neighbour.get_block(front[0], front[1], front[2]), block)
+                    });
+                    let color = if covered { theme.debug_warning } else { theme.debug_secondary };
+                    let voxel = origin + IVec3::new(pos[0] as i32, pos[1] as i32, pos[2] as i32);
+                    // Just off the face so it isn't hidden in the depth buffer
+                    let face_center = voxel.as_vec3() + Vec3::splat(0.5) + step.as_vec3() * 0.51;
+                    gizmos.rect(face_center, rotation, Vec2::splat(0.8), color);
+                }
+            }
+        }
+
+        let color = match (missing, stale) {
+            (0, _) => theme.debug_primary,
+            (_, true) => theme.debug_warning,
+            (_, false) => theme.debug_secondary,
+        };
+        let box_center = origin.as_vec3() + Vec3::splat(size as f32 / 2.0);
+        gizmos.cuboid(Transform::from_translation(box_center).with_scale(Vec3::splat(size as f32)), color);
+    }
+}
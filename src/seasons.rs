@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+use crate::console::{Console, ConsoleCommand};
+use crate::day_night::{TimeOfDay, WorldClock};
+use crate::game_rules::GameRules;
+use crate::outline::ChunkMaterials;
+use crate::water::TerrainMaterial;
+
+pub const DAYS_PER_SEASON: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub const ALL: [Season; 4] = [Season::Spring, Season::Summer, Season::Autumn, Season::Winter];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        }
+    }
+
+    // Hue foliage drifts towards at the height of the season, and how strongly
+    fn tint(self) -> LinearRgba {
+        match self {
+            Season::Spring => LinearRgba::new(0.35, 0.9, 0.25, 0.0),
+            Season::Summer => LinearRgba::new(0.45, 0.8, 0.15, 0.35),
+            Season::Autumn => LinearRgba::new(0.95, 0.45, 0.1, 0.75),
+            Season::Winter => LinearRgba::new(0.55, 0.5, 0.4, 0.6),
+        }
+    }
+}
+
+// Position in the year: the current season and how far through it we are, 0..1
+fn year_position(clock: &WorldClock, time_of_day: &TimeOfDay) -> (Season, f32) {
+    let days = (clock.day % (DAYS_PER_SEASON * 4)) as f32 + time_of_day.0;
+    let seasons = days / DAYS_PER_SEASON as f32;
+    (Season::ALL[seasons as usize % 4], seasons.fract())
+}
+
+// Holds the season's tint through its middle and blends into the next one over its last quarter
+pub fn season_tint(clock: &WorldClock, time_of_day: &TimeOfDay) -> LinearRgba {
+    let (season, progress) = year_position(clock, time_of_day);
+    let next = Season::ALL[(Season::ALL.iter().position(|&s| s == season).unwrap() + 1) % 4];
+    let blend = ((progress - 0.75) / 0.25).clamp(0.0, 1.0);
+    season.tint().mix(&next.tint(), blend)
+}
+
+pub fn apply_season_tint(
+    clock: Res<WorldClock>,
+    time_of_day: Res<TimeOfDay>,
+    rules: Res<GameRules>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut terrain_materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    if !clock.is_changed() && !time_of_day.is_changed() && !rules.is_changed() {
+        return;
+    }
+
+    let tint = if rules.seasons { season_tint(&clock, &time_of_day) } else { LinearRgba::NONE };
+    let Some(current) = terrain_materials.get(&chunk_materials.standard).map(|material| material.extension.settings.season_tint) else {
+        return;
+    };
+    // The tint moves a tiny bit every frame; only re-upload the material for visible steps
+    let difference = current.to_vec4() - tint.to_vec4();
+    if difference.abs().max_element() < 0.005 && (current.alpha == 0.0) == (tint.alpha == 0.0) {
+        return;
+    }
+    for handle in chunk_materials.terrain() {
+        if let Some(material) = terrain_materials.get_mut(handle) {
+            material.extension.settings.season_tint = tint;
+        }
+    }
+}
+
+// `season [spring|summer|autumn|winter]` shows the date or skips to the start of a season
+pub fn season_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut clock: ResMut<WorldClock>,
+    time_of_day: Res<TimeOfDay>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "season") {
+        let Some(name) = command.args.first() else {
+            let (season, progress) = year_position(&clock, &time_of_day);
+            console.print(format!("Day {}, {} ({:.0}% through)", clock.day, season.name(), progress * 100.0));
+            continue;
+        };
+        let Some(index) = Season::ALL.iter().position(|season| season.name() == name) else {
+            let names: Vec<_> = Season::ALL.iter().map(|season| season.name()).collect();
+            console.print(format!("Unknown season '{}', expected one of {}", name, names.join(", ")));
+            continue;
+        };
+
+        // Forward to the next start of that season, the calendar never runs backwards
+        let year = DAYS_PER_SEASON * 4;
+        let target = index as u32 * DAYS_PER_SEASON;
+        let days_ahead = (target + year - clock.day % year) % year;
+        clock.day += days_ahead;
+        console.print(format!("Skipped to day {}, start of {}", clock.day, Season::ALL[index].name()));
+    }
+}
@@ -0,0 +1,167 @@
+// For the ShaderType derive, see outline.rs
+#![allow(dead_code)]
+
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey, NotShadowCaster};
+use bevy::prelude::*;
+use bevy::render::mesh::MeshVertexBufferLayoutRef;
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, ShaderRef, ShaderType, SpecializedMeshPipelineError,
+};
+use crate::crosshair::ReachIndicator;
+use crate::player::PlayerReach;
+use crate::theme::Theme;
+use crate::voxel_world::VoxelWorld;
+use crate::{VoxelRemover, REACH_INDICATOR_RANGE, SELECTION_LINE_WIDTH};
+
+// Pulls the box towards the camera in the depth test so its edges win against the
+// coplanar block faces. The slope term covers faces seen at grazing angles.
+const SELECTION_DEPTH_BIAS: i32 = 4;
+const SELECTION_SLOPE_BIAS: f32 = 1.0;
+
+// Wireframe box around the targeted voxel. The fragment shader keeps only the fragments
+// near the face edges, measured in pixels from the UV derivatives, so lines stay the same
+// width at any distance and get a one-pixel antialiased falloff.
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+pub struct SelectionMaterial {
+    #[uniform(0)]
+    pub settings: SelectionSettings,
+}
+
+#[derive(ShaderType, Debug, Clone)]
+pub struct SelectionSettings {
+    pub color: LinearRgba,
+    // In pixels
+    pub line_width: f32,
+}
+
+impl Material for SelectionMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/selection_outline.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    // Sorts the box in front of other transparent surfaces at the same depth, like water
+    fn depth_bias(&self) -> f32 {
+        SELECTION_DEPTH_BIAS as f32
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // Back faces show the far edges once the block is gone, and keep the box visible from inside
+        descriptor.primitive.cull_mode = None;
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.bias.constant = SELECTION_DEPTH_BIAS;
+            depth_stencil.bias.slope_scale = SELECTION_SLOPE_BIAS;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Component)]
+pub struct SelectionHighlight;
+
+pub fn spawn_selection_highlight(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<SelectionMaterial>>,
+    theme: Res<Theme>,
+) {
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Cuboid::from_length(1.0)),
+            material: materials.add(SelectionMaterial {
+                settings: SelectionSettings {
+                    color: theme.selection_highlight.to_linear(),
+                    line_width: SELECTION_LINE_WIDTH,
+                },
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        SelectionHighlight,
+        NotShadowCaster,
+    ));
+}
+
+// The voxel the player is looking at, raycast once per frame from the eye (the camera, which
+// sits EYE_HEIGHT above the feet) after movement and collision, so mining, placement, the
+// selection box and the other tools all agree on one target. `voxel` is the first solid voxel
+// within the player's reach and `place_at` the empty cell in front of its targeted face;
+// `out_of_reach` is a voxel hit past the reach, up to REACH_INDICATOR_RANGE, looked for only
+// while the reach indicator is on.
+#[derive(Resource, Default)]
+pub struct TargetedBlock {
+    pub voxel: Option<IVec3>,
+    pub place_at: Option<IVec3>,
+    pub out_of_reach: Option<IVec3>,
+}
+
+pub fn update_targeted_block(
+    voxel_world: VoxelWorld,
+    camera_query: Query<&Transform, With<VoxelRemover>>,
+    reach: PlayerReach,
+    reach_indicator: Res<ReachIndicator>,
+    mut target: ResMut<TargetedBlock>,
+) {
+    let Ok(eye) = camera_query.get_single() else {
+        return;
+    };
+    let ray = Ray3d::new(eye.translation, *eye.forward());
+    let hit = voxel_world.raycast_with_previous(ray, reach.get());
+    let out_of_reach = if hit.is_none() && reach_indicator.enabled && REACH_INDICATOR_RANGE > reach.get() {
+        voxel_world.raycast(ray, REACH_INDICATOR_RANGE)
+    } else {
+        None
+    };
+
+    let next = TargetedBlock {
+        voxel: hit.map(|(voxel, _)| voxel),
+        place_at: hit.map(|(_, previous)| previous),
+        out_of_reach,
+    };
+    if target.voxel != next.voxel || target.place_at != next.place_at || target.out_of_reach != next.out_of_reach {
+        *target = next;
+    }
+}
+
+pub fn update_selection_highlight(
+    target: Res<TargetedBlock>,
+    mut highlight_query: Query<(&mut Transform, &mut Visibility), With<SelectionHighlight>>,
+) {
+    let Ok((mut transform, mut visibility)) = highlight_query.get_single_mut() else {
+        return;
+    };
+
+    match target.voxel {
+        Some(hit) => {
+            transform.translation = hit.as_vec3() + Vec3::splat(0.5);
+            visibility.set_if_neq(Visibility::Inherited);
+        }
+        None => {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+}
+
+pub fn apply_theme_to_selection(
+    theme: Res<Theme>,
+    highlight_query: Query<&Handle<SelectionMaterial>, With<SelectionHighlight>>,
+    mut materials: ResMut<Assets<SelectionMaterial>>,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+
+    for handle in &highlight_query {
+        if let Some(material) = materials.get_mut(handle) {
+            material.settings.color = theme.selection_highlight.to_linear();
+        }
+    }
+}
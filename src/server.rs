@@ -0,0 +1,303 @@
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::prelude::*;
+use std::collections::{HashSet, VecDeque};
+use std::net::TcpListener;
+use std::time::Duration;
+use crate::block;
+use crate::chunk_format;
+use crate::chunk_pool::ChunkPool;
+use crate::dimension::Dimension;
+use crate::net::{ClientMessage, Connection, ServerMessage, PROTOCOL_VERSION};
+use crate::player::PlayerStats;
+use crate::spawn;
+use crate::world::World;
+use crate::worldgen::WorldGenerator;
+use crate::worlds::WorldMeta;
+use crate::{CHUNK_SIZE, DEFAULT_RENDER_DISTANCE, FLY_SPEED_TIERS, MAX_RENDER_DISTANCE, PREFETCH_MAX_RINGS};
+
+const SERVER_TICK_RATE: f64 = 60.0;
+// Chunks generated or loaded and streamed per client per tick
+const CHUNK_SENDS_PER_TICK: usize = 4;
+// Queued chunk requests per client; more than this is a misbehaving client
+const MAX_PENDING_REQUESTS: usize = 4096;
+const AUTOSAVE_SECONDS: f32 = 30.0;
+// Voxels an edit may lie past the player's reach, for a position report still on its way
+const EDIT_REACH_SLACK: f32 = 2.0;
+// Chunks from the player's position a client may ask for: the largest render distance, the
+// prefetch rings past it and one more for a position report still on its way
+const MAX_REQUEST_DISTANCE: u32 = (MAX_RENDER_DISTANCE + PREFETCH_MAX_RINGS + 1) as u32;
+// Voxels/s a reported position may move, twice the fastest flying speed
+const MAX_CLIENT_SPEED: f32 = 2.0 * FLY_SPEED_TIERS[FLY_SPEED_TIERS.len() - 1];
+// Seconds of unused movement a client may save up, for reports that arrive in bursts
+const MAX_MOVEMENT_BANKED: f32 = 1.0;
+// Positions farther out are refused, which keeps the voxel coordinates of every chunk a client
+// may request inside i32
+const MAX_POSITION_COORD: f32 = 1.0e9;
+
+// Headless authoritative server: owns the World, runs worldgen, validates edits and streams chunks
+// to clients on demand. Runs without rendering, windowing or input (`voxelfun server [port]`,
+// serving the world given with `--world`).
+pub struct ServerPlugin {
+    pub port: u16,
+    pub world: WorldMeta,
+}
+
+impl Plugin for ServerPlugin {
+    // If the port can't be opened the app exits with an error after its first update
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / SERVER_TICK_RATE))));
+        let listener = match TcpListener::bind(("0.0.0.0", self.port)).and_then(|listener| listener.set_nonblocking(true).map(|_| listener)) {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("Could not listen on port {}: {}", self.port, err);
+                app.world_mut().send_event(AppExit::error());
+                return;
+            }
+        };
+        println!("Server listening on port {}", self.port);
+
+        app.insert_resource(World::for_dimension(CHUNK_SIZE, DEFAULT_RENDER_DISTANCE, Dimension::Overworld, &self.world))
+            .insert_resource(Dimension::Overworld.generator(self.world.seed))
+            .init_resource::<ChunkPool>()
+            .insert_resource(Server { listener, clients: Vec::new(), next_client_id: 0, spawn: spawn::spawn_eye(&self.world, Dimension::Overworld) })
+            .insert_resource(AutosaveTimer(Timer::from_seconds(AUTOSAVE_SECONDS, TimerMode::Repeating)))
+            .add_systems(Update, (
+                accept_clients,
+                handle_client_messages.after(accept_clients),
+                stream_chunks.after(handle_client_messages),
+                release_unwatched_chunks.after(stream_chunks),
+                flush_clients.after(release_unwatched_chunks),
+                autosave_server_world,
+                log_world_notifications,
+            ));
+    }
+}
+
+struct RemoteClient {
+    id: u32,
+    connection: Connection,
+    greeted: bool,
+    // Chunks this client asked for that haven't been sent yet, oldest first
+    pending: VecDeque<(i32, i32, i32)>,
+    // Chunks the client holds; it receives every block change inside them
+    watched: HashSet<(i32, i32, i32)>,
+    // Dropped once the disconnect message is flushed
+    kicked: bool,
+    // The player's eye as far as the server trusts it: the spawn from the welcome on, then
+    // following the client's reports no faster than MAX_CLIENT_SPEED. None before the hello.
+    position: Option<Vec3>,
+    // Voxels the position may still move, refilled every tick
+    movement_allowance: f32,
+}
+
+impl RemoteClient {
+    fn kick(&mut self, reason: String) {
+        println!("Disconnecting client {}: {}", self.id, reason);
+        self.connection.send(&ServerMessage::Disconnect { reason });
+        self.kicked = true;
+    }
+}
+
+#[derive(Resource)]
+pub struct Server {
+    listener: TcpListener,
+    clients: Vec<RemoteClient>,
+    next_client_id: u32,
+    // Where every client starts, see spawn::spawn_eye
+    spawn: Vec3,
+}
+
+impl Server {
+    fn broadcast_block_change(&mut self, chunk_key: (i32, i32, i32), pos: IVec3, block: block::BlockId) {
+        for client in self.clients.iter_mut().filter(|client| client.watched.contains(&chunk_key)) {
+            client.connection.send(&ServerMessage::BlockChange { pos, block });
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AutosaveTimer(Timer);
+
+fn accept_clients(mut server: ResMut<Server>) {
+    loop {
+        match server.listener.accept() {
+            Ok((stream, addr)) => match Connection::new(stream) {
+                Ok(connection) => {
+                    let id = server.next_client_id;
+                    server.next_client_id += 1;
+                    println!("Client {} connected from {}", id, addr);
+                    server.clients.push(RemoteClient { id, connection, greeted: false, pending: VecDeque::new(), watched: HashSet::new(), kicked: false, position: None, movement_allowance: 0.0 });
+                }
+                Err(err) => println!("Could not set up connection from {}: {}", addr, err),
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(err) => {
+                println!("Accepting a client failed: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+fn handle_client_messages(mut server: ResMut<Server>, mut world: ResMut<World>, time: Res<Time>) {
+    let server = &mut *server;
+    let mut changes = Vec::new();
+    // The server doesn't know the client's game mode, so it allows the longest reach any has
+    let stats = PlayerStats::default();
+    let max_reach = stats.reach.max(stats.fly_reach) + EDIT_REACH_SLACK;
+    let spawn = server.spawn;
+
+    for client in server.clients.iter_mut().filter(|client| !client.kicked) {
+        client.movement_allowance = (client.movement_allowance + MAX_CLIENT_SPEED * time.delta_seconds()).min(MAX_CLIENT_SPEED * MAX_MOVEMENT_BANKED);
+        for message in client.connection.receive::<ClientMessage>() {
+            match message {
+                ClientMessage::Hello { protocol_version } => {
+                    if protocol_version != PROTOCOL_VERSION {
+                        client.kick(format!("Server speaks protocol {}, client {}", PROTOCOL_VERSION, protocol_version));
+                        break;
+                    }
+                    client.greeted = true;
+                    client.position = Some(spawn);
+                    client.connection.send(&ServerMessage::Welcome { protocol_version: PROTOCOL_VERSION, chunk_size: world.chunk_size as u16, spawn });
+                }
+                _ if !client.greeted => {
+                    client.kick("Expected hello first".to_string());
+                    break;
+                }
+                ClientMessage::RequestChunk(chunk_key) => {
+                    // Only chunks around the player, so a client can't have the server generate
+                    // and save chunks anywhere
+                    let in_range = client.position.is_some_and(|position| {
+                        let (center, _) = world.world_to_voxel(position.floor().as_ivec3());
+                        let distance = chunk_key.0.abs_diff(center.0).max(chunk_key.1.abs_diff(center.1)).max(chunk_key.2.abs_diff(center.2));
+                        distance <= MAX_REQUEST_DISTANCE
+                    });
+                    if !in_range {
+                        client.connection.send(&ServerMessage::ChunkRefused(chunk_key));
+                    } else if client.pending.len() < MAX_PENDING_REQUESTS && !client.pending.contains(&chunk_key) {
+                        client.pending.push_back(chunk_key);
+                    }
+                }
+                ClientMessage::ForgetChunk(chunk_key) => {
+                    client.pending.retain(|&pending| pending != chunk_key);
+                    client.watched.remove(&chunk_key);
+                }
+                ClientMessage::Position(reported) => {
+                    if !reported.is_finite() || reported.abs().max_element() > MAX_POSITION_COORD {
+                        continue;
+                    }
+                    // Edits and chunk requests are checked against this, so a client can't claim
+                    // to stand wherever it wants to edit
+                    if let Some(position) = &mut client.position {
+                        let step = (reported - *position).clamp_length_max(client.movement_allowance);
+                        *position += step;
+                        client.movement_allowance -= step.length();
+                    }
+                }
+                ClientMessage::SetBlock { pos, block } => {
+                    let (chunk_key, voxel_pos) = world.world_to_voxel(pos);
+                    // Only edits within reach of the player, to loaded chunks the client can see,
+                    // with blocks that exist
+                    let in_reach = client.position.is_some_and(|position| position.distance(pos.as_vec3() + 0.5) <= max_reach);
+                    let valid = (block as usize) < block::block_count()
+                        && in_reach
+                        && client.watched.contains(&chunk_key)
+                        && world.chunks.contains_key(&chunk_key);
+                    if !valid {
+                        // Put the client's copy back in line with the server
+                        if let Some(actual) = world.get_block(chunk_key, voxel_pos) {
+                            client.connection.send(&ServerMessage::BlockChange { pos, block: actual });
+                        }
+                        continue;
+                    }
+                    if world.set_block(chunk_key, voxel_pos, block).is_some_and(|old| old != block) {
+                        changes.push((chunk_key, pos, block));
+                    }
+                }
+            }
+        }
+    }
+
+    for (chunk_key, pos, block) in changes {
+        server.broadcast_block_change(chunk_key, pos, block);
+    }
+}
+
+fn stream_chunks(mut server: ResMut<Server>, mut world: ResMut<World>, generator: Res<WorldGenerator>, mut pool: ResMut<ChunkPool>) {
+    let server = &mut *server;
+    let mut changes = Vec::new();
+
+    for client in server.clients.iter_mut().filter(|client| !client.kicked) {
+        for _ in 0..CHUNK_SENDS_PER_TICK {
+            let Some(chunk_key) = client.pending.pop_front() else {
+                break;
+            };
+            for (neighbour_key, voxel_pos) in world.load_or_generate_chunk(chunk_key, &generator, &mut pool) {
+                let pos = world.voxel_to_world(neighbour_key, voxel_pos);
+                let block = world.get_block(neighbour_key, voxel_pos).unwrap_or(block::AIR);
+                changes.push((neighbour_key, pos, block));
+            }
+            let blob = chunk_format::encode_chunk(chunk_key, &world.chunks[&chunk_key]);
+            client.connection.send(&ServerMessage::ChunkData { chunk_key, blob });
+            client.watched.insert(chunk_key);
+        }
+    }
+
+    // Generation spilled structures into chunks clients already hold
+    for (chunk_key, pos, block) in changes {
+        server.broadcast_block_change(chunk_key, pos, block);
+    }
+}
+
+// Chunks no client holds or waits for are saved and dropped
+fn release_unwatched_chunks(server: Res<Server>, mut world: ResMut<World>, mut pool: ResMut<ChunkPool>) {
+    let in_use: HashSet<_> = server.clients.iter()
+        .flat_map(|client| client.watched.iter().chain(client.pending.iter()))
+        .copied()
+        .collect();
+    let unused: Vec<_> = world.chunks.keys().filter(|key| !in_use.contains(key)).copied().collect();
+
+    let (_, unsaved) = world.save_modified(&unused, false);
+    for chunk_key in unused {
+        if unsaved.contains(&chunk_key) {
+            continue;
+        }
+        if let Some(chunk) = world.chunks.remove(&chunk_key) {
+            pool.recycle(chunk);
+        }
+    }
+}
+
+fn flush_clients(mut server: ResMut<Server>) {
+    for client in &mut server.clients {
+        client.connection.flush();
+    }
+    server.clients.retain(|client| {
+        let gone = client.connection.is_closed() || (client.kicked && !client.connection.has_pending_output());
+        if client.connection.overflowed() {
+            // Too far behind for a disconnect message to get through
+            println!("Disconnecting client {}: stopped reading its messages", client.id);
+        }
+        if gone {
+            println!("Client {} disconnected", client.id);
+        }
+        !gone
+    });
+}
+
+fn autosave_server_world(time: Res<Time>, mut timer: ResMut<AutosaveTimer>, mut world: ResMut<World>) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let saved = world.save_all_modified();
+    if saved > 0 {
+        println!("Saved {} edited chunk(s)", saved);
+    }
+}
+
+// There is no HUD on the server, so World's messages go to the log
+fn log_world_notifications(mut world: ResMut<World>) {
+    for notification in world.notifications.drain(..) {
+        println!("{:?}: {}", notification.level, notification.message);
+    }
+}
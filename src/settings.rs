@@ -0,0 +1,239 @@
+use std::fs;
+use std::path::Path;
+use bevy::prelude::*;
+use crate::{FLY_SPEED_TIERS, MOVEMENT_SMOOTHING};
+
+// Altitude-based darkening applied to vertex colors at mesh time, on top of (and independent
+// from) propagated light. Above `surface_y` nothing changes; below it brightness falls off
+// along the curve until it bottoms out at `min_brightness` at `dark_y`.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthDarknessCurve {
+    pub enabled: bool,
+    pub surface_y: f32,
+    pub dark_y: f32,
+    pub min_brightness: f32,
+    // >1 keeps shallow caves brighter for longer, <1 darkens quickly right below the surface
+    pub exponent: f32,
+}
+
+impl Default for DepthDarknessCurve {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            surface_y: 0.0,
+            dark_y: -48.0,
+            min_brightness: 0.08,
+            exponent: 1.5,
+        }
+    }
+}
+
+impl DepthDarknessCurve {
+    pub fn brightness_at(&self, world_y: f32) -> f32 {
+        if !self.enabled || world_y >= self.surface_y {
+            return 1.0;
+        }
+        let depth = ((self.surface_y - world_y) / (self.surface_y - self.dark_y).max(f32::EPSILON)).clamp(0.0, 1.0);
+        1.0 - (1.0 - self.min_brightness) * depth.powf(self.exponent)
+    }
+}
+
+// How the block texture array is sampled. The GPU only filters anisotropically when every
+// filter is linear, so pixel-art mode (nearest magnification) always samples with anisotropy 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureFiltering {
+    pub pixel_art: bool,
+    // 1, 2, 4, 8 or 16 samples
+    pub anisotropy: u16,
+}
+
+impl Default for TextureFiltering {
+    fn default() -> Self {
+        Self {
+            pixel_art: true,
+            anisotropy: 8,
+        }
+    }
+}
+
+// Settings the mesher needs; copied into every meshing task. Texture filtering rides along
+// so all graphics options live in one place, but changing it never remeshes.
+#[derive(Clone, Copy, Debug)]
+pub struct GraphicsSettings {
+    pub smooth_lighting: bool,
+    pub depth_darkness: DepthDarknessCurve,
+    pub texture_filtering: TextureFiltering,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            smooth_lighting: true,
+            depth_darkness: DepthDarknessCurve::default(),
+            texture_filtering: TextureFiltering::default(),
+        }
+    }
+}
+
+// Player movement speeds in voxels/s and how quickly the camera reaches them, stored as
+// `name value` lines in MOVEMENT_SETTINGS_FILE and changed in game with `/movement <name> [value]`.
+// Sprint picks the faster speed of the current mode; flying also covers noclip.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct MovementSettings {
+    pub walk_speed: f32,
+    pub sprint_speed: f32,
+    // Stepped through FLY_SPEED_TIERS with the speed modifier + mouse wheel
+    pub fly_speed: f32,
+    pub fast_fly_speed: f32,
+    // 1/s; how quickly velocity follows the input when speeding up and when slowing down
+    pub acceleration: f32,
+    pub deceleration: f32,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self {
+            walk_speed: 4.3,
+            sprint_speed: 7.0,
+            fly_speed: FLY_SPEED_TIERS[1],
+            fast_fly_speed: 48.0,
+            acceleration: MOVEMENT_SMOOTHING,
+            deceleration: MOVEMENT_SMOOTHING * 1.5,
+        }
+    }
+}
+
+impl MovementSettings {
+    pub const NAMES: [&'static str; 6] = ["walkSpeed", "sprintSpeed", "flySpeed", "fastFlySpeed", "acceleration", "deceleration"];
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        match name {
+            "walkSpeed" => Some(self.walk_speed),
+            "sprintSpeed" => Some(self.sprint_speed),
+            "flySpeed" => Some(self.fly_speed),
+            "fastFlySpeed" => Some(self.fast_fly_speed),
+            "acceleration" => Some(self.acceleration),
+            "deceleration" => Some(self.deceleration),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "walkSpeed" => Some(&mut self.walk_speed),
+            "sprintSpeed" => Some(&mut self.sprint_speed),
+            "flySpeed" => Some(&mut self.fly_speed),
+            "fastFlySpeed" => Some(&mut self.fast_fly_speed),
+            "acceleration" => Some(&mut self.acceleration),
+            "deceleration" => Some(&mut self.deceleration),
+            _ => None,
+        }
+    }
+
+    pub fn speed(&self, flying: bool, sprinting: bool) -> f32 {
+        match (flying, sprinting) {
+            (false, false) => self.walk_speed,
+            (false, true) => self.sprint_speed,
+            (true, false) => self.fly_speed,
+            (true, true) => self.fast_fly_speed,
+        }
+    }
+
+    // Unknown names, malformed lines and negative values are skipped so older or newer files still load
+    pub fn load(path: &Path) -> Self {
+        let mut settings = Self::default();
+        let Ok(contents) = fs::read_to_string(path) else {
+            return settings;
+        };
+        for line in contents.lines() {
+            let mut parts = line.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                if let (Some(setting), Ok(value)) = (settings.get_mut(name), value.parse::<f32>()) {
+                    if value.is_finite() && value >= 0.0 {
+                        *setting = value;
+                    }
+                }
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let contents: String = Self::NAMES.iter()
+            .map(|name| format!("{} {}\n", name, self.get(name).unwrap()))
+            .collect();
+        fs::write(path, contents)
+    }
+}
+
+// Sound volumes from 0 (muted) to 1, each scaled by `master`, stored as `name value` lines in
+// AUDIO_SETTINGS_FILE and changed in game with `/volume <name> [value]`
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct AudioSettings {
+    pub master: f32,
+    // Breaking and placing blocks
+    pub blocks: f32,
+    pub footsteps: f32,
+    pub ambient: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 0.8,
+            blocks: 1.0,
+            footsteps: 0.6,
+            ambient: 0.5,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub const NAMES: [&'static str; 4] = ["master", "blocks", "footsteps", "ambient"];
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        match name {
+            "master" => Some(self.master),
+            "blocks" => Some(self.blocks),
+            "footsteps" => Some(self.footsteps),
+            "ambient" => Some(self.ambient),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "master" => Some(&mut self.master),
+            "blocks" => Some(&mut self.blocks),
+            "footsteps" => Some(&mut self.footsteps),
+            "ambient" => Some(&mut self.ambient),
+            _ => None,
+        }
+    }
+
+    // Unknown names and malformed lines are skipped, values clamped to 0..1
+    pub fn load(path: &Path) -> Self {
+        let mut settings = Self::default();
+        let Ok(contents) = fs::read_to_string(path) else {
+            return settings;
+        };
+        for line in contents.lines() {
+            let mut parts = line.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                if let (Some(setting), Ok(value)) = (settings.get_mut(name), value.parse::<f32>()) {
+                    if value.is_finite() {
+                        *setting = value.clamp(0.0, 1.0);
+                    }
+                }
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let contents: String = Self::NAMES.iter()
+            .map(|name| format!("{} {}\n", name, self.get(name).unwrap()))
+            .collect();
+        fs::write(path, contents)
+    }
+}
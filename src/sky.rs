@@ -0,0 +1,119 @@
+use bevy::core_pipeline::Skybox;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension};
+use crate::day_night::TimeOfDay;
+use crate::tuning::LightingTuning;
+use crate::weather::Weather;
+use crate::world::World;
+
+const SKY_FACE_SIZE: u32 = 64;
+// Daytime sky brightness in cd/m^2, scaled down at night
+pub const SKY_BRIGHTNESS: f32 = 1000.0;
+const NIGHT_SKY_BRIGHTNESS: f32 = 0.04; // fraction of daytime
+// Fog starts at this fraction of the loaded distance and is opaque at the loading boundary.
+// The default; LightingTuning::fog_density changes it at runtime.
+pub const FOG_START_FRACTION: f32 = 0.6;
+// How fast the fog follows a changed render distance, 1/s. Growing, it uncovers the new ring
+// about as fast as it streams in; shrinking, it closes in before the chunks past it unload.
+const FOG_DISTANCE_SMOOTHING: f32 = 1.5;
+
+pub const ZENITH_COLOR: LinearRgba = LinearRgba::rgb(0.12, 0.32, 0.85);
+pub const HORIZON_COLOR: LinearRgba = LinearRgba::rgb(0.62, 0.76, 0.95);
+pub const GROUND_COLOR: LinearRgba = LinearRgba::rgb(0.32, 0.36, 0.42);
+pub const NIGHT_HORIZON_COLOR: LinearRgba = LinearRgba::rgb(0.01, 0.012, 0.03);
+// Fog under full storm clouds, and the share of daylight the clouds take from the sky
+const OVERCAST_COLOR: LinearRgba = LinearRgba::rgb(0.36, 0.38, 0.42);
+const OVERCAST_DIMMING: f32 = 0.6;
+
+// Sky color for a view direction: ground below the horizon, blending up to the zenith above it
+fn sky_color(direction: Vec3) -> LinearRgba {
+    let height = direction.normalize().y;
+    if height < 0.0 {
+        HORIZON_COLOR.mix(&GROUND_COLOR, (-height * 4.0).min(1.0))
+    } else {
+        HORIZON_COLOR.mix(&ZENITH_COLOR, height.sqrt())
+    }
+}
+
+// Procedural gradient cubemap: six faces stacked vertically in +X, -X, +Y, -Y, +Z, -Z order
+pub fn build_sky_cubemap() -> Image {
+    let size = SKY_FACE_SIZE;
+    let mut data = Vec::with_capacity((size * size * 6 * 4) as usize);
+
+    for face in 0..6 {
+        for y in 0..size {
+            for x in 0..size {
+                // Texel centre in [-1, 1], v pointing down the face
+                let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let direction = match face {
+                    0 => Vec3::new(1.0, -v, -u),
+                    1 => Vec3::new(-1.0, -v, u),
+                    2 => Vec3::new(u, 1.0, v),
+                    3 => Vec3::new(u, -1.0, -v),
+                    4 => Vec3::new(u, -v, 1.0),
+                    _ => Vec3::new(-u, -v, -1.0),
+                };
+                data.extend_from_slice(&Color::LinearRgba(sky_color(direction)).to_srgba().to_u8_array());
+            }
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d { width: size, height: size * 6, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.reinterpret_stacked_2d_as_array(6);
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..default()
+    });
+    image
+}
+
+pub fn fog_settings(world: &World) -> FogSettings {
+    let far = world.render_distance as f32 * world.chunk_size as f32;
+    FogSettings {
+        color: Color::LinearRgba(HORIZON_COLOR),
+        falloff: FogFalloff::Linear { start: far * FOG_START_FRACTION, end: far },
+        ..default()
+    }
+}
+
+// Keeps fog and sky matching the time of day, and the fog end on the chunk loading boundary
+pub fn update_sky_and_fog(
+    world: Res<World>,
+    time_of_day: Res<TimeOfDay>,
+    time: Res<Time>,
+    tuning: Res<LightingTuning>,
+    weather: Res<Weather>,
+    mut fog_distance: Local<Option<f32>>,
+    mut camera_query: Query<(&mut FogSettings, &mut Skybox)>,
+) {
+    let target = world.render_distance as f32 * world.chunk_size as f32;
+    let far = fog_distance.get_or_insert(target);
+    let settled = *far == target;
+    if !settled {
+        *far += (target - *far) * (1.0 - (-FOG_DISTANCE_SMOOTHING * time.delta_seconds()).exp());
+        if (target - *far).abs() < 0.5 {
+            *far = target;
+        }
+    }
+    if settled && !world.is_changed() && !time_of_day.is_changed() && !tuning.is_changed() && !weather.is_changed() {
+        return;
+    }
+    let far = *far;
+
+    let daylight = if world.dimension.has_skylight() { time_of_day.daylight() } else { 0.0 };
+    for (mut fog, mut skybox) in &mut camera_query {
+        fog.falloff = FogFalloff::Linear { start: far * (1.0 - tuning.fog_density), end: far };
+        let horizon = HORIZON_COLOR.mix(&OVERCAST_COLOR, weather.overcast);
+        let daylight = daylight * (1.0 - OVERCAST_DIMMING * weather.overcast);
+        fog.color = Color::LinearRgba(NIGHT_HORIZON_COLOR.mix(&horizon, daylight));
+        skybox.brightness = SKY_BRIGHTNESS * NIGHT_SKY_BRIGHTNESS.max(daylight);
+    }
+}
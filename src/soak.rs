@@ -0,0 +1,200 @@
+use bevy::asset::AssetPlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use std::f32::consts::TAU;
+use std::time::Duration;
+use crate::bench;
+use crate::chunk_pool::ChunkPool;
+use crate::dimension::Dimension;
+use crate::outline::{ChunkMaterials, ToonMode};
+use crate::world::{self, ChunkUpdateBudget};
+use crate::{handle_meshing_tasks, prioritize_chunks, process_chunk_queue, update_chunks, CHUNK_SIZE, DEFAULT_RENDER_DISTANCE};
+
+pub const DEFAULT_SOAK_CROSSINGS: usize = 2000;
+// The simulated player flies a horizontal circle this far from the origin, at a fixed height
+const SOAK_LOOP_RADIUS: f32 = 24.0 * CHUNK_SIZE as f32;
+const SOAK_HEIGHT: f32 = 20.0;
+// Fast enough for prefetching to kick in, see PREFETCH_MIN_SPEED
+const SOAK_SPEED: f32 = 60.0; // voxels/s
+const SOAK_FRAME: Duration = Duration::from_micros(16_667);
+// The first lap starts from an empty world, so the second one is the reference for the rest
+const BASELINE_LAP: usize = 1;
+// How much a later lap's peak may exceed the baseline peak before it counts as a leak.
+// Meshing runs on other threads, so counts wobble a little from lap to lap.
+const GROWTH_TOLERANCE: f64 = 0.25;
+const GROWTH_SLACK: u64 = 64;
+
+// Everything that should stay bounded while chunks stream in and out
+#[derive(Clone, Copy, Default)]
+struct SoakSample {
+    entities: u64,
+    meshes: u64,
+    chunks: u64,
+    chunk_entities: u64,
+    chunk_last_accessed: u64,
+    pending_structure_blocks: u64,
+    load_queue: u64,
+    unload_queue: u64,
+    pooled_chunks: u64,
+    resident_bytes: Option<u64>,
+}
+
+impl SoakSample {
+    const NAMES: [&'static str; 10] = [
+        "entities", "meshes", "chunks", "chunk_entities", "chunk_last_accessed",
+        "pending_structure_blocks", "load_queue", "unload_queue", "pooled_chunks", "resident_bytes",
+    ];
+
+    fn take(app: &App) -> Self {
+        let app_world = app.world();
+        let world = app_world.resource::<world::World>();
+        Self {
+            entities: app_world.entities().len() as u64,
+            meshes: app_world.resource::<Assets<Mesh>>().len() as u64,
+            chunks: world.chunks.len() as u64,
+            chunk_entities: world.chunk_entities.len() as u64,
+            chunk_last_accessed: world.chunk_last_accessed.len() as u64,
+            pending_structure_blocks: world.pending_structures.values().map(Vec::len).sum::<usize>() as u64,
+            load_queue: world.chunk_load_queue.len() as u64,
+            unload_queue: world.chunk_unload_queue.len() as u64,
+            pooled_chunks: app_world.resource::<ChunkPool>().free_chunks() as u64,
+            resident_bytes: bench::resident_bytes(),
+        }
+    }
+
+    fn values(&self) -> [Option<u64>; 10] {
+        [
+            Some(self.entities), Some(self.meshes), Some(self.chunks), Some(self.chunk_entities),
+            Some(self.chunk_last_accessed), Some(self.pending_structure_blocks),
+            Some(self.load_queue), Some(self.unload_queue), Some(self.pooled_chunks), self.resident_bytes,
+        ]
+    }
+
+    fn max(self, other: Self) -> Self {
+        Self {
+            entities: self.entities.max(other.entities),
+            meshes: self.meshes.max(other.meshes),
+            chunks: self.chunks.max(other.chunks),
+            chunk_entities: self.chunk_entities.max(other.chunk_entities),
+            chunk_last_accessed: self.chunk_last_accessed.max(other.chunk_last_accessed),
+            pending_structure_blocks: self.pending_structure_blocks.max(other.pending_structure_blocks),
+            load_queue: self.load_queue.max(other.load_queue),
+            unload_queue: self.unload_queue.max(other.unload_queue),
+            pooled_chunks: self.pooled_chunks.max(other.pooled_chunks),
+            resident_bytes: self.resident_bytes.max(other.resident_bytes),
+        }
+    }
+
+    fn to_json(self) -> String {
+        let fields: Vec<String> = Self::NAMES.iter().zip(self.values())
+            .map(|(name, value)| format!("\"{}\": {}", name, value.map_or("null".to_string(), |value| value.to_string())))
+            .collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+// The streaming systems of the game, headless: no window, renderer or physics. Materials are
+// never drawn, so the chunk entities get placeholder handles.
+fn soak_app(dimension: Dimension) -> App {
+    let mut voxel_world = world::World { dimension, save_dir: None, ..world::World::new(CHUNK_SIZE, DEFAULT_RENDER_DISTANCE) };
+    // Chunks unload as soon as they leave range, so the numbers don't depend on how many
+    // frames the machine gets through per second
+    voxel_world.unload_grace_period = 0.0;
+
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(SOAK_FRAME))
+        .insert_resource(voxel_world)
+        .insert_resource(dimension.generator(0))
+        .init_resource::<ChunkUpdateBudget>()
+        .init_resource::<ChunkPool>()
+        .init_resource::<ToonMode>()
+        .insert_resource(ChunkMaterials {
+            standard: Handle::default(),
+            outlined: Handle::default(),
+            cutout: Handle::default(),
+            translucent: Handle::default(),
+            water: Handle::default(),
+        })
+        .add_systems(Update, (
+            update_chunks,
+            prioritize_chunks,
+            process_chunk_queue,
+            handle_meshing_tasks,
+            world::fade_out_chunks,
+        ).chain());
+    app.finish();
+    app.cleanup();
+    app
+}
+
+fn loop_position(angle: f32) -> Vec3 {
+    Vec3::new(angle.cos() * SOAK_LOOP_RADIUS, SOAK_HEIGHT, angle.sin() * SOAK_LOOP_RADIUS)
+}
+
+// `voxelfun --soak [crossings]`: flies a simulated player around a large circle until it has
+// crossed `crossings` chunk borders, and checks that entity and mesh counts, memory and the
+// chunk bookkeeping of World stay bounded. Prints the peak of every lap as JSON and returns
+// false if a later lap grew past the baseline lap.
+pub fn run(crossings: usize, dimension: Dimension) -> bool {
+    let mut app = soak_app(dimension);
+    let start = loop_position(0.0);
+    let player = app.world_mut()
+        .spawn((Camera::default(), Transform::from_translation(start)))
+        .id();
+
+    let step = SOAK_SPEED * SOAK_FRAME.as_secs_f32() / SOAK_LOOP_RADIUS;
+    let chunk_of = |position: Vec3| (position / CHUNK_SIZE as f32).floor().as_ivec3();
+    let mut angle = 0.0;
+    let mut crossed = 0;
+    let mut last_chunk = chunk_of(start);
+    let mut laps: Vec<SoakSample> = vec![SoakSample::default()];
+
+    while crossed < crossings {
+        angle += step;
+        let position = loop_position(angle);
+        // Look along the path, like a player flying it would
+        let ahead = loop_position(angle + step) - position;
+        *app.world_mut().get_mut::<Transform>(player).unwrap() = Transform::from_translation(position).looking_to(ahead, Vec3::Y);
+        app.update();
+
+        let chunk = chunk_of(position);
+        if chunk != last_chunk {
+            crossed += 1;
+            last_chunk = chunk;
+        }
+
+        let lap = (angle / TAU) as usize;
+        if lap >= laps.len() {
+            println!("{{\"lap\": {}, \"crossings\": {}, \"peak\": {}}}", laps.len() - 1, crossed, laps[laps.len() - 1].to_json());
+            laps.push(SoakSample::default());
+        }
+        let sample = SoakSample::take(&app);
+        let current = laps.last_mut().unwrap();
+        *current = current.max(sample);
+    }
+    println!("{{\"lap\": {}, \"crossings\": {}, \"peak\": {}}}", laps.len() - 1, crossed, laps[laps.len() - 1].to_json());
+
+    // The last lap is usually cut short, so every full lap after the baseline is checked
+    let full_laps = &laps[..laps.len() - 1];
+    let Some(baseline) = full_laps.get(BASELINE_LAP) else {
+        println!("Too few crossings for a full lap after the baseline, nothing to compare");
+        return true;
+    };
+    let mut bounded = true;
+    for (lap, peak) in full_laps.iter().enumerate().skip(BASELINE_LAP + 1) {
+        for ((name, base), value) in SoakSample::NAMES.iter().zip(baseline.values()).zip(peak.values()) {
+            let (Some(base), Some(value)) = (base, value) else {
+                continue;
+            };
+            let limit = (base as f64 * (1.0 + GROWTH_TOLERANCE)) as u64 + GROWTH_SLACK;
+            if value > limit {
+                println!("Lap {}: {} grew to {}, baseline lap peaked at {}", lap, name, value, base);
+                bounded = false;
+            }
+        }
+    }
+    bounded
+}
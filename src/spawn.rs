@@ -0,0 +1,141 @@
+#[cfg(feature = "render")]
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use crate::camera_controller::CameraController;
+use crate::console::{Console, ConsoleCommand};
+use crate::dimension::Dimension;
+#[cfg(feature = "render")]
+use crate::game_rules::GameRules;
+#[cfg(feature = "render")]
+use crate::inventory::Inventory;
+use crate::player::{PlayerBody, EYE_HEIGHT};
+#[cfg(feature = "render")]
+use crate::player::{GameMode, Noclip};
+use crate::worldgen::WorldGenerator;
+use crate::worlds::WorldMeta;
+use crate::SPAWN_SEARCH_RADIUS;
+#[cfg(feature = "render")]
+use crate::VOID_Y;
+
+// Columns checked along each ring of the spawn search are this far apart
+const SPAWN_SEARCH_STEP: i32 = 4;
+// Where the player appears in dimensions without a surface to stand on
+const FALLBACK_SPAWN_EYE: Vec3 = Vec3::new(0.0, 2.0, 0.5);
+
+// Moves the player back to the world spawn and stops them, e.g. after `/spawn` or falling into the void
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct RespawnEvent;
+
+// The overworld cell a new world's player starts in: the first column on dry land in square
+// rings around the origin, checked every SPAWN_SEARCH_STEP voxels up to SPAWN_SEARCH_RADIUS.
+// A world that is all ocean that far out spawns above the water at the origin.
+pub fn find_spawn(generator: &WorldGenerator) -> IVec3 {
+    let sea_level = generator.config.sea_level;
+    for ring in 0..=SPAWN_SEARCH_RADIUS / SPAWN_SEARCH_STEP {
+        let extent = ring * SPAWN_SEARCH_STEP;
+        for x in (-extent..=extent).step_by(SPAWN_SEARCH_STEP as usize) {
+            for z in (-extent..=extent).step_by(SPAWN_SEARCH_STEP as usize) {
+                // Only the ring's border, the inside was searched already
+                if x.abs() != extent && z.abs() != extent {
+                    continue;
+                }
+                let ground = generator.ground_height(x, z);
+                if ground > sea_level {
+                    return IVec3::new(x, ground, z);
+                }
+            }
+        }
+    }
+    IVec3::new(0, generator.ground_height(0, 0).max(sea_level + 1), 0)
+}
+
+// Where the player's camera goes when spawning in `dimension`
+pub fn spawn_eye(meta: &WorldMeta, dimension: Dimension) -> Vec3 {
+    match (dimension, meta.spawn) {
+        (Dimension::Overworld, Some(spawn)) => spawn.as_vec3() + Vec3::new(0.5, EYE_HEIGHT, 0.5),
+        _ => FALLBACK_SPAWN_EYE,
+    }
+}
+
+// What falling into the void costs: everything in the inventory, unless keepInventory is on
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+pub struct DeathPenalty<'w> {
+    rules: Res<'w, GameRules>,
+    inventory: ResMut<'w, Inventory>,
+}
+
+#[cfg(feature = "render")]
+impl DeathPenalty<'_> {
+    fn apply(&mut self) {
+        if !self.rules.keep_inventory {
+            self.inventory.counts.clear();
+        }
+    }
+}
+
+// Puts the player back at spawn on a RespawnEvent, or when they fall below VOID_Y while walking
+#[cfg(feature = "render")]
+pub fn respawn_player(
+    mut respawns: EventReader<RespawnEvent>,
+    meta: Res<WorldMeta>,
+    dimension: Res<Dimension>,
+    game_mode: Res<GameMode>,
+    noclip: Res<Noclip>,
+    mut death_penalty: DeathPenalty,
+    mut player_query: Query<(&mut Transform, &mut PlayerBody, Option<&mut CameraController>)>,
+) {
+    let requested = respawns.read().count() > 0;
+    for (mut transform, mut body, controller) in &mut player_query {
+        let fell_out = *game_mode == GameMode::Walking && !noclip.enabled && transform.translation.y < VOID_Y;
+        if !requested && !fell_out {
+            continue;
+        }
+        if fell_out {
+            death_penalty.apply();
+        }
+        let eye = spawn_eye(&meta, *dimension);
+        transform.translation = eye;
+        body.vertical_velocity = 0.0;
+        body.last_position = Some(eye);
+        if let Some(mut controller) = controller {
+            controller.velocity = Vec3::ZERO;
+        }
+    }
+}
+
+// `spawn` takes the player back to the world spawn, `spawn set` moves the spawn to where they stand
+pub fn spawn_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut meta: ResMut<WorldMeta>,
+    dimension: Res<Dimension>,
+    player_query: Query<&Transform, With<PlayerBody>>,
+    mut respawns: EventWriter<RespawnEvent>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "spawn") {
+        match command.args.first().map(String::as_str) {
+            None => {
+                respawns.send(RespawnEvent);
+                console.print(format!("Back to spawn at {:?}", spawn_eye(&meta, *dimension).floor().as_ivec3()));
+            }
+            Some("set") => {
+                if *dimension != Dimension::Overworld {
+                    console.print("The spawn point can only be set in the overworld");
+                    continue;
+                }
+                let Ok(player) = player_query.get_single() else {
+                    continue;
+                };
+                let spawn = (player.translation - Vec3::Y * EYE_HEIGHT).floor().as_ivec3();
+                meta.spawn = Some(spawn);
+                match meta.save() {
+                    Ok(()) => console.print(format!("Spawn point set to {:?}", spawn)),
+                    Err(err) => console.print(format!("Could not save the spawn point: {}", err)),
+                }
+            }
+            Some(_) => console.print("Usage: spawn [set]"),
+        }
+    }
+}
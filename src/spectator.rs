@@ -0,0 +1,83 @@
+use bevy::prelude::*;
+use crate::blob_shadow::BlobShadow;
+use crate::camera_controller::CameraController;
+use crate::console::{Console, ConsoleCommand};
+use crate::player::PlayerBody;
+use crate::world::StreamingAnchor;
+use crate::PLAYER_SHADOW_RADIUS;
+
+// Spectator mode detaches the camera from the player. The player's body, its StreamingAnchor and
+// shadow move to a parked entity that stays where the player stood, so chunks keep streaming
+// around it, mobs keep chasing it and autosave keeps saving it, while the camera flies freely
+// through terrain. Leaving spectator mode takes the camera back to the parked body.
+#[derive(Resource, Default)]
+pub struct Spectator {
+    parked: Option<Entity>,
+}
+
+impl Spectator {
+    pub fn enabled(&self) -> bool {
+        self.parked.is_some()
+    }
+}
+
+#[derive(Component)]
+pub struct ParkedPlayer;
+
+// `spectator [on|off]`
+pub fn spectator_command(
+    mut commands: Commands,
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut spectator: ResMut<Spectator>,
+    mut camera_query: Query<(Entity, &mut Transform, &mut CameraController, Option<&mut PlayerBody>), Without<ParkedPlayer>>,
+    mut parked_query: Query<(&Transform, &mut PlayerBody), With<ParkedPlayer>>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "spectator") {
+        let enabled = match command.args.first().map(String::as_str) {
+            None => !spectator.enabled(),
+            Some("on" | "true") => true,
+            Some("off" | "false") => false,
+            Some(value) => {
+                console.print(format!("Expected on or off, got '{}'", value));
+                continue;
+            }
+        };
+        let Ok((camera, mut transform, mut controller, body)) = camera_query.get_single_mut() else {
+            continue;
+        };
+
+        match (enabled, spectator.parked) {
+            (true, None) => {
+                let Some(mut body) = body else {
+                    continue;
+                };
+                let parked = commands.spawn((
+                    ParkedPlayer,
+                    std::mem::take(&mut *body),
+                    StreamingAnchor::default(),
+                    BlobShadow { radius: PLAYER_SHADOW_RADIUS },
+                    TransformBundle::from_transform(*transform),
+                    Name::new("Parked player"),
+                )).id();
+                commands.entity(camera).remove::<(PlayerBody, StreamingAnchor, BlobShadow)>();
+                spectator.parked = Some(parked);
+            }
+            (false, Some(parked)) => {
+                if let Ok((parked_transform, mut body)) = parked_query.get_mut(parked) {
+                    *transform = *parked_transform;
+                    commands.entity(camera).insert((
+                        std::mem::take(&mut *body),
+                        StreamingAnchor::default(),
+                        BlobShadow { radius: PLAYER_SHADOW_RADIUS },
+                    ));
+                }
+                controller.velocity = Vec3::ZERO;
+                commands.entity(parked).despawn_recursive();
+                spectator.parked = None;
+            }
+            _ => {}
+        }
+        console.print(format!("Spectator mode {}", if enabled { "on" } else { "off" }));
+    }
+}
@@ -0,0 +1,612 @@
+use std::collections::HashMap;
+use crate::block::{BlockId, AIR};
+
+// Voxel container of a chunk. The representation is picked per chunk and follows its edits:
+// - Uniform: the whole chunk is one block (open sky, deep stone), no per-voxel data at all.
+// - Sparse: nearly everything is one background block and the few other voxels sit in a map,
+//   like a sky chunk with a tree top poking into it.
+// - Dense: paletted; every distinct block is stored once in a palette and voxels store a
+//   bit-packed index into it, so two block types need 1 bit per voxel, and so on. Indices
+//   never straddle two words, which keeps get/set to a single shift and mask.
+#[derive(Clone)]
+pub struct ChunkStorage {
+    voxels: Voxels,
+    len: usize,
+    // Word buffer of the last dense representation, kept so a chunk going dense again (or
+    // recycled through ChunkPool) doesn't reallocate it
+    spare: Vec<u64>,
+}
+
+#[derive(Clone)]
+enum Voxels {
+    Uniform(BlockId),
+    Sparse(SparseVoxels),
+    Dense(PackedVoxels),
+}
+
+#[derive(Clone)]
+struct SparseVoxels {
+    background: BlockId,
+    // Voxel index to block, for the voxels not holding `background`
+    blocks: HashMap<u32, BlockId>,
+}
+
+#[derive(Clone)]
+struct PackedVoxels {
+    palette: Vec<BlockId>,
+    // Voxels per palette entry. Entries down to zero are reused by the next new block.
+    counts: Vec<u32>,
+    bits_per_index: u32,
+    data: Vec<u64>,
+}
+
+// Sparse chunks turn dense once more than len / SPARSE_PROMOTE_DIVISOR voxels differ from the
+// background, dense ones turn sparse again at len / SPARSE_DEMOTE_DIVISOR. Around the first a
+// map entry costs about what a couple of bits per voxel do; the gap keeps single edits near
+// the limit from converting back and forth.
+const SPARSE_PROMOTE_DIVISOR: usize = 64;
+const SPARSE_DEMOTE_DIVISOR: usize = 256;
+
+const STORAGE_FORMAT_VERSION: u8 = 1;
+
+impl ChunkStorage {
+    pub fn new(len: usize) -> Self {
+        Self::filled(len, AIR)
+    }
+
+    pub fn filled(len: usize, block: BlockId) -> Self {
+        Self {
+            voxels: Voxels::Uniform(block),
+            len,
+            spare: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Distinct blocks in the chunk
+    pub fn palette(&self) -> Vec<BlockId> {
+        match &self.voxels {
+            Voxels::Uniform(block) => vec![*block],
+            Voxels::Sparse(sparse) => {
+                let mut others: Vec<BlockId> = sparse.blocks.values().copied().collect();
+                others.sort_unstable();
+                others.dedup();
+                std::iter::once(sparse.background).chain(others).collect()
+            }
+            Voxels::Dense(packed) => packed.palette.iter().zip(&packed.counts)
+                .filter(|&(_, &count)| count > 0)
+                .map(|(&block, _)| block)
+                .collect(),
+        }
+    }
+
+    // The block every voxel holds, if they all hold the same one
+    pub fn uniform_block(&self) -> Option<BlockId> {
+        match self.voxels {
+            Voxels::Uniform(block) => Some(block),
+            _ => None,
+        }
+    }
+
+    // Index width of the packed form, which is what to_bytes writes
+    pub fn bits_per_index(&self) -> u32 {
+        match &self.voxels {
+            Voxels::Dense(packed) => packed.bits_per_index,
+            _ => bits_for(self.palette().len()),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> BlockId {
+        match &self.voxels {
+            Voxels::Uniform(block) => *block,
+            Voxels::Sparse(sparse) => sparse.blocks.get(&(index as u32)).copied().unwrap_or(sparse.background),
+            Voxels::Dense(packed) => packed.palette[packed.read_index(index, self.len)],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, block: BlockId) {
+        if index >= self.len {
+            return;
+        }
+
+        match &mut self.voxels {
+            Voxels::Uniform(uniform) => {
+                if *uniform == block {
+                    return;
+                }
+                let background = *uniform;
+                self.voxels = Voxels::Sparse(SparseVoxels { background, blocks: HashMap::from([(index as u32, block)]) });
+            }
+            Voxels::Sparse(sparse) => {
+                if block == sparse.background {
+                    sparse.blocks.remove(&(index as u32));
+                } else {
+                    sparse.blocks.insert(index as u32, block);
+                }
+            }
+            Voxels::Dense(packed) => packed.set(index, block, self.len),
+        }
+        self.settle(block);
+    }
+
+    // Every voxel in index order, whatever the representation
+    pub fn iter(&self) -> impl Iterator<Item = BlockId> + '_ {
+        (0..self.len).map(|index| self.get(index))
+    }
+
+    // (index, block) of the voxels not holding `block`, in index order. Only touches those
+    // voxels when the chunk is uniform or sparse over `block`, so meshing a sky chunk skips the air.
+    pub fn iter_except(&self, block: BlockId) -> Box<dyn Iterator<Item = (usize, BlockId)> + '_> {
+        match &self.voxels {
+            Voxels::Uniform(uniform) if *uniform == block => Box::new(std::iter::empty()),
+            Voxels::Sparse(sparse) if sparse.background == block => {
+                let mut others: Vec<(usize, BlockId)> = sparse.blocks.iter().map(|(&index, &block)| (index as usize, block)).collect();
+                others.sort_unstable();
+                Box::new(others.into_iter())
+            }
+            _ => Box::new(self.iter().enumerate().filter(move |&(_, other)| other != block)),
+        }
+    }
+
+    pub fn fill(&mut self, block: BlockId) {
+        self.keep_spare();
+        self.voxels = Voxels::Uniform(block);
+    }
+
+    // Drops palette entries no voxel refers to anymore, shrinks the index width to match and
+    // moves to a sparse or uniform representation if the chunk now fits one
+    pub fn compact(&mut self) {
+        let Voxels::Dense(packed) = &mut self.voxels else {
+            return;
+        };
+        let blocks: Vec<BlockId> = (0..self.len).map(|index| packed.palette[packed.read_index(index, self.len)]).collect();
+        let data = std::mem::take(&mut packed.data);
+        self.voxels = Voxels::Dense(PackedVoxels::from_blocks(blocks.into_iter(), self.len, data));
+        if let Some(block) = self.dominant_block() {
+            self.settle(block);
+        }
+    }
+
+    pub fn heap_size(&self) -> usize {
+        let voxels = match &self.voxels {
+            Voxels::Uniform(_) => 0,
+            // One control byte per bucket on top of the entry
+            Voxels::Sparse(sparse) => sparse.blocks.capacity() * (std::mem::size_of::<(u32, BlockId)>() + 1),
+            Voxels::Dense(packed) => {
+                packed.palette.len() * std::mem::size_of::<BlockId>()
+                    + packed.counts.len() * std::mem::size_of::<u32>()
+                    + packed.data.len() * std::mem::size_of::<u64>()
+            }
+        };
+        voxels + self.spare.capacity() * std::mem::size_of::<u64>()
+    }
+
+    // Layout: version u8, voxel count u32, palette length u16, palette entries u16,
+    // bits per index u8, packed words u64. Everything little-endian. Every representation
+    // is written in the compacted packed form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let packed = PackedVoxels::from_blocks(self.iter(), self.len, Vec::new());
+
+        let mut bytes = Vec::with_capacity(8 + packed.palette.len() * 2 + packed.data.len() * 8);
+        bytes.push(STORAGE_FORMAT_VERSION);
+        bytes.extend_from_slice(&(self.len as u32).to_le_bytes());
+        bytes.extend_from_slice(&(packed.palette.len() as u16).to_le_bytes());
+        for block in &packed.palette {
+            bytes.extend_from_slice(&block.to_le_bytes());
+        }
+        bytes.push(packed.bits_per_index as u8);
+        for word in &packed.data {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    // `expected_len` is the chunk's voxel count; bytes claiming any other length are rejected
+    // before anything is allocated for them
+    pub fn from_bytes(bytes: &[u8], expected_len: usize) -> Option<Self> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.read_u8()? != STORAGE_FORMAT_VERSION {
+            return None;
+        }
+
+        let len = reader.read_u32()? as usize;
+        if len != expected_len {
+            return None;
+        }
+        let palette_len = reader.read_u16()? as usize;
+        if palette_len == 0 {
+            return None;
+        }
+
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            palette.push(reader.read_u16()?);
+        }
+
+        let bits_per_index = reader.read_u8()? as u32;
+        if bits_per_index > 16 || bits_for(palette_len) > bits_per_index {
+            return None;
+        }
+
+        let word_count = word_count(len, bits_per_index);
+        let mut data = Vec::with_capacity(word_count);
+        for _ in 0..word_count {
+            data.push(reader.read_u64()?);
+        }
+
+        let mut packed = PackedVoxels { counts: vec![0; palette.len()], palette, bits_per_index, data };
+        for index in 0..len {
+            let palette_index = packed.read_index(index, len);
+            *packed.counts.get_mut(palette_index)? += 1;
+        }
+
+        let mut storage = Self { voxels: Voxels::Dense(packed), len, spare: Vec::new() };
+        if let Some(block) = storage.dominant_block() {
+            storage.settle(block);
+        }
+        Some(storage)
+    }
+
+    // Replaces every voxel's block with `remap(block)`, like translating a save's block IDs
+    pub fn remap(&mut self, remap: impl Fn(BlockId) -> BlockId) {
+        if let Voxels::Uniform(block) = &mut self.voxels {
+            *block = remap(*block);
+            return;
+        }
+        let blocks: Vec<BlockId> = self.iter().map(remap).collect();
+        self.keep_spare();
+        let data = std::mem::take(&mut self.spare);
+        self.voxels = Voxels::Dense(PackedVoxels::from_blocks(blocks.into_iter(), self.len, data));
+        if let Some(block) = self.dominant_block() {
+            self.settle(block);
+        }
+    }
+
+    // Run-length form of the same data, far smaller for layered terrain where long stretches of
+    // voxels repeat. Layout: version u8, voxel count u32, palette length u16, palette entries u16,
+    // run count u32, then (palette index u16, length u16) per run in voxel order.
+    pub fn to_run_bytes(&self) -> Vec<u8> {
+        let mut palette: Vec<BlockId> = Vec::new();
+        let mut runs: Vec<(u16, u16)> = Vec::new();
+        for block in self.iter() {
+            let palette_index = palette.iter().position(|&b| b == block).unwrap_or_else(|| {
+                palette.push(block);
+                palette.len() - 1
+            }) as u16;
+            match runs.last_mut() {
+                Some((last, length)) if *last == palette_index && *length < u16::MAX => *length += 1,
+                _ => runs.push((palette_index, 1)),
+            }
+        }
+        if palette.is_empty() {
+            palette.push(AIR);
+        }
+
+        let mut bytes = Vec::with_capacity(11 + palette.len() * 2 + runs.len() * 4);
+        bytes.push(STORAGE_FORMAT_VERSION);
+        bytes.extend_from_slice(&(self.len as u32).to_le_bytes());
+        bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for block in &palette {
+            bytes.extend_from_slice(&block.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (palette_index, length) in runs {
+            bytes.extend_from_slice(&palette_index.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        bytes
+    }
+
+    // Same `expected_len` check as from_bytes
+    pub fn from_run_bytes(bytes: &[u8], expected_len: usize) -> Option<Self> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.read_u8()? != STORAGE_FORMAT_VERSION {
+            return None;
+        }
+
+        let len = reader.read_u32()? as usize;
+        if len != expected_len {
+            return None;
+        }
+        let palette_len = reader.read_u16()? as usize;
+        if palette_len == 0 {
+            return None;
+        }
+
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            palette.push(reader.read_u16()?);
+        }
+
+        let run_count = reader.read_u32()? as usize;
+        let mut blocks = Vec::with_capacity(len);
+        for _ in 0..run_count {
+            let block = *palette.get(reader.read_u16()? as usize)?;
+            let length = reader.read_u16()? as usize;
+            if blocks.len() + length > len {
+                return None;
+            }
+            blocks.extend(std::iter::repeat_n(block, length));
+        }
+        if blocks.len() != len {
+            return None;
+        }
+
+        let packed = PackedVoxels::from_blocks(blocks.into_iter(), len, Vec::new());
+        let mut storage = Self { voxels: Voxels::Dense(packed), len, spare: Vec::new() };
+        if let Some(block) = storage.dominant_block() {
+            storage.settle(block);
+        }
+        Some(storage)
+    }
+
+    // Moves to the representation that fits the chunk after `written` was stored
+    fn settle(&mut self, written: BlockId) {
+        match &self.voxels {
+            Voxels::Sparse(sparse) if sparse.blocks.is_empty() => {
+                self.voxels = Voxels::Uniform(sparse.background);
+            }
+            Voxels::Sparse(sparse) if sparse.blocks.len() > self.len / SPARSE_PROMOTE_DIVISOR => {
+                let blocks: Vec<BlockId> = self.iter().collect();
+                let data = std::mem::take(&mut self.spare);
+                self.voxels = Voxels::Dense(PackedVoxels::from_blocks(blocks.into_iter(), self.len, data));
+            }
+            // Only the written block gained voxels, so it's the only one that can newly dominate
+            Voxels::Dense(packed) if packed.count(written) + self.len / SPARSE_DEMOTE_DIVISOR >= self.len => {
+                let blocks: HashMap<u32, BlockId> = self.iter().enumerate()
+                    .filter(|&(_, block)| block != written)
+                    .map(|(index, block)| (index as u32, block))
+                    .collect();
+                self.keep_spare();
+                self.voxels = if blocks.is_empty() {
+                    Voxels::Uniform(written)
+                } else {
+                    Voxels::Sparse(SparseVoxels { background: written, blocks })
+                };
+            }
+            _ => {}
+        }
+    }
+
+    // The block most voxels of a dense chunk hold
+    fn dominant_block(&self) -> Option<BlockId> {
+        let Voxels::Dense(packed) = &self.voxels else {
+            return None;
+        };
+        packed.palette.iter().zip(&packed.counts).max_by_key(|&(_, &count)| count).map(|(&block, _)| block)
+    }
+
+    fn keep_spare(&mut self) {
+        if let Voxels::Dense(packed) = &mut self.voxels {
+            self.spare = std::mem::take(&mut packed.data);
+        }
+    }
+}
+
+impl PackedVoxels {
+    // Packs `len` blocks with the smallest palette and index width that hold them, into `data`'s allocation
+    fn from_blocks(blocks: impl Iterator<Item = BlockId>, len: usize, data: Vec<u64>) -> Self {
+        let mut palette = Vec::new();
+        let mut counts = Vec::new();
+        let indices: Vec<usize> = blocks.map(|block| {
+            let palette_index = palette.iter().position(|&b| b == block).unwrap_or_else(|| {
+                palette.push(block);
+                counts.push(0);
+                palette.len() - 1
+            });
+            counts[palette_index] += 1;
+            palette_index
+        }).collect();
+        if palette.is_empty() {
+            palette.push(AIR);
+            counts.push(0);
+        }
+
+        let mut packed = Self { palette, counts, bits_per_index: 0, data };
+        packed.repack_from(bits_for(packed.palette.len()), len, indices.into_iter());
+        packed
+    }
+
+    fn count(&self, block: BlockId) -> usize {
+        self.palette.iter().position(|&b| b == block).map_or(0, |palette_index| self.counts[palette_index] as usize)
+    }
+
+    fn set(&mut self, index: usize, block: BlockId, len: usize) {
+        let old = self.read_index(index, len);
+        if self.palette[old] == block {
+            return;
+        }
+        self.counts[old] -= 1;
+
+        let palette_index = match self.palette.iter().position(|&b| b == block) {
+            Some(palette_index) => palette_index,
+            None => match self.counts.iter().position(|&count| count == 0) {
+                Some(unused) => {
+                    self.palette[unused] = block;
+                    unused
+                }
+                None => {
+                    self.palette.push(block);
+                    self.counts.push(0);
+                    let needed = bits_for(self.palette.len());
+                    if needed > self.bits_per_index {
+                        self.repack(needed, len);
+                    }
+                    self.palette.len() - 1
+                }
+            },
+        };
+
+        self.counts[palette_index] += 1;
+        self.write_index(index, palette_index);
+    }
+
+    fn read_index(&self, index: usize, len: usize) -> usize {
+        if self.bits_per_index == 0 || index >= len {
+            return 0;
+        }
+
+        let per_word = 64 / self.bits_per_index as usize;
+        let shift = (index % per_word) as u32 * self.bits_per_index;
+        let mask = (1u64 << self.bits_per_index) - 1;
+        ((self.data[index / per_word] >> shift) & mask) as usize
+    }
+
+    fn write_index(&mut self, index: usize, palette_index: usize) {
+        if self.bits_per_index == 0 {
+            return;
+        }
+
+        let per_word = 64 / self.bits_per_index as usize;
+        let shift = (index % per_word) as u32 * self.bits_per_index;
+        let mask = ((1u64 << self.bits_per_index) - 1) << shift;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !mask) | (((palette_index as u64) << shift) & mask);
+    }
+
+    fn repack(&mut self, bits_per_index: u32, len: usize) {
+        let indices: Vec<usize> = (0..len).map(|i| self.read_index(i, len)).collect();
+        self.repack_from(bits_per_index, len, indices.into_iter());
+    }
+
+    // Reuses the allocation of `data`, which a chunk recycled by ChunkPool brings along
+    fn repack_from(&mut self, bits_per_index: u32, len: usize, indices: impl Iterator<Item = usize>) {
+        self.bits_per_index = bits_per_index;
+        self.data.clear();
+        self.data.resize(word_count(len, bits_per_index), 0);
+        for (i, palette_index) in indices.enumerate() {
+            self.write_index(i, palette_index);
+        }
+    }
+}
+
+fn bits_for(palette_len: usize) -> u32 {
+    if palette_len <= 1 {
+        0
+    } else {
+        usize::BITS - (palette_len - 1).leading_zeros()
+    }
+}
+
+fn word_count(len: usize, bits_per_index: u32) -> usize {
+    if bits_per_index == 0 {
+        0
+    } else {
+        len.div_ceil(64 / bits_per_index as usize)
+    }
+}
+
+// Little-endian cursor over a byte slice, shared with the on-disk chunk format
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.offset)
+    }
+
+    pub fn take_slice(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(slice)
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let slice = self.bytes.get(self.offset..self.offset + N)?;
+        self.offset += N;
+        slice.try_into().ok()
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        Some(self.take::<1>()?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take()?))
+    }
+
+    pub fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn read_i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.take()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{DIRT, GRASS, LOG, STONE};
+
+    const LEN: usize = 16 * 16 * 16;
+
+    #[test]
+    fn compact_after_removing_blocks_keeps_the_rest() {
+        let mut storage = ChunkStorage::new(LEN);
+        for index in 0..LEN {
+            storage.set(index, [STONE, DIRT, GRASS, LOG][index % 4]);
+        }
+        // The air the chunk started as keeps its entry too
+        assert_eq!(storage.bits_per_index(), 3);
+
+        // Dig out the dirt and grass; their palette entries stay behind until compaction
+        for index in (0..LEN).filter(|index| index % 4 == 1 || index % 4 == 2) {
+            storage.set(index, AIR);
+        }
+        let expected: Vec<BlockId> = storage.iter().collect();
+        storage.compact();
+
+        assert!(storage.iter().eq(expected.iter().copied()));
+        let mut palette = storage.palette();
+        palette.sort_unstable();
+        assert_eq!(palette, vec![AIR, STONE, LOG]);
+        assert_eq!(storage.bits_per_index(), 2);
+
+        let read_back = ChunkStorage::from_bytes(&storage.to_bytes(), LEN).expect("compacted bytes decode");
+        assert!(read_back.iter().eq(expected.iter().copied()));
+    }
+
+    #[test]
+    fn compact_settles_a_mostly_removed_chunk() {
+        let mut storage = ChunkStorage::new(LEN);
+        for index in 0..LEN {
+            storage.set(index, [STONE, DIRT][index % 2]);
+        }
+        for index in 2..LEN {
+            storage.set(index, STONE);
+        }
+        storage.compact();
+
+        assert_eq!(storage.palette(), vec![STONE, DIRT]);
+        assert_eq!(storage.get(1), DIRT);
+        assert!(storage.iter().enumerate().all(|(index, block)| block == if index == 1 { DIRT } else { STONE }));
+
+        storage.set(1, STONE);
+        storage.compact();
+        assert_eq!(storage.uniform_block(), Some(STONE));
+    }
+}
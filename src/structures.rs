@@ -0,0 +1,338 @@
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+use crate::console::{Console, ConsoleCommand};
+use crate::player::PlayerBody;
+use crate::world::World;
+use crate::block::{BlockId, AIR, CHEST, DIRT, GLASS, GRASS, LOG, STONE_BRICKS, WATER};
+use crate::decoration::ChunkRng;
+use crate::biome::ColumnSampler;
+use crate::terrain::Chunk;
+use crate::worldgen::{mix_seed, GenContext, OverflowBlock, WorldGenConfig, WorldGenerator, STRUCTURE_STAGE};
+
+const STRUCTURE_SEED: u32 = 0x57c7_0a11;
+// Structures are planned per square region of this many chunks, at most one per region
+const REGION_CHUNKS: i32 = 8;
+const VILLAGE_CHANCE: f32 = 0.25;
+const RUIN_CHANCE: f32 = 0.3;
+// Keeps a structure's centre this far from its region's border
+const REGION_MARGIN: i32 = 24;
+// Air cleared above flattened ground so hillsides don't cut into buildings
+const CLEARANCE: i32 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructureKind {
+    Village,
+    Ruin,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PieceKind {
+    House,
+    Well,
+    Ruin,
+}
+
+// One prefab of a structure; `min` is its lowest corner at floor level
+#[derive(Clone, Copy, Debug)]
+struct Piece {
+    kind: PieceKind,
+    min: IVec3,
+    size: IVec3,
+    // Side of the footprint the door is on, towards the village centre
+    door: IVec3,
+}
+
+// Everything a region's structure consists of, decided from the seed and region coordinates
+// alone so every chunk agrees on it regardless of generation order
+#[derive(Clone, Debug)]
+pub struct StructurePlan {
+    pub kind: StructureKind,
+    // Centre column at floor level; the ground is flattened to just below it
+    pub anchor: IVec3,
+    pieces: Vec<Piece>,
+    paths: Vec<IVec3>,
+    world_seed: u32,
+}
+
+impl StructurePlan {
+    // The chunk holding the ground block under the anchor builds the whole structure;
+    // whatever lies in other chunks goes through the pending-structure queue
+    pub fn anchor_chunk(&self, chunk_size: i32) -> (i32, i32, i32) {
+        let ground = (self.anchor - IVec3::Y).div_euclid(IVec3::splat(chunk_size));
+        (ground.x, ground.y, ground.z)
+    }
+
+    // Every block of the structure in placement order: flattening first, then the pieces.
+    // All of them replace what is there.
+    pub fn blocks(&self) -> Vec<OverflowBlock> {
+        let columns = ColumnSampler::new(self.world_seed);
+        let mut builder = Builder { blocks: Vec::new() };
+        let floor_y = self.anchor.y;
+
+        for piece in &self.pieces {
+            for x in piece.min.x - 1..=piece.min.x + piece.size.x {
+                for z in piece.min.z - 1..=piece.min.z + piece.size.z {
+                    builder.flatten_column(&columns, x, z, floor_y, CLEARANCE);
+                }
+            }
+        }
+        for &path in &self.paths {
+            builder.flatten_column(&columns, path.x, path.z, floor_y, 3);
+            builder.set(path - IVec3::Y, DIRT);
+        }
+
+        for piece in &self.pieces {
+            match piece.kind {
+                PieceKind::House => builder.house(piece),
+                PieceKind::Well => builder.well(piece),
+                PieceKind::Ruin => builder.ruin(piece, &mut ChunkRng::for_chunk((piece.min.x, piece.min.y, piece.min.z), mix_seed(STRUCTURE_SEED, self.world_seed))),
+            }
+        }
+        builder.blocks
+    }
+}
+
+// Decides whether region (rx, rz) holds a structure, and lays it out
+pub fn plan_region(region: (i32, i32), chunk_size: usize, config: &WorldGenConfig) -> Option<StructurePlan> {
+    let mut rng = ChunkRng::for_chunk((region.0, 0, region.1), mix_seed(STRUCTURE_SEED, config.seed));
+    let roll = rng.next_f32();
+    let kind = if roll < VILLAGE_CHANCE {
+        StructureKind::Village
+    } else if roll < VILLAGE_CHANCE + RUIN_CHANCE {
+        StructureKind::Ruin
+    } else {
+        return None;
+    };
+
+    let region_size = REGION_CHUNKS * chunk_size as i32;
+    let x = region.0 * region_size + rng.range(REGION_MARGIN, region_size - REGION_MARGIN - 1);
+    let z = region.1 * region_size + rng.range(REGION_MARGIN, region_size - REGION_MARGIN - 1);
+    let ground = ColumnSampler::new(config.seed).height(x, z) as i32;
+    // Nothing gets built on the sea floor
+    if ground <= config.sea_level {
+        return None;
+    }
+    let anchor = IVec3::new(x, ground, z);
+
+    let mut pieces = Vec::new();
+    let mut paths = Vec::new();
+    match kind {
+        StructureKind::Village => {
+            pieces.push(Piece { kind: PieceKind::Well, min: anchor - IVec3::new(1, 0, 1), size: IVec3::new(3, 4, 3), door: IVec3::ZERO });
+            let houses = rng.range(3, 5);
+            let start_angle = rng.next_f32() * TAU;
+            for i in 0..houses {
+                let angle = start_angle + i as f32 / houses as f32 * TAU;
+                let distance = rng.range(9, 12) as f32;
+                let centre = anchor + IVec3::new((angle.cos() * distance).round() as i32, 0, (angle.sin() * distance).round() as i32);
+                let size = IVec3::new(rng.range(5, 7), 5, rng.range(5, 7));
+                // Door on the side facing the well
+                let to_well = (anchor - centre).as_vec3();
+                let door = if to_well.x.abs() > to_well.z.abs() {
+                    IVec3::X * to_well.x.signum() as i32
+                } else {
+                    IVec3::Z * to_well.z.signum() as i32
+                };
+                pieces.push(Piece { kind: PieceKind::House, min: centre - IVec3::new(size.x / 2, 0, size.z / 2), size, door });
+                paths.extend(path_between(anchor, centre));
+            }
+        }
+        StructureKind::Ruin => {
+            let size = IVec3::new(rng.range(6, 9), 4, rng.range(6, 9));
+            pieces.push(Piece { kind: PieceKind::Ruin, min: anchor - IVec3::new(size.x / 2, 0, size.z / 2), size, door: IVec3::ZERO });
+        }
+    }
+
+    Some(StructurePlan { kind, anchor, pieces, paths, world_seed: config.seed })
+}
+
+// Straight line of floor-level columns, x first then z
+fn path_between(from: IVec3, to: IVec3) -> Vec<IVec3> {
+    let mut path = Vec::new();
+    let mut pos = from;
+    while pos.x != to.x {
+        pos.x += (to.x - pos.x).signum();
+        path.push(pos);
+    }
+    while pos.z != to.z {
+        pos.z += (to.z - pos.z).signum();
+        path.push(pos);
+    }
+    path
+}
+
+struct Builder {
+    blocks: Vec<OverflowBlock>,
+}
+
+impl Builder {
+    fn set(&mut self, pos: IVec3, block: BlockId) {
+        self.blocks.push(OverflowBlock { pos, block, replace: true });
+    }
+
+    // Raises or cuts the column so its top block sits right under `floor_y`, then clears above
+    fn flatten_column(&mut self, columns: &ColumnSampler, x: i32, z: i32, floor_y: i32, clearance: i32) {
+        let ground = columns.height(x, z) as i32;
+        for y in ground.min(floor_y - 3)..floor_y - 1 {
+            self.set(IVec3::new(x, y, z), DIRT);
+        }
+        self.set(IVec3::new(x, floor_y - 1, z), GRASS);
+        for y in floor_y..floor_y + clearance {
+            self.set(IVec3::new(x, y, z), AIR);
+        }
+    }
+
+    fn house(&mut self, piece: &Piece) {
+        let (min, size) = (piece.min, piece.size);
+        let max = min + size - IVec3::ONE;
+        let wall_top = min.y + 2;
+        let centre = min + IVec3::new(size.x / 2, 0, size.z / 2);
+        let door = IVec3::new(
+            if piece.door.x > 0 { max.x } else if piece.door.x < 0 { min.x } else { centre.x },
+            min.y,
+            if piece.door.z > 0 { max.z } else if piece.door.z < 0 { min.z } else { centre.z },
+        );
+
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                self.set(IVec3::new(x, min.y - 1, z), STONE_BRICKS);
+                let edge_x = x == min.x || x == max.x;
+                let edge_z = z == min.z || z == max.z;
+                if !edge_x && !edge_z {
+                    continue;
+                }
+                for y in min.y..=wall_top {
+                    let pos = IVec3::new(x, y, z);
+                    let block = if edge_x && edge_z {
+                        LOG
+                    } else if pos.x == door.x && pos.z == door.z && y < wall_top {
+                        AIR
+                    } else if y == min.y + 1 && (x == centre.x || z == centre.z) {
+                        GLASS
+                    } else {
+                        STONE_BRICKS
+                    };
+                    self.set(pos, block);
+                }
+            }
+        }
+
+        // Two-step roof
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                self.set(IVec3::new(x, wall_top + 1, z), LOG);
+                if x > min.x && x < max.x && z > min.z && z < max.z {
+                    self.set(IVec3::new(x, wall_top + 2, z), LOG);
+                }
+            }
+        }
+        // Corner opposite the door
+        let chest = IVec3::new(
+            if door.x == min.x { max.x - 1 } else { min.x + 1 },
+            min.y,
+            if door.z == min.z { max.z - 1 } else { min.z + 1 },
+        );
+        self.set(chest, CHEST);
+    }
+
+    fn well(&mut self, piece: &Piece) {
+        let min = piece.min;
+        let max = min + piece.size - IVec3::ONE;
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                let pos = IVec3::new(x, min.y, z);
+                let edge = x == min.x || x == max.x || z == min.z || z == max.z;
+                for depth in 1..=3 {
+                    self.set(pos - IVec3::Y * depth, if edge { STONE_BRICKS } else { WATER });
+                }
+                self.set(pos - IVec3::Y * 4, STONE_BRICKS);
+                if edge {
+                    self.set(pos, STONE_BRICKS);
+                }
+                // Corner posts carrying a small roof
+                if (x == min.x || x == max.x) && (z == min.z || z == max.z) {
+                    self.set(pos + IVec3::Y, LOG);
+                    self.set(pos + IVec3::Y * 2, LOG);
+                }
+                self.set(pos + IVec3::Y * 3, STONE_BRICKS);
+            }
+        }
+    }
+
+    // Broken walls of uneven height, a patchy floor and a chest left behind
+    fn ruin(&mut self, piece: &Piece, rng: &mut ChunkRng) {
+        let min = piece.min;
+        let max = min + piece.size - IVec3::ONE;
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                if rng.next_f32() < 0.7 {
+                    self.set(IVec3::new(x, min.y - 1, z), STONE_BRICKS);
+                }
+                if x != min.x && x != max.x && z != min.z && z != max.z {
+                    continue;
+                }
+                let height = rng.range(0, piece.size.y);
+                for y in min.y..min.y + height {
+                    if rng.next_f32() < 0.85 {
+                        self.set(IVec3::new(x, y, z), STONE_BRICKS);
+                    }
+                }
+            }
+        }
+        self.set(IVec3::new(min.x + 1 + rng.range(0, piece.size.x - 3), min.y, min.z + 1 + rng.range(0, piece.size.z - 3)), CHEST);
+    }
+}
+
+// Builds the structure of this chunk's region if this is its anchor chunk
+pub fn generate_structures(chunk: &mut Chunk, context: &mut GenContext) {
+    let size = chunk.width as i32;
+    let (cx, cy, cz) = context.chunk_key;
+    let region = (cx.div_euclid(REGION_CHUNKS), cz.div_euclid(REGION_CHUNKS));
+    let Some(plan) = plan_region(region, chunk.width, context.config) else {
+        return;
+    };
+    if plan.anchor_chunk(size) != context.chunk_key {
+        return;
+    }
+
+    let origin = IVec3::new(cx, cy, cz) * size;
+    for block in plan.blocks() {
+        let local = block.pos - origin;
+        if local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(size)).all() {
+            chunk.set_block(local.x as usize, local.y as usize, local.z as usize, block.block);
+        } else {
+            context.overflow.push(block);
+        }
+    }
+}
+
+// `locate`: nearest planned village or ruin within a few regions of the player
+pub fn locate_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    generator: Res<WorldGenerator>,
+    world: Res<World>,
+    player_query: Query<&Transform, With<PlayerBody>>,
+) {
+    for _ in console_commands.read().filter(|command| command.name == "locate") {
+        if !generator.stage_names().any(|name| name == STRUCTURE_STAGE) {
+            console.print("This world has no structures");
+            continue;
+        }
+        let Ok(transform) = player_query.get_single() else {
+            continue;
+        };
+        let position = transform.translation.floor().as_ivec3();
+        let region_size = REGION_CHUNKS * world.chunk_size as i32;
+        let (rx, rz) = (position.x.div_euclid(region_size), position.z.div_euclid(region_size));
+
+        let nearest = (-3..=3)
+            .flat_map(|dx| (-3..=3).map(move |dz| (rx + dx, rz + dz)))
+            .filter_map(|region| plan_region(region, world.chunk_size, &generator.config))
+            .min_by_key(|plan| (plan.anchor - position).with_y(0).length_squared());
+        match nearest {
+            Some(plan) => console.print(format!("{:?} at {} {} {}", plan.kind, plan.anchor.x, plan.anchor.y, plan.anchor.z)),
+            None => console.print("No structures nearby"),
+        }
+    }
+}
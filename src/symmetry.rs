@@ -0,0 +1,120 @@
+use bevy::prelude::*;
+use crate::selection::TargetedBlock;
+use crate::keybindings::{Action, Actions};
+use crate::notifications::NotificationEvent;
+use crate::theme::Theme;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymmetryMode {
+    #[default]
+    Off,
+    // Mirror across the plane x = origin.x
+    MirrorX,
+    // Mirror across the plane z = origin.z
+    MirrorZ,
+    // Both planes, four copies
+    MirrorXZ,
+    // Four copies rotated 90 degrees apart around the vertical axis through the origin
+    Radial4,
+}
+
+impl SymmetryMode {
+    pub fn next(self) -> Self {
+        match self {
+            SymmetryMode::Off => SymmetryMode::MirrorX,
+            SymmetryMode::MirrorX => SymmetryMode::MirrorZ,
+            SymmetryMode::MirrorZ => SymmetryMode::MirrorXZ,
+            SymmetryMode::MirrorXZ => SymmetryMode::Radial4,
+            SymmetryMode::Radial4 => SymmetryMode::Off,
+        }
+    }
+}
+
+// Build assist that repeats every placement and removal at its symmetric positions.
+// Planes and the radial axis pass through the centre of the origin voxel.
+#[derive(Resource, Default)]
+pub struct Symmetry {
+    pub mode: SymmetryMode,
+    pub origin: IVec3,
+}
+
+impl Symmetry {
+    // Every voxel an edit at `pos` applies to, `pos` first, without duplicates
+    pub fn images(&self, pos: IVec3) -> Vec<IVec3> {
+        let d = pos - self.origin;
+        let mirror_x = IVec3::new(self.origin.x - d.x, pos.y, pos.z);
+        let mirror_z = IVec3::new(pos.x, pos.y, self.origin.z - d.z);
+
+        let candidates = match self.mode {
+            SymmetryMode::Off => vec![pos],
+            SymmetryMode::MirrorX => vec![pos, mirror_x],
+            SymmetryMode::MirrorZ => vec![pos, mirror_z],
+            SymmetryMode::MirrorXZ => vec![pos, mirror_x, mirror_z, IVec3::new(mirror_x.x, pos.y, mirror_z.z)],
+            SymmetryMode::Radial4 => vec![
+                pos,
+                self.origin + IVec3::new(-d.z, d.y, d.x),
+                self.origin + IVec3::new(-d.x, d.y, -d.z),
+                self.origin + IVec3::new(d.z, d.y, -d.x),
+            ],
+        };
+
+        let mut images = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if !images.contains(&candidate) {
+                images.push(candidate);
+            }
+        }
+        images
+    }
+}
+
+// Symmetry cycles the mode, SymmetryOrigin moves the origin to the targeted voxel
+pub fn configure_symmetry(
+    actions: Actions,
+    target: Res<TargetedBlock>,
+    mut symmetry: ResMut<Symmetry>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if actions.just_pressed(Action::Symmetry) {
+        symmetry.mode = symmetry.mode.next();
+        notifications.send(NotificationEvent::info(format!("Symmetry {:?} around {:?}", symmetry.mode, symmetry.origin)));
+        return;
+    }
+    if !actions.just_pressed(Action::SymmetryOrigin) {
+        return;
+    }
+
+    if let Some(target) = target.voxel {
+        symmetry.origin = target;
+        println!("Symmetry origin set to {:?}", target);
+    }
+}
+
+pub fn draw_symmetry_guides(
+    symmetry: Res<Symmetry>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    const GUIDE_EXTENT: f32 = 32.0;
+    let center = symmetry.origin.as_vec3() + Vec3::splat(0.5);
+    let half_size = Vec2::splat(GUIDE_EXTENT);
+
+    match symmetry.mode {
+        SymmetryMode::Off => return,
+        SymmetryMode::MirrorX => {
+            gizmos.rect(center, Quat::from_rotation_y(std::f32::consts::FRAC_PI_2), half_size * 2.0, theme.debug_primary);
+        }
+        SymmetryMode::MirrorZ => {
+            gizmos.rect(center, Quat::IDENTITY, half_size * 2.0, theme.debug_primary);
+        }
+        SymmetryMode::MirrorXZ => {
+            gizmos.rect(center, Quat::from_rotation_y(std::f32::consts::FRAC_PI_2), half_size * 2.0, theme.debug_primary);
+            gizmos.rect(center, Quat::IDENTITY, half_size * 2.0, theme.debug_primary);
+        }
+        SymmetryMode::Radial4 => {
+            gizmos.line(center - Vec3::Y * GUIDE_EXTENT, center + Vec3::Y * GUIDE_EXTENT, theme.debug_primary);
+        }
+    }
+
+    gizmos.cuboid(Transform::from_translation(center).with_scale(Vec3::splat(1.04)), theme.debug_primary);
+}
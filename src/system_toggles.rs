@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+use crate::console::{Console, ConsoleCommand};
+
+// Runtime switches for the major subsystems, for narrowing down which one costs the frame time.
+// `/system` lists them, `/system <name> [on|off]` shows or flips one. Not saved.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct SystemToggles {
+    pub streaming: bool,
+    pub meshing: bool,
+    pub lighting: bool,
+    pub weather: bool,
+    pub seasons: bool,
+    pub item_drops: bool,
+    pub shadows: bool,
+    pub particles: bool,
+    pub block_updates: bool,
+}
+
+impl Default for SystemToggles {
+    fn default() -> Self {
+        Self {
+            streaming: true,
+            meshing: true,
+            lighting: true,
+            weather: true,
+            seasons: true,
+            item_drops: true,
+            shadows: true,
+            particles: true,
+            block_updates: true,
+        }
+    }
+}
+
+impl SystemToggles {
+    pub const NAMES: [&'static str; 9] = ["streaming", "meshing", "lighting", "weather", "seasons", "itemDrops", "shadows", "particles", "blockUpdates"];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "streaming" => Some(self.streaming),
+            "meshing" => Some(self.meshing),
+            "lighting" => Some(self.lighting),
+            "weather" => Some(self.weather),
+            "seasons" => Some(self.seasons),
+            "itemDrops" => Some(self.item_drops),
+            "shadows" => Some(self.shadows),
+            "particles" => Some(self.particles),
+            "blockUpdates" => Some(self.block_updates),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "streaming" => Some(&mut self.streaming),
+            "meshing" => Some(&mut self.meshing),
+            "lighting" => Some(&mut self.lighting),
+            "weather" => Some(&mut self.weather),
+            "seasons" => Some(&mut self.seasons),
+            "itemDrops" => Some(&mut self.item_drops),
+            "shadows" => Some(&mut self.shadows),
+            "particles" => Some(&mut self.particles),
+            "blockUpdates" => Some(&mut self.block_updates),
+            _ => None,
+        }
+    }
+
+    // Names of the switched off systems, for the debug overlay
+    pub fn disabled(&self) -> Vec<&'static str> {
+        Self::NAMES.into_iter().filter(|name| !self.get(name).unwrap()).collect()
+    }
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value {
+        "on" | "true" => Some(true),
+        "off" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn system_toggle_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut toggles: ResMut<SystemToggles>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "system") {
+        let Some(name) = command.args.first() else {
+            for name in SystemToggles::NAMES {
+                console.print(format!("{} {}", name, if toggles.get(name).unwrap() { "on" } else { "off" }));
+            }
+            continue;
+        };
+        let Some(current) = toggles.get(name) else {
+            console.print(format!("Unknown system '{}', expected one of {}", name, SystemToggles::NAMES.join(", ")));
+            continue;
+        };
+
+        // A bare name flips the switch
+        let value = match command.args.get(1) {
+            None => Some(!current),
+            Some(value) => parse_switch(value),
+        };
+        match value {
+            Some(value) => {
+                *toggles.get_mut(name).unwrap() = value;
+                console.print(format!("{} {}", name, if value { "on" } else { "off" }));
+            }
+            None => console.print(format!("Expected on or off, got '{}'", command.args[1])),
+        }
+    }
+}
+
+// Run conditions for the systems behind each switch
+pub fn streaming_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.streaming
+}
+
+pub fn meshing_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.meshing
+}
+
+pub fn lighting_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.lighting
+}
+
+pub fn weather_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.weather
+}
+
+pub fn seasons_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.seasons
+}
+
+pub fn item_drops_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.item_drops
+}
+
+pub fn shadows_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.shadows
+}
+
+pub fn particles_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.particles
+}
+
+pub fn block_updates_enabled(toggles: Res<SystemToggles>) -> bool {
+    toggles.block_updates
+}
@@ -0,0 +1,36 @@
+use std::future::Future;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::block_on;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::tasks::{AsyncComputeTaskPool, IoTaskPool, Task};
+
+// Work handed off the main thread, polled each frame or blocked on like a bevy Task. On wasm the
+// task pools run spawned futures on the main thread and hand back nothing to poll, so the work
+// runs to completion when it's spawned instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub type BackgroundTask<T> = Task<T>;
+#[cfg(target_arch = "wasm32")]
+pub type BackgroundTask<T> = futures::future::Ready<T>;
+
+// CPU-bound work like meshing and encoding
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn_compute<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> BackgroundTask<T> {
+    AsyncComputeTaskPool::get().spawn(future)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn spawn_compute<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> BackgroundTask<T> {
+    futures::future::ready(block_on(future))
+}
+
+// File writes
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn_io<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> BackgroundTask<T> {
+    IoTaskPool::get().spawn(future)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn spawn_io<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> BackgroundTask<T> {
+    futures::future::ready(block_on(future))
+}
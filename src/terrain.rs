@@ -1,307 +1,238 @@
 use bevy::prelude::*;
-use bevy::tasks::{AsyncComputeTaskPool, Task};
-use bytemuck::{Pod, Zeroable};
-use std::collections::{HashMap, HashSet, VecDeque};
-use bevy::render::mesh::{Indices, PrimitiveTopology};
-use noise::{NoiseFn, Perlin};
-use std::sync::{Arc, Mutex};
-use crate::UNLOAD_GRACE_PERIOD;
+use std::collections::{BTreeMap, HashMap};
+use crate::biome::ChunkColumns;
+#[cfg(feature = "render")]
+use crate::chunk_pool::ChunkPool;
+use crate::block_entity::BlockEntityData;
+use crate::block_updates::ScheduledTicks;
+use crate::item_drop::StoredItemDrop;
+use crate::block::{self, BlockId, AIR, STONE};
+use crate::storage::ChunkStorage;
+#[cfg(feature = "render")]
+use crate::meshing::ChunkMeshingTask;
+use crate::lighting;
+#[cfg(feature = "render")]
+use crate::world::World;
 
 #[derive(Component)]
 pub struct Chunk {
-    pub voxels: Vec<bool>,
+    pub voxels: ChunkStorage,
     pub width: usize,
     pub height: usize,
     pub depth: usize,
     pub last_accessed: f32,
     pub boxified: Vec<bool>,
+    // Extra state of chests, signs, ... keyed by local position. Kept in step with the
+    // voxels by set_block and saved with them.
+    pub block_entities: HashMap<(usize, usize, usize), BlockEntityData>,
+    // Item drops lying in this chunk, written back by item_drop::store_item_drops while loaded
+    pub item_drops: Vec<StoredItemDrop>,
+    // worldgen::GENERATOR_VERSION the terrain came from, 0 if unknown (saved before it was recorded)
+    pub generator_version: u32,
+    // Blocks written through World::set_block since generation, the latest write per voxel.
+    // Lets `voxelfun upgrade` replay them onto terrain from a newer generator.
+    pub edits: BTreeMap<(usize, usize, usize), BlockId>,
+    // Pending block ticks, see block_updates
+    pub scheduled_ticks: ScheduledTicks,
+    // State of blocks that come in several shapes, like the layers of snow, keyed by local
+    // position; absent means state 0. Reset by set_block whenever the block changes and saved
+    // with the voxels.
+    pub block_states: HashMap<(usize, usize, usize), u8>,
+    // Facing layers of the neighbouring chunks. Only filled in on the copy a meshing task works on.
+    pub borders: ChunkBorders,
 }
 
-#[derive(Component)]
-pub struct ChunkMeshingTask(pub Task<Mesh>, pub (i32, i32, i32));
-
-impl Chunk {
+// The layer of each neighbouring chunk that touches this one, so meshing can cull faces across
+// chunk borders. Indexed by direction: -X, +X, -Y, +Y, -Z, +Z. Each layer is laid out along the
+// two remaining axes, lower axis first; None where the neighbour isn't loaded.
+#[derive(Clone, Default)]
+pub struct ChunkBorders {
+    pub layers: [Option<Vec<BlockId>>; 6],
+    // Light sources in this chunk and those around it close enough to light it
+    pub lights: Vec<lighting::LightSource>,
+    // Voxels of the 27 chunks around and including this one, indexed by neighbour_index. Only
+    // handed over when a source outside the chunk shines into it, so its light can be flooded
+    // through the blocks it crosses on the way; None for this chunk and unloaded neighbours.
+    pub neighbours: [Option<ChunkStorage>; 27],
+    // Whether skylight falls into the chunk, off in dimensions without a sky
+    pub skylight: bool,
+}
 
-    pub fn generate_mesh_task(&self, chunk_key: (i32, i32, i32)) -> ChunkMeshingTask {
-        let voxels = self.voxels.clone();
-        let width = self.width;
-        let height = self.height;
-        let depth = self.depth;
+impl ChunkBorders {
+    pub const DIRECTIONS: [IVec3; 6] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Y, IVec3::Y, IVec3::NEG_Z, IVec3::Z];
 
-        let task = AsyncComputeTaskPool::get().spawn(async move {
-            let mut chunk = Chunk {
-                voxels,
-                width,
-                height,
-                depth,
-                last_accessed: 0.0,
-                boxified: vec![false; width * height * depth],
-            };
-            chunk.generate_mesh()
-        });
+    // Bit `direction` set for each neighbour layer that's missing
+    pub fn missing_mask(&self) -> u8 {
+        self.layers.iter().enumerate()
+            .filter(|(_, layer)| layer.is_none())
+            .fold(0, |mask, (direction, _)| mask | 1 << direction)
+    }
 
-        ChunkMeshingTask(task, chunk_key)
+    // The layer of `neighbour` that faces the chunk it lies in `direction` of
+    pub fn extract_layer(neighbour: &Chunk, direction: usize) -> Vec<BlockId> {
+        let dims = [neighbour.width, neighbour.height, neighbour.depth];
+        let axis = direction / 2;
+        // The neighbour on our negative side touches us with its last layer
+        let fixed = if direction.is_multiple_of(2) { dims[axis] - 1 } else { 0 };
+        let (u_axis, v_axis) = Self::layer_axes(axis);
+        let mut layer = Vec::with_capacity(dims[u_axis] * dims[v_axis]);
+        for v in 0..dims[v_axis] {
+            for u in 0..dims[u_axis] {
+                let mut pos = [0; 3];
+                pos[axis] = fixed;
+                pos[u_axis] = u;
+                pos[v_axis] = v;
+                layer.push(neighbour.get_block(pos[0], pos[1], pos[2]));
+            }
+        }
+        layer
     }
 
+    pub fn layer_axes(axis: usize) -> (usize, usize) {
+        match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        }
+    }
 
+    // Slot in `neighbours` of the chunk at `offset` (each component -1..=1)
+    pub fn neighbour_index(offset: IVec3) -> usize {
+        ((offset.x + 1) + (offset.y + 1) * 3 + (offset.z + 1) * 9) as usize
+    }
+}
 
-    pub fn generate_terrain(&mut self, chunk_x: i32, chunk_y: i32, chunk_z: i32) {
-        let perlin = Perlin::new(0);
-        let chunk_size = self.width as f64;
+// Chunk streaming and storage counters, refreshed every frame for the debug overlay
+#[derive(Resource, Default)]
+pub struct ChunkDiagnostics {
+    pub loaded_chunks: usize,
+    pub load_queue: usize,
+    pub unload_queue: usize,
+    pub meshing_tasks: usize,
+    pub pending_structures: usize,
+    pub voxel_storage_bytes: usize,
+    // Free chunks in ChunkPool, and how many generated chunks came from it or were allocated
+    pub pooled_chunks: usize,
+    pub reused_chunks: u64,
+    pub allocated_chunks: u64,
+}
 
+impl Chunk {
+    // Stone below each column's height, air above. Chunks wholly below or above every column
+    // are filled in one go and stay uniform, so deep rock and open sky cost no per-voxel storage.
+    pub fn generate_terrain(&mut self, chunk_y: i32, columns: &ChunkColumns) {
+        let base_y = chunk_y * self.height as i32;
+        let (lowest, highest) = columns.height_range();
+        if base_y + self.height as i32 <= lowest {
+            self.voxels.fill(STONE);
+            return;
+        }
+        if base_y >= highest {
+            self.voxels.fill(AIR);
+            return;
+        }
         for x in 0..self.width {
             for z in 0..self.depth {
-                let world_x = chunk_x as f64 * chunk_size + x as f64;
-                let world_z = chunk_z as f64 * chunk_size + z as f64;
-
-                // Adjust these values to change the terrain characteristics
-                let frequency = 0.01;
-                let amplitude = 32.0;
-
-                let height = ((perlin.get([world_x * frequency, world_z * frequency]) + 1.0) * 0.5 * amplitude) as usize;
-
+                let height = columns.get(x, z).height as i32;
                 for y in 0..self.height {
-                    let world_y = if chunk_y >= 0 {
-                        (chunk_y as usize * self.height) + y
-                    } else {
-                        y.saturating_sub(chunk_y.unsigned_abs() as usize * self.height)
-                    };
-                    if world_y < self.height {
-                        if world_y < height {
-                            self.set_voxel(x, y, z, true);
-                        } else {
-                            self.set_voxel(x, y, z, false);
-                        }
-                    }
+                    self.set_voxel(x, y, z, base_y + (y as i32) < height);
                 }
             }
         }
     }
 
-
     pub fn new(width: usize, height: usize, depth: usize) -> Self {
-        let voxels = vec![false; width * height * depth];
+        let voxels = ChunkStorage::new(width * height * depth);
         let boxified = vec![false; width * height * depth];
-        Self { voxels, width, height, depth, last_accessed: 0.0, boxified }
+        Self { voxels, width, height, depth, last_accessed: 0.0, boxified, block_entities: HashMap::new(), item_drops: Vec::new(), generator_version: 0, edits: BTreeMap::new(), scheduled_ticks: ScheduledTicks::default(), block_states: HashMap::new(), borders: ChunkBorders::default() }
     }
 
-    pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> bool {
-        if x < self.width && y < self.height && z < self.depth {
-            self.voxels[x + y * self.width + z * self.width * self.height]
-        } else {
-            false
-        }
+    // Back to an empty all-air chunk, keeping the allocations of its buffers. See ChunkPool.
+    pub fn clear(&mut self) {
+        self.voxels.fill(AIR);
+        self.last_accessed = 0.0;
+        self.boxified.fill(false);
+        self.block_entities.clear();
+        self.item_drops.clear();
+        self.generator_version = 0;
+        self.edits.clear();
+        self.scheduled_ticks.clear();
+        self.block_states.clear();
+        self.borders = ChunkBorders::default();
     }
 
-    pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, value: bool) {
-        if x < self.width && y < self.height && z < self.depth {
-            self.voxels[x + y * self.width + z * self.width * self.height] = value;
-        }
+    pub fn from_storage(width: usize, height: usize, depth: usize, voxels: ChunkStorage) -> Self {
+        let boxified = vec![false; width * height * depth];
+        Self { voxels, width, height, depth, last_accessed: 0.0, boxified, block_entities: HashMap::new(), item_drops: Vec::new(), generator_version: 0, edits: BTreeMap::new(), scheduled_ticks: ScheduledTicks::default(), block_states: HashMap::new(), borders: ChunkBorders::default() }
     }
 
-    fn get_box_index(&self, x: usize, y: usize, z: usize) -> usize {
-        x + y * self.width + z * self.width * self.height
+    pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> bool {
+        block::is_solid(self.get_block(x, y, z))
     }
 
-    fn is_boxified(&self, x: usize, y: usize, z: usize) -> bool {
-        self.boxified[self.get_box_index(x, y, z)]
+    // Whether light stops at this voxel, see block::is_opaque
+    pub fn is_opaque(&self, x: usize, y: usize, z: usize) -> bool {
+        block::is_opaque(self.get_block(x, y, z))
     }
 
-    fn set_boxified(&mut self, x: usize, y: usize, z: usize, value: bool) {
-        let index: usize = self.get_box_index(x, y, z);
-        self.boxified[index] = value;
+    pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, value: bool) {
+        self.set_block(x, y, z, if value { STONE } else { AIR });
     }
 
-    pub fn merge_voxels(&mut self) -> Vec<(usize, usize, usize, usize, usize, usize)> {
-        let mut boxes = Vec::new();
-        self.boxified.fill(false);
-
-        for x in 0..self.width {
-            for y in 0..self.height {
-                for z in 0..self.depth {
-                    if self.get_voxel(x, y, z) && !self.is_boxified(x, y, z) {
-                        let mut nx = 1;
-                        let mut ny = 1;
-                        let mut nz = 1;
-
-                        // Merge in x direction
-                        for i in x + 1..self.width {
-                            if self.get_voxel(i, y, z) && !self.is_boxified(i, y, z) {
-                                nx += 1;
-                            } else {
-                                break;
-                            }
-                        }
-
-                        // Merge in y direction
-                        for j in y + 1..self.height {
-                            let mut valid = true;
-                            for i in x..x + nx {
-                                if !self.get_voxel(i, j, z) || self.is_boxified(i, j, z) {
-                                    valid = false;
-                                    break;
-                                }
-                            }
-                            if valid {
-                                ny += 1;
-                            } else {
-                                break;
-                            }
-                        }
-
-                        // Merge in z direction
-                        for k in z + 1..self.depth {
-                            let mut valid = true;
-                            for i in x..x + nx {
-                                for j in y..y + ny {
-                                    if !self.get_voxel(i, j, k) || self.is_boxified(i, j, k) {
-                                        valid = false;
-                                        break;
-                                    }
-                                }
-                                if !valid {
-                                    break;
-                                }
-                            }
-                            if valid {
-                                nz += 1;
-                            } else {
-                                break;
-                            }
-                        }
-
-                        // Mark voxels as boxified
-                        for i in x..x + nx {
-                            for j in y..y + ny {
-                                for k in z..z + nz {
-                                    self.set_boxified(i, j, k, true);
-                                }
-                            }
-                        }
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> BlockId {
+        if x < self.width && y < self.height && z < self.depth {
+            self.voxels.get(x + y * self.width + z * self.width * self.height)
+        } else {
+            AIR
+        }
+    }
 
-                        boxes.push((x, y, z, nx, ny, nz));
-                    }
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: BlockId) {
+        if x < self.width && y < self.height && z < self.depth {
+            let index = x + y * self.width + z * self.width * self.height;
+            let old = self.voxels.get(index);
+            self.voxels.set(index, block);
+
+            // Replacing a block always resets its block entity and state
+            if old != block {
+                self.block_states.remove(&(x, y, z));
+                if block::has_block_entity(old) {
+                    self.block_entities.remove(&(x, y, z));
+                }
+                if let Some(data) = BlockEntityData::default_for(block) {
+                    self.block_entities.insert((x, y, z), data);
                 }
             }
         }
-        boxes
     }
 
-    pub fn generate_mesh(&mut self) -> Mesh {
-        let mut positions = Vec::new();
-        let mut indices = Vec::new();
-        let mut normals = Vec::new();
-        let mut uvs: Vec<[f32; 2]> = Vec::new();
-
-        let mut index_count = 0;
-        for (x, y, z, nx, ny, nz) in self.merge_voxels() {
-            // Vertices for each face of the box
-            let face_vertices = [
-                // Front face
-                [x as f32, y as f32, z as f32],
-                [(x + nx) as f32, y as f32, z as f32],
-                [x as f32, (y + ny) as f32, z as f32],
-                [(x + nx) as f32, (y + ny) as f32, z as f32],
-
-                // Back face
-                [x as f32, y as f32, (z + nz) as f32],
-                [(x + nx) as f32, y as f32, (z + nz) as f32],
-                [x as f32, (y + ny) as f32, (z + nz) as f32],
-                [(x + nx) as f32, (y + ny) as f32, (z + nz) as f32],
-
-                // Left face
-                [x as f32, y as f32, z as f32],
-                [x as f32, (y + ny) as f32, z as f32],
-                [x as f32, y as f32, (z + nz) as f32],
-                [x as f32, (y + ny) as f32, (z + nz) as f32],
-
-                // Right face
-                [(x + nx) as f32, y as f32, z as f32],
-                [(x + nx) as f32, (y + ny) as f32, z as f32],
-                [(x + nx) as f32, y as f32, (z + nz) as f32],
-                [(x + nx) as f32, (y + ny) as f32, (z + nz) as f32],
-
-                // Top face
-                [x as f32, (y + ny) as f32, z as f32],
-                [(x + nx) as f32, (y + ny) as f32, z as f32],
-                [x as f32, (y + ny) as f32, (z + nz) as f32],
-                [(x + nx) as f32, (y + ny) as f32, (z + nz) as f32],
-
-                // Bottom face
-                [x as f32, y as f32, z as f32],
-                [(x + nx) as f32, y as f32, z as f32],
-                [x as f32, y as f32, (z + nz) as f32],
-                [(x + nx) as f32, y as f32, (z + nz) as f32],
-            ];
-
-            // Indices for each face of the box
-            let face_indices = [
-                // Correct winding order for each face
-                0, 2, 1, 2, 3, 1,   // Front face
-                4, 5, 6, 5, 7, 6,   // Back face
-                8, 10, 9, 10, 11, 9, // Left face
-                12, 13, 14, 13, 15, 14, // Right face
-                16, 18, 17, 18, 19, 17, // Top face
-                20, 21, 22, 21, 23, 22, // Bottom face
-            ];
-
-            // Normals for each face of the box
-            let face_normals = [
-                [0.0f32, 0.0f32, -1.0f32], // Front face
-                [0.0f32, 0.0f32, -1.0f32],
-                [0.0f32, 0.0f32, -1.0f32],
-                [0.0f32, 0.0f32, -1.0f32],
-
-                [0.0f32, 0.0f32, 1.0f32],  // Back face
-                [0.0f32, 0.0f32, 1.0f32],
-                [0.0f32, 0.0f32, 1.0f32],
-                [0.0f32, 0.0f32, 1.0f32],
-
-                [-1.0f32, 0.0f32, 0.0f32], // Left face
-                [-1.0f32, 0.0f32, 0.0f32],
-                [-1.0f32, 0.0f32, 0.0f32],
-                [-1.0f32, 0.0f32, 0.0f32],
-
-                [1.0f32, 0.0f32, 0.0f32],  // Right face
-                [1.0f32, 0.0f32, 0.0f32],
-                [1.0f32, 0.0f32, 0.0f32],
-                [1.0f32, 0.0f32, 0.0f32],
-
-                [0.0f32, 1.0f32, 0.0f32],  // Top face
-                [0.0f32, 1.0f32, 0.0f32],
-                [0.0f32, 1.0f32, 0.0f32],
-                [0.0f32, 1.0f32, 0.0f32],
-
-                [0.0f32, -1.0f32, 0.0f32], // Bottom face
-                [0.0f32, -1.0f32, 0.0f32],
-                [0.0f32, -1.0f32, 0.0f32],
-                [0.0f32, -1.0f32, 0.0f32],
-            ];
-
-            // UV coordinates for each face of the box
-            let face_uvs = [
-                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Front face
-                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Back face
-                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Left face
-                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Right face
-                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Top face
-                [0.0f32, 0.0f32], [1.0f32, 0.0f32], [0.0f32, 1.0f32], [1.0f32, 1.0f32], // Bottom face
-            ];
-
-            positions.extend_from_slice(&face_vertices);
-            indices.extend(face_indices.iter().map(|&i| i + index_count));
-            normals.extend_from_slice(&face_normals);
-            uvs.extend_from_slice(&face_uvs);
+    pub fn get_state(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.block_states.get(&(x, y, z)).copied().unwrap_or(0)
+    }
 
-            index_count += 24; // 24 vertices per voxel
+    pub fn set_state(&mut self, x: usize, y: usize, z: usize, state: u8) {
+        if state == 0 {
+            self.block_states.remove(&(x, y, z));
+        } else {
+            self.block_states.insert((x, y, z), state);
         }
-
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, Default::default());
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-        mesh.insert_indices(Indices::U32(indices));
-
-        mesh
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "render")]
+pub fn update_chunk_diagnostics(
+    mut diagnostics: ResMut<ChunkDiagnostics>,
+    world: Res<World>,
+    pool: Res<ChunkPool>,
+    meshing_tasks: Query<(), With<ChunkMeshingTask>>,
+) {
+    diagnostics.loaded_chunks = world.chunks.len();
+    diagnostics.load_queue = world.chunk_load_queue.len();
+    diagnostics.unload_queue = world.chunk_unload_queue.len();
+    diagnostics.meshing_tasks = meshing_tasks.iter().count();
+    diagnostics.pending_structures = world.pending_structures.values().map(Vec::len).sum();
+    diagnostics.voxel_storage_bytes = world.chunks.values().map(|chunk| chunk.voxels.heap_size()).sum();
+    diagnostics.pooled_chunks = pool.free_chunks();
+    diagnostics.reused_chunks = pool.reused;
+    diagnostics.allocated_chunks = pool.allocated;
+}
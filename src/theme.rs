@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use crate::keybindings::{Action, Actions};
+use crate::outline::{ChunkMaterials, OutlinedMaterial};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ThemeKind {
+    #[default]
+    Default,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+impl ThemeKind {
+    pub fn next(self) -> Self {
+        match self {
+            ThemeKind::Default => ThemeKind::Deuteranopia,
+            ThemeKind::Deuteranopia => ThemeKind::Protanopia,
+            ThemeKind::Protanopia => ThemeKind::Tritanopia,
+            ThemeKind::Tritanopia => ThemeKind::HighContrast,
+            ThemeKind::HighContrast => ThemeKind::Default,
+        }
+    }
+}
+
+// Every color the HUD, gizmos and highlights draw with lives here, so a single
+// switch swaps them all. The color-blind palettes are built from Okabe-Ito colors,
+// which stay distinguishable under the common forms of color vision deficiency.
+#[derive(Resource, Clone, Debug)]
+pub struct Theme {
+    pub kind: ThemeKind,
+    pub selection_highlight: Color,
+    pub voxel_outline: Color,
+    pub debug_primary: Color,
+    pub debug_secondary: Color,
+    pub debug_warning: Color,
+    pub hud_text: Color,
+    pub hud_background: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::from_kind(ThemeKind::Default)
+    }
+}
+
+impl Theme {
+    pub fn from_kind(kind: ThemeKind) -> Self {
+        match kind {
+            ThemeKind::Default => Self {
+                kind,
+                selection_highlight: Color::srgb(1.0, 1.0, 1.0),
+                voxel_outline: Color::srgb(0.05, 0.05, 0.08),
+                debug_primary: Color::srgb(0.2, 0.9, 0.2),
+                debug_secondary: Color::srgb(0.9, 0.9, 0.2),
+                debug_warning: Color::srgb(0.9, 0.2, 0.2),
+                hud_text: Color::srgb(1.0, 1.0, 1.0),
+                hud_background: Color::srgba(0.0, 0.0, 0.0, 0.5),
+            },
+            // Red-green deficiencies: lean on the blue/orange axis
+            ThemeKind::Deuteranopia | ThemeKind::Protanopia => Self {
+                kind,
+                selection_highlight: Color::srgb_u8(0xF0, 0xE4, 0x42),
+                voxel_outline: Color::srgb(0.05, 0.05, 0.08),
+                debug_primary: Color::srgb_u8(0x56, 0xB4, 0xE9),
+                debug_secondary: Color::srgb_u8(0xE6, 0x9F, 0x00),
+                debug_warning: Color::srgb_u8(0xD5, 0x5E, 0x00),
+                hud_text: Color::srgb(1.0, 1.0, 1.0),
+                hud_background: Color::srgba(0.0, 0.0, 0.0, 0.6),
+            },
+            // Blue-yellow deficiency: lean on the red/teal axis
+            ThemeKind::Tritanopia => Self {
+                kind,
+                selection_highlight: Color::srgb_u8(0xCC, 0x79, 0xA7),
+                voxel_outline: Color::srgb(0.05, 0.05, 0.05),
+                debug_primary: Color::srgb_u8(0x00, 0x9E, 0x73),
+                debug_secondary: Color::srgb_u8(0xCC, 0x79, 0xA7),
+                debug_warning: Color::srgb_u8(0xD5, 0x5E, 0x00),
+                hud_text: Color::srgb(1.0, 1.0, 1.0),
+                hud_background: Color::srgba(0.0, 0.0, 0.0, 0.6),
+            },
+            ThemeKind::HighContrast => Self {
+                kind,
+                selection_highlight: Color::srgb(1.0, 1.0, 0.0),
+                voxel_outline: Color::srgb(0.0, 0.0, 0.0),
+                debug_primary: Color::srgb(0.0, 1.0, 1.0),
+                debug_secondary: Color::srgb(1.0, 0.0, 1.0),
+                debug_warning: Color::srgb(1.0, 1.0, 0.0),
+                hud_text: Color::srgb(1.0, 1.0, 1.0),
+                hud_background: Color::srgba(0.0, 0.0, 0.0, 0.9),
+            },
+        }
+    }
+}
+
+pub fn cycle_theme(
+    actions: Actions,
+    mut theme: ResMut<Theme>,
+) {
+    if actions.just_pressed(Action::CycleTheme) {
+        *theme = Theme::from_kind(theme.kind.next());
+        println!("Switched to {:?} theme", theme.kind);
+    }
+}
+
+pub fn apply_theme_to_materials(
+    theme: Res<Theme>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut outlined_materials: ResMut<Assets<OutlinedMaterial>>,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+
+    if let Some(material) = outlined_materials.get_mut(&chunk_materials.outlined) {
+        material.extension.settings.color = theme.voxel_outline.to_linear();
+    }
+}
@@ -0,0 +1,95 @@
+#[cfg(feature = "render")]
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use crate::block::{self, TORCH};
+#[cfg(feature = "render")]
+use crate::block_entity::{BlockEntity, Torch};
+use crate::voxel_world::VoxelWorld;
+
+#[cfg(feature = "render")]
+const TORCH_WIDTH: f32 = 0.125;
+#[cfg(feature = "render")]
+const TORCH_HEIGHT: f32 = 0.6;
+// How far wall torches lean away from the wall they hang on, in radians
+#[cfg(feature = "render")]
+const WALL_TORCH_TILT: f32 = 0.35;
+// Brighter than 1 so the flame glows even in the dark
+#[cfg(feature = "render")]
+const TORCH_EMISSIVE: LinearRgba = LinearRgba::rgb(4.0, 2.4, 0.8);
+
+// Sides a torch can hang on, checked in order after the floor
+const WALL_DIRECTIONS: [IVec3; 4] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z];
+
+#[cfg(feature = "render")]
+#[derive(Resource)]
+pub struct TorchAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+#[cfg(feature = "render")]
+pub fn setup_torches(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let [r, g, b] = block::variant_color(TORCH, 0);
+    commands.insert_resource(TorchAssets {
+        mesh: meshes.add(Cuboid::new(TORCH_WIDTH, TORCH_HEIGHT, TORCH_WIDTH)),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgb(r, g, b),
+            emissive: TORCH_EMISSIVE,
+            ..default()
+        }),
+    });
+}
+
+// Where the stick sits inside its cell (relative to the cell center): standing on the floor
+// when there is one, otherwise leaning off the first solid wall. The light itself comes
+// from the voxel lighting, the mesh only has to glow.
+#[cfg(feature = "render")]
+fn torch_transform(voxel_world: &VoxelWorld, pos: IVec3) -> Transform {
+    let floor = Vec3::new(0.0, (TORCH_HEIGHT - 1.0) / 2.0, 0.0);
+    if voxel_world.is_solid(pos - IVec3::Y) {
+        return Transform::from_translation(floor);
+    }
+
+    match WALL_DIRECTIONS.into_iter().find(|&direction| voxel_world.is_solid(pos + direction)) {
+        Some(direction) => {
+            let direction = direction.as_vec3();
+            Transform::from_translation(direction * (0.5 - TORCH_WIDTH) + Vec3::Y * 0.1)
+                .with_rotation(Quat::from_axis_angle(direction.cross(Vec3::Y), WALL_TORCH_TILT))
+        }
+        None => Transform::from_translation(floor),
+    }
+}
+
+// Torches stand on a solid floor or hang on a solid wall; see block_updates for what happens
+// when both are gone
+pub fn is_supported(voxel_world: &VoxelWorld, pos: IVec3) -> bool {
+    voxel_world.is_solid(pos - IVec3::Y) || WALL_DIRECTIONS.into_iter().any(|direction| voxel_world.is_solid(pos + direction))
+}
+
+// Gives every new torch block entity its mesh. The block entity despawns with the block,
+// taking the mesh with it.
+#[cfg(feature = "render")]
+pub fn attach_torch_meshes(
+    mut commands: Commands,
+    assets: Res<TorchAssets>,
+    voxel_world: VoxelWorld,
+    torches: Query<(Entity, &BlockEntity), Added<Torch>>,
+) {
+    for (entity, owner) in &torches {
+        let mesh = commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material.clone(),
+                transform: torch_transform(&voxel_world, owner.pos),
+                ..default()
+            },
+            NotShadowCaster,
+        )).id();
+        commands.entity(entity).add_child(mesh);
+    }
+}
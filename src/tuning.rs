@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+use crate::keybindings::{Action, Actions};
+use crate::outline::{ChunkMaterials, OutlinedMaterial};
+use crate::sky::FOG_START_FRACTION;
+use crate::theme::Theme;
+use crate::water::TerrainMaterial;
+
+// Lighting parameters for looking at the terrain shader live: ambient light and ambient occlusion
+// go to the terrain materials' uniform, fog density to the camera's fog. Not saved.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct LightingTuning {
+    // Scale of the ambient light on terrain
+    pub ambient_strength: f32,
+    // Fraction of the light each opaque cell around a corner takes away
+    pub ao_strength: f32,
+    // 0 only the loading boundary is fogged, 1 fog starts at the camera
+    pub fog_density: f32,
+}
+
+impl Default for LightingTuning {
+    fn default() -> Self {
+        Self {
+            ambient_strength: 1.0,
+            // Each occluder used to count as a dark light sample, which cost 1 - 0.8^3.75 of
+            // full daylight; kept as the default look
+            ao_strength: 0.57,
+            fog_density: 1.0 - FOG_START_FRACTION,
+        }
+    }
+}
+
+// Name, range and step of each parameter, in panel order
+const PARAMETERS: [(&str, f32, f32, f32); 3] = [
+    ("Ambient strength", 0.0, 4.0, 0.1),
+    ("AO strength", 0.0, 1.0, 0.05),
+    ("Fog density", 0.0, 1.0, 0.05),
+];
+
+impl LightingTuning {
+    fn parameter(&self, index: usize) -> f32 {
+        match index {
+            0 => self.ambient_strength,
+            1 => self.ao_strength,
+            _ => self.fog_density,
+        }
+    }
+
+    fn parameter_mut(&mut self, index: usize) -> &mut f32 {
+        match index {
+            0 => &mut self.ambient_strength,
+            1 => &mut self.ao_strength,
+            _ => &mut self.fog_density,
+        }
+    }
+}
+
+// The panel is opened with Action::TuningPanel; arrows up and down pick a parameter while it's
+// open, left and right change it
+#[derive(Resource, Default)]
+pub struct TuningPanel {
+    pub visible: bool,
+    selected: usize,
+}
+
+#[derive(Component)]
+pub struct TuningPanelText;
+
+pub fn tuning_panel_visible(panel: Res<TuningPanel>) -> bool {
+    panel.visible
+}
+
+pub fn spawn_tuning_panel(mut commands: Commands, theme: Res<Theme>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: theme.hud_text,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        })
+        .with_background_color(theme.hud_background),
+        Visibility::Hidden,
+        TuningPanelText,
+    ));
+}
+
+pub fn toggle_tuning_panel(
+    actions: Actions,
+    mut panel: ResMut<TuningPanel>,
+    mut text_query: Query<&mut Visibility, With<TuningPanelText>>,
+) {
+    if !actions.just_pressed(Action::TuningPanel) {
+        return;
+    }
+
+    panel.visible = !panel.visible;
+    for mut visibility in &mut text_query {
+        *visibility = if panel.visible { Visibility::Visible } else { Visibility::Hidden };
+    }
+}
+
+pub fn adjust_tuning(actions: Actions, mut panel: ResMut<TuningPanel>, mut tuning: ResMut<LightingTuning>) {
+    if actions.just_pressed(Action::TuneNext) {
+        panel.selected = (panel.selected + 1) % PARAMETERS.len();
+    }
+    if actions.just_pressed(Action::TunePrevious) {
+        panel.selected = (panel.selected + PARAMETERS.len() - 1) % PARAMETERS.len();
+    }
+    let direction = actions.just_pressed(Action::TuneIncrease) as i32 - actions.just_pressed(Action::TuneDecrease) as i32;
+    if direction != 0 {
+        let (_, min, max, step) = PARAMETERS[panel.selected];
+        let value = tuning.parameter_mut(panel.selected);
+        // Rounded to the step so repeated presses don't drift
+        *value = (((*value / step).round() + direction as f32) * step).clamp(min, max);
+    }
+}
+
+pub fn update_tuning_panel(
+    panel: Res<TuningPanel>,
+    tuning: Res<LightingTuning>,
+    theme: Res<Theme>,
+    mut text_query: Query<(&mut Text, &mut BackgroundColor), With<TuningPanelText>>,
+) {
+    if !panel.is_changed() && !tuning.is_changed() && !theme.is_changed() {
+        return;
+    }
+    let mut contents = "Lighting (arrows to tune)".to_string();
+    for (index, (name, ..)) in PARAMETERS.iter().enumerate() {
+        let marker = if index == panel.selected { ">" } else { " " };
+        contents.push_str(&format!("\n{} {:<17}{:.2}", marker, name, tuning.parameter(index)));
+    }
+
+    for (mut text, mut background_color) in &mut text_query {
+        text.sections[0].value = contents.clone();
+        text.sections[0].style.color = theme.hud_text;
+        background_color.0 = theme.hud_background;
+    }
+}
+
+// Copies the shader's parameters into the uniforms of every material drawing terrain
+pub fn apply_lighting_tuning(
+    tuning: Res<LightingTuning>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut terrain_materials: ResMut<Assets<TerrainMaterial>>,
+    mut outlined_materials: ResMut<Assets<OutlinedMaterial>>,
+) {
+    if !tuning.is_changed() {
+        return;
+    }
+    // Every asset write re-uploads the material, so fog changes leave them alone
+    let target = (tuning.ambient_strength, tuning.ao_strength);
+    let current = terrain_materials.get(&chunk_materials.standard)
+        .map(|material| (material.extension.settings.ambient_strength, material.extension.settings.ao_strength));
+    if current.is_none_or(|current| current == target) {
+        return;
+    }
+    for handle in chunk_materials.terrain() {
+        if let Some(material) = terrain_materials.get_mut(handle) {
+            (material.extension.settings.ambient_strength, material.extension.settings.ao_strength) = target;
+        }
+    }
+    if let Some(material) = outlined_materials.get_mut(&chunk_materials.outlined) {
+        material.extension.settings.ao_strength = tuning.ao_strength;
+    }
+}
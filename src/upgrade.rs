@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::block::{self, AIR};
+use crate::block_ids::BlockIdMap;
+use crate::chunk_format::{self, FormatError};
+use crate::dimension::Dimension;
+use crate::save::{self, REGION_EXTENSION};
+use crate::terrain::Chunk;
+use crate::worldgen::{OverflowBlock, WorldGenerator, GENERATOR_VERSION};
+use crate::CHUNK_SIZE;
+
+// Voxels from a chunk face over which blended terrain fades from the new generator to the old
+const BLEND_WIDTH: usize = 6;
+const BLEND_SEED: u32 = 0xb1e2_d5ed;
+
+// What `voxelfun upgrade` does with saved chunks generated by another GENERATOR_VERSION.
+// Chunks that were never edited aren't saved at all and pick up the new generator by themselves,
+// so only the saved ones, and the seams between them and fresh terrain, need a decision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpgradeMode {
+    // Leave the saved chunks as they are, seams and all
+    Keep,
+    // Keep the saved terrain, but fade its faces towards the new generator where they meet
+    // freshly generated chunks, so the seams become gradual
+    Blend,
+    // Generate the chunks again and replay their edit logs on top. Chunks without edits are
+    // dropped from the save. Chunks saved before edit logs existed can't be replayed and are kept.
+    Regenerate,
+}
+
+impl UpgradeMode {
+    pub const NAMES: [&'static str; 3] = ["keep", "blend", "regenerate"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "keep" => Some(UpgradeMode::Keep),
+            "blend" => Some(UpgradeMode::Blend),
+            "regenerate" => Some(UpgradeMode::Regenerate),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct UpgradeReport {
+    pub chunks: usize,
+    // Saved chunks per generator version they were made with
+    pub versions: BTreeMap<u32, usize>,
+    pub unreadable: usize,
+    pub blended: usize,
+    pub regenerated: usize,
+    pub dropped: usize,
+    // Outdated chunks the mode left alone
+    pub kept: usize,
+}
+
+impl UpgradeReport {
+    pub fn outdated(&self) -> usize {
+        self.versions.iter().filter(|(&version, _)| version != GENERATOR_VERSION).map(|(_, count)| count).sum()
+    }
+}
+
+struct Region {
+    path: PathBuf,
+    blobs: BTreeMap<(i32, i32, i32), Vec<u8>>,
+}
+
+// Structure blocks that neighbouring chunks spill over their borders, generated on demand.
+// Trees and buildings cross chunk borders, so a chunk generated on its own would miss them.
+struct OverflowCache<'a> {
+    generator: &'a WorldGenerator,
+    chunk_size: usize,
+    overflow: HashMap<(i32, i32, i32), Vec<OverflowBlock>>,
+}
+
+impl OverflowCache<'_> {
+    // The chunk as the game would see it with all its neighbours generated
+    fn generate(&mut self, chunk_key: (i32, i32, i32)) -> Chunk {
+        let (mut chunk, own_overflow) = self.generator.generate(chunk_key, self.chunk_size);
+        self.overflow.insert(chunk_key, own_overflow);
+
+        let size = self.chunk_size as i32;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbour = (chunk_key.0 + dx, chunk_key.1 + dy, chunk_key.2 + dz);
+                    if neighbour == chunk_key {
+                        continue;
+                    }
+                    let generator = self.generator;
+                    let chunk_size = self.chunk_size;
+                    let overflow = self.overflow.entry(neighbour)
+                        .or_insert_with(|| generator.generate(neighbour, chunk_size).1);
+
+                    for &OverflowBlock { pos, block, replace } in overflow.iter() {
+                        let key = (pos.x.div_euclid(size), pos.y.div_euclid(size), pos.z.div_euclid(size));
+                        if key != chunk_key {
+                            continue;
+                        }
+                        let (x, y, z) = (pos.x.rem_euclid(size) as usize, pos.y.rem_euclid(size) as usize, pos.z.rem_euclid(size) as usize);
+                        if replace || chunk.get_block(x, y, z) == AIR {
+                            chunk.set_block(x, y, z, block);
+                        }
+                    }
+                }
+            }
+        }
+        chunk
+    }
+}
+
+fn read_regions(save_dir: &Path) -> Result<Vec<Region>, FormatError> {
+    let mut regions = Vec::new();
+    for dir_entry in fs::read_dir(save_dir)? {
+        let path = dir_entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(REGION_EXTENSION) || save::parse_region_name(&path).is_none() {
+            continue;
+        }
+        match save::read_region_blobs(&path) {
+            Ok(blobs) => regions.push(Region { path, blobs }),
+            Err(err) => println!("{}: unreadable region left in place: {}", path.display(), err),
+        }
+    }
+    Ok(regions)
+}
+
+// Terrain of `old` blended with `new` near the faces listed in `open_faces` (-X, +X, -Y, +Y, -Z, +Z):
+// each voxel takes the new block with a chance that falls from 1 at the face to 0 BLEND_WIDTH in.
+// Voxels in the edit log keep what the player put there.
+fn blend_chunk(old: &mut Chunk, new: &Chunk, chunk_key: (i32, i32, i32), open_faces: [bool; 6]) {
+    let size = [old.width, old.height, old.depth];
+    for z in 0..old.depth {
+        for y in 0..old.height {
+            for x in 0..old.width {
+                let pos = [x, y, z];
+                let distance = (0..6)
+                    .filter(|&face| open_faces[face])
+                    .map(|face| {
+                        let axis = face / 2;
+                        if face % 2 == 0 { pos[axis] } else { size[axis] - 1 - pos[axis] }
+                    })
+                    .min();
+                let Some(distance) = distance.filter(|&distance| distance < BLEND_WIDTH) else {
+                    continue;
+                };
+                if old.edits.contains_key(&(x, y, z)) {
+                    continue;
+                }
+
+                let weight = 1.0 - (distance as f32 + 0.5) / BLEND_WIDTH as f32;
+                let world = [
+                    chunk_key.0 * old.width as i32 + x as i32,
+                    chunk_key.1 * old.height as i32 + y as i32,
+                    chunk_key.2 * old.depth as i32 + z as i32,
+                ];
+                let roll = block::position_hash(world[0], world[1], world[2], BLEND_SEED) as f32 / u32::MAX as f32;
+                if roll < weight {
+                    old.set_block(x, y, z, new.get_block(x, y, z));
+                }
+            }
+        }
+    }
+}
+
+// Fresh terrain with the old chunk's edits replayed on top. Chests, signs and other block
+// entities, and block states like snow depth, survive wherever their block still stands afterwards.
+fn regenerate_chunk(old: Chunk, mut new: Chunk) -> Chunk {
+    for (&(x, y, z), &block) in &old.edits {
+        new.set_block(x, y, z, block);
+    }
+    // States only mean something for the block they were set on
+    for (&(x, y, z), &state) in &old.block_states {
+        if new.get_block(x, y, z) == old.get_block(x, y, z) {
+            new.set_state(x, y, z, state);
+        }
+    }
+    for (pos, data) in old.block_entities {
+        if block::has_block_entity(new.get_block(pos.0, pos.1, pos.2)) {
+            new.block_entities.insert(pos, data);
+        }
+    }
+    new.edits = old.edits;
+    new.item_drops = old.item_drops;
+    new.scheduled_ticks = old.scheduled_ticks;
+    new
+}
+
+// `voxelfun upgrade [save dir] [mode]`: reports which generator versions the saved chunks of a
+// world come from and, given a mode, brings the outdated ones in line with the current generator
+// of `dimension` with the world's `seed` and block `ids`. Without a mode nothing is written.
+pub fn upgrade(save_dir: &Path, mode: Option<UpgradeMode>, dimension: Dimension, seed: u32, ids: &BlockIdMap) -> Result<UpgradeReport, FormatError> {
+    let mut report = UpgradeReport::default();
+    let mut regions = read_regions(save_dir)?;
+
+    let mut chunks = HashMap::new();
+    for region in &regions {
+        for (&chunk_key, blob) in &region.blobs {
+            report.chunks += 1;
+            match chunk_format::decode_chunk_with_ids(blob, ids) {
+                Ok((_, chunk)) => {
+                    *report.versions.entry(chunk.generator_version).or_default() += 1;
+                    chunks.insert(chunk_key, chunk);
+                }
+                Err(_) => report.unreadable += 1,
+            }
+        }
+    }
+
+    let Some(mode) = mode.filter(|&mode| mode != UpgradeMode::Keep) else {
+        report.kept = report.outdated();
+        return Ok(report);
+    };
+
+    // Decided from the versions before anything is rewritten, so the order regions are
+    // processed in doesn't matter
+    let versions: HashMap<_, _> = chunks.iter().map(|(&chunk_key, chunk)| (chunk_key, chunk.generator_version)).collect();
+    let outdated = |chunk_key: &(i32, i32, i32)| versions.get(chunk_key).is_some_and(|&version| version != GENERATOR_VERSION);
+    let generator = dimension.generator(seed);
+    let mut cache = OverflowCache {
+        generator: &generator,
+        chunk_size: chunks.values().next().map_or(CHUNK_SIZE, |chunk| chunk.width),
+        overflow: HashMap::new(),
+    };
+
+    let mut upgraded = BTreeMap::new();
+    for (chunk_key, mut chunk) in chunks.into_iter().filter(|(chunk_key, _)| outdated(chunk_key)) {
+        let versioned = chunk.generator_version != 0;
+        match mode {
+            UpgradeMode::Blend => {
+                // Faces towards chunks that will come from the current generator: unsaved ones,
+                // and saved ones that already did
+                let neighbours = [(-1, 0, 0), (1, 0, 0), (0, -1, 0), (0, 1, 0), (0, 0, -1), (0, 0, 1)];
+                let open_faces = neighbours.map(|(dx, dy, dz)| {
+                    let neighbour = (chunk_key.0 + dx, chunk_key.1 + dy, chunk_key.2 + dz);
+                    !outdated(&neighbour)
+                });
+                blend_chunk(&mut chunk, &cache.generate(chunk_key), chunk_key, open_faces);
+                // Without an edit log a later regenerate couldn't tell the player's blocks apart,
+                // so unversioned chunks stay marked as such
+                if versioned {
+                    chunk.generator_version = GENERATOR_VERSION;
+                }
+                upgraded.insert(chunk_key, Some(chunk));
+                report.blended += 1;
+            }
+            UpgradeMode::Regenerate if !versioned => report.kept += 1,
+            UpgradeMode::Regenerate => {
+                if chunk.edits.is_empty() && chunk.block_entities.is_empty() && chunk.item_drops.is_empty() && chunk.scheduled_ticks.is_empty() && chunk.block_states.is_empty() {
+                    upgraded.insert(chunk_key, None);
+                    report.dropped += 1;
+                } else {
+                    upgraded.insert(chunk_key, Some(regenerate_chunk(chunk, cache.generate(chunk_key))));
+                    report.regenerated += 1;
+                }
+            }
+            UpgradeMode::Keep => unreachable!(),
+        }
+    }
+
+    for region in &mut regions {
+        let keys: Vec<_> = region.blobs.keys().filter(|chunk_key| upgraded.contains_key(chunk_key)).copied().collect();
+        for chunk_key in &keys {
+            match &upgraded[chunk_key] {
+                Some(chunk) => region.blobs.insert(*chunk_key, chunk_format::encode_chunk_with_ids(*chunk_key, chunk, ids)),
+                None => region.blobs.remove(chunk_key),
+            };
+        }
+        if keys.is_empty() {
+            continue;
+        }
+        if region.blobs.is_empty() {
+            fs::remove_file(&region.path)?;
+        } else {
+            save::write_region_blobs(&region.path, &region.blobs)?;
+        }
+    }
+
+    Ok(report)
+}
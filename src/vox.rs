@@ -0,0 +1,264 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use std::fmt;
+use crate::block::{self, BlockId};
+#[cfg(feature = "render")]
+use crate::blueprint::{Blueprint, BlueprintPlacement};
+#[cfg(feature = "render")]
+use crate::console::{Console, ConsoleCommand};
+#[cfg(feature = "render")]
+use crate::history::EditHistory;
+#[cfg(feature = "render")]
+use crate::notifications::NotificationEvent;
+use crate::storage::ByteReader;
+#[cfg(feature = "render")]
+use crate::voxel_world::VoxelWorld;
+#[cfg(feature = "render")]
+use crate::MAX_REGION_VOLUME;
+
+// A MagicaVoxel model. Coordinates are converted to this world's Y-up axes on load
+// (MagicaVoxel is Z-up), so `size` and `voxels` can be stamped into the world as they are.
+#[derive(Asset, TypePath, Clone, Debug)]
+pub struct VoxelModel {
+    pub size: UVec3,
+    // sRGB colors by palette index; index 0 is unused, as in the file
+    pub palette: Vec<[u8; 4]>,
+    // Filled cells and their palette index
+    pub voxels: Vec<(UVec3, u8)>,
+}
+
+impl VoxelModel {
+    // Closest block for every palette entry, by distance in linear color
+    pub fn block_palette(&self) -> Vec<BlockId> {
+        let candidates: Vec<BlockId> = (1..block::block_count() as BlockId)
+            .filter(|&block| block::is_solid(block) && !block::has_block_entity(block))
+            .collect();
+        self.palette.iter().map(|&[r, g, b, _]| {
+            let color = Color::srgb_u8(r, g, b).to_linear();
+            let color = Vec3::new(color.red, color.green, color.blue);
+            *candidates.iter().min_by(|&&a, &&b| {
+                let distance = |block| Vec3::from(block::variant_color(block, 0)).distance_squared(color);
+                distance(a).total_cmp(&distance(b))
+            }).unwrap()
+        }).collect()
+    }
+
+    #[cfg(feature = "render")]
+    pub fn to_blueprint(&self) -> Blueprint {
+        let blocks = self.block_palette();
+        Blueprint {
+            size: self.size.as_ivec3(),
+            cells: self.voxels.iter().map(|&(pos, index)| (pos.as_ivec3(), blocks[index as usize])).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(std::io::Error),
+    BadMagic,
+    Truncated,
+    NoModel,
+    // A voxel lies outside its model's declared size
+    OutOfBounds,
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VoxError::Io(err) => write!(f, "io error: {}", err),
+            VoxError::BadMagic => write!(f, "not a MagicaVoxel file"),
+            VoxError::Truncated => write!(f, "data ends early"),
+            VoxError::NoModel => write!(f, "file contains no model"),
+            VoxError::OutOfBounds => write!(f, "voxel outside the model bounds"),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+impl From<std::io::Error> for VoxError {
+    fn from(err: std::io::Error) -> Self {
+        VoxError::Io(err)
+    }
+}
+
+// Reads the first model of a .vox file. Files with several models (scene graphs) load their
+// first one only; the scene transforms aren't applied.
+//
+//   "VOX " version i32, then chunks of (id [u8; 4], content len i32, children len i32, content):
+//   MAIN   parent of everything else
+//   SIZE   x, y, z i32
+//   XYZI   count i32, then (x, y, z, palette index) u8 per voxel
+//   RGBA   256 colors; file entry i is palette index i + 1
+pub fn parse_vox(bytes: &[u8]) -> Result<VoxelModel, VoxError> {
+    let mut reader = ByteReader::new(bytes);
+    if reader.take_slice(4) != Some(b"VOX ".as_slice()) {
+        return Err(VoxError::BadMagic);
+    }
+    reader.read_u32().ok_or(VoxError::Truncated)?;
+
+    let mut size = None;
+    let mut voxels = None;
+    let mut palette = default_palette();
+
+    while reader.remaining() > 0 {
+        let id = reader.take_slice(4).ok_or(VoxError::Truncated)?;
+        let content_len = reader.read_u32().ok_or(VoxError::Truncated)? as usize;
+        reader.read_u32().ok_or(VoxError::Truncated)?;
+        // MAIN's children are the chunks that follow, so walk into it instead of skipping it
+        if id == b"MAIN" {
+            reader.take_slice(content_len).ok_or(VoxError::Truncated)?;
+            continue;
+        }
+        let mut content = ByteReader::new(reader.take_slice(content_len).ok_or(VoxError::Truncated)?);
+
+        match id {
+            b"SIZE" if size.is_none() => {
+                let x = content.read_u32().ok_or(VoxError::Truncated)?;
+                let y = content.read_u32().ok_or(VoxError::Truncated)?;
+                let z = content.read_u32().ok_or(VoxError::Truncated)?;
+                size = Some(UVec3::new(x, z, y));
+            }
+            b"XYZI" if voxels.is_none() => {
+                let count = content.read_u32().ok_or(VoxError::Truncated)?;
+                // Four bytes per cell
+                let mut cells = Vec::with_capacity((count as usize).min(content.remaining() / 4));
+                for _ in 0..count {
+                    let cell = content.take_slice(4).ok_or(VoxError::Truncated)?;
+                    cells.push((UVec3::new(cell[0] as u32, cell[2] as u32, cell[1] as u32), cell[3]));
+                }
+                voxels = Some(cells);
+            }
+            b"RGBA" => {
+                for entry in &mut palette[1..] {
+                    let color = content.take_slice(4).ok_or(VoxError::Truncated)?;
+                    *entry = [color[0], color[1], color[2], color[3]];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (Some(size), Some(voxels)) = (size, voxels) else {
+        return Err(VoxError::NoModel);
+    };
+    if voxels.iter().any(|&(pos, _)| pos.cmpge(size).any()) {
+        return Err(VoxError::OutOfBounds);
+    }
+    Ok(VoxelModel { size, palette, voxels })
+}
+
+// Stand-in for files without an RGBA chunk: a grey ramp instead of MagicaVoxel's built-in palette
+fn default_palette() -> Vec<[u8; 4]> {
+    (0..256).map(|index| {
+        let shade = 255 - index as u8;
+        [shade, shade, shade, 255]
+    }).collect()
+}
+
+#[derive(Default)]
+pub struct VoxLoader;
+
+impl AssetLoader for VoxLoader {
+    type Asset = VoxelModel;
+    type Settings = ();
+    type Error = VoxError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<VoxelModel, VoxError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        parse_vox(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vox"]
+    }
+}
+
+// Model requested from the console, with where to stamp it (None hands it to the blueprint tool)
+#[cfg(feature = "render")]
+#[derive(Resource, Default)]
+pub struct VoxImport {
+    pending: Option<(Handle<VoxelModel>, Option<IVec3>)>,
+}
+
+// `vox <file in assets/> [x y z]`: stamps a model at a world position, or picks it up
+// as a blueprint to place by hand
+#[cfg(feature = "render")]
+pub fn vox_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut import: ResMut<VoxImport>,
+    asset_server: Res<AssetServer>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "vox") {
+        let Some(path) = command.args.first() else {
+            console.print("usage: vox <file> [x y z]");
+            continue;
+        };
+        let coords: Vec<i32> = command.args[1..].iter().filter_map(|arg| arg.parse().ok()).collect();
+        let origin = match coords[..] {
+            [] => None,
+            [x, y, z] if command.args.len() == 4 => Some(IVec3::new(x, y, z)),
+            _ => {
+                console.print("Expected three integer coordinates");
+                continue;
+            }
+        };
+        import.pending = Some((asset_server.load(path.clone()), origin));
+        console.print(format!("Loading {}", path));
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn finish_vox_import(
+    mut import: ResMut<VoxImport>,
+    models: Res<Assets<VoxelModel>>,
+    asset_server: Res<AssetServer>,
+    mut voxel_world: VoxelWorld,
+    mut placement: ResMut<BlueprintPlacement>,
+    mut history: ResMut<EditHistory>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    let Some((handle, origin)) = import.pending.as_ref() else {
+        return;
+    };
+    if let Some(bevy::asset::LoadState::Failed(err)) = asset_server.get_load_state(handle) {
+        notifications.send(NotificationEvent::error(format!("Could not load model: {}", err)));
+        import.pending = None;
+        return;
+    }
+    let Some(model) = models.get(handle) else {
+        return;
+    };
+    // Duplicate cells can make a model write more often than its size suggests
+    let volume = model.size.as_i64vec3().element_product().max(model.voxels.len() as i64);
+    if volume > MAX_REGION_VOLUME {
+        notifications.send(NotificationEvent::warning(format!("Model of {} voxels exceeds the limit of {}", volume, MAX_REGION_VOLUME)));
+        import.pending = None;
+        return;
+    }
+
+    match origin {
+        Some(origin) => {
+            let edits = voxel_world.place_model(*origin, model);
+            notifications.send(NotificationEvent::info(format!("Placed model, {} block(s)", edits.len())));
+            history.record(edits);
+        }
+        None => {
+            let blueprint = model.to_blueprint();
+            notifications.send(NotificationEvent::info(format!("Loaded model of {} block(s), {:?}", blueprint.cells.len(), blueprint.size)));
+            placement.blueprint = Some(blueprint);
+            placement.rotation = 0;
+            placement.active = true;
+        }
+    }
+    import.pending = None;
+}
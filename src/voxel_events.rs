@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use crate::block::{self, BlockId};
+use crate::block_updates::BlockUpdates;
+use crate::world::World;
+
+// Request to write a block. This is the only way gameplay code should change voxels:
+// `apply_voxel_events` applies it, remeshes the affected chunks, reports what happened and
+// queues block updates for the neighbours.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct VoxelSetEvent {
+    pub chunk_key: (i32, i32, i32),
+    pub voxel_pos: (usize, usize, usize),
+    pub block: BlockId,
+    // See Chunk::block_states; 0 for blocks without states
+    pub state: u8,
+}
+
+// A solid block was replaced by air
+#[derive(Event, Clone, Copy, Debug)]
+pub struct VoxelBrokenEvent {
+    pub chunk_key: (i32, i32, i32),
+    pub voxel_pos: (usize, usize, usize),
+    pub block: BlockId,
+}
+
+// A solid block was written where there was air or a different block
+#[derive(Event, Clone, Copy, Debug)]
+pub struct VoxelPlacedEvent {
+    pub chunk_key: (i32, i32, i32),
+    pub voxel_pos: (usize, usize, usize),
+    pub block: BlockId,
+}
+
+pub fn apply_voxel_events(
+    mut set_events: EventReader<VoxelSetEvent>,
+    mut broken_events: EventWriter<VoxelBrokenEvent>,
+    mut placed_events: EventWriter<VoxelPlacedEvent>,
+    mut world: ResMut<World>,
+    mut updates: ResMut<BlockUpdates>,
+    mut commands: Commands,
+) {
+    let mut edited = Vec::new();
+
+    for event in set_events.read() {
+        let Some(old) = world.set_block(event.chunk_key, event.voxel_pos, event.block) else {
+            continue;
+        };
+        let old_state = world.set_state(event.chunk_key, event.voxel_pos, event.state).unwrap_or(0);
+        if old == event.block && old_state == event.state {
+            continue;
+        }
+
+        if block::is_solid(old) && !block::is_solid(event.block) {
+            broken_events.send(VoxelBrokenEvent { chunk_key: event.chunk_key, voxel_pos: event.voxel_pos, block: old });
+        }
+        if block::is_solid(event.block) {
+            placed_events.send(VoxelPlacedEvent { chunk_key: event.chunk_key, voxel_pos: event.voxel_pos, block: event.block });
+        }
+
+        updates.notify_neighbours(world.voxel_to_world(event.chunk_key, event.voxel_pos));
+        edited.push((event.chunk_key, event.voxel_pos));
+    }
+
+    // All edits of this frame share one remesh pass
+    if !edited.is_empty() {
+        world.remesh_edited(edited, &mut commands);
+    }
+}
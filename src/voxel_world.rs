@@ -0,0 +1,167 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::block::{self, BlockId};
+use crate::block_entity::{BlockEntities, BlockEntityData};
+use crate::history::VoxelEdit;
+use crate::vox::VoxelModel;
+use crate::voxel_events::VoxelSetEvent;
+use crate::world::World;
+
+// World-space voxel access for systems. All coordinates are integer world voxel positions;
+// conversion to chunk key + local position happens in World::world_to_voxel only.
+// Writes go out as VoxelSetEvents so apply_voxel_events stays the single place that edits chunks.
+#[derive(SystemParam)]
+pub struct VoxelWorld<'w> {
+    world: Res<'w, World>,
+    block_entities: Res<'w, BlockEntities>,
+    set_events: EventWriter<'w, VoxelSetEvent>,
+}
+
+impl<'w> VoxelWorld<'w> {
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    // Voxel containing a point in world space
+    pub fn voxel_at(&self, point: Vec3) -> IVec3 {
+        point.floor().as_ivec3()
+    }
+
+    pub fn to_chunk_local(&self, pos: IVec3) -> ((i32, i32, i32), (usize, usize, usize)) {
+        self.world.world_to_voxel(pos)
+    }
+
+    pub fn to_world(&self, chunk_key: (i32, i32, i32), voxel_pos: (usize, usize, usize)) -> IVec3 {
+        self.world.voxel_to_world(chunk_key, voxel_pos)
+    }
+
+    // None if the chunk holding `pos` isn't loaded
+    pub fn get_block(&self, pos: IVec3) -> Option<BlockId> {
+        let (chunk_key, voxel_pos) = self.world.world_to_voxel(pos);
+        self.world.get_block(chunk_key, voxel_pos)
+    }
+
+    // Block state at `pos`, see Chunk::block_states. None if the chunk isn't loaded.
+    pub fn get_state(&self, pos: IVec3) -> Option<u8> {
+        let (chunk_key, voxel_pos) = self.world.world_to_voxel(pos);
+        self.world.get_state(chunk_key, voxel_pos)
+    }
+
+    pub fn is_solid(&self, pos: IVec3) -> bool {
+        self.get_block(pos).is_some_and(block::is_solid)
+    }
+
+    // Stamps a model with its minimum corner at `origin`, mapping its palette to the closest blocks.
+    // Empty model cells keep what is there, and cells in unloaded chunks are skipped. Every touched
+    // chunk is remeshed and saved through the usual edit path; the returned edits form one undo step.
+    pub fn place_model(&mut self, origin: IVec3, model: &VoxelModel) -> Vec<VoxelEdit> {
+        let blocks = model.block_palette();
+        let mut edits = Vec::new();
+        for &(pos, index) in &model.voxels {
+            let world_pos = origin + pos.as_ivec3();
+            let block = blocks[index as usize];
+            match self.get_block(world_pos) {
+                Some(old) if old != block => {
+                    self.set_block(world_pos, block);
+                    let (chunk_key, voxel_pos) = self.to_chunk_local(world_pos);
+                    edits.push(VoxelEdit { chunk_key, voxel_pos, old, new: block });
+                }
+                _ => {}
+            }
+        }
+        edits
+    }
+
+    // Highest solid voxel in the column at or below `pos`, looking at most `max_depth` voxels down.
+    // None if the column is open or runs into an unloaded chunk first.
+    pub fn ground_below(&self, pos: IVec3, max_depth: i32) -> Option<IVec3> {
+        (0..=max_depth)
+            .map(|depth| pos - IVec3::Y * depth)
+            .map_while(|voxel| self.get_block(voxel).map(|block| (voxel, block)))
+            .find(|&(_, block)| block::is_solid(block))
+            .map(|(voxel, _)| voxel)
+    }
+
+    // Entity mirroring the block entity at `pos`; query its Chest / Sign component for the state.
+    // Blocks placed this frame get their entity at the end of the frame.
+    pub fn block_entity(&self, pos: IVec3) -> Option<Entity> {
+        self.block_entities.get(pos)
+    }
+
+    // Saved state of the block entity at `pos`, available as soon as the block is written
+    pub fn block_entity_data(&self, pos: IVec3) -> Option<&BlockEntityData> {
+        self.world.block_entity_data(pos)
+    }
+
+    // Applied by apply_voxel_events later this frame
+    pub fn set_block(&mut self, pos: IVec3, block: BlockId) {
+        self.set_block_with_state(pos, block, 0);
+    }
+
+    pub fn set_block_with_state(&mut self, pos: IVec3, block: BlockId, state: u8) {
+        let (chunk_key, voxel_pos) = self.world.world_to_voxel(pos);
+        self.set_events.send(VoxelSetEvent { chunk_key, voxel_pos, block, state });
+    }
+
+    // First solid voxel along the ray within max_distance
+    pub fn raycast(&self, ray: Ray3d, max_distance: f32) -> Option<IVec3> {
+        self.world
+            .raycast(ray.origin, ray.direction, max_distance)
+            .map(|(chunk_key, voxel_pos)| self.world.voxel_to_world(chunk_key, voxel_pos))
+    }
+
+    // (hit voxel, empty voxel in front of it along the ray)
+    pub fn raycast_with_previous(&self, ray: Ray3d, max_distance: f32) -> Option<(IVec3, IVec3)> {
+        self.world
+            .raycast_with_previous(ray.origin, ray.direction, max_distance)
+            .map(|((chunk_key, voxel_pos), previous)| (self.world.voxel_to_world(chunk_key, voxel_pos), previous))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+    use crate::{CHUNK_SIZE, DEFAULT_RENDER_DISTANCE};
+
+    fn with_voxel_world(test: impl FnOnce(&VoxelWorld)) {
+        let mut app_world = bevy::ecs::world::World::new();
+        app_world.insert_resource(World::new(CHUNK_SIZE, DEFAULT_RENDER_DISTANCE));
+        app_world.init_resource::<BlockEntities>();
+        app_world.init_resource::<Events<VoxelSetEvent>>();
+        let mut state = SystemState::<VoxelWorld>::new(&mut app_world);
+        test(&state.get_mut(&mut app_world));
+    }
+
+    #[test]
+    fn negative_positions_round_down_into_the_chunk_below() {
+        let last = CHUNK_SIZE - 1;
+        with_voxel_world(|voxel_world| {
+            assert_eq!(voxel_world.to_chunk_local(IVec3::new(-1, -1, -1)), ((-1, -1, -1), (last, last, last)));
+            let far = -(CHUNK_SIZE as i32) - 1;
+            assert_eq!(voxel_world.to_chunk_local(IVec3::new(far, 0, -3)), ((-2, 0, -1), (last, 0, CHUNK_SIZE - 3)));
+            assert_eq!(voxel_world.voxel_at(Vec3::new(-0.5, -0.01, 0.99)), IVec3::new(-1, -1, 0));
+        });
+    }
+
+    #[test]
+    fn chunk_borders_belong_to_the_chunk_they_start() {
+        let size = CHUNK_SIZE as i32;
+        with_voxel_world(|voxel_world| {
+            assert_eq!(voxel_world.to_chunk_local(IVec3::new(size, -size, 0)), ((1, -1, 0), (0, 0, 0)));
+            assert_eq!(voxel_world.to_chunk_local(IVec3::new(size - 1, -size - 1, 0)), ((0, -2, 0), (CHUNK_SIZE - 1, CHUNK_SIZE - 1, 0)));
+        });
+    }
+
+    #[test]
+    fn chunk_local_positions_convert_back_to_the_same_voxel() {
+        let size = CHUNK_SIZE as i32;
+        with_voxel_world(|voxel_world| {
+            for coordinate in [-size - 1, -size, -1, 0, 1, size - 1, size, 3 * size + 5] {
+                let pos = IVec3::new(coordinate, -coordinate, coordinate / 2);
+                let (chunk_key, voxel_pos) = voxel_world.to_chunk_local(pos);
+                assert_eq!(voxel_world.to_world(chunk_key, voxel_pos), pos);
+            }
+        });
+    }
+}
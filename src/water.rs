@@ -0,0 +1,109 @@
+// For the ShaderType derive, see outline.rs
+#![allow(dead_code)]
+
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use crate::tuning::LightingTuning;
+
+pub type TerrainMaterial = ExtendedMaterial<StandardMaterial, VoxelTerrain>;
+pub type WaterMaterial = ExtendedMaterial<StandardMaterial, VoxelWater>;
+
+// Weather and water effects on terrain faces, driven by flags the mesher writes into UV_1:
+// x = 1 on faces looking into water, which get animated caustics fading out with distance
+// from the camera; y = 1 on top faces open to the sky, which darken and turn glossy with wetness.
+// Vertex color alpha marks foliage, which takes on the seasonal tint, and counts the opaque
+// cells around smooth-lit corners for ambient occlusion, see terrain::OCCLUSION_ALPHA_STEP.
+// UV_1.x also carries the block's layer in `block_textures`, see block_textures::pack_shader_flag.
+// The fractions of UV_1 hold each corner's block light (x) and skylight (y); the shader scales
+// skylight by `skylight` and darkens faces from the brighter of the two.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct VoxelTerrain {
+    // Bindings 0-99 belong to the base StandardMaterial
+    #[uniform(100)]
+    pub settings: VoxelTerrainSettings,
+    // Stitched from the content packs' block textures, see block_textures
+    #[texture(101, dimension = "2d_array")]
+    #[sampler(102)]
+    pub block_textures: Handle<Image>,
+}
+
+#[derive(ShaderType, Reflect, Debug, Clone)]
+pub struct VoxelTerrainSettings {
+    pub caustics_strength: f32,
+    // Distance from the camera at which caustics have faded out completely
+    pub caustics_range: f32,
+    // 0 dry, 1 soaked; set from Weather
+    pub wetness: f32,
+    // Hue foliage shifts towards, alpha is how far; set from the WorldClock by seasons
+    pub season_tint: LinearRgba,
+    // Set from LightingTuning: scale of the ambient light, and how much each occluding cell
+    // darkens a corner
+    pub ambient_strength: f32,
+    pub ao_strength: f32,
+    // Fraction of skylight levels left at this time of day, set by day_night
+    pub skylight: f32,
+}
+
+impl MaterialExtension for VoxelTerrain {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/voxel_terrain.wgsl".into()
+    }
+}
+
+// Rippling water surface with foam along shorelines. The water mesher writes a per-vertex
+// foam amount into UV_1.x from neighbouring solid blocks.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct VoxelWater {
+    #[uniform(100)]
+    pub settings: VoxelWaterSettings,
+}
+
+#[derive(ShaderType, Reflect, Debug, Clone)]
+pub struct VoxelWaterSettings {
+    pub foam_color: LinearRgba,
+    pub ripple_strength: f32,
+}
+
+impl MaterialExtension for VoxelWater {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/voxel_water.wgsl".into()
+    }
+}
+
+// Child of a chunk entity holding the chunk's water surface
+#[derive(Component)]
+pub struct ChunkWater;
+
+pub fn water_material() -> WaterMaterial {
+    ExtendedMaterial {
+        base: StandardMaterial {
+            base_color: Color::WHITE,
+            alpha_mode: AlphaMode::Blend,
+            perceptual_roughness: 0.08,
+            reflectance: 0.6,
+            ..default()
+        },
+        extension: VoxelWater {
+            settings: VoxelWaterSettings {
+                foam_color: LinearRgba::new(0.9, 0.95, 1.0, 0.9),
+                ripple_strength: 0.08,
+            },
+        },
+    }
+}
+
+pub fn terrain_extension(block_textures: Handle<Image>) -> VoxelTerrain {
+    VoxelTerrain {
+        settings: VoxelTerrainSettings {
+            caustics_strength: 0.6,
+            caustics_range: 48.0,
+            wetness: 0.0,
+            season_tint: LinearRgba::NONE,
+            ambient_strength: LightingTuning::default().ambient_strength,
+            ao_strength: LightingTuning::default().ao_strength,
+            skylight: 1.0,
+        },
+        block_textures,
+    }
+}
@@ -0,0 +1,308 @@
+use bevy::prelude::*;
+use crate::block::{self, AIR, MAX_SNOW_LAYERS, SNOW_LAYER};
+use crate::block_updates::{BlockUpdates, SNOW_MELT_TICKS};
+use crate::console::{Console, ConsoleCommand};
+use crate::decoration::ChunkRng;
+use crate::dimension::Dimension;
+#[cfg(feature = "render")]
+use crate::outline::ChunkMaterials;
+use crate::player::PlayerBody;
+use crate::voxel_world::VoxelWorld;
+#[cfg(feature = "render")]
+use crate::water::TerrainMaterial;
+use crate::worldgen::WorldGenerator;
+
+pub const WEATHER_SEED: u32 = 0x5e0_57a7;
+// Colder than this, precipitation falls as snow and snow lying around doesn't melt; see
+// WorldGenerator::temperature
+pub const SNOW_TEMPERATURE: f32 = 0.15;
+// Seconds for the sky to cloud over fully or clear up
+const OVERCAST_SECONDS: f32 = 30.0;
+// Seconds of rain until exposed surfaces are soaked, halved in a storm, and of dry weather
+// until they're dry again
+const WETTING_SECONDS: f32 = 20.0;
+const DRYING_SECONDS: f32 = 60.0;
+// Snow settles on a few random columns around the player at a time
+const SURFACE_TICK_SECONDS: f32 = 0.25;
+const SNOW_COLUMNS_PER_TICK: usize = 6;
+const SURFACE_RADIUS: i32 = 24;
+// How far above and below the player a column is searched for its surface
+const SURFACE_SEARCH_HEIGHT: i32 = 32;
+
+// What the sky is doing. Advances on its own while the weatherCycle game rule is on: each
+// state lasts a random while, then rolls the next one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WeatherState {
+    #[default]
+    Clear,
+    Rain,
+    Storm,
+}
+
+impl WeatherState {
+    pub const NAMES: [&'static str; 3] = ["clear", "rain", "storm"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clear" => Some(WeatherState::Clear),
+            "rain" => Some(WeatherState::Rain),
+            "storm" => Some(WeatherState::Storm),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WeatherState::Clear => "clear",
+            WeatherState::Rain => "rain",
+            WeatherState::Storm => "storm",
+        }
+    }
+
+    // Share of the precipitation drops falling, and how overcast the sky gets
+    pub fn intensity(self) -> f32 {
+        match self {
+            WeatherState::Clear => 0.0,
+            WeatherState::Rain => 0.5,
+            WeatherState::Storm => 1.0,
+        }
+    }
+
+    // Seconds the state lasts before the next roll
+    fn duration(self, rng: &mut ChunkRng) -> f32 {
+        let (min, max) = match self {
+            WeatherState::Clear => (300.0, 900.0),
+            WeatherState::Rain => (120.0, 360.0),
+            WeatherState::Storm => (60.0, 180.0),
+        };
+        min + (max - min) * rng.next_f32()
+    }
+
+    // Storms mostly calm down into rain rather than clearing up at once
+    fn next(self, rng: &mut ChunkRng) -> Self {
+        let roll = rng.next_f32();
+        match self {
+            WeatherState::Clear if roll < 0.8 => WeatherState::Rain,
+            WeatherState::Clear => WeatherState::Storm,
+            WeatherState::Rain if roll < 0.6 => WeatherState::Clear,
+            WeatherState::Rain => WeatherState::Storm,
+            WeatherState::Storm if roll < 0.7 => WeatherState::Rain,
+            WeatherState::Storm => WeatherState::Clear,
+        }
+    }
+}
+
+// What comes down where the player is: nothing in clear weather or without a sky, otherwise
+// snow where it's colder than SNOW_TEMPERATURE and rain elsewhere
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precipitation {
+    #[default]
+    Clear,
+    Rain,
+    Snow,
+}
+
+impl Precipitation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Precipitation::Clear => "clear",
+            Precipitation::Rain => "rain",
+            Precipitation::Snow => "snow",
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Weather {
+    pub state: WeatherState,
+    // Until the state machine rolls the next state
+    pub seconds_left: f32,
+    pub precipitation: Precipitation,
+    // 0 dry, 1 soaked. Follows the rain with a delay so surfaces dry off gradually.
+    pub wetness: f32,
+    // 0 clear sky, 1 full storm clouds. Eases towards the state's intensity.
+    pub overcast: f32,
+    surface_timer: Timer,
+    rng: ChunkRng,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        let mut rng = ChunkRng::for_chunk((0, 0, 0), WEATHER_SEED);
+        Self {
+            state: WeatherState::Clear,
+            seconds_left: WeatherState::Clear.duration(&mut rng),
+            precipitation: Precipitation::Clear,
+            wetness: 0.0,
+            overcast: 0.0,
+            surface_timer: Timer::from_seconds(SURFACE_TICK_SECONDS, TimerMode::Repeating),
+            rng,
+        }
+    }
+}
+
+impl Weather {
+    fn set_state(&mut self, state: WeatherState) {
+        self.state = state;
+        self.seconds_left = state.duration(&mut self.rng);
+    }
+}
+
+// `weather` shows the weather, `weather <clear|rain|storm>` changes it for the state's usual time
+pub fn weather_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut weather: ResMut<Weather>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "weather") {
+        let Some(name) = command.args.first() else {
+            console.print(format!(
+                "Weather is {} for {:.0}s, {} here",
+                weather.state.name(), weather.seconds_left, weather.precipitation.name(),
+            ));
+            continue;
+        };
+        match WeatherState::from_name(name) {
+            Some(state) => {
+                weather.set_state(state);
+                console.print(format!("Weather set to {}", state.name()));
+            }
+            None => console.print(format!("Unknown weather '{}', expected one of {}", name, WeatherState::NAMES.join(", "))),
+        }
+    }
+}
+
+// Runs only while the weatherCycle game rule is on
+pub fn advance_weather(time: Res<Time>, mut weather: ResMut<Weather>) {
+    weather.seconds_left -= time.delta_seconds();
+    if weather.seconds_left <= 0.0 {
+        let next = weather.state.next(&mut weather.rng);
+        weather.set_state(next);
+    }
+}
+
+// Picks rain or snow from the temperature where the player stands, and eases the overcast
+pub fn update_precipitation(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    dimension: Res<Dimension>,
+    generator: Res<WorldGenerator>,
+    player_query: Query<&Transform, With<PlayerBody>>,
+) {
+    let precipitation = match player_query.get_single() {
+        _ if weather.state == WeatherState::Clear || !dimension.has_skylight() => Precipitation::Clear,
+        Ok(player) => {
+            let pos = player.translation.floor().as_ivec3();
+            if generator.temperature(pos.x, pos.y, pos.z) < SNOW_TEMPERATURE { Precipitation::Snow } else { Precipitation::Rain }
+        }
+        Err(_) => Precipitation::Clear,
+    };
+    if precipitation != weather.precipitation {
+        weather.precipitation = precipitation;
+    }
+
+    let target = if dimension.has_skylight() { weather.state.intensity() } else { 0.0 };
+    let step = time.delta_seconds() / OVERCAST_SECONDS;
+    let overcast = weather.overcast + (target - weather.overcast).clamp(-step, step);
+    if overcast != weather.overcast {
+        weather.overcast = overcast;
+    }
+}
+
+pub fn update_wetness(time: Res<Time>, mut weather: ResMut<Weather>) {
+    let delta = if weather.precipitation == Precipitation::Rain {
+        time.delta_seconds() * weather.state.intensity() * 2.0 / WETTING_SECONDS
+    } else {
+        -time.delta_seconds() / DRYING_SECONDS
+    };
+    let wetness = (weather.wetness + delta).clamp(0.0, 1.0);
+    // Avoid change detection every frame once fully dry or soaked
+    if wetness != weather.wetness {
+        weather.wetness = wetness;
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn apply_wetness_to_materials(
+    weather: Res<Weather>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut terrain_materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    if !weather.is_changed() {
+        return;
+    }
+
+    let Some(current) = terrain_materials.get(&chunk_materials.standard).map(|material| material.extension.settings.wetness) else {
+        return;
+    };
+    // Every asset write re-uploads the material, so skip steps too small to see,
+    // but always land exactly on fully dry / soaked
+    let at_limit = weather.wetness == 0.0 || weather.wetness == 1.0;
+    if current == weather.wetness || ((current - weather.wetness).abs() < 0.01 && !at_limit) {
+        return;
+    }
+    for handle in chunk_materials.terrain() {
+        if let Some(material) = terrain_materials.get_mut(handle) {
+            material.extension.settings.wetness = weather.wetness;
+        }
+    }
+}
+
+// While it snows, snow settles on random surface columns around the player: a layer on bare
+// ground, or one more on a snow layer until it reaches MAX_SNOW_LAYERS. Every layer schedules a
+// block tick, on which snow lying somewhere warm melts away layer by layer, see block_updates.
+// Edits go through VoxelWorld so the chunks are remeshed and saved like any other change, but
+// they stay out of the undo history.
+pub fn update_surface_snow(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    mut voxel_world: VoxelWorld,
+    mut updates: ResMut<BlockUpdates>,
+    player_query: Query<&Transform, With<PlayerBody>>,
+) {
+    if weather.precipitation != Precipitation::Snow || !weather.surface_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let center = voxel_world.voxel_at(player.translation);
+
+    for _ in 0..SNOW_COLUMNS_PER_TICK {
+        let x = center.x + weather.rng.range(-SURFACE_RADIUS, SURFACE_RADIUS);
+        let z = center.z + weather.rng.range(-SURFACE_RADIUS, SURFACE_RADIUS);
+        let Some(surface) = surface_at(&voxel_world, x, z, center.y) else {
+            continue;
+        };
+        let Some(block) = voxel_world.get_block(surface) else {
+            continue;
+        };
+
+        match block {
+            SNOW_LAYER => {
+                let state = voxel_world.get_state(surface).unwrap_or(0) + 1;
+                if state < MAX_SNOW_LAYERS {
+                    voxel_world.set_block_with_state(surface, SNOW_LAYER, state);
+                    updates.schedule(surface, SNOW_MELT_TICKS);
+                }
+            }
+            block if block::is_solid(block) => {
+                voxel_world.set_block(surface + IVec3::Y, SNOW_LAYER);
+                updates.schedule(surface + IVec3::Y, SNOW_MELT_TICKS);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Topmost non-air voxel of a column near `around_y`, None if it reaches into unloaded chunks
+// or has nothing in range
+fn surface_at(voxel_world: &VoxelWorld, x: i32, z: i32, around_y: i32) -> Option<IVec3> {
+    for y in (around_y - SURFACE_SEARCH_HEIGHT..=around_y + SURFACE_SEARCH_HEIGHT).rev() {
+        let pos = IVec3::new(x, y, z);
+        if voxel_world.get_block(pos)? != AIR {
+            return Some(pos);
+        }
+    }
+    None
+}
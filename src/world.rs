@@ -1,8 +1,209 @@
 use bevy::prelude::*;
+#[cfg(feature = "physics")]
+use bevy_xpbd_3d::prelude::Collider;
 use std::collections::{HashMap, HashSet, VecDeque};
-use crate::terrain::{Chunk, ChunkMeshingTask};
-use std::time::Instant;
-use crate::UNLOAD_GRACE_PERIOD;
+use crate::terrain::{Chunk, ChunkBorders};
+#[cfg(feature = "render")]
+use crate::meshing::ChunkMeshingTask;
+use crate::chunk_pool::ChunkPool;
+use std::path::PathBuf;
+use std::time::Duration;
+use bevy::utils::Instant;
+use crate::{CHUNK_FADE_OUT_SECONDS, MAX_RENDER_DISTANCE, MIN_RENDER_DISTANCE, DEFAULT_WORLD_NAME, UNLOAD_GRACE_PERIOD};
+use crate::{MAX_PENDING_STRUCTURE_CHUNKS, SAVE_RETRY_MAX_SECONDS, SAVE_RETRY_SECONDS};
+use crate::{PREFETCH_LOOKAHEAD_SECONDS, PREFETCH_MAX_RINGS, PREFETCH_MIN_SPEED, PREFETCH_YAW_WEIGHT};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{CHUNK_GENERATION_BUDGET_MS, MAX_CHUNK_LOADS_PER_FRAME, MAX_CHUNK_UNLOADS_PER_FRAME, MAX_MESH_UPLOADS_PER_FRAME};
+#[cfg(target_arch = "wasm32")]
+use crate::{WASM_CHUNK_GENERATION_BUDGET_MS, WASM_MAX_CHUNK_LOADS_PER_FRAME, WASM_MAX_CHUNK_UNLOADS_PER_FRAME, WASM_MAX_MESH_UPLOADS_PER_FRAME};
+use crate::block::{self, BlockId, AIR};
+use crate::dimension::Dimension;
+#[cfg(feature = "render")]
+use crate::lighting::{LightSource, MAX_LIGHT};
+use crate::block_entity::BlockEntityData;
+use crate::block_ids::BlockIdMap;
+use crate::chunk_format;
+use crate::determinism;
+use crate::save::{self, ChunkBlob, FailedRegion};
+use crate::settings::GraphicsSettings;
+use crate::worlds::{self, WorldMeta};
+use crate::worldgen::{OverflowBlock, WorldGenerator};
+use crate::console::{Console, ConsoleCommand};
+use crate::notifications::NotificationEvent;
+
+// Chunks stream in around every entity carrying this, looking ahead along its forward direction:
+// the player, whether or not the camera is with them (see spectator.rs), and whatever else a game
+// wants kept loaded, like a second split-screen player or a cinematic camera. The loaded area is
+// the union of the anchors' render distance cubes. Where anchors compete for the load queue, a
+// higher weight gets an anchor's chunks loaded sooner; at 2.0 a chunk counts as half as far away.
+#[derive(Component)]
+pub struct StreamingAnchor {
+    pub weight: f32,
+}
+
+impl Default for StreamingAnchor {
+    fn default() -> Self {
+        Self { weight: 1.0 }
+    }
+}
+
+// Where a StreamingAnchor is this frame and how it moves, see World::predict_prefetch
+#[derive(Clone, Copy, Debug)]
+pub struct AnchorMotion {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub forward: Vec3,
+}
+
+// Marks an entity as owned by a chunk (particles, block entities, mobs, debug gizmos).
+// Such entities get parented under the chunk entity so unloading the chunk cleans them up.
+#[derive(Component)]
+pub struct ChunkScoped(pub (i32, i32, i32));
+
+// An unloaded chunk's entity shrinking away before it is despawned, so unloading doesn't pop
+#[derive(Component)]
+pub struct ChunkFadeOut {
+    pub timer: Timer,
+    pub origin: Vec3,
+    pub size: f32,
+}
+
+// Caps on main-thread chunk work per frame, so crossing a chunk border spreads
+// generation and mesh uploads over several frames instead of hitching
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkUpdateBudget {
+    pub max_generations_per_frame: usize,
+    pub max_mesh_uploads_per_frame: usize,
+    // Shrinking the render distance can send thousands of chunks out of range at once
+    pub max_unloads_per_frame: usize,
+    // Generation stops early once this much time was spent in a frame; at least one chunk always runs
+    pub generation_time_budget: Duration,
+}
+
+impl Default for ChunkUpdateBudget {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default() -> Self {
+        Self {
+            max_generations_per_frame: MAX_CHUNK_LOADS_PER_FRAME,
+            max_mesh_uploads_per_frame: MAX_MESH_UPLOADS_PER_FRAME,
+            max_unloads_per_frame: MAX_CHUNK_UNLOADS_PER_FRAME,
+            generation_time_budget: Duration::from_secs_f32(CHUNK_GENERATION_BUDGET_MS / 1000.0),
+        }
+    }
+
+    // Browsers have no threads for the task pools (they run on the main thread between frames),
+    // so generation and uploads are sliced much thinner to keep the page responsive
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Self {
+        Self {
+            max_generations_per_frame: WASM_MAX_CHUNK_LOADS_PER_FRAME,
+            max_mesh_uploads_per_frame: WASM_MAX_MESH_UPLOADS_PER_FRAME,
+            max_unloads_per_frame: WASM_MAX_CHUNK_UNLOADS_PER_FRAME,
+            generation_time_budget: Duration::from_secs_f32(WASM_CHUNK_GENERATION_BUDGET_MS / 1000.0),
+        }
+    }
+}
+
+// Every voxel a ray passes through, in order, with the distance at which the ray enters it.
+// Grid traversal after Amanatides & Woo, so unlike sampling at fixed steps it can't slip
+// past the corner of a voxel.
+pub struct VoxelRay {
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    voxel: IVec3,
+    step: IVec3,
+    // Distance along the ray to the next voxel boundary on each axis, and between two boundaries
+    t_max: Vec3,
+    t_delta: Vec3,
+    t: f32,
+}
+
+impl VoxelRay {
+    // Nudge past a boundary when restarting, so the traversal doesn't start in the voxel it left
+    pub const EPSILON: f32 = 1e-3;
+
+    pub fn new(origin: Vec3, direction: Dir3, max_distance: f32) -> Self {
+        let mut ray = Self {
+            origin,
+            direction: *direction,
+            max_distance,
+            voxel: IVec3::ZERO,
+            step: IVec3::ZERO,
+            t_max: Vec3::ZERO,
+            t_delta: Vec3::ZERO,
+            t: 0.0,
+        };
+        ray.restart_at(0.0);
+        ray
+    }
+
+    // Continues the traversal from `distance` along the ray, skipping every voxel before it
+    pub fn restart_at(&mut self, distance: f32) {
+        let position = self.origin + self.direction * distance;
+        self.voxel = position.floor().as_ivec3();
+        self.t = distance;
+        for axis in 0..3 {
+            let d = self.direction[axis];
+            if d == 0.0 {
+                self.step[axis] = 0;
+                self.t_delta[axis] = f32::INFINITY;
+                self.t_max[axis] = f32::INFINITY;
+                continue;
+            }
+            let boundary = if d > 0.0 { self.voxel[axis] + 1 } else { self.voxel[axis] };
+            self.step[axis] = d.signum() as i32;
+            self.t_delta[axis] = 1.0 / d.abs();
+            self.t_max[axis] = distance + (boundary as f32 - position[axis]) / d;
+        }
+    }
+}
+
+impl Iterator for VoxelRay {
+    type Item = (IVec3, f32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.t > self.max_distance {
+            return None;
+        }
+        let current = (self.voxel, self.t);
+        let axis = if self.t_max.x <= self.t_max.y && self.t_max.x <= self.t_max.z {
+            0
+        } else if self.t_max.y <= self.t_max.z {
+            1
+        } else {
+            2
+        };
+        self.t = self.t_max[axis];
+        self.voxel[axis] += self.step[axis];
+        self.t_max[axis] += self.t_delta[axis];
+        Some(current)
+    }
+}
+
+// A voxel as the chunk holding it and its position inside that chunk
+pub type ChunkVoxel = ((i32, i32, i32), (usize, usize, usize));
+
+// Structure overflow for a chunk that isn't loaded yet, see worldgen::OverflowBlock
+#[derive(Clone, Copy, Debug)]
+pub struct PendingBlock {
+    pub voxel_pos: (usize, usize, usize),
+    pub block: BlockId,
+    pub replace: bool,
+}
+
+// A PendingBlock with the chunk it waits for, as stored in pending structure files
+pub type ChunkPendingBlock = ((i32, i32, i32), PendingBlock);
+
+// A generated chunk's key and determinism::hash_generated_chunk of it
+pub type GeneratedHash = ((i32, i32, i32), u64);
+
+// A region whose last write failed, and when writing it may be tried again
+#[derive(Clone, Copy, Debug)]
+pub struct SaveBackoff {
+    pub failures: u32,
+    pub retry_at: Instant,
+}
 
 #[derive(Resource)]
 pub struct World {
@@ -10,12 +211,41 @@ pub struct World {
     pub chunk_entities: HashMap<(i32, i32, i32), Entity>,
     pub chunk_size: usize,
     pub render_distance: i32,
-    pub last_player_chunk: (i32, i32, i32),
+    // Chunks the StreamingAnchors stood in at the last update_chunks
+    pub anchor_chunks: Vec<(i32, i32, i32)>,
     pub chunk_load_queue: VecDeque<(i32, i32, i32)>,
     pub chunk_unload_queue: VecDeque<(i32, i32, i32)>,
     pub chunk_last_accessed: HashMap<(i32, i32, i32), Instant>,
     pub unload_grace_period: f32,
-    pub chunk_loading_queue: Vec<(i32, i32, i32)>
+    pub graphics: GraphicsSettings,
+    // Structure blocks waiting for their chunk to generate, keyed by that chunk
+    pub pending_structures: HashMap<(i32, i32, i32), Vec<PendingBlock>>,
+    // Chunks edited since they were loaded; these are written to disk before they unload
+    pub modified_chunks: HashSet<(i32, i32, i32)>,
+    // Chunks an autosave is writing in the background. They stay loaded until it finishes.
+    pub saving_chunks: HashSet<(i32, i32, i32)>,
+    // Regions whose last write failed, by region key. Their chunks wait out the backoff before
+    // they're written again, instead of rewriting the region every frame on a full disk.
+    pub save_backoff: HashMap<(i32, i32, i32), SaveBackoff>,
+    // Where edited chunks are saved, None keeps edits in memory only
+    pub save_dir: Option<PathBuf>,
+    // The save's block IDs, see block_ids.rs
+    pub block_ids: BlockIdMap,
+    // Seconds an unloaded chunk takes to fade out, 0 despawns immediately
+    pub fade_out_duration: f32,
+    // Messages for the player from World methods, sent as NotificationEvents by forward_world_notifications
+    pub notifications: Vec<NotificationEvent>,
+    // Light sources placed or removed since the last remesh_edited, which relights around them
+    pub light_changes: Vec<IVec3>,
+    // Chunks past the render distance in the direction of travel, loaded at lowest priority
+    pub prefetch_chunks: HashSet<(i32, i32, i32)>,
+    // Decides whether meshing adds skylight; the generator comes with it as its own resource
+    pub dimension: Dimension,
+    // Chunks waiting to be remeshed by flush_dirty_chunks, in the order they were marked
+    pub dirty_chunks: Vec<(i32, i32, i32)>,
+    // Hashes of the chunks generated since the determinism audit last took them, None unless
+    // an audit is running
+    pub generated_hashes: Option<Vec<GeneratedHash>>,
 }
 
 impl World {
@@ -25,150 +255,756 @@ impl World {
             chunk_entities: HashMap::new(),
             chunk_size,
             render_distance,
-            last_player_chunk: (0, 0, 0),
+            anchor_chunks: Vec::new(),
             chunk_load_queue: VecDeque::new(),
             chunk_unload_queue: VecDeque::new(),
             chunk_last_accessed: HashMap::new(),
             unload_grace_period: UNLOAD_GRACE_PERIOD,
-            chunk_loading_queue: Vec::new(),
+            graphics: GraphicsSettings::default(),
+            pending_structures: HashMap::new(),
+            modified_chunks: HashSet::new(),
+            saving_chunks: HashSet::new(),
+            save_backoff: HashMap::new(),
+            // Without `persistence` edits stay in memory unless save_dir is set by hand
+            save_dir: cfg!(feature = "persistence").then(|| worlds::world_directory(DEFAULT_WORLD_NAME)),
+            block_ids: BlockIdMap::default(),
+            fade_out_duration: CHUNK_FADE_OUT_SECONDS,
+            notifications: Vec::new(),
+            light_changes: Vec::new(),
+            prefetch_chunks: HashSet::new(),
+            dimension: Dimension::Overworld,
+            dirty_chunks: Vec::new(),
+            generated_hashes: None,
         }
     }
 
-    pub fn update_chunks(&mut self, player_chunk_x: i32, player_chunk_y: i32, player_chunk_z: i32) {
-        self.last_player_chunk = (player_chunk_x, player_chunk_y, player_chunk_z);
+    // A world saving to `dimension`'s part of the world's directory, with its block IDs, and lit like it
+    pub fn for_dimension(chunk_size: usize, render_distance: i32, dimension: Dimension, meta: &WorldMeta) -> Self {
+        Self {
+            save_dir: cfg!(feature = "persistence").then(|| dimension.save_dir(&meta.directory())),
+            block_ids: meta.block_ids(),
+            dimension,
+            ..Self::new(chunk_size, render_distance)
+        }
+    }
+
+    // Takes effect on the next update_chunks: the new ring of chunks joins the load queue
+    // nearest-first, or the chunks now out of range unload over the next frames after their
+    // grace period. Returns the distance actually set.
+    pub fn set_render_distance(&mut self, render_distance: i32) -> i32 {
+        self.render_distance = render_distance.clamp(MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE);
+        self.render_distance
+    }
+
+    // Queues loads for the chunks around `anchor_chunks` (the chunk each StreamingAnchor is in) and
+    // unloads for the loaded chunks no anchor wants any more
+    pub fn update_chunks(&mut self, anchor_chunks: &[(i32, i32, i32)]) {
+        self.anchor_chunks = anchor_chunks.to_vec();
         let now = Instant::now();
 
         // Clear previous queues
         self.chunk_load_queue.clear();
         self.chunk_unload_queue.clear();
 
-        // Determine chunks to load
-        for x in (player_chunk_x - self.render_distance)..=(player_chunk_x + self.render_distance) {
-            for y in (player_chunk_y - self.render_distance)..=(player_chunk_y + self.render_distance) {
-                for z in (player_chunk_z - self.render_distance)..=(player_chunk_z + self.render_distance) {
-                    let chunk_key = (x, y, z);
-                    if !self.chunks.contains_key(&chunk_key) {
-                        self.chunk_load_queue.push_back(chunk_key);
+        // Desired set: the full cube around every anchor, overlapping cubes queued once
+        let mut desired = HashSet::new();
+        for &(anchor_x, anchor_y, anchor_z) in anchor_chunks {
+            for x in (anchor_x - self.render_distance)..=(anchor_x + self.render_distance) {
+                for y in (anchor_y - self.render_distance)..=(anchor_y + self.render_distance) {
+                    for z in (anchor_z - self.render_distance)..=(anchor_z + self.render_distance) {
+                        let chunk_key = (x, y, z);
+                        if !desired.insert(chunk_key) {
+                            continue;
+                        }
+                        if !self.chunks.contains_key(&chunk_key) {
+                            self.chunk_load_queue.push_back(chunk_key);
+                        }
+                        self.chunk_last_accessed.insert(chunk_key, now);
                     }
-                    self.chunk_last_accessed.insert(chunk_key, now);
                 }
             }
         }
 
-        // Determine chunks to unload
+        // Prefetched chunks are kept like any other desired chunk until the direction changes
+        for &chunk_key in &self.prefetch_chunks {
+            if desired.insert(chunk_key) {
+                if !self.chunks.contains_key(&chunk_key) {
+                    self.chunk_load_queue.push_back(chunk_key);
+                }
+                self.chunk_last_accessed.insert(chunk_key, now);
+            }
+        }
+
+        // Every loaded chunk outside the desired set unloads once its grace period has passed,
+        // however far it is from the anchors
         let chunks_to_remove: Vec<(i32, i32, i32)> = self.chunks.keys()
-            .filter(|&&key| {
-                let (x, y, z) = key;
-                let distance = ((x - player_chunk_x).pow(2) + (y - player_chunk_y).pow(2) + (z - player_chunk_z).pow(2)) as f32;
-                distance > (self.render_distance as f32).powi(2)
-            })
+            .filter(|key| !desired.contains(key))
             .cloned()
             .collect();
 
         for chunk_key in chunks_to_remove {
-            if let Some(last_accessed) = self.chunk_last_accessed.get(&chunk_key) {
-                if now.duration_since(*last_accessed).as_secs_f32() > self.unload_grace_period {
-                    self.chunk_unload_queue.push_back(chunk_key);
-                }
+            let expired = self.chunk_last_accessed.get(&chunk_key)
+                .is_none_or(|last_accessed| now.duration_since(*last_accessed).as_secs_f32() > self.unload_grace_period);
+            if expired {
+                self.chunk_unload_queue.push_back(chunk_key);
             }
         }
+
+        // Forget access times of chunks that went out of range without ever loading
+        let chunks = &self.chunks;
+        self.chunk_last_accessed.retain(|key, _| desired.contains(key) || chunks.contains_key(key));
+
+        // Overflow waiting for chunks the anchors left behind would otherwise pile up forever. It
+        // moves to its region's pending file, because the chunk that spilled it may have been
+        // saved and never generate again. Without a save directory nothing is ever saved, so that
+        // chunk generates again when an anchor comes back and writes it anew; dropping it is safe.
+        // The margin past the loaded area covers structures reaching a couple of chunks out.
+        // A large render distance can still gather more than MAX_PENDING_STRUCTURE_CHUNKS nearby;
+        // the farthest of those go the same way.
+        let keep_distance = self.render_distance + PREFETCH_MAX_RINGS + 2;
+        let anchor_distance = |&(x, y, z): &(i32, i32, i32)| {
+            anchor_chunks.iter()
+                .map(|&(ax, ay, az)| (IVec3::new(x, y, z) - IVec3::new(ax, ay, az)).abs().max_element())
+                .min()
+                .unwrap_or(i32::MAX)
+        };
+        let (mut kept, mut left_behind): (Vec<_>, Vec<_>) = self.pending_structures.keys()
+            .map(|chunk_key| (anchor_distance(chunk_key), *chunk_key))
+            .partition(|&(distance, _)| distance <= keep_distance);
+        if kept.len() > MAX_PENDING_STRUCTURE_CHUNKS {
+            kept.sort_unstable();
+            left_behind.extend(kept.drain(MAX_PENDING_STRUCTURE_CHUNKS..));
+        }
+        let left_behind: Vec<_> = left_behind.into_iter().map(|(_, chunk_key)| chunk_key).collect();
+        self.store_pending_structures(&left_behind);
     }
 
-    pub fn raycast(&self, origin: Vec3, direction: Dir3, max_distance: f32) -> Option<((i32, i32, i32), (usize, usize, usize))> {
-        let dir = direction.normalize();
-        let step = 0.1; // Smaller step for more precision
-        let mut current_pos = origin;
+    // Moves the structure overflow waiting for `chunk_keys` out of memory into their regions'
+    // pending files, where load_or_generate_chunk picks it up. Dropped without a save directory.
+    pub fn store_pending_structures(&mut self, chunk_keys: &[(i32, i32, i32)]) {
+        let blocks: Vec<_> = chunk_keys.iter().filter_map(|chunk_key| self.pending_structures.remove_entry(chunk_key)).collect();
+        let Some(save_dir) = self.save_dir.as_ref() else {
+            return;
+        };
+        if blocks.is_empty() {
+            return;
+        }
+        if let Err(err) = save::save_pending_blocks(save_dir, blocks, &self.block_ids) {
+            self.notifications.push(NotificationEvent::error(format!("Could not save structure overflow: {}", err)));
+        }
+    }
 
-        for _ in 0..((max_distance / step) as i32) {
-            let chunk_x = (current_pos.x / self.chunk_size as f32).floor() as i32;
-            let chunk_y = (current_pos.y / self.chunk_size as f32).floor() as i32;
-            let chunk_z = (current_pos.z / self.chunk_size as f32).floor() as i32;
-            let chunk_key = (chunk_x, chunk_y, chunk_z);
+    // Picks the chunks to prefetch: a few rings past the render distance along each anchor's
+    // direction of travel, more the faster it moves, so fast flight finds terrain already
+    // generated. The view yaw bends the direction a little, since players mostly fly where
+    // they look. Nothing is prefetched at walking speed, nor anything another anchor already
+    // has within its render distance.
+    pub fn predict_prefetch(&mut self, anchors: &[AnchorMotion]) {
+        self.prefetch_chunks.clear();
+        let size = self.chunk_size as f32;
+        let anchor_keys: Vec<IVec3> = anchors.iter().map(|anchor| (anchor.position / size).floor().as_ivec3()).collect();
 
-            if let Some(chunk) = self.chunks.get(&chunk_key) {
-                let local_x = (current_pos.x.rem_euclid(self.chunk_size as f32) as usize).min(self.chunk_size - 1);
-                let local_y = (current_pos.y.rem_euclid(self.chunk_size as f32) as usize).min(self.chunk_size - 1);
-                let local_z = (current_pos.z.rem_euclid(self.chunk_size as f32) as usize).min(self.chunk_size - 1);
+        for anchor in anchors {
+            let speed = anchor.velocity.length();
+            if speed < PREFETCH_MIN_SPEED {
+                continue;
+            }
+
+            let yaw = Vec3::new(anchor.forward.x, 0.0, anchor.forward.z).normalize_or_zero();
+            let direction = (anchor.velocity / speed + yaw * PREFETCH_YAW_WEIGHT).normalize_or_zero();
+            if direction == Vec3::ZERO {
+                continue;
+            }
+            // Stretched so each step crosses one ring of the cube the render distance spans
+            let step = direction / direction.abs().max_element();
 
-                if chunk.get_voxel(local_x, local_y, local_z) {
-                    return Some((chunk_key, (local_x, local_y, local_z)));
+            let rings = ((speed * PREFETCH_LOOKAHEAD_SECONDS / size).ceil() as i32).min(PREFETCH_MAX_RINGS);
+            let anchor_chunk = (anchor.position / size).floor();
+            for ring in 1..=rings {
+                let center = (anchor_chunk + step * (self.render_distance + ring) as f32).round().as_ivec3();
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        for dz in -1..=1 {
+                            let key = center + IVec3::new(dx, dy, dz);
+                            if anchor_keys.iter().all(|&anchor_key| (key - anchor_key).abs().max_element() > self.render_distance) {
+                                self.prefetch_chunks.insert((key.x, key.y, key.z));
+                            }
+                        }
+                    }
                 }
             }
+        }
+    }
+
+    pub fn raycast(&self, origin: Vec3, direction: Dir3, max_distance: f32) -> Option<ChunkVoxel> {
+        self.raycast_with_previous(origin, direction, max_distance).map(|(hit, _)| hit)
+    }
 
-            current_pos += dir * step;
+    // Like raycast, but also returns the last empty voxel the ray passed through before the hit,
+    // which is where a placed block should go
+    pub fn raycast_with_previous(&self, origin: Vec3, direction: Dir3, max_distance: f32) -> Option<(ChunkVoxel, IVec3)> {
+        let _span = info_span!("voxel_raycast").entered();
+        let mut ray = VoxelRay::new(origin, direction, max_distance);
+        let mut previous_voxel = origin.floor().as_ivec3();
+
+        while let Some((voxel, _)) = ray.next() {
+            let (chunk_key, voxel_pos) = self.world_to_voxel(voxel);
+            let empty = |chunk: &&Chunk| chunk.voxels.uniform_block().is_some_and(|block| !block::is_targetable(block));
+            let chunk = self.chunks.get(&chunk_key).filter(|chunk| !empty(chunk));
+            let Some(chunk) = chunk else {
+                // Nothing to hit in unloaded or uniformly empty chunks, so jump to where the ray leaves them
+                let exit = self.chunk_exit_distance(chunk_key, origin, *direction);
+                previous_voxel = (origin + *direction * (exit - VoxelRay::EPSILON)).floor().as_ivec3();
+                ray.restart_at(exit + VoxelRay::EPSILON);
+                continue;
+            };
+            if block::is_targetable(chunk.get_block(voxel_pos.0, voxel_pos.1, voxel_pos.2)) {
+                return Some(((chunk_key, voxel_pos), previous_voxel));
+            }
+            previous_voxel = voxel;
         }
 
         None
     }
 
-    pub fn remove_voxel(&mut self, chunk_key: (i32, i32, i32), voxel_pos: (usize, usize, usize), commands: &mut Commands) {
-        println!("Attempting to remove voxel at chunk {:?}, position {:?}", chunk_key, voxel_pos);
+    // Distance along the ray at which it leaves the chunk it is in
+    fn chunk_exit_distance(&self, chunk_key: (i32, i32, i32), origin: Vec3, direction: Vec3) -> f32 {
+        let size = self.chunk_size as f32;
+        let min = Vec3::new(chunk_key.0 as f32, chunk_key.1 as f32, chunk_key.2 as f32) * size;
+        (0..3)
+            .filter(|&axis| direction[axis] != 0.0)
+            .map(|axis| {
+                let boundary = if direction[axis] > 0.0 { min[axis] + size } else { min[axis] };
+                (boundary - origin[axis]) / direction[axis]
+            })
+            .fold(f32::INFINITY, f32::min)
+    }
 
-        if let Some(chunk) = self.chunks.get_mut(&chunk_key) {
-            let (x, y, z) = voxel_pos;
-            if chunk.get_voxel(x, y, z) {
-                chunk.set_voxel(x, y, z, false);
-                println!("Voxel removed successfully");
+    pub fn world_to_voxel(&self, world_pos: IVec3) -> ((i32, i32, i32), (usize, usize, usize)) {
+        split_world_pos(world_pos, self.chunk_size)
+    }
 
-                // Regenerate mesh for the modified chunk
-                if let Some(entity) = self.chunk_entities.get(&chunk_key) {
-                    let task = chunk.generate_mesh_task(chunk_key);
-                    commands.spawn(task);
-                    println!("Mesh regeneration task spawned for chunk {:?}", chunk_key);
-                } else {
-                    println!("Could not find entity for chunk {:?}", chunk_key);
+    pub fn voxel_to_world(&self, chunk_key: (i32, i32, i32), voxel_pos: (usize, usize, usize)) -> IVec3 {
+        let size = self.chunk_size as i32;
+        IVec3::new(
+            chunk_key.0 * size + voxel_pos.0 as i32,
+            chunk_key.1 * size + voxel_pos.1 as i32,
+            chunk_key.2 * size + voxel_pos.2 as i32,
+        )
+    }
+
+    pub fn get_block(&self, chunk_key: (i32, i32, i32), voxel_pos: (usize, usize, usize)) -> Option<BlockId> {
+        let (x, y, z) = voxel_pos;
+        self.chunks.get(&chunk_key).map(|chunk| chunk.get_block(x, y, z))
+    }
+
+    // Writes a block without remeshing and returns the block it replaced, None if the chunk isn't loaded.
+    // Gameplay code should send a VoxelSetEvent instead so observers and remeshing stay in sync.
+    pub fn set_block(&mut self, chunk_key: (i32, i32, i32), voxel_pos: (usize, usize, usize), block: BlockId) -> Option<BlockId> {
+        let (x, y, z) = voxel_pos;
+        let chunk = self.chunks.get_mut(&chunk_key)?;
+        let old = chunk.get_block(x, y, z);
+        chunk.set_block(x, y, z, block);
+        if old != block {
+            chunk.edits.insert(voxel_pos, block);
+            self.modified_chunks.insert(chunk_key);
+            if block::light_emission(old) > 0 || block::light_emission(block) > 0 {
+                self.light_changes.push(self.voxel_to_world(chunk_key, voxel_pos));
+            }
+        }
+        Some(old)
+    }
+
+    pub fn get_state(&self, chunk_key: (i32, i32, i32), voxel_pos: (usize, usize, usize)) -> Option<u8> {
+        let (x, y, z) = voxel_pos;
+        self.chunks.get(&chunk_key).map(|chunk| chunk.get_state(x, y, z))
+    }
+
+    // Like set_block for the block state, see Chunk::block_states. Returns the old state.
+    pub fn set_state(&mut self, chunk_key: (i32, i32, i32), voxel_pos: (usize, usize, usize), state: u8) -> Option<u8> {
+        let (x, y, z) = voxel_pos;
+        let chunk = self.chunks.get_mut(&chunk_key)?;
+        let old = chunk.get_state(x, y, z);
+        if old != state {
+            chunk.set_state(x, y, z, state);
+            self.modified_chunks.insert(chunk_key);
+        }
+        Some(old)
+    }
+
+    pub fn block_entity_data(&self, pos: IVec3) -> Option<&BlockEntityData> {
+        let (chunk_key, voxel_pos) = self.world_to_voxel(pos);
+        self.chunks.get(&chunk_key)?.block_entities.get(&voxel_pos)
+    }
+
+    // Replaces the state of an existing block entity; ignored if there is none at `pos`
+    pub fn set_block_entity_data(&mut self, pos: IVec3, data: BlockEntityData) {
+        let (chunk_key, voxel_pos) = self.world_to_voxel(pos);
+        let Some(entry) = self.chunks.get_mut(&chunk_key).and_then(|chunk| chunk.block_entities.get_mut(&voxel_pos)) else {
+            return;
+        };
+        if *entry != data {
+            *entry = data;
+            self.modified_chunks.insert(chunk_key);
+        }
+    }
+
+    // Remeshes every chunk touched by a batch of edits exactly once. Neighbouring chunks are
+    // only included when an edit sits on the shared border, since only then do their faces change.
+    // Edits within reach of a light source, and light sources placed or broken, change block
+    // light up to MAX_LIGHT cells away, so every chunk in that range is relit as well.
+    #[cfg(feature = "render")]
+    pub fn remesh_edited(&mut self, edited: impl IntoIterator<Item = ((i32, i32, i32), (usize, usize, usize))>, commands: &mut Commands) {
+        let last = self.chunk_size - 1;
+        let mut to_remesh = HashSet::new();
+        let mut relit = std::mem::take(&mut self.light_changes);
+        // Region edits touch many cells per chunk, look each chunk's sources up once
+        let mut sources_by_chunk: HashMap<(i32, i32, i32), Vec<LightSource>> = HashMap::new();
+
+        for ((cx, cy, cz), (x, y, z)) in edited {
+            to_remesh.insert((cx, cy, cz));
+            if x == 0 { to_remesh.insert((cx - 1, cy, cz)); }
+            if x == last { to_remesh.insert((cx + 1, cy, cz)); }
+            if y == 0 { to_remesh.insert((cx, cy - 1, cz)); }
+            if y == last { to_remesh.insert((cx, cy + 1, cz)); }
+            if z == 0 { to_remesh.insert((cx, cy, cz - 1)); }
+            if z == last { to_remesh.insert((cx, cy, cz + 1)); }
+
+            let pos = self.voxel_to_world((cx, cy, cz), (x, y, z));
+            let lit = sources_by_chunk.entry((cx, cy, cz))
+                .or_insert_with(|| self.light_sources_around((cx, cy, cz)))
+                .iter()
+                .any(|source| (source.pos - pos).abs().element_sum() < source.level as i32);
+            if lit {
+                relit.push(pos);
+            }
+        }
+
+        let size = self.chunk_size as i32;
+        for pos in relit {
+            let min = (pos - IVec3::splat(MAX_LIGHT as i32)).div_euclid(IVec3::splat(size));
+            let max = (pos + IVec3::splat(MAX_LIGHT as i32)).div_euclid(IVec3::splat(size));
+            for cx in min.x..=max.x {
+                for cy in min.y..=max.y {
+                    for cz in min.z..=max.z {
+                        to_remesh.insert((cx, cy, cz));
+                    }
                 }
+            }
+        }
 
-                // Check and update neighboring chunks if necessary
-                self.update_neighboring_chunks(chunk_key, commands);
-            } else {
-                println!("No voxel found at the specified position");
+        for chunk_key in to_remesh {
+            if let Some(task) = self.mesh_task(chunk_key) {
+                commands.spawn(task);
+            }
+        }
+    }
+
+    // Without `render` there are no meshes to keep up to date
+    #[cfg(not(feature = "render"))]
+    pub fn remesh_edited(&mut self, _edited: impl IntoIterator<Item = ((i32, i32, i32), (usize, usize, usize))>, _commands: &mut Commands) {
+        self.light_changes.clear();
+    }
+
+    // Light sources in a chunk and the 26 around it, with positions in world space
+    #[cfg(feature = "render")]
+    fn light_sources_around(&self, chunk_key: (i32, i32, i32)) -> Vec<LightSource> {
+        let mut sources = Vec::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let key = (chunk_key.0 + dx, chunk_key.1 + dy, chunk_key.2 + dz);
+                    let Some(chunk) = self.chunks.get(&key) else {
+                        continue;
+                    };
+                    // Every light source is a block entity, so there's no need to scan the voxels
+                    for &voxel_pos in chunk.block_entities.keys() {
+                        let level = block::light_emission(chunk.get_block(voxel_pos.0, voxel_pos.1, voxel_pos.2));
+                        if level > 0 {
+                            sources.push(LightSource { pos: self.voxel_to_world(key, voxel_pos), level });
+                        }
+                    }
+                }
             }
-        } else {
-            println!("Chunk not found for key {:?}", chunk_key);
         }
+        sources
     }
 
-    fn update_neighboring_chunks(&mut self, chunk_key: (i32, i32, i32), commands: &mut Commands) {
-        let (cx, cy, cz) = chunk_key;
-        let neighbors = [
-            (cx - 1, cy, cz), (cx + 1, cy, cz),
-            (cx, cy - 1, cz), (cx, cy + 1, cz),
-            (cx, cy, cz - 1), (cx, cy, cz + 1),
-        ];
+    #[cfg(feature = "render")]
+    pub fn remesh_all(&mut self, commands: &mut Commands) {
+        for &chunk_key in self.chunks.keys() {
+            commands.spawn(self.mesh_task(chunk_key).unwrap());
+        }
+    }
 
-        for neighbor_key in neighbors.iter() {
-            if let Some(chunk) = self.chunks.get(neighbor_key) {
-                let task = chunk.generate_mesh_task(*neighbor_key);
+    // Meshing task for a loaded chunk, with the facing layers of its loaded neighbours so
+    // faces against them are culled, and the light sources shining into it
+    #[cfg(feature = "render")]
+    pub fn mesh_task(&self, chunk_key: (i32, i32, i32)) -> Option<ChunkMeshingTask> {
+        let chunk = self.chunks.get(&chunk_key)?;
+        let mut borders = ChunkBorders { skylight: self.dimension.has_skylight(), ..default() };
+        for (direction, offset) in ChunkBorders::DIRECTIONS.iter().enumerate() {
+            let neighbour_key = (chunk_key.0 + offset.x, chunk_key.1 + offset.y, chunk_key.2 + offset.z);
+            borders.layers[direction] = self.chunks.get(&neighbour_key)
+                .map(|neighbour| ChunkBorders::extract_layer(neighbour, direction));
+        }
+        // Keep the sources whose light reaches into the chunk, moved into its local space
+        let origin = self.voxel_to_world(chunk_key, (0, 0, 0));
+        let size = IVec3::splat(self.chunk_size as i32);
+        borders.lights = self.light_sources_around(chunk_key).into_iter()
+            .map(|source| LightSource { pos: source.pos - origin, ..source })
+            .filter(|source| {
+                let outside = (-source.pos).max(source.pos - (size - IVec3::ONE)).max(IVec3::ZERO);
+                outside.element_sum() < source.level as i32
+            })
+            .collect();
+        // Light from outside has to find its way through the neighbours' blocks
+        if borders.lights.iter().any(|source| source.pos.cmplt(IVec3::ZERO).any() || source.pos.cmpge(size).any()) {
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let offset = IVec3::new(dx, dy, dz);
+                        if offset == IVec3::ZERO {
+                            continue;
+                        }
+                        borders.neighbours[ChunkBorders::neighbour_index(offset)] = self.chunks
+                            .get(&(chunk_key.0 + dx, chunk_key.1 + dy, chunk_key.2 + dz))
+                            .map(|neighbour| neighbour.voxels.clone());
+                    }
+                }
+            }
+        }
+        Some(chunk.generate_mesh_task(chunk_key, self.graphics, borders))
+    }
+
+    // Marks a chunk that just became available for meshing, along with its loaded neighbours,
+    // whose border faces were built against nothing while it was missing. A neighbour bordering
+    // several chunks that load in the same frame is remeshed once, by flush_dirty_chunks.
+    pub fn mark_new_chunk(&mut self, chunk_key: (i32, i32, i32)) {
+        if !self.chunks.contains_key(&chunk_key) {
+            return;
+        }
+        self.mark_dirty(chunk_key);
+        for offset in ChunkBorders::DIRECTIONS {
+            let neighbour_key = (chunk_key.0 + offset.x, chunk_key.1 + offset.y, chunk_key.2 + offset.z);
+            if self.chunks.contains_key(&neighbour_key) {
+                self.mark_dirty(neighbour_key);
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, chunk_key: (i32, i32, i32)) {
+        if !self.dirty_chunks.contains(&chunk_key) {
+            self.dirty_chunks.push(chunk_key);
+        }
+    }
+
+    // Starts a meshing task for every chunk marked dirty that is still loaded
+    #[cfg(feature = "render")]
+    pub fn flush_dirty_chunks(&mut self, commands: &mut Commands) {
+        for chunk_key in std::mem::take(&mut self.dirty_chunks) {
+            if let Some(task) = self.mesh_task(chunk_key) {
                 commands.spawn(task);
-                println!("Mesh regeneration task spawned for neighboring chunk {:?}", neighbor_key);
             }
         }
     }
 
-    pub fn process_queue(&mut self, commands: &mut Commands, materials: &mut ResMut<Assets<StandardMaterial>>, meshes: &mut ResMut<Assets<Mesh>>) {
-        // Process load queue
-        while let Some(chunk_key) = self.chunk_load_queue.pop_front() {
+    #[cfg(not(feature = "render"))]
+    pub fn flush_dirty_chunks(&mut self, _commands: &mut Commands) {
+        self.dirty_chunks.clear();
+    }
+
+    pub fn process_queue(&mut self, generator: &WorldGenerator, pool: &mut ChunkPool, budget: &ChunkUpdateBudget, commands: &mut Commands) {
+        // Process the front of the load queue, which prioritize_chunks keeps nearest-first.
+        // update_chunks rebuilds the queue every frame, so whatever is left is picked up again.
+        let frame_start = Instant::now();
+        let mut loads = 0;
+        while loads < budget.max_generations_per_frame {
+            if loads > 0 && frame_start.elapsed() > budget.generation_time_budget {
+                break;
+            }
+
+            let Some(chunk_key) = self.chunk_load_queue.pop_front() else {
+                break;
+            };
             if !self.chunks.contains_key(&chunk_key) {
-                let mut chunk = Chunk::new(self.chunk_size, self.chunk_size, self.chunk_size);
-                chunk.generate_terrain(chunk_key.0, chunk_key.1, chunk_key.2);
-                self.chunks.insert(chunk_key, chunk);
+                loads += 1;
+                let neighbour_edits = self.load_or_generate_chunk(chunk_key, generator, pool);
 
-                let task = self.chunks[&chunk_key].generate_mesh_task(chunk_key);
-                commands.spawn(task);
+                self.mark_new_chunk(chunk_key);
+                if !neighbour_edits.is_empty() {
+                    self.remesh_edited(neighbour_edits, commands);
+                }
             }
         }
+        self.flush_dirty_chunks(commands);
+
+        // Process unload queue. update_chunks rebuilds it every frame as well, so chunks over
+        // the budget simply go in a later frame.
+        let count = budget.max_unloads_per_frame.min(self.chunk_unload_queue.len());
+        let unloading: Vec<_> = self.chunk_unload_queue.drain(..count).collect();
+        // Edits must reach disk before the chunk is dropped, each region written once for all
+        // its chunks; the ones that couldn't be saved stay loaded
+        let (saved, unsaved) = self.save_modified(&unloading, false);
+        for chunk_key in unloading {
+            if unsaved.contains(&chunk_key) {
+                continue;
+            }
 
-        // Process unload queue
-        while let Some(chunk_key) = self.chunk_unload_queue.pop_front() {
             if let Some(entity) = self.chunk_entities.remove(&chunk_key) {
-                commands.entity(entity).despawn();
+                if self.fade_out_duration > 0.0 {
+                    let size = self.chunk_size as f32;
+                    let origin = Vec3::new(chunk_key.0 as f32, chunk_key.1 as f32, chunk_key.2 as f32) * size;
+                    // Nothing should keep resting on terrain that is going away
+                    #[cfg(feature = "physics")]
+                    commands.entity(entity).remove::<Collider>();
+                    commands.entity(entity).insert(ChunkFadeOut {
+                        timer: Timer::from_seconds(self.fade_out_duration, TimerMode::Once),
+                        origin,
+                        size,
+                    });
+                } else {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            if let Some(chunk) = self.chunks.remove(&chunk_key) {
+                pool.recycle(chunk);
             }
-            self.chunks.remove(&chunk_key);
             self.chunk_last_accessed.remove(&chunk_key);
         }
+
+        if saved > 0 {
+            self.notifications.push(NotificationEvent::info(format!("Saved {} edited chunk(s)", saved)));
+        }
+    }
+
+    // Makes `chunk_key` loaded: from disk if it was edited in an earlier visit, generated otherwise.
+    // Returns the voxels structure overflow was written into: those of other loaded chunks for a
+    // generated chunk, those of the chunk itself for a saved one.
+    pub fn load_or_generate_chunk(&mut self, chunk_key: (i32, i32, i32), generator: &WorldGenerator, pool: &mut ChunkPool) -> Vec<ChunkVoxel> {
+        if self.chunks.contains_key(&chunk_key) {
+            return Vec::new();
+        }
+        let _span = info_span!("chunk_generation", ?chunk_key).entered();
+
+        if let Some(mut chunk) = self.load_saved_chunk(chunk_key) {
+            // Trees from neighbours generated since the chunk was saved. Like place_structure_overflow
+            // into an edited chunk, they only fill air, so the player's edits win.
+            let mut pending = self.take_saved_pending_blocks(chunk_key);
+            pending.extend(self.pending_structures.remove(&chunk_key).unwrap_or_default());
+            let mut edited = Vec::new();
+            for PendingBlock { voxel_pos, block, .. } in pending {
+                let (x, y, z) = voxel_pos;
+                if block != AIR && chunk.get_block(x, y, z) == AIR {
+                    chunk.set_block(x, y, z, block);
+                    edited.push((chunk_key, voxel_pos));
+                }
+            }
+            if !edited.is_empty() {
+                self.modified_chunks.insert(chunk_key);
+            }
+            self.chunks.insert(chunk_key, chunk);
+            return edited;
+        }
+
+        let chunk = pool.take(self.chunk_size, self.chunk_size, self.chunk_size);
+        let (mut chunk, overflow) = generator.generate_into(chunk_key, chunk);
+        if let Some(hashes) = &mut self.generated_hashes {
+            hashes.push((chunk_key, determinism::hash_generated_chunk(&chunk, &overflow)));
+        }
+
+        // Trees and structures from neighbours generated earlier that reach into this chunk,
+        // the ones that waited on disk first since they were spilled before the rest
+        let mut pending = self.take_saved_pending_blocks(chunk_key);
+        pending.extend(self.pending_structures.remove(&chunk_key).unwrap_or_default());
+        for PendingBlock { voxel_pos: (x, y, z), block, replace } in pending {
+            if replace || chunk.get_block(x, y, z) == AIR {
+                chunk.set_block(x, y, z, block);
+            }
+        }
+
+        self.chunks.insert(chunk_key, chunk);
+        self.place_structure_overflow(overflow)
+    }
+
+    // Structure overflow for `chunk_key` that update_chunks moved to disk, removed from there
+    fn take_saved_pending_blocks(&mut self, chunk_key: (i32, i32, i32)) -> Vec<PendingBlock> {
+        let Some(save_dir) = self.save_dir.as_ref() else {
+            return Vec::new();
+        };
+        match save::take_pending_blocks(save_dir, chunk_key, &self.block_ids) {
+            Ok(blocks) => {
+                let size = self.chunk_size;
+                blocks.into_iter().filter(|block| block.voxel_pos.0 < size && block.voxel_pos.1 < size && block.voxel_pos.2 < size).collect()
+            }
+            Err(err) => {
+                self.notifications.push(NotificationEvent::warning(format!("Structure overflow saved for chunk {:?} is unreadable: {}", chunk_key, err)));
+                Vec::new()
+            }
+        }
+    }
+
+    fn load_saved_chunk(&mut self, chunk_key: (i32, i32, i32)) -> Option<Chunk> {
+        let save_dir = self.save_dir.as_ref()?;
+        let _span = info_span!("chunk_disk_load", ?chunk_key).entered();
+        match save::load_chunk(save_dir, chunk_key, &self.block_ids) {
+            // Chunks saved in an older format are written back in the current one when they unload
+            Ok(Some((header, chunk))) => {
+                if header.version < chunk_format::FORMAT_VERSION {
+                    self.modified_chunks.insert(chunk_key);
+                }
+                Some(chunk)
+            }
+            Ok(None) => None,
+            Err(err) => {
+                self.notifications.push(NotificationEvent::warning(format!("Saved chunk {:?} is unreadable and was regenerated: {}", chunk_key, err)));
+                None
+            }
+        }
+    }
+
+    // Writes the chunks among `chunk_keys` that have unsaved edits, each region once. Returns how
+    // many were written and the ones that must stay loaded: those whose save failed, is still
+    // running in an autosave, or waits out its region's backoff (unless `force`).
+    pub fn save_modified(&mut self, chunk_keys: &[(i32, i32, i32)], force: bool) -> (usize, HashSet<(i32, i32, i32)>) {
+        let mut unsaved = HashSet::new();
+        let mut blobs = Vec::new();
+        for &chunk_key in chunk_keys {
+            if self.saving_chunks.contains(&chunk_key) {
+                unsaved.insert(chunk_key);
+                continue;
+            }
+            if !self.modified_chunks.contains(&chunk_key) {
+                continue;
+            }
+            if !force && !self.can_save(chunk_key) {
+                unsaved.insert(chunk_key);
+                continue;
+            }
+            self.modified_chunks.remove(&chunk_key);
+            if let (Some(_), Some(chunk)) = (self.save_dir.as_ref(), self.chunks.get(&chunk_key)) {
+                blobs.push((chunk_key, chunk_format::encode_chunk_with_ids(chunk_key, chunk, &self.block_ids)));
+            }
+        }
+        let Some(save_dir) = self.save_dir.as_ref() else {
+            return (0, unsaved);
+        };
+        if blobs.is_empty() {
+            return (0, unsaved);
+        }
+
+        let written: Vec<_> = blobs.iter().map(|(chunk_key, _)| *chunk_key).collect();
+        let failed = save::save_chunk_blobs(save_dir, blobs);
+        for (chunk_keys, _) in &failed {
+            unsaved.extend(chunk_keys.iter().copied());
+        }
+        let saved = written.len() - failed.iter().map(|(chunk_keys, _)| chunk_keys.len()).sum::<usize>();
+        self.finish_save(&written, failed);
+        (saved, unsaved)
+    }
+
+    // False while the chunk's region waits out the backoff from a failed write
+    fn can_save(&self, chunk_key: (i32, i32, i32)) -> bool {
+        self.save_backoff.get(&save::region_key(chunk_key)).is_none_or(|backoff| Instant::now() >= backoff.retry_at)
+    }
+
+    // Regions that were written clear their backoff. Chunks of regions that failed are marked
+    // unsaved again, and the region waits twice as long as last time before the next try.
+    fn finish_save(&mut self, written: &[(i32, i32, i32)], failed: Vec<FailedRegion>) {
+        let now = Instant::now();
+        let failed_regions: HashSet<_> = failed.iter().filter_map(|(chunk_keys, _)| chunk_keys.first()).map(|&chunk_key| save::region_key(chunk_key)).collect();
+        for &chunk_key in written {
+            let region = save::region_key(chunk_key);
+            if !failed_regions.contains(&region) {
+                self.save_backoff.remove(&region);
+            }
+        }
+        for (chunk_keys, err) in failed {
+            let Some(&first) = chunk_keys.first() else {
+                continue;
+            };
+            let region = save::region_key(first);
+            let backoff = self.save_backoff.entry(region).or_insert(SaveBackoff { failures: 0, retry_at: now });
+            backoff.failures += 1;
+            let delay = (SAVE_RETRY_SECONDS * 2f32.powi(backoff.failures as i32 - 1)).min(SAVE_RETRY_MAX_SECONDS);
+            backoff.retry_at = now + Duration::from_secs_f32(delay);
+            self.notifications.push(NotificationEvent::error(format!(
+                "Could not save {} chunk(s) of region {:?}, retrying in {:.0}s: {}", chunk_keys.len(), region, delay, err
+            )));
+            self.modified_chunks.extend(chunk_keys);
+        }
+    }
+
+    // Writes structure blocks that crossed a chunk border: straight into loaded chunks (returned so
+    // they can be remeshed), or into the pending queue of chunks that haven't generated yet.
+    // Chunks the player has edited only get blocks in their air, so a structure never flattens
+    // what was built there. Loaded chunks that were written to are saved like edited ones, since
+    // the chunk that spilled the blocks may never generate again.
+    fn place_structure_overflow(&mut self, blocks: Vec<OverflowBlock>) -> Vec<ChunkVoxel> {
+        let mut edited = Vec::new();
+        let mut player_edited = HashMap::new();
+
+        for OverflowBlock { pos, block, replace } in blocks {
+            let (chunk_key, voxel_pos) = self.world_to_voxel(pos);
+            if let Some(chunk) = self.chunks.get_mut(&chunk_key) {
+                // Decided before this call marks the chunk modified itself
+                let protected = *player_edited.entry(chunk_key)
+                    .or_insert_with(|| !chunk.edits.is_empty() || self.modified_chunks.contains(&chunk_key));
+                let (x, y, z) = voxel_pos;
+                let current = chunk.get_block(x, y, z);
+                if current != block && ((replace && !protected) || current == AIR) {
+                    chunk.set_block(x, y, z, block);
+                    self.modified_chunks.insert(chunk_key);
+                    edited.push((chunk_key, voxel_pos));
+                }
+            } else {
+                self.pending_structures.entry(chunk_key).or_default().push(PendingBlock { voxel_pos, block, replace });
+            }
+        }
+
+        edited
+    }
+
+    // Encoded copies of every chunk with unsaved edits, for writing off the main thread. The
+    // chunks count as saved from here on and move to `saving_chunks`; see finish_background_save.
+    pub fn take_unsaved_chunks(&mut self) -> Vec<ChunkBlob> {
+        if self.save_dir.is_none() {
+            self.modified_chunks.clear();
+            return Vec::new();
+        }
+        let mut blobs = Vec::new();
+        let modified: Vec<_> = self.modified_chunks.iter().copied().collect();
+        for chunk_key in modified {
+            // Left marked for a later autosave
+            if !self.can_save(chunk_key) {
+                continue;
+            }
+            self.modified_chunks.remove(&chunk_key);
+            if let Some(chunk) = self.chunks.get(&chunk_key) {
+                blobs.push((chunk_key, chunk_format::encode_chunk_with_ids(chunk_key, chunk, &self.block_ids)));
+                self.saving_chunks.insert(chunk_key);
+            }
+        }
+        blobs
     }
 
+    // Chunks of a background save that failed to write are marked unsaved again, unless they were
+    // edited since, which marked them already, and their regions back off like in save_modified
+    pub fn finish_background_save(&mut self, chunk_keys: &[(i32, i32, i32)], failed: Vec<FailedRegion>) {
+        for chunk_key in chunk_keys {
+            self.saving_chunks.remove(chunk_key);
+        }
+        self.finish_save(chunk_keys, failed);
+    }
+
+    // Returns the number of chunks written, trying regions that are backing off as well;
+    // failures are reported through notifications
+    pub fn save_all_modified(&mut self) -> usize {
+        let modified: Vec<_> = self.modified_chunks.iter().copied().collect();
+        self.save_modified(&modified, true).0
+    }
+
+    #[cfg(feature = "render")]
     pub fn spawn_chunk_entity(&mut self, commands: &mut Commands, chunk_key: (i32, i32, i32), mesh: Handle<Mesh>, material: Handle<StandardMaterial>) {
         let chunk_position = Vec3::new(
             chunk_key.0 as f32 * self.chunk_size as f32,
@@ -185,4 +1021,108 @@ impl World {
 
         self.chunk_entities.insert(chunk_key, chunk_entity);
     }
-}
\ No newline at end of file
+}
+
+// Chunk key and position inside that chunk of a world voxel position. Coordinates round down,
+// so -1 is the last voxel of chunk -1. For code that has a chunk size but no World.
+pub fn split_world_pos(world_pos: IVec3, chunk_size: usize) -> ((i32, i32, i32), (usize, usize, usize)) {
+    let size = chunk_size as i32;
+    let chunk_key = (world_pos.x.div_euclid(size), world_pos.y.div_euclid(size), world_pos.z.div_euclid(size));
+    let voxel_pos = (
+        world_pos.x.rem_euclid(size) as usize,
+        world_pos.y.rem_euclid(size) as usize,
+        world_pos.z.rem_euclid(size) as usize,
+    );
+    (chunk_key, voxel_pos)
+}
+
+// Shrinks fading chunks towards their centre and despawns them once the fade completes
+pub fn fade_out_chunks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut fading: Query<(Entity, &mut ChunkFadeOut, &mut Transform)>,
+) {
+    for (entity, mut fade, mut transform) in &mut fading {
+        fade.timer.tick(time.delta());
+        if fade.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let scale = 1.0 - fade.timer.fraction();
+        transform.scale = Vec3::splat(scale);
+        transform.translation = fade.origin + Vec3::splat(fade.size * 0.5 * (1.0 - scale));
+    }
+}
+
+pub fn forward_world_notifications(mut world: ResMut<World>, mut notifications: EventWriter<NotificationEvent>) {
+    if !world.notifications.is_empty() {
+        notifications.send_batch(world.notifications.drain(..));
+    }
+}
+
+// `renderdistance [chunks]` shows or changes how many chunks around the player stay loaded
+pub fn render_distance_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut world: ResMut<World>,
+) {
+    for command in console_commands.read().filter(|command| command.name == "renderdistance") {
+        let Some(arg) = command.args.first() else {
+            console.print(format!("Render distance is {} chunks", world.render_distance));
+            continue;
+        };
+        match arg.parse::<i32>() {
+            Ok(distance) => {
+                let set = world.set_render_distance(distance);
+                console.print(format!("Render distance set to {} chunks", set));
+            }
+            Err(_) => console.print(format!("Expected a number of chunks from {} to {}", MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{LEAVES, LOG, STONE};
+    use crate::CHUNK_SIZE;
+
+    #[test]
+    fn overflow_into_a_saved_chunk_is_applied_on_load() {
+        let save_dir = std::env::temp_dir().join(format!("voxelfun-overflow-{}", std::process::id()));
+        let mut world = World { save_dir: Some(save_dir.clone()), ..World::new(CHUNK_SIZE, 2) };
+        let generator = Dimension::Overworld.generator(0);
+        let mut pool = ChunkPool::default();
+        let chunk_key = (0, 9, 0);
+
+        let mut chunk = Chunk::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+        chunk.set_block(1, 1, 1, STONE);
+        world.chunks.insert(chunk_key, chunk);
+        world.modified_chunks.insert(chunk_key);
+        let (saved, _) = world.save_modified(&[chunk_key], true);
+        assert_eq!(saved, 1);
+        world.chunks.remove(&chunk_key);
+
+        // One block waiting on disk, two in memory, one of them where the player built
+        let on_disk = PendingBlock { voxel_pos: (2, 2, 2), block: LOG, replace: false };
+        save::save_pending_blocks(&save_dir, vec![(chunk_key, vec![on_disk])], &world.block_ids).unwrap();
+        world.pending_structures.insert(chunk_key, vec![
+            PendingBlock { voxel_pos: (3, 3, 3), block: LEAVES, replace: false },
+            PendingBlock { voxel_pos: (1, 1, 1), block: LEAVES, replace: true },
+        ]);
+
+        let mut edited = world.load_or_generate_chunk(chunk_key, &generator, &mut pool);
+        edited.sort_unstable();
+        assert_eq!(edited, vec![(chunk_key, (2, 2, 2)), (chunk_key, (3, 3, 3))]);
+        let chunk = &world.chunks[&chunk_key];
+        assert_eq!(chunk.get_block(2, 2, 2), LOG);
+        assert_eq!(chunk.get_block(3, 3, 3), LEAVES);
+        assert_eq!(chunk.get_block(1, 1, 1), STONE);
+        assert!(world.modified_chunks.contains(&chunk_key));
+        assert!(!world.pending_structures.contains_key(&chunk_key));
+        assert!(save::take_pending_blocks(&save_dir, chunk_key, &world.block_ids).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&save_dir).unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+use bevy::prelude::*;
+use std::sync::Arc;
+use crate::biome::{Biome, ChunkColumns, Column, ColumnSampler, HeightmapCache};
+use crate::block::{BlockId, AIR, COAL_ORE, GOLD_ORE, IRON_ORE, STONE, WATER};
+use crate::decoration::{self, ChunkRng};
+use crate::structures;
+use crate::terrain::Chunk;
+use crate::HEIGHTMAP_CACHE_COLUMNS;
+use std::time::Duration;
+use bevy::utils::Instant;
+
+const ORE_SEED: u32 = 0x0e5e_ed01;
+
+// Bump whenever a change here gives existing chunk keys different blocks. Chunks remember the
+// version they were generated with, so `voxelfun upgrade` can find saved ones that predate it.
+// Re-record the golden chunk hashes afterwards with `voxelfun --golden record`, see golden.rs.
+pub const GENERATOR_VERSION: u32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct OreConfig {
+    pub block: BlockId,
+    // World-space height range veins may start in, inclusive
+    pub min_y: i32,
+    pub max_y: i32,
+    // Average number of veins per chunk, fractional parts are rolled
+    pub veins_per_chunk: f32,
+    // Random-walk steps per vein, roughly the number of ore blocks
+    pub vein_size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct WorldGenConfig {
+    // Per-world seed mixed into every noise and random seed with mix_seed. 0 generates the
+    // world every save had before worlds got seeds.
+    pub seed: u32,
+    pub ores: Vec<OreConfig>,
+    // World-space height water fills up to, exclusive
+    pub sea_level: i32,
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            ores: vec![
+                OreConfig { block: COAL_ORE, min_y: -64, max_y: 16, veins_per_chunk: 1.5, vein_size: 10 },
+                OreConfig { block: IRON_ORE, min_y: -64, max_y: 0, veins_per_chunk: 1.0, vein_size: 7 },
+                OreConfig { block: GOLD_ORE, min_y: -128, max_y: -24, veins_per_chunk: 0.35, vein_size: 5 },
+            ],
+            sea_level: 10,
+        }
+    }
+}
+
+// A feature's own seed constant combined with the world seed. Seed 0 leaves `base` unchanged.
+pub fn mix_seed(base: u32, world_seed: u32) -> u32 {
+    base ^ world_seed.wrapping_mul(0x9e37_79b9)
+}
+
+// Names of the built-in stages, for WorldGenerator::insert_before / insert_after
+pub const HEIGHTMAP_STAGE: &str = "heightmap";
+pub const WATER_STAGE: &str = "water";
+pub const ORE_STAGE: &str = "ores";
+pub const SURFACE_STAGE: &str = "surface";
+pub const DECORATION_STAGE: &str = "decoration";
+pub const STRUCTURE_STAGE: &str = "structures";
+
+// A block a stage wants written outside its chunk, in world coordinates. Plain blocks only
+// fill air (tree leaves never cut into a hill); `replace` ones overwrite whatever is there,
+// which is how large structures flatten the ground they stand on.
+#[derive(Clone, Copy, Debug)]
+pub struct OverflowBlock {
+    pub pos: IVec3,
+    pub block: BlockId,
+    pub replace: bool,
+}
+
+// State shared by the stages generating one chunk
+pub struct GenContext<'a> {
+    pub chunk_key: (i32, i32, i32),
+    pub config: &'a WorldGenConfig,
+    // Height, palette and vegetation of every column, with biome borders blended. Shared with
+    // the other chunks of the column through the generator's HeightmapCache.
+    pub columns: Arc<ChunkColumns>,
+    // Blocks a stage wanted to write outside this chunk
+    pub overflow: Vec<OverflowBlock>,
+}
+
+// One step of chunk generation. Stages run in order on the same chunk, so a stage sees
+// everything the stages before it wrote. Must be deterministic in (chunk_key, config).
+pub trait GenStage: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext);
+
+    // Whether the stage leaves a chunk lying wholly above its columns' ground and the sea
+    // untouched. Such chunks skip generation and stay uniform air when every stage says so;
+    // stages that don't know keep the default and always run.
+    fn only_near_ground(&self) -> bool {
+        false
+    }
+}
+
+// Base terrain from the height noise
+pub struct HeightmapStage;
+
+impl GenStage for HeightmapStage {
+    fn name(&self) -> &str {
+        HEIGHTMAP_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        chunk.generate_terrain(context.chunk_key.1, &context.columns);
+    }
+
+    fn only_near_ground(&self) -> bool {
+        true
+    }
+}
+
+// Floods every open cell below sea level
+pub struct WaterStage;
+
+impl GenStage for WaterStage {
+    fn name(&self) -> &str {
+        WATER_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        let base_y = context.chunk_key.1 * chunk.height as i32;
+        let top = (context.config.sea_level - base_y).clamp(0, chunk.height as i32) as usize;
+        for x in 0..chunk.width {
+            for y in 0..top {
+                for z in 0..chunk.depth {
+                    if chunk.get_block(x, y, z) == AIR {
+                        chunk.set_block(x, y, z, WATER);
+                    }
+                }
+            }
+        }
+    }
+
+    fn only_near_ground(&self) -> bool {
+        true
+    }
+}
+
+pub struct OreStage;
+
+impl GenStage for OreStage {
+    fn name(&self) -> &str {
+        ORE_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        generate_ores(chunk, context.chunk_key, context.config);
+    }
+
+    fn only_near_ground(&self) -> bool {
+        true
+    }
+}
+
+// Grass and dirt on top of exposed ground
+pub struct SurfaceStage;
+
+impl GenStage for SurfaceStage {
+    fn name(&self) -> &str {
+        SURFACE_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        decoration::apply_surface(chunk, context.chunk_key.1, &context.columns, context.config.sea_level);
+    }
+
+    fn only_near_ground(&self) -> bool {
+        true
+    }
+}
+
+// Trees, grass tufts and flowers
+pub struct DecorationStage;
+
+impl GenStage for DecorationStage {
+    fn name(&self) -> &str {
+        DECORATION_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        let overflow = decoration::decorate_chunk(chunk, context.chunk_key, &context.columns, context.config);
+        context.overflow.extend(overflow.into_iter().map(|(pos, block)| OverflowBlock { pos, block, replace: false }));
+    }
+
+    fn only_near_ground(&self) -> bool {
+        true
+    }
+}
+
+// Ordered list of generation stages run for every new chunk. Insert custom stages (caves,
+// structures, ...) relative to the built-in ones instead of editing them.
+#[derive(Resource)]
+pub struct WorldGenerator {
+    pub config: WorldGenConfig,
+    stages: Vec<Box<dyn GenStage>>,
+    columns: ColumnSampler,
+    heightmaps: HeightmapCache,
+}
+
+impl Default for WorldGenerator {
+    fn default() -> Self {
+        Self::seeded(0)
+    }
+}
+
+impl WorldGenerator {
+    // The overworld generator for a world seed
+    pub fn seeded(seed: u32) -> Self {
+        Self::new(WorldGenConfig { seed, ..default() })
+            .with_stage(HeightmapStage)
+            .with_stage(WaterStage)
+            .with_stage(OreStage)
+            .with_stage(SurfaceStage)
+            .with_stage(DecorationStage)
+            .with_stage(StructureStage)
+    }
+
+    // A generator without any stages
+    pub fn new(config: WorldGenConfig) -> Self {
+        let columns = ColumnSampler::new(config.seed);
+        Self { config, stages: Vec::new(), columns, heightmaps: HeightmapCache::new(HEIGHTMAP_CACHE_COLUMNS) }
+    }
+
+    pub fn with_stage(mut self, stage: impl GenStage) -> Self {
+        self.push_stage(stage);
+        self
+    }
+
+    pub fn push_stage(&mut self, stage: impl GenStage) {
+        self.stages.push(Box::new(stage));
+    }
+
+    // Returns false, leaving the pipeline unchanged, if no stage is called `before`
+    pub fn insert_before(&mut self, before: &str, stage: impl GenStage) -> bool {
+        let Some(index) = self.stage_index(before) else {
+            return false;
+        };
+        self.stages.insert(index, Box::new(stage));
+        true
+    }
+
+    pub fn insert_after(&mut self, after: &str, stage: impl GenStage) -> bool {
+        let Some(index) = self.stage_index(after) else {
+            return false;
+        };
+        self.stages.insert(index + 1, Box::new(stage));
+        true
+    }
+
+    pub fn remove_stage(&mut self, name: &str) -> bool {
+        let Some(index) = self.stage_index(name) else {
+            return false;
+        };
+        self.stages.remove(index);
+        true
+    }
+
+    pub fn stage_names(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|stage| stage.name())
+    }
+
+    // World-space height the overworld's ground reaches in a column, the first open cell above it
+    pub fn ground_height(&self, world_x: i32, world_z: i32) -> i32 {
+        self.columns.height(world_x, world_z) as i32
+    }
+
+    // Ground height and surface blocks of an overworld column, as HeightmapStage lays them down
+    pub fn column(&self, world_x: i32, world_z: i32) -> Column {
+        self.columns.column(world_x, world_z)
+    }
+
+    pub fn biome(&self, world_x: i32, world_z: i32) -> Biome {
+        self.columns.biome(world_x, world_z)
+    }
+
+    // Temperature at a point of the overworld, see ColumnSampler::temperature
+    pub fn temperature(&self, world_x: i32, world_y: i32, world_z: i32) -> f32 {
+        self.columns.temperature(world_x, world_y, world_z, self.config.sea_level)
+    }
+
+    fn stage_index(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|stage| stage.name() == name)
+    }
+
+    // Runs every stage on a fresh chunk. Returns the chunk and the blocks that spilled past its border.
+    pub fn generate(&self, chunk_key: (i32, i32, i32), chunk_size: usize) -> (Chunk, Vec<OverflowBlock>) {
+        self.generate_timed(chunk_key, chunk_size, |_, _| {})
+    }
+
+    // Like generate, reporting how long allocating the chunk ("chunk_new") and each stage took
+    pub fn generate_timed(
+        &self,
+        chunk_key: (i32, i32, i32),
+        chunk_size: usize,
+        mut on_stage: impl FnMut(&str, Duration),
+    ) -> (Chunk, Vec<OverflowBlock>) {
+        let start = Instant::now();
+        let chunk = Chunk::new(chunk_size, chunk_size, chunk_size);
+        on_stage("chunk_new", start.elapsed());
+        self.generate_into_timed(chunk_key, chunk, on_stage)
+    }
+
+    // Like generate, on an empty chunk from the caller, usually one recycled by ChunkPool
+    pub fn generate_into(&self, chunk_key: (i32, i32, i32), chunk: Chunk) -> (Chunk, Vec<OverflowBlock>) {
+        self.generate_into_timed(chunk_key, chunk, |_, _| {})
+    }
+
+    fn generate_into_timed(
+        &self,
+        chunk_key: (i32, i32, i32),
+        mut chunk: Chunk,
+        mut on_stage: impl FnMut(&str, Duration),
+    ) -> (Chunk, Vec<OverflowBlock>) {
+        let chunk_size = chunk.width;
+        chunk.generator_version = GENERATOR_VERSION;
+        let start = Instant::now();
+        let columns = self.heightmaps.columns(&self.columns, chunk_key, chunk_size, chunk_size);
+        on_stage("columns", start.elapsed());
+        let mut context = GenContext { chunk_key, config: &self.config, columns, overflow: Vec::new() };
+
+        // Open sky: the columns were all this chunk needed to know it stays empty
+        let base_y = chunk_key.1 * chunk_size as i32;
+        let (_, highest) = context.columns.height_range();
+        if base_y >= highest && base_y >= self.config.sea_level && self.stages.iter().all(|stage| stage.only_near_ground()) {
+            chunk.voxels.fill(AIR);
+            return (chunk, context.overflow);
+        }
+
+        for stage in &self.stages {
+            let _span = info_span!("gen_stage", stage = stage.name()).entered();
+            let start = Instant::now();
+            stage.generate(&mut chunk, &mut context);
+            on_stage(stage.name(), start.elapsed());
+        }
+
+        (chunk, context.overflow)
+    }
+}
+
+// Villages and ruins, see structures
+pub struct StructureStage;
+
+impl GenStage for StructureStage {
+    fn name(&self) -> &str {
+        STRUCTURE_STAGE
+    }
+
+    fn generate(&self, chunk: &mut Chunk, context: &mut GenContext) {
+        structures::generate_structures(chunk, context);
+    }
+
+    fn only_near_ground(&self) -> bool {
+        true
+    }
+}
+
+// Seeds random-walk ore blobs into stone. Veins are kept inside the chunk, so a vein
+// started near a border is simply cut short instead of spilling into the neighbour.
+pub fn generate_ores(chunk: &mut Chunk, chunk_key: (i32, i32, i32), config: &WorldGenConfig) {
+    let chunk_min_y = chunk_key.1 * chunk.height as i32;
+    let chunk_max_y = chunk_min_y + chunk.height as i32 - 1;
+
+    for (ore_index, ore) in config.ores.iter().enumerate() {
+        if ore.max_y < chunk_min_y || ore.min_y > chunk_max_y {
+            continue;
+        }
+
+        let mut rng = ChunkRng::for_chunk(chunk_key, mix_seed(ORE_SEED.wrapping_add(ore_index as u32), config.seed));
+        let mut vein_count = ore.veins_per_chunk.floor() as u32;
+        if rng.next_f32() < ore.veins_per_chunk.fract() {
+            vein_count += 1;
+        }
+
+        for _ in 0..vein_count {
+            let mut x = rng.range(0, chunk.width as i32 - 1);
+            let mut y = rng.range(0, chunk.height as i32 - 1);
+            let mut z = rng.range(0, chunk.depth as i32 - 1);
+
+            let world_y = chunk_min_y + y;
+            if world_y < ore.min_y || world_y > ore.max_y {
+                continue;
+            }
+
+            for _ in 0..ore.vein_size {
+                if chunk.get_block(x as usize, y as usize, z as usize) == STONE {
+                    chunk.set_block(x as usize, y as usize, z as usize, ore.block);
+                }
+
+                match rng.range(0, 5) {
+                    0 => x -= 1,
+                    1 => x += 1,
+                    2 => y -= 1,
+                    3 => y += 1,
+                    4 => z -= 1,
+                    _ => z += 1,
+                }
+                if x < 0 || y < 0 || z < 0 || x >= chunk.width as i32 || y >= chunk.height as i32 || z >= chunk.depth as i32 {
+                    break;
+                }
+            }
+        }
+    }
+}
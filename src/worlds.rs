@@ -0,0 +1,232 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use crate::block::BlockId;
+use crate::block_ids::{self, BlockIdMap};
+use crate::player::GameMode;
+use crate::spawn;
+use crate::worldgen::WorldGenerator;
+use crate::{DEFAULT_WORLD_NAME, WORLDS_DIRECTORY};
+
+pub const WORLD_META_FILE: &str = "world.txt";
+
+// A named world. Each one lives in its own directory under WORLDS_DIRECTORY with its region
+// files, game rules and player, and this as `name value` lines in WORLD_META_FILE.
+// `--world <name>` picks the world to play (the default one without it), `voxelfun worlds`
+// lists, creates and deletes them.
+#[derive(Resource, Clone, Debug)]
+pub struct WorldMeta {
+    pub name: String,
+    pub seed: u32,
+    // Seconds since the Unix epoch
+    pub last_played: u64,
+    pub game_mode: GameMode,
+    // Overworld cell the player spawns and respawns standing in, found when the world is created
+    pub spawn: Option<IVec3>,
+    // Name of the block saved under each ID, as "block <id> <name>" lines; see block_ids.rs
+    pub block_names: Vec<String>,
+}
+
+impl WorldMeta {
+    fn new(name: &str, seed: u32) -> Self {
+        Self { name: name.to_string(), seed, last_played: now(), game_mode: GameMode::default(), spawn: None, block_names: Vec::new() }
+    }
+
+    pub fn block_ids(&self) -> BlockIdMap {
+        BlockIdMap::new(&self.block_names)
+    }
+
+    pub fn directory(&self) -> PathBuf {
+        world_directory(&self.name)
+    }
+
+    // None if the world has no meta file. Unknown names and malformed lines are skipped.
+    pub fn load(name: &str) -> Option<Self> {
+        let contents = fs::read_to_string(world_directory(name).join(WORLD_META_FILE)).ok()?;
+        let mut meta = Self::new(name, 0);
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            match key {
+                "seed" => meta.seed = value.parse().unwrap_or(meta.seed),
+                "lastPlayed" => meta.last_played = value.parse().unwrap_or(meta.last_played),
+                "gameMode" => meta.game_mode = GameMode::parse(value).unwrap_or(meta.game_mode),
+                "spawn" => meta.spawn = parse_cell(value).or(meta.spawn),
+                "block" => {
+                    // Parsed as a BlockId so an id past BlockId::MAX can't grow the table to it
+                    let Some((id, block)) = value.split_once(' ').and_then(|(id, block)| Some((id.parse::<BlockId>().ok()? as usize, block))) else {
+                        continue;
+                    };
+                    if meta.block_names.len() <= id {
+                        meta.block_names.resize(id + 1, String::new());
+                    }
+                    meta.block_names[id] = block.to_string();
+                }
+                _ => {}
+            }
+        }
+        Some(meta)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let directory = self.directory();
+        fs::create_dir_all(&directory)?;
+        let mut contents = format!(
+            "name {}\nseed {}\nlastPlayed {}\ngameMode {}\n",
+            self.name, self.seed, self.last_played, self.game_mode.name(),
+        );
+        if let Some(spawn) = self.spawn {
+            contents += &format!("spawn {} {} {}\n", spawn.x, spawn.y, spawn.z);
+        }
+        for (id, block) in self.block_names.iter().enumerate() {
+            contents += &format!("block {} {}\n", id, block);
+        }
+        fs::write(directory.join(WORLD_META_FILE), contents)
+    }
+}
+
+fn parse_cell(value: &str) -> Option<IVec3> {
+    let coordinates: Vec<i32> = value.split_whitespace().map(str::parse).collect::<Result<_, _>>().ok()?;
+    match coordinates.as_slice() {
+        &[x, y, z] => Some(IVec3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+pub fn world_directory(name: &str) -> PathBuf {
+    Path::new(WORLDS_DIRECTORY).join(name)
+}
+
+// World names double as directory names
+fn check_name(name: &str) -> Result<(), String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        Ok(())
+    } else {
+        Err(format!("invalid world name '{}': use letters, digits, '-' and '_'", name))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn random_seed() -> u32 {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (elapsed.as_secs() as u32).wrapping_mul(0x9e37_79b9) ^ elapsed.subsec_nanos()
+}
+
+// Creates a world with `seed`, or a random one. Fails if the world exists already.
+pub fn create(name: &str, seed: Option<u32>) -> Result<WorldMeta, String> {
+    check_name(name)?;
+    if world_directory(name).exists() {
+        return Err(format!("world '{}' exists already", name));
+    }
+    let mut meta = WorldMeta::new(name, seed.unwrap_or_else(random_seed));
+    meta.spawn = Some(spawn::find_spawn(&WorldGenerator::seeded(meta.seed)));
+    meta.block_names = block_ids::registered_names();
+    meta.save().map_err(|err| format!("could not create world '{}': {}", name, err))?;
+    Ok(meta)
+}
+
+// Loads the world to play and stamps it as played now. A world that doesn't exist yet is
+// created; the default world and directories saved before worlds had meta files get seed 0,
+// which is the terrain they were generated with.
+pub fn open(name: &str) -> Result<WorldMeta, String> {
+    check_name(name)?;
+    let mut meta = match WorldMeta::load(name) {
+        Some(meta) => meta,
+        None if name == DEFAULT_WORLD_NAME || world_directory(name).exists() => WorldMeta::new(name, 0),
+        None => create(name, None)?,
+    };
+    meta.last_played = now();
+    // Worlds created before spawn points were stored get one now
+    if meta.spawn.is_none() {
+        meta.spawn = Some(spawn::find_spawn(&WorldGenerator::seeded(meta.seed)));
+    }
+    // Worlds saved before block IDs were recorded used the registry's order, which had only
+    // grown at the end until then. Blocks registered since the world was last played get new IDs.
+    if meta.block_names.is_empty() {
+        meta.block_names = block_ids::registered_names();
+    }
+    block_ids::register_new_blocks(&mut meta.block_names);
+    let unknown = block_ids::unknown_blocks(&meta.block_names);
+    if !unknown.is_empty() {
+        println!("World '{}' has blocks this version doesn't know, they load as air: {}", name, unknown.join(", "));
+    }
+    meta.save().map_err(|err| format!("could not save world '{}': {}", name, err))?;
+    Ok(meta)
+}
+
+pub fn delete(name: &str) -> Result<(), String> {
+    check_name(name)?;
+    let directory = world_directory(name);
+    if !directory.join(WORLD_META_FILE).exists() {
+        return Err(format!("no world called '{}'", name));
+    }
+    fs::remove_dir_all(&directory).map_err(|err| format!("could not delete world '{}': {}", name, err))
+}
+
+// Every world with a meta file, most recently played first
+pub fn list() -> Vec<WorldMeta> {
+    let Ok(entries) = fs::read_dir(WORLDS_DIRECTORY) else {
+        return Vec::new();
+    };
+    let mut worlds: Vec<WorldMeta> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| WorldMeta::load(&name))
+        .collect();
+    worlds.sort_by(|a, b| b.last_played.cmp(&a.last_played).then_with(|| a.name.cmp(&b.name)));
+    worlds
+}
+
+// Takes `--world <name>` out of the command line like Dimension::take_from_args
+pub fn take_from_args(args: &mut Vec<String>) -> String {
+    let Some(index) = args.iter().position(|arg| arg == "--world") else {
+        return DEFAULT_WORLD_NAME.to_string();
+    };
+    args.drain(index..(index + 2).min(args.len())).nth(1).unwrap_or_default()
+}
+
+// `voxelfun worlds` lists the worlds, `worlds create <name> [seed]` and `worlds delete <name>`
+// manage them. Returns the process exit code.
+pub fn run_command(program: &str, args: &[String]) -> i32 {
+    let usage = || {
+        eprintln!("usage: {} worlds [create <name> [seed] | delete <name>]", program);
+        2
+    };
+    let result = match args {
+        [] => {
+            for meta in list() {
+                println!("{:<20} seed {:<10} {:<8} last played {}", meta.name, meta.seed, meta.game_mode.name(), meta.last_played);
+            }
+            Ok(())
+        }
+        [command, name] if command == "create" => create(name, None).map(|meta| println!("Created '{}' with seed {}", meta.name, meta.seed)),
+        [command, name, seed] if command == "create" => match seed.parse() {
+            Ok(seed) => create(name, Some(seed)).map(|meta| println!("Created '{}' with seed {}", meta.name, meta.seed)),
+            Err(_) => return usage(),
+        },
+        [command, name] if command == "delete" => delete(name).map(|()| println!("Deleted '{}'", name)),
+        _ => return usage(),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+// Keeps the game mode in the meta file current, so the world reopens in it
+pub fn record_game_mode(game_mode: Res<GameMode>, mut meta: ResMut<WorldMeta>, mut world: ResMut<crate::world::World>) {
+    if !game_mode.is_changed() || meta.game_mode == *game_mode {
+        return;
+    }
+    meta.game_mode = *game_mode;
+    if let Err(err) = meta.save() {
+        world.notifications.push(crate::notifications::NotificationEvent::error(format!("Could not save the world: {}", err)));
+    }
+}